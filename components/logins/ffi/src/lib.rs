@@ -35,7 +35,9 @@ pub extern "C" fn sync15_passwords_state_new(
     ENGINES.insert_with_result(error, || -> logins::Result<_> {
         let path = db_path.as_str();
         let key = encryption_key.as_str();
-        Ok(Arc::new(Mutex::new(PasswordEngine::new(path, Some(key))?)))
+        // The report is logged when opening, and isn't passed over the FFI.
+        let (engine, _) = PasswordEngine::new_with_recovery(path, Some(key))?;
+        Ok(Arc::new(Mutex::new(engine)))
     })
 }
 
@@ -98,10 +100,8 @@ pub unsafe extern "C" fn sync15_passwords_state_new_with_hex_key(
         let key = bytes_to_key_string(encryption_key, encryption_key_len as usize);
         // We have a Option<String>, but need an Option<&str>...
         let opt_key_ref = key.as_deref();
-        Ok(Arc::new(Mutex::new(PasswordEngine::new(
-            path,
-            opt_key_ref,
-        )?)))
+        let (engine, _) = PasswordEngine::new_with_recovery(path, opt_key_ref)?;
+        Ok(Arc::new(Mutex::new(engine)))
     })
 }
 
@@ -15,7 +15,7 @@ use rusqlite::{
 };
 use serde_derive::*;
 use sql_support::{self, ConnExt};
-use sql_support::{SqlInterruptHandle, SqlInterruptScope};
+use sql_support::{RecoveryReport, SqlInterruptHandle, SqlInterruptScope};
 use std::collections::HashSet;
 use std::ops::Deref;
 use std::path::Path;
@@ -116,6 +116,89 @@ impl LoginDb {
         )?)
     }
 
+    /// Like `open`, but if the database is corrupt, moves it aside, creates
+    /// a fresh database in its place, and copies in the logins that can
+    /// still be read. The returned report describes what was recovered, and
+    /// is `None` if the database opened normally.
+    ///
+    /// SQLCipher can't tell a corrupt database from a wrong key, so for
+    /// encrypted databases, we only recover from errors that SQLite reports
+    /// after the key was accepted.
+    pub fn open_with_recovery(
+        path: impl AsRef<Path>,
+        encryption_key: Option<&str>,
+    ) -> Result<(Self, Option<RecoveryReport>)> {
+        let path = path.as_ref();
+        let err = match Self::open(path, encryption_key) {
+            Ok(db) => return Ok((db, None)),
+            Err(err) => err,
+        };
+        let recoverable = match err.kind() {
+            ErrorKind::SqlError(rusqlite::Error::SqliteFailure(e, _)) => {
+                e.code == rusqlite::ErrorCode::DatabaseCorrupt
+                    || (e.code == rusqlite::ErrorCode::NotADatabase && encryption_key.is_none())
+            }
+            _ => false,
+        };
+        if !recoverable || !path.exists() {
+            return Err(err);
+        }
+        log::error!("Failed to open logins database, recovering: {}", err);
+        let corrupt_path = sql_support::move_aside(path)?;
+        let db = Self::open(path, encryption_key)?;
+        let report = db
+            .salvage(&corrupt_path, encryption_key)
+            .unwrap_or_else(|e| {
+                log::error!("Failed to salvage the corrupt database: {}", e);
+                RecoveryReport::new(corrupt_path)
+            });
+        log::info!(
+            "Recovered {} logins from the corrupt database ({} failed)",
+            report.recovered(),
+            report.failed()
+        );
+        Ok((db, Some(report)))
+    }
+
+    /// Copies the local and mirror logins that can still be read from the
+    /// corrupt database at `corrupt_path`. Sync metadata isn't copied, so
+    /// the next sync will be treated as a first sync.
+    fn salvage(&self, corrupt_path: &Path, encryption_key: Option<&str>) -> Result<RecoveryReport> {
+        let mut report = RecoveryReport::new(corrupt_path);
+        let src = match open_salvage_source(corrupt_path, encryption_key) {
+            Ok(src) => src,
+            Err(e) => {
+                log::warn!("Can't open the corrupt database: {}", e);
+                return Ok(report);
+            }
+        };
+        report.integrity_ok = sql_support::check_integrity(&src).unwrap_or(false);
+
+        let tx = self.db.unchecked_transaction()?;
+        let common_cols = schema::COMMON_COLS
+            .split(',')
+            .map(str::trim)
+            .collect::<Vec<_>>();
+        for (table, extra_cols) in &[
+            (
+                "loginsL",
+                &["local_modified", "is_deleted", "sync_status"][..],
+            ),
+            ("loginsM", &["server_modified", "is_overridden"][..]),
+        ] {
+            let columns = common_cols
+                .iter()
+                .chain(extra_cols.iter())
+                .cloned()
+                .collect::<Vec<_>>();
+            report
+                .tables
+                .push(sql_support::salvage_table(&src, &tx, table, &columns));
+        }
+        tx.commit()?;
+        Ok(report)
+    }
+
     pub fn open_in_memory(encryption_key: Option<&str>) -> Result<Self> {
         Ok(Self::with_connection(
             Connection::open_in_memory()?,
@@ -202,6 +285,15 @@ fn ensure_valid_salt(salt: &str) -> Result<()> {
     Err(ErrorKind::InvalidSalt.into())
 }
 
+fn open_salvage_source(path: &Path, encryption_key: Option<&str>) -> Result<Connection> {
+    let src = Connection::open(path)?;
+    if let Some(key) = encryption_key {
+        src.set_pragma("key", key)?;
+        sqlcipher_3_compat(&src)?;
+    }
+    Ok(src)
+}
+
 fn sqlcipher_3_compat(conn: &Connection) -> Result<()> {
     // SQLcipher pre-4.0.0 compatibility. Using SHA1 still
     // is less than ideal, but should be fine. Real uses of
@@ -1281,6 +1373,10 @@ impl<'a> Store for LoginStore<'a> {
         Ok(())
     }
 
+    fn get_last_sync(&self) -> result::Result<Option<ServerTimestamp>, failure::Error> {
+        Ok(self.db.get_last_sync()?)
+    }
+
     fn get_collection_requests(
         &self,
         server_timestamp: ServerTimestamp,
@@ -1989,6 +2085,71 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_salvage() {
+        let dir = tempdir::TempDir::new("salvage").unwrap();
+        let corrupt_path = dir.path().join("logins.sqlite.corrupt");
+        {
+            let db = LoginDb::open(&corrupt_path, Some("testing")).unwrap();
+            for hostname in &["https://example.com", "https://example.org"] {
+                db.add(Login {
+                    hostname: (*hostname).into(),
+                    http_realm: Some("Realm".into()),
+                    password: "test".into(),
+                    ..Login::default()
+                })
+                .unwrap();
+            }
+        }
+
+        let db = LoginDb::open(dir.path().join("logins.sqlite"), Some("testing")).unwrap();
+        let report = db.salvage(&corrupt_path, Some("testing")).unwrap();
+        assert!(report.integrity_ok);
+        assert!(report.is_complete());
+        assert_eq!(report.tables[0].table, "loginsL");
+        assert_eq!(report.tables[0].recovered, 2);
+        assert_eq!(report.tables[1].recovered, 0);
+        let mut hostnames = db
+            .get_all()
+            .unwrap()
+            .into_iter()
+            .map(|login| login.hostname)
+            .collect::<Vec<_>>();
+        hostnames.sort();
+        assert_eq!(
+            hostnames,
+            vec!["https://example.com", "https://example.org"]
+        );
+    }
+
+    #[test]
+    fn test_open_with_recovery() {
+        let dir = tempdir::TempDir::new("open_with_recovery").unwrap();
+        let dbpath = dir.path().join("logins.sqlite");
+        std::fs::write(&dbpath, vec![b'x'; 4096]).unwrap();
+        let (db, report) = LoginDb::open_with_recovery(&dbpath, None).unwrap();
+        let report = report.expect("should recover");
+        assert_eq!(
+            report.corrupt_path,
+            dir.path().join("logins.sqlite.corrupt")
+        );
+        assert_eq!(report.recovered(), 0);
+        assert!(db.get_all().unwrap().is_empty());
+        drop(db);
+
+        // Opening a healthy database doesn't report anything.
+        let (_, report) = LoginDb::open_with_recovery(&dbpath, None).unwrap();
+        assert!(report.is_none());
+
+        // A wrong key looks like a corrupt database, so we shouldn't try to
+        // recover an encrypted database that we can't decrypt.
+        let encrypted_path = dir.path().join("encrypted.sqlite");
+        drop(LoginDb::open(&encrypted_path, Some("testing")).unwrap());
+        let _ = LoginDb::open_with_recovery(&encrypted_path, Some("wrong"));
+        assert!(encrypted_path.exists());
+        assert!(!dir.path().join("encrypted.sqlite.corrupt").exists());
+    }
+
     #[test]
     fn test_ensure_valid_salt() {
         assert!(ensure_valid_salt("bobo").is_err());
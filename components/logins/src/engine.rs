@@ -4,6 +4,7 @@
 use crate::db::{LoginDb, LoginStore, MigrationMetrics};
 use crate::error::*;
 use crate::login::Login;
+use sql_support::RecoveryReport;
 use std::cell::Cell;
 use std::path::Path;
 use sync15::{
@@ -36,6 +37,22 @@ impl PasswordEngine {
         })
     }
 
+    /// Like `new`, but recovers what it can from a corrupt database, instead
+    /// of failing to open it. See `LoginDb::open_with_recovery` for details.
+    pub fn new_with_recovery(
+        path: impl AsRef<Path>,
+        encryption_key: Option<&str>,
+    ) -> Result<(Self, Option<RecoveryReport>)> {
+        let (db, report) = LoginDb::open_with_recovery(path, encryption_key)?;
+        Ok((
+            Self {
+                db,
+                mem_cached_state: Cell::default(),
+            },
+            report,
+        ))
+    }
+
     pub fn new_in_memory(encryption_key: Option<&str>) -> Result<Self> {
         let db = LoginDb::open_in_memory(encryption_key)?;
         Ok(Self {
@@ -56,6 +56,9 @@ pub enum ErrorKind {
 
     #[fail(display = "Protobuf decode error: {}", _0)]
     ProtobufDecodeError(#[fail(cause)] prost::DecodeError),
+
+    #[fail(display = "IO error: {}", _0)]
+    IoError(#[fail(cause)] std::io::Error),
 }
 
 error_support::define_error! {
@@ -67,6 +70,7 @@ error_support::define_error! {
         (InvalidLogin, InvalidLogin),
         (Interrupted, interrupt_support::Interrupted),
         (ProtobufDecodeError, prost::DecodeError),
+        (IoError, std::io::Error),
     }
 }
 
@@ -111,6 +115,7 @@ impl Error {
                 InvalidLogin::IllegalFieldValue { .. } => "InvalidLogin::IllegalFieldValue",
             },
             ErrorKind::ProtobufDecodeError(_) => "BufDecodeError",
+            ErrorKind::IoError(_) => "IoError",
         }
     }
 }
@@ -23,6 +23,7 @@ pub use crate::db::LoginStore;
 pub use crate::engine::*;
 pub use crate::error::*;
 pub use crate::login::*;
+pub use sql_support::{RecoveryReport, TableRecovery};
 
 pub mod msg_types {
     include!("mozilla.appservices.logins.protobuf.rs");
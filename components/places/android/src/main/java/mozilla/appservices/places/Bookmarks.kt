@@ -99,7 +99,17 @@ data class BookmarkItem(
      * Note that the bookmark storage layer treats NULL and the
      * empty string as equivalent in titles.
      */
-    val title: String
+    val title: String,
+
+    /**
+     * The search keyword for this bookmark's URL, if it has one.
+     */
+    val keyword: String? = null,
+
+    /**
+     * The tags for this bookmark's URL, ordered by tag.
+     */
+    val tags: List<String> = listOf()
 ) : BookmarkTreeNode() {
     override val type get() = BookmarkType.Bookmark
 }
@@ -225,6 +235,49 @@ interface ReadableBookmarksConnection : InterruptibleConnection {
      */
     fun getBookmarkUrlForKeyword(keyword: String): String?
 
+    /**
+     * Returns all search keywords, ordered by keyword.
+     *
+     * @return A list of keywords and the URLs they're set for.
+     *
+     * @throws OperationInterrupted if this database implements [InterruptibleConnection] and
+     * has its `interrupt()` method called on another thread.
+     */
+    fun getBookmarkKeywords(): List<BookmarkKeyword>
+
+    /**
+     * Returns all tags that are used by at least one URL, ordered by tag.
+     *
+     * @return A list of tags, and the number of URLs with each tag.
+     *
+     * @throws OperationInterrupted if this database implements [InterruptibleConnection] and
+     * has its `interrupt()` method called on another thread.
+     */
+    fun getAllTags(): List<TagInfo>
+
+    /**
+     * Returns the tags for a URL.
+     *
+     * @param url The URL to look up.
+     * @return A list of tags for the URL, which is empty if it has none.
+     *
+     * @throws UrlParseFailed If `url` does not refer to a valid URL.
+     * @throws OperationInterrupted if this database implements [InterruptibleConnection] and
+     * has its `interrupt()` method called on another thread.
+     */
+    fun getTagsForUrl(url: String): List<String>
+
+    /**
+     * Returns the URLs with a tag.
+     *
+     * @param tag The tag to look up.
+     * @return A list of URLs with the tag.
+     *
+     * @throws OperationInterrupted if this database implements [InterruptibleConnection] and
+     * has its `interrupt()` method called on another thread.
+     */
+    fun getUrlsWithTag(tag: String): List<String>
+
     /**
      * Returns the list of bookmarks that match the provided search string.
      *
@@ -273,6 +326,20 @@ interface WritableBookmarksConnection : ReadableBookmarksConnection {
      */
     fun deleteBookmarkNode(guid: String): Boolean
 
+    /**
+     * Undo the most recent bookmark change made on this connection.
+     *
+     * The connection remembers the last few bookmarks that were created,
+     * updated, moved, or deleted. Undoing a deletion restores the deleted
+     * bookmark, and all its children, with their original GUIDs.
+     *
+     * @return Whether or not there was a change to undo.
+     *
+     * @throws UnknownBookmarkItem If the change can't be undone because the
+     * bookmark it changed no longer exists. The change is forgotten.
+     */
+    fun undoBookmarkChange(): Boolean
+
     /**
      * Delete all bookmarks without affecting history
      *
@@ -360,6 +427,158 @@ interface WritableBookmarksConnection : ReadableBookmarksConnection {
      * folder node.
      */
     fun updateBookmark(guid: String, info: BookmarkUpdateInfo)
+
+    /**
+     * Reorder the children of a folder, in a single transaction.
+     *
+     * GUIDs in `orderedGUIDs` that aren't children of the folder are ignored.
+     * Children that aren't listed are moved after the listed ones, in their
+     * current order.
+     *
+     * @param folderGUID The GUID of the folder to reorder.
+     * @param orderedGUIDs The GUIDs of the folder's children, in their new order.
+     *
+     * @throws UnknownBookmarkItem If `folderGUID` does not refer to a known bookmark.
+     * @throws InvalidParent If `folderGUID` does not refer to a folder node.
+     */
+    fun reorderBookmarkChildren(folderGUID: String, orderedGUIDs: List<String>)
+
+    /**
+     * Move several bookmarks into a folder, in a single transaction.
+     *
+     * @param guids The GUIDs of the bookmarks to move, in the order they
+     * should appear in the folder.
+     * @param parentGUID The GUID of the folder to move them into.
+     * @param position The index where to insert the bookmarks, not counting
+     * the bookmarks being moved. If not provided, they will be appended.
+     *
+     * @throws CannotUpdateRoot If one of `guids` is a bookmark root, or `parentGUID`
+     * is [BookmarkRoot.Root] (e.g. "root________")
+     * @throws UnknownBookmarkItem If `parentGUID`, or one of `guids`, does not
+     * refer to a known bookmark.
+     * @throws InvalidParent If `parentGUID` does not refer to a folder node, or
+     * refers to one of the folders being moved, or one of their descendants.
+     */
+    fun moveBookmarks(guids: List<String>, parentGUID: String, position: Int? = null)
+
+    /**
+     * Set the search keyword for a bookmarked URL, replacing its existing
+     * keyword. Keywords are unique, so if another URL already has this
+     * keyword, it's removed from that URL.
+     *
+     * Keywords are trimmed and lowercased before they're stored.
+     *
+     * @param url The bookmarked URL.
+     * @param keyword The new keyword.
+     * @param postData Optional POST data to submit when using the keyword.
+     * This is stored locally, but isn't synced.
+     *
+     * @throws UrlParseFailed If `url` does not refer to a valid URL.
+     */
+    fun setBookmarkKeyword(url: String, keyword: String, postData: String? = null)
+
+    /**
+     * Remove the search keyword for a URL.
+     *
+     * @param url The URL whose keyword should be removed.
+     * @return Whether or not the URL had a keyword.
+     *
+     * @throws UrlParseFailed If `url` does not refer to a valid URL.
+     */
+    fun removeBookmarkKeyword(url: String): Boolean
+
+    /**
+     * Add a tag to a URL. Tags are trimmed before they're stored, and
+     * bookmarks with the URL are marked as changed for the next sync.
+     *
+     * @param url The URL to tag.
+     * @param tag The tag to add.
+     *
+     * @throws UrlParseFailed If `url` does not refer to a valid URL.
+     */
+    fun tagUrl(url: String, tag: String)
+
+    /**
+     * Remove a tag from a URL.
+     *
+     * @param url The URL to untag.
+     * @param tag The tag to remove.
+     *
+     * @throws UrlParseFailed If `url` does not refer to a valid URL.
+     */
+    fun untagUrl(url: String, tag: String)
+
+    /**
+     * Remove a tag from all URLs.
+     *
+     * @param tag The tag to remove.
+     */
+    fun removeTag(tag: String)
+
+    /**
+     * Rename a tag. If `newTag` already exists, the two tags are merged, so
+     * every URL with `oldTag` ends up tagged with `newTag` instead.
+     *
+     * @param oldTag The tag to rename.
+     * @param newTag The new name for the tag.
+     */
+    fun renameTag(oldTag: String, newTag: String)
+}
+
+/**
+ * A tag, and the number of URLs that have it.
+ */
+data class TagInfo(
+    val tag: String,
+    val urlCount: Int,
+
+    /**
+     * When the tag was last renamed or added, in milliseconds since the
+     * unix epoch.
+     */
+    val lastModified: Long
+) {
+    companion object {
+        internal fun fromMessage(msg: MsgTypes.TagInfo): TagInfo {
+            return TagInfo(
+                tag = msg.tag,
+                urlCount = msg.urlCount,
+                lastModified = msg.lastModified
+            )
+        }
+
+        internal fun fromCollectionMessage(msg: MsgTypes.TagInfoList): List<TagInfo> {
+            return msg.tagsList.map { fromMessage(it) }
+        }
+    }
+}
+
+/**
+ * A search keyword for a bookmarked URL.
+ */
+data class BookmarkKeyword(
+    val keyword: String,
+    val url: String,
+
+    /**
+     * POST data to submit when using the keyword. This is stored locally,
+     * but isn't synced.
+     */
+    val postData: String? = null
+) {
+    companion object {
+        internal fun fromMessage(msg: MsgTypes.BookmarkKeyword): BookmarkKeyword {
+            return BookmarkKeyword(
+                keyword = msg.keyword,
+                url = msg.url,
+                postData = if (msg.hasPostData()) { msg.postData } else { null }
+            )
+        }
+
+        internal fun fromCollectionMessage(msg: MsgTypes.BookmarkKeywordList): List<BookmarkKeyword> {
+            return msg.keywordsList.map { fromMessage(it) }
+        }
+    }
 }
 
 /**
@@ -491,7 +710,9 @@ internal fun unpackProtobuf(msg: MsgTypes.BookmarkNode): BookmarkTreeNode {
                     dateAdded = dateAdded,
                     lastModified = lastModified,
                     title = title,
-                    url = msg.url
+                    url = msg.url,
+                    keyword = if (msg.hasKeyword()) { msg.keyword } else { null },
+                    tags = msg.tagsList
             )
         }
 
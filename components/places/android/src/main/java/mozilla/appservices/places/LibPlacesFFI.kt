@@ -30,6 +30,12 @@ internal interface LibPlacesFFI : Library {
         out_err: RustError.ByReference
     ): PlacesApiHandle
 
+    fun places_api_new_with_frecency_settings(
+        db_path: String,
+        frecency_settings_json: String,
+        out_err: RustError.ByReference
+    ): PlacesApiHandle
+
     /** Create a new places connection */
     fun places_connection_new(
         handle: PlacesApiHandle,
@@ -63,14 +69,27 @@ internal interface LibPlacesFFI : Library {
         out_err: RustError.ByReference
     )
 
-    /** Returns JSON string, which you need to free with places_destroy_string */
+    /** Returns a protobuf-encoded SearchResultList, which you need to free with
+     * places_destroy_bytebuffer. `mode` is a `SearchMode`. `interrupt_handle`
+     * may be null. */
     fun places_query_autocomplete(
         handle: PlacesConnectionHandle,
         search: String,
         limit: Int,
+        mode: Byte,
+        interrupt_handle: RawAutocompleteInterruptHandle?,
         out_err: RustError.ByReference
     ): RustBuffer.ByValue
 
+    fun places_new_autocomplete_interrupt_handle(
+        out_err: RustError.ByReference
+    ): RawAutocompleteInterruptHandle?
+
+    fun places_autocomplete_interrupt(
+        handle: RawAutocompleteInterruptHandle,
+        out_err: RustError.ByReference
+    )
+
     /** Returns a URL, or null if no match was found. */
     fun places_match_url(
         handle: PlacesConnectionHandle,
@@ -119,6 +138,13 @@ internal interface LibPlacesFFI : Library {
         out_err: RustError.ByReference
     )
 
+    fun places_delete_visits_for_host(
+        handle: PlacesConnectionHandle,
+        host: String,
+        includeSubdomains: Byte,
+        out_err: RustError.ByReference
+    )
+
     fun places_delete_visits_between(
         handle: PlacesConnectionHandle,
         start: Long,
@@ -143,11 +169,17 @@ internal interface LibPlacesFFI : Library {
         out_err: RustError.ByReference
     )
 
-    fun places_prune_destructively(
+    fun places_set_frecency_settings(
         handle: PlacesConnectionHandle,
+        frecency_settings_json: String,
         out_err: RustError.ByReference
     )
 
+    fun places_prune_destructively(
+        handle: PlacesConnectionHandle,
+        out_err: RustError.ByReference
+    ): RustBuffer.ByValue
+
     fun places_delete_everything(
         handle: PlacesConnectionHandle,
         out_err: RustError.ByReference
@@ -161,6 +193,74 @@ internal interface LibPlacesFFI : Library {
         error: RustError.ByReference
     ): RustBuffer.ByValue
 
+    fun places_get_top_frecent_site_infos(
+        handle: PlacesConnectionHandle,
+        numItems: Int,
+        frecencyThreshold: Long,
+        error: RustError.ByReference
+    ): RustBuffer.ByValue
+
+    fun places_block_top_site(
+        handle: PlacesConnectionHandle,
+        url: String,
+        out_err: RustError.ByReference
+    )
+
+    fun places_unblock_top_site(
+        handle: PlacesConnectionHandle,
+        url: String,
+        out_err: RustError.ByReference
+    )
+
+    fun places_clear_top_sites_blocklist(
+        handle: PlacesConnectionHandle,
+        out_err: RustError.ByReference
+    )
+
+    fun places_tag_url(
+        handle: PlacesConnectionHandle,
+        url: String,
+        tag: String,
+        out_err: RustError.ByReference
+    )
+
+    fun places_untag_url(
+        handle: PlacesConnectionHandle,
+        url: String,
+        tag: String,
+        out_err: RustError.ByReference
+    )
+
+    fun places_remove_tag(
+        handle: PlacesConnectionHandle,
+        tag: String,
+        out_err: RustError.ByReference
+    )
+
+    fun places_rename_tag(
+        handle: PlacesConnectionHandle,
+        oldTag: String,
+        newTag: String,
+        out_err: RustError.ByReference
+    )
+
+    fun places_get_all_tags(
+        handle: PlacesConnectionHandle,
+        error: RustError.ByReference
+    ): RustBuffer.ByValue
+
+    fun places_get_tags_for_url(
+        handle: PlacesConnectionHandle,
+        url: String,
+        error: RustError.ByReference
+    ): RustBuffer.ByValue
+
+    fun places_get_urls_with_tag(
+        handle: PlacesConnectionHandle,
+        tag: String,
+        error: RustError.ByReference
+    ): RustBuffer.ByValue
+
     fun places_get_visit_page(
         handle: PlacesConnectionHandle,
         offset: Long,
@@ -178,6 +278,57 @@ internal interface LibPlacesFFI : Library {
         error: RustError.ByReference
     ): RustBuffer.ByValue
 
+    fun places_get_history_sections(
+        handle: PlacesConnectionHandle,
+        search: String?,
+        excludeTypes: Int,
+        utcOffsetMinutes: Int,
+        limit: Int,
+        cursor: String?,
+        error: RustError.ByReference
+    ): RustBuffer.ByValue
+
+    fun places_reading_list_add_item(
+        handle: PlacesConnectionHandle,
+        url: String,
+        title: String?,
+        excerpt: String?,
+        sourceDevice: String?,
+        error: RustError.ByReference
+    ): RustBuffer.ByValue
+
+    fun places_reading_list_get_item(
+        handle: PlacesConnectionHandle,
+        url: String,
+        error: RustError.ByReference
+    ): RustBuffer.ByValue
+
+    fun places_reading_list_get_items(
+        handle: PlacesConnectionHandle,
+        filter: Byte,
+        error: RustError.ByReference
+    ): RustBuffer.ByValue
+
+    // Returns 1 if the item exists.
+    fun places_reading_list_mark_read(
+        handle: PlacesConnectionHandle,
+        url: String,
+        read: Byte,
+        error: RustError.ByReference
+    ): Byte
+
+    // Returns 1 if the item existed and was deleted.
+    fun places_reading_list_delete_item(
+        handle: PlacesConnectionHandle,
+        url: String,
+        error: RustError.ByReference
+    ): Byte
+
+    fun places_reading_list_delete_all_items(
+        handle: PlacesConnectionHandle,
+        error: RustError.ByReference
+    )
+
     fun places_get_visit_count(
         handle: PlacesConnectionHandle,
         excludeTypes: Int,
@@ -221,6 +372,25 @@ internal interface LibPlacesFFI : Library {
         error: RustError.ByReference
     ): Pointer?
 
+    fun bookmarks_get_keywords(
+        handle: PlacesConnectionHandle,
+        error: RustError.ByReference
+    ): RustBuffer.ByValue
+
+    fun bookmarks_set_keyword(
+        handle: PlacesConnectionHandle,
+        url: String,
+        keyword: String,
+        postData: String?,
+        error: RustError.ByReference
+    )
+
+    fun bookmarks_remove_keyword(
+        handle: PlacesConnectionHandle,
+        url: String,
+        error: RustError.ByReference
+    ): Byte
+
     fun bookmarks_get_tree(
         handle: PlacesConnectionHandle,
         optRootId: String?,
@@ -262,6 +432,23 @@ internal interface LibPlacesFFI : Library {
         error: RustError.ByReference
     )
 
+    fun bookmarks_reorder_children(
+        handle: PlacesConnectionHandle,
+        folderGuid: String,
+        data: Pointer,
+        len: Int,
+        error: RustError.ByReference
+    )
+
+    fun bookmarks_move_many(
+        handle: PlacesConnectionHandle,
+        data: Pointer,
+        len: Int,
+        newParentGuid: String,
+        position: Int,
+        error: RustError.ByReference
+    )
+
     // Returns 1 if the item existed and was deleted.
     fun bookmarks_delete(
         handle: PlacesConnectionHandle,
@@ -269,6 +456,11 @@ internal interface LibPlacesFFI : Library {
         error: RustError.ByReference
     ): Byte
 
+    fun bookmarks_undo(
+        handle: PlacesConnectionHandle,
+        error: RustError.ByReference
+    ): Byte
+
     fun bookmarks_delete_everything(
         handle: PlacesConnectionHandle,
         error: RustError.ByReference
@@ -288,6 +480,12 @@ internal interface LibPlacesFFI : Library {
         err: RustError.ByReference
     )
 
+    fun places_api_set_search_engines(
+        apiHandle: PlacesApiHandle,
+        search_engines_json: String,
+        out_err: RustError.ByReference
+    )
+
     /** Destroy connection created using `places_connection_new` */
     fun places_connection_destroy(handle: PlacesConnectionHandle, out_err: RustError.ByReference)
 
@@ -297,6 +495,9 @@ internal interface LibPlacesFFI : Library {
     /** Destroy handle created using `places_new_interrupt_handle` */
     fun places_interrupt_handle_destroy(obj: RawPlacesInterruptHandle)
 
+    /** Destroy handle created using `places_new_autocomplete_interrupt_handle` */
+    fun places_autocomplete_interrupt_handle_destroy(obj: RawAutocompleteInterruptHandle)
+
     fun places_destroy_bytebuffer(bb: RustBuffer.ByValue)
 
     fun places_accept_result(
@@ -313,3 +514,6 @@ internal typealias PlacesApiHandle = Long
 // This doesn't use a handle to avoid unnecessary locking and
 // because the type is panic safe, sync, and send.
 class RawPlacesInterruptHandle : PointerType()
+
+// Like `RawPlacesInterruptHandle`, but only interrupts a single autocomplete query.
+class RawAutocompleteInterruptHandle : PointerType()
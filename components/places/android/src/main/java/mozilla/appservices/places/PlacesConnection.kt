@@ -14,6 +14,7 @@ import org.json.JSONObject
 import org.json.JSONException
 import java.nio.ByteBuffer
 import java.nio.ByteOrder
+import java.util.TimeZone
 import java.util.concurrent.atomic.AtomicLong
 import java.util.concurrent.atomic.AtomicReference
 import java.lang.ref.WeakReference
@@ -38,13 +39,21 @@ import mozilla.components.service.glean.private.LabeledMetricType
  *
  * @param path an absolute path to a file that will be used for the internal database.
  */
-class PlacesApi(path: String) : PlacesManager, AutoCloseable {
+class PlacesApi(
+    path: String,
+    frecencySettings: FrecencySettings? = null
+) : PlacesManager, AutoCloseable {
     private var handle: AtomicLong = AtomicLong(0)
     private var writeConn: PlacesWriterConnection
 
     init {
         handle.set(rustCall(this) { error ->
-            LibPlacesFFI.INSTANCE.places_api_new(path, error)
+            if (frecencySettings == null) {
+                LibPlacesFFI.INSTANCE.places_api_new(path, error)
+            } else {
+                LibPlacesFFI.INSTANCE.places_api_new_with_frecency_settings(
+                        path, frecencySettings.toJSON().toString(), error)
+            }
         })
         writeConn = PlacesWriterConnection(rustCall(this) { error ->
             LibPlacesFFI.INSTANCE.places_connection_new(handle.get(), READ_WRITE, error)
@@ -166,6 +175,13 @@ class PlacesApi(path: String) : PlacesManager, AutoCloseable {
             LibPlacesFFI.INSTANCE.bookmarks_reset(this.handle.get(), error)
         }
     }
+
+    override fun setSearchEngines(engines: List<SearchEngine>) {
+        val json = JSONArray(engines.map { it.toJSON() }).toString()
+        rustCall(this) { error ->
+            LibPlacesFFI.INSTANCE.places_api_set_search_engines(this.handle.get(), json, error)
+        }
+    }
 }
 
 internal inline fun <U> rustCall(syncOn: Any, callback: (RustError.ByReference) -> U): U {
@@ -262,16 +278,33 @@ open class PlacesConnection internal constructor(connHandle: Long) : Interruptib
 open class PlacesReaderConnection internal constructor(connHandle: Long) :
         PlacesConnection(connHandle),
         ReadableHistoryConnection,
-        ReadableBookmarksConnection {
-    override fun queryAutocomplete(query: String, limit: Int): List<SearchResult> {
-        val resultBuffer = rustCall { error ->
-            LibPlacesFFI.INSTANCE.places_query_autocomplete(this.handle.get(), query, limit, error)
-        }
-        try {
-            val results = MsgTypes.SearchResultList.parseFrom(resultBuffer.asCodedInputStream()!!)
-            return SearchResult.fromCollectionMessage(results)
-        } finally {
-            LibPlacesFFI.INSTANCE.places_destroy_bytebuffer(resultBuffer)
+        ReadableBookmarksConnection,
+        ReadableReadingListConnection {
+    // The interrupt handle for the most recent autocomplete query, if it's
+    // still running.
+    private val pendingAutocomplete: AtomicReference<AutocompleteInterruptHandle?> = AtomicReference(null)
+
+    override fun queryAutocomplete(query: String, limit: Int, mode: SearchMode): List<SearchResult> {
+        AutocompleteInterruptHandle(rustCall { error ->
+            LibPlacesFFI.INSTANCE.places_new_autocomplete_interrupt_handle(error)
+        }!!).use { interruptHandle ->
+            // The results of any query that's still running are stale now,
+            // so there's no point in waiting for it to finish.
+            pendingAutocomplete.getAndSet(interruptHandle)?.interrupt()
+            try {
+                val resultBuffer = rustCall { error ->
+                    LibPlacesFFI.INSTANCE.places_query_autocomplete(
+                        this.handle.get(), query, limit, mode.value.toByte(), interruptHandle.raw, error)
+                }
+                try {
+                    val results = MsgTypes.SearchResultList.parseFrom(resultBuffer.asCodedInputStream()!!)
+                    return SearchResult.fromCollectionMessage(results)
+                } finally {
+                    LibPlacesFFI.INSTANCE.places_destroy_bytebuffer(resultBuffer)
+                }
+            } finally {
+                pendingAutocomplete.compareAndSet(interruptHandle, null)
+            }
         }
     }
 
@@ -344,6 +377,23 @@ open class PlacesReaderConnection internal constructor(connHandle: Long) :
         }
     }
 
+    override fun getTopFrecentSiteInfos(numItems: Int, frecencyThreshold: Long): List<TopFrecentSiteInfo> {
+        readQueryCounters.measure {
+            val infoBuffer = rustCall { error ->
+                PlacesManagerMetrics.readQueryTime.measure {
+                    LibPlacesFFI.INSTANCE.places_get_top_frecent_site_infos(
+                            this.handle.get(), numItems, frecencyThreshold, error)
+                }
+            }
+            try {
+                val infos = MsgTypes.TopFrecentSiteInfos.parseFrom(infoBuffer.asCodedInputStream()!!)
+                return TopFrecentSiteInfo.fromMessage(infos)
+            } finally {
+                LibPlacesFFI.INSTANCE.places_destroy_bytebuffer(infoBuffer)
+            }
+        }
+    }
+
     override fun getVisitPage(offset: Long, count: Long, excludeTypes: List<VisitType>): List<VisitInfo> {
         val infoBuffer = rustCall { error ->
             LibPlacesFFI.INSTANCE.places_get_visit_page(
@@ -375,6 +425,30 @@ open class PlacesReaderConnection internal constructor(connHandle: Long) :
         }
     }
 
+    override fun getHistorySections(
+        limit: Int,
+        cursor: String?,
+        search: String?,
+        excludeTypes: List<VisitType>,
+        utcOffsetMinutes: Int
+    ): HistorySections {
+        readQueryCounters.measure {
+            val rustBuf = rustCall { error ->
+                PlacesManagerMetrics.readQueryTime.measure {
+                    LibPlacesFFI.INSTANCE.places_get_history_sections(
+                            this.handle.get(), search, visitTransitionSet(excludeTypes),
+                            utcOffsetMinutes, limit, cursor, error)
+                }
+            }
+            try {
+                val msg = MsgTypes.HistorySections.parseFrom(rustBuf.asCodedInputStream()!!)
+                return HistorySections.fromMessage(msg)
+            } finally {
+                LibPlacesFFI.INSTANCE.places_destroy_bytebuffer(rustBuf)
+            }
+        }
+    }
+
     override fun getVisitCount(excludeTypes: List<VisitType>): Long {
         return rustCall { error ->
             LibPlacesFFI.INSTANCE.places_get_visit_count(
@@ -399,6 +473,40 @@ open class PlacesReaderConnection internal constructor(connHandle: Long) :
         }
     }
 
+    override fun getReadingListItem(url: String): ReadingListItem? {
+        readQueryCounters.measure {
+            val rustBuf = rustCall { err ->
+                PlacesManagerMetrics.readQueryTime.measure {
+                    LibPlacesFFI.INSTANCE.places_reading_list_get_item(this.handle.get(), url, err)
+                }
+            }
+            try {
+                return rustBuf.asCodedInputStream()?.let { stream ->
+                    ReadingListItem.fromMessage(MsgTypes.ReadingListItem.parseFrom(stream))
+                }
+            } finally {
+                LibPlacesFFI.INSTANCE.places_destroy_bytebuffer(rustBuf)
+            }
+        }
+    }
+
+    override fun getReadingListItems(filter: ReadingListFilter): List<ReadingListItem> {
+        readQueryCounters.measure {
+            val rustBuf = rustCall { err ->
+                PlacesManagerMetrics.readQueryTime.measure {
+                    LibPlacesFFI.INSTANCE.places_reading_list_get_items(
+                            this.handle.get(), filter.value.toByte(), err)
+                }
+            }
+            try {
+                val msg = MsgTypes.ReadingListItems.parseFrom(rustBuf.asCodedInputStream()!!)
+                return ReadingListItem.fromCollectionMessage(msg)
+            } finally {
+                LibPlacesFFI.INSTANCE.places_destroy_bytebuffer(rustBuf)
+            }
+        }
+    }
+
     override fun getBookmarksTree(rootGUID: String, recursive: Boolean): BookmarkTreeNode? {
         val rustBuf = rustCall { err ->
             PlacesManagerMetrics.scanQueryTime.measure {
@@ -441,6 +549,23 @@ open class PlacesReaderConnection internal constructor(connHandle: Long) :
         }
     }
 
+    override fun getBookmarkKeywords(): List<BookmarkKeyword> {
+        readQueryCounters.measure {
+            val rustBuf = rustCall { err ->
+                PlacesManagerMetrics.readQueryTime.measure {
+                    LibPlacesFFI.INSTANCE.bookmarks_get_keywords(this.handle.get(), err)
+                }
+            }
+
+            try {
+                val message = MsgTypes.BookmarkKeywordList.parseFrom(rustBuf.asCodedInputStream()!!)
+                return BookmarkKeyword.fromCollectionMessage(message)
+            } finally {
+                LibPlacesFFI.INSTANCE.places_destroy_bytebuffer(rustBuf)
+            }
+        }
+    }
+
     override fun searchBookmarks(query: String, limit: Int): List<BookmarkItem> {
         readQueryCounters.measure {
             val rustBuf = rustCall { err ->
@@ -500,7 +625,8 @@ fun visitTransitionSet(l: List<VisitType>): Int {
 class PlacesWriterConnection internal constructor(connHandle: Long, api: PlacesApi) :
         PlacesReaderConnection(connHandle),
         WritableHistoryConnection,
-        WritableBookmarksConnection {
+        WritableBookmarksConnection,
+        WritableReadingListConnection {
     // The reference to our PlacesAPI. Mostly used to know how to handle getting closed.
     val apiRef = WeakReference(api)
     override fun noteObservation(data: VisitObservation) {
@@ -525,6 +651,18 @@ class PlacesWriterConnection internal constructor(connHandle: Long, api: PlacesA
         }
     }
 
+    override fun deleteVisitsForHost(host: String, includeSubdomains: Boolean) {
+        val includeSubdomainsArg: Byte = if (includeSubdomains) { 1 } else { 0 }
+        return writeQueryCounters.measure {
+            rustCall { error ->
+                PlacesManagerMetrics.writeQueryTime.measure {
+                    LibPlacesFFI.INSTANCE.places_delete_visits_for_host(
+                        this.handle.get(), host, includeSubdomainsArg, error)
+                }
+            }
+        }
+    }
+
     override fun deleteVisit(url: String, visitTimestamp: Long) {
         return writeQueryCounters.measure {
             rustCall { error ->
@@ -536,6 +674,47 @@ class PlacesWriterConnection internal constructor(connHandle: Long, api: PlacesA
         }
     }
 
+    override fun setFrecencySettings(settings: FrecencySettings) {
+        val json = settings.toJSON().toString()
+        return writeQueryCounters.measure {
+            rustCall { error ->
+                PlacesManagerMetrics.writeQueryTime.measure {
+                    LibPlacesFFI.INSTANCE.places_set_frecency_settings(this.handle.get(), json, error)
+                }
+            }
+        }
+    }
+
+    override fun blockTopSite(url: String) {
+        return writeQueryCounters.measure {
+            rustCall { error ->
+                PlacesManagerMetrics.writeQueryTime.measure {
+                    LibPlacesFFI.INSTANCE.places_block_top_site(this.handle.get(), url, error)
+                }
+            }
+        }
+    }
+
+    override fun unblockTopSite(url: String) {
+        return writeQueryCounters.measure {
+            rustCall { error ->
+                PlacesManagerMetrics.writeQueryTime.measure {
+                    LibPlacesFFI.INSTANCE.places_unblock_top_site(this.handle.get(), url, error)
+                }
+            }
+        }
+    }
+
+    override fun clearTopSitesBlocklist() {
+        return writeQueryCounters.measure {
+            rustCall { error ->
+                PlacesManagerMetrics.writeQueryTime.measure {
+                    LibPlacesFFI.INSTANCE.places_clear_top_sites_blocklist(this.handle.get(), error)
+                }
+            }
+        }
+    }
+
     override fun deleteVisitsSince(since: Long) {
         deleteVisitsBetween(since, Long.MAX_VALUE)
     }
@@ -563,10 +742,16 @@ class PlacesWriterConnection internal constructor(connHandle: Long, api: PlacesA
         }
     }
 
-    override fun pruneDestructively() {
-        rustCall { error ->
+    override fun pruneDestructively(): PruneMetrics {
+        val metricsBuffer = rustCall { error ->
             LibPlacesFFI.INSTANCE.places_prune_destructively(this.handle.get(), error)
         }
+        try {
+            val metrics = MsgTypes.PruneMetrics.parseFrom(metricsBuffer.asCodedInputStream()!!)
+            return PruneMetrics.fromMessage(metrics)
+        } finally {
+            LibPlacesFFI.INSTANCE.places_destroy_bytebuffer(metricsBuffer)
+        }
     }
 
     override fun deleteEverything() {
@@ -600,6 +785,17 @@ class PlacesWriterConnection internal constructor(connHandle: Long, api: PlacesA
         }
     }
 
+    override fun undoBookmarkChange(): Boolean {
+        return writeQueryCounters.measure {
+            rustCall { error ->
+                val undoneByte = PlacesManagerMetrics.writeQueryTime.measure {
+                    LibPlacesFFI.INSTANCE.bookmarks_undo(this.handle.get(), error)
+                }
+                undoneByte.toInt() != 0
+            }
+        }
+    }
+
     // Does the shared insert work, takes the position just because
     // its a little tedious to type out setting it
     private fun doInsert(builder: MsgTypes.BookmarkNode.Builder, position: Int?): String {
@@ -631,6 +827,55 @@ class PlacesWriterConnection internal constructor(connHandle: Long, api: PlacesA
         return this.doInsert(builder, position)
     }
 
+    override fun getAllTags(): List<TagInfo> {
+        readQueryCounters.measure {
+            val rustBuf = rustCall { err ->
+                PlacesManagerMetrics.readQueryTime.measure {
+                    LibPlacesFFI.INSTANCE.places_get_all_tags(this.handle.get(), err)
+                }
+            }
+
+            try {
+                val message = MsgTypes.TagInfoList.parseFrom(rustBuf.asCodedInputStream()!!)
+                return TagInfo.fromCollectionMessage(message)
+            } finally {
+                LibPlacesFFI.INSTANCE.places_destroy_bytebuffer(rustBuf)
+            }
+        }
+    }
+
+    override fun getTagsForUrl(url: String): List<String> {
+        readQueryCounters.measure {
+            val rustBuf = rustCall { err ->
+                PlacesManagerMetrics.readQueryTime.measure {
+                    LibPlacesFFI.INSTANCE.places_get_tags_for_url(this.handle.get(), url, err)
+                }
+            }
+
+            try {
+                return MsgTypes.StringList.parseFrom(rustBuf.asCodedInputStream()!!).valuesList
+            } finally {
+                LibPlacesFFI.INSTANCE.places_destroy_bytebuffer(rustBuf)
+            }
+        }
+    }
+
+    override fun getUrlsWithTag(tag: String): List<String> {
+        readQueryCounters.measure {
+            val rustBuf = rustCall { err ->
+                PlacesManagerMetrics.readQueryTime.measure {
+                    LibPlacesFFI.INSTANCE.places_get_urls_with_tag(this.handle.get(), tag, err)
+                }
+            }
+
+            try {
+                return MsgTypes.StringList.parseFrom(rustBuf.asCodedInputStream()!!).valuesList
+            } finally {
+                LibPlacesFFI.INSTANCE.places_destroy_bytebuffer(rustBuf)
+            }
+        }
+    }
+
     override fun createBookmarkItem(parentGUID: String, url: String, title: String, position: Int?): String {
         val builder = MsgTypes.BookmarkNode.newBuilder()
                 .setNodeType(BookmarkType.Bookmark.value)
@@ -640,6 +885,122 @@ class PlacesWriterConnection internal constructor(connHandle: Long, api: PlacesA
         return this.doInsert(builder, position)
     }
 
+    override fun setBookmarkKeyword(url: String, keyword: String, postData: String?) {
+        return writeQueryCounters.measure {
+            rustCall { error ->
+                PlacesManagerMetrics.writeQueryTime.measure {
+                    LibPlacesFFI.INSTANCE.bookmarks_set_keyword(this.handle.get(), url, keyword, postData, error)
+                }
+            }
+        }
+    }
+
+    override fun addReadingListItem(
+        url: String,
+        title: String?,
+        excerpt: String?,
+        sourceDevice: String?
+    ): ReadingListItem {
+        return writeQueryCounters.measure {
+            val rustBuf = rustCall { error ->
+                PlacesManagerMetrics.writeQueryTime.measure {
+                    LibPlacesFFI.INSTANCE.places_reading_list_add_item(
+                            this.handle.get(), url, title, excerpt, sourceDevice, error)
+                }
+            }
+            try {
+                val msg = MsgTypes.ReadingListItem.parseFrom(rustBuf.asCodedInputStream()!!)
+                ReadingListItem.fromMessage(msg)
+            } finally {
+                LibPlacesFFI.INSTANCE.places_destroy_bytebuffer(rustBuf)
+            }
+        }
+    }
+
+    override fun markReadingListItemRead(url: String, read: Boolean): Boolean {
+        val readArg: Byte = if (read) { 1 } else { 0 }
+        return writeQueryCounters.measure {
+            rustCall { error ->
+                val existedByte = PlacesManagerMetrics.writeQueryTime.measure {
+                    LibPlacesFFI.INSTANCE.places_reading_list_mark_read(this.handle.get(), url, readArg, error)
+                }
+                existedByte.toInt() != 0
+            }
+        }
+    }
+
+    override fun deleteReadingListItem(url: String): Boolean {
+        return writeQueryCounters.measure {
+            rustCall { error ->
+                val existedByte = PlacesManagerMetrics.writeQueryTime.measure {
+                    LibPlacesFFI.INSTANCE.places_reading_list_delete_item(this.handle.get(), url, error)
+                }
+                existedByte.toInt() != 0
+            }
+        }
+    }
+
+    override fun deleteAllReadingListItems() {
+        return writeQueryCounters.measure {
+            rustCall { error ->
+                PlacesManagerMetrics.writeQueryTime.measure {
+                    LibPlacesFFI.INSTANCE.places_reading_list_delete_all_items(this.handle.get(), error)
+                }
+            }
+        }
+    }
+
+    override fun removeBookmarkKeyword(url: String): Boolean {
+        return writeQueryCounters.measure {
+            rustCall { error ->
+                val existedByte = PlacesManagerMetrics.writeQueryTime.measure {
+                    LibPlacesFFI.INSTANCE.bookmarks_remove_keyword(this.handle.get(), url, error)
+                }
+                existedByte.toInt() != 0
+            }
+        }
+    }
+
+    override fun tagUrl(url: String, tag: String) {
+        return writeQueryCounters.measure {
+            rustCall { error ->
+                PlacesManagerMetrics.writeQueryTime.measure {
+                    LibPlacesFFI.INSTANCE.places_tag_url(this.handle.get(), url, tag, error)
+                }
+            }
+        }
+    }
+
+    override fun untagUrl(url: String, tag: String) {
+        return writeQueryCounters.measure {
+            rustCall { error ->
+                PlacesManagerMetrics.writeQueryTime.measure {
+                    LibPlacesFFI.INSTANCE.places_untag_url(this.handle.get(), url, tag, error)
+                }
+            }
+        }
+    }
+
+    override fun removeTag(tag: String) {
+        return writeQueryCounters.measure {
+            rustCall { error ->
+                PlacesManagerMetrics.writeQueryTime.measure {
+                    LibPlacesFFI.INSTANCE.places_remove_tag(this.handle.get(), tag, error)
+                }
+            }
+        }
+    }
+
+    override fun renameTag(oldTag: String, newTag: String) {
+        return writeQueryCounters.measure {
+            rustCall { error ->
+                PlacesManagerMetrics.writeQueryTime.measure {
+                    LibPlacesFFI.INSTANCE.places_rename_tag(this.handle.get(), oldTag, newTag, error)
+                }
+            }
+        }
+    }
+
     override fun updateBookmark(guid: String, info: BookmarkUpdateInfo) {
         val buf = info.toProtobuf(guid)
         val (nioBuf, len) = buf.toNioDirectBuffer()
@@ -653,6 +1014,34 @@ class PlacesWriterConnection internal constructor(connHandle: Long, api: PlacesA
         }
     }
 
+    override fun reorderBookmarkChildren(folderGUID: String, orderedGUIDs: List<String>) {
+        val buf = MsgTypes.StringList.newBuilder().addAllValues(orderedGUIDs).build()
+        val (nioBuf, len) = buf.toNioDirectBuffer()
+        return writeQueryCounters.measure {
+            rustCall { err ->
+                val ptr = Native.getDirectBufferPointer(nioBuf)
+                PlacesManagerMetrics.writeQueryTime.measure {
+                    LibPlacesFFI.INSTANCE.bookmarks_reorder_children(
+                            this.handle.get(), folderGUID, ptr, len, err)
+                }
+            }
+        }
+    }
+
+    override fun moveBookmarks(guids: List<String>, parentGUID: String, position: Int?) {
+        val buf = MsgTypes.StringList.newBuilder().addAllValues(guids).build()
+        val (nioBuf, len) = buf.toNioDirectBuffer()
+        return writeQueryCounters.measure {
+            rustCall { err ->
+                val ptr = Native.getDirectBufferPointer(nioBuf)
+                PlacesManagerMetrics.writeQueryTime.measure {
+                    LibPlacesFFI.INSTANCE.bookmarks_move_many(
+                            this.handle.get(), ptr, len, parentGUID, position ?: -1, err)
+                }
+            }
+        }
+    }
+
     override fun acceptResult(searchString: String, url: String) {
         rustCall { error ->
             LibPlacesFFI.INSTANCE.places_accept_result(
@@ -793,6 +1182,16 @@ interface PlacesManager {
      * but those are handled internally in the Rust code.
      */
     fun resetBookmarkSyncMetadata()
+
+    /**
+     * Sets the search engines used to recognize search result pages. When a
+     * result page is visited, what the user searched for is stored, and
+     * returned with visits, top sites, and search results for that page.
+     *
+     * Engines aren't persisted, so this should be called each time the
+     * `PlacesApi` is created. Until then, no search terms are stored.
+     */
+    fun setSearchEngines(engines: List<SearchEngine>)
 }
 
 interface InterruptibleConnection : AutoCloseable {
@@ -806,11 +1205,16 @@ interface ReadableHistoryConnection : InterruptibleConnection {
     /**
      * A way to search the internal database tailored for autocompletion purposes.
      *
+     * Starting a new query cancels any query that's still running on this
+     * connection, which will throw [OperationInterrupted].
+     *
      * @param query a string to match results against.
      * @param limit a maximum number of results to retrieve.
+     * @param mode how to find matches. [SearchMode.FULL_TEXT] is faster for
+     *        large histories, but only matches the beginnings of words.
      * @return a list of [SearchResult] matching the [query], in arbitrary order.
      */
-    fun queryAutocomplete(query: String, limit: Int): List<SearchResult>
+    fun queryAutocomplete(query: String, limit: Int, mode: SearchMode = SearchMode.MATCH): List<SearchResult>
 
     /**
      * See if a url that's sufficiently close to `search` exists in
@@ -854,6 +1258,16 @@ interface ReadableHistoryConnection : InterruptibleConnection {
         excludeTypes: List<VisitType> = listOf()
     ): List<VisitInfo>
 
+    /**
+     * Returns the most frecent pages the user has visited, for showing as
+     * "top sites". Downloads, redirects, and pages blocked with
+     * [WritableHistoryConnection.blockTopSite] are excluded.
+     *
+     * @param numItems The maximum number of pages to return.
+     * @param frecencyThreshold The minimum frecency of the pages to return.
+     */
+    fun getTopFrecentSiteInfos(numItems: Int, frecencyThreshold: Long = 0): List<TopFrecentSiteInfo>
+
     /**
      * Return a "page" of history results. Each page will have visits in descending order
      * with respect to their visit timestamps. In the case of ties, their row id will
@@ -886,6 +1300,28 @@ interface ReadableHistoryConnection : InterruptibleConnection {
         excludeTypes: List<VisitType> = listOf()
     ): VisitInfosWithBound
 
+    /**
+     * Get a page of visited sites, grouped by the day they were visited.
+     * Each site's visits on a day are collapsed into one [HistorySite].
+     *
+     * @param limit The maximum number of sites to return.
+     * @param cursor The [HistorySections.cursor] of the previous page, or null
+     * to get the first page. Visits made after the first page was fetched
+     * aren't included in later pages.
+     * @param search If provided, only include visits to pages whose title or
+     * URL matches every word in this string.
+     * @param excludeTypes List of visit types to exclude.
+     * @param utcOffsetMinutes The offset from UTC used to find where each day
+     * starts. Defaults to the current offset of the default time zone.
+     */
+    fun getHistorySections(
+        limit: Int,
+        cursor: String? = null,
+        search: String? = null,
+        excludeTypes: List<VisitType> = listOf(),
+        utcOffsetMinutes: Int = TimeZone.getDefault().getOffset(System.currentTimeMillis()) / 60000
+    ): HistorySections
+
     /**
      * Get the number of history visits.
      *
@@ -921,6 +1357,7 @@ interface WritableHistoryConnection : ReadableHistoryConnection {
      *
      * - `VACUUM`ing.
      * - Requesting that the indices in our tables be optimized.
+     * - Recalculating stale frecencies, and decaying frecencies over time.
      * - Expiring irrelevant history visits.
      * - Periodic repair or deletion of corrupted records.
      * - etc.
@@ -936,11 +1373,17 @@ interface WritableHistoryConnection : ReadableHistoryConnection {
      * to be synced, however due to the way history sync works, this can
      * still cause data loss.
      *
+     * The least frecent pages that aren't bookmarked are removed first,
+     * followed by the oldest visits to bookmarked pages, until history is
+     * small enough. Bookmarks, tags, and keywords are always kept.
+     *
      * As a result, this should only be called if a low disk space
      * notification is received from the OS, and things like the network
      * cache have already been cleared.
+     *
+     * @return The number of pages and visits that were removed.
      */
-    fun pruneDestructively()
+    fun pruneDestructively(): PruneMetrics
 
     /**
      * Delete everything locally.
@@ -975,6 +1418,17 @@ interface WritableHistoryConnection : ReadableHistoryConnection {
      */
     fun deleteVisitsFor(url: String)
 
+    /**
+     * Deletes all pages and visits for a host, like `deleteVisitsFor` does
+     * for a single page. This is useful for "Forget about this site".
+     *
+     * @param host The host, like "example.com". If the URLs include a port,
+     * the host must include it, too, like "example.com:8080".
+     * @param includeSubdomains Whether to also delete pages and visits for
+     * all subdomains of the host, like "www.example.com".
+     */
+    fun deleteVisitsForHost(host: String, includeSubdomains: Boolean = false)
+
     /**
      * Deletes all visits which occurred since the specified time. If the
      * deletion removes the last visit for a place, the place itself will also
@@ -1018,6 +1472,37 @@ interface WritableHistoryConnection : ReadableHistoryConnection {
      * @param url The chosen URL string
      */
     fun acceptResult(searchString: String, url: String)
+
+    /**
+     * Changes the settings used to calculate frecencies. The settings are
+     * persisted, and also used by other connections. If they changed, the
+     * frecencies of existing pages are recalculated in the background.
+     *
+     * @param settings The new settings. Any which are null take their
+     * default values.
+     */
+    fun setFrecencySettings(settings: FrecencySettings)
+
+    /**
+     * Prevents a page from being returned by `getTopFrecentSiteInfos`. The
+     * page stays blocked even if it's removed from history.
+     *
+     * @param url The URL of the page to block.
+     */
+    fun blockTopSite(url: String)
+
+    /**
+     * Allows a page blocked with `blockTopSite` to be returned by
+     * `getTopFrecentSiteInfos` again.
+     *
+     * @param url The URL of the page to unblock.
+     */
+    fun unblockTopSite(url: String)
+
+    /**
+     * Unblocks all pages blocked with `blockTopSite`.
+     */
+    fun clearTopSitesBlocklist()
 }
 
 class InterruptHandle internal constructor(raw: RawPlacesInterruptHandle) : AutoCloseable {
@@ -1044,6 +1529,34 @@ class InterruptHandle internal constructor(raw: RawPlacesInterruptHandle) : Auto
     }
 }
 
+internal class AutocompleteInterruptHandle(raw: RawAutocompleteInterruptHandle) : AutoCloseable {
+    // We synchronize all accesses, so that the handle can't be destroyed
+    // while another thread is interrupting the query.
+    private val handle: AtomicReference<RawAutocompleteInterruptHandle?> = AtomicReference(raw)
+
+    val raw: RawAutocompleteInterruptHandle?
+        get() = handle.get()
+
+    @Synchronized
+    override fun close() {
+        val toFree = handle.getAndSet(null)
+        if (toFree != null) {
+            LibPlacesFFI.INSTANCE.places_autocomplete_interrupt_handle_destroy(toFree)
+        }
+    }
+
+    @Synchronized
+    fun interrupt() {
+        handle.get()?.let {
+            val e = RustError.ByReference()
+            LibPlacesFFI.INSTANCE.places_autocomplete_interrupt(it, e)
+            if (e.isFailure()) {
+                throw e.intoException()
+            }
+        }
+    }
+}
+
 open class PlacesException(msg: String) : Exception(msg)
 open class InternalPanic(msg: String) : PlacesException(msg)
 open class UrlParseFailed(msg: String) : PlacesException(msg)
@@ -1113,6 +1626,19 @@ fun stringOrNull(jsonObject: JSONObject, key: String): String? {
     }
 }
 
+/**
+ * How [ReadableHistoryConnection.queryAutocomplete] finds matches.
+ */
+enum class SearchMode(val value: Int) {
+    /** Match the query anywhere in the URL, title, or tags of every page. */
+    MATCH(1),
+    /**
+     * Use the full-text index, which is much faster for large histories, but
+     * only matches words that start with a word in the query.
+     */
+    FULL_TEXT(2),
+}
+
 enum class SearchResultReason {
     KEYWORD,
     ORIGIN,
@@ -1139,7 +1665,9 @@ data class SearchResult(
     val url: String,
     val title: String,
     val frecency: Long,
-    val reasons: List<SearchResultReason>
+    val reasons: List<SearchResultReason>,
+    val searchTerm: String? = null,
+    val searchEngine: String? = null
 ) {
     companion object {
         internal fun fromMessage(msg: MsgTypes.SearchResultMessage): SearchResult {
@@ -1149,7 +1677,9 @@ data class SearchResult(
                 frecency = msg.frecency,
                 reasons = msg.reasonsList.map {
                     SearchResultReason.fromMessage(it)
-                }
+                },
+                searchTerm = if (msg.hasSearchTerm()) { msg.searchTerm } else { null },
+                searchEngine = if (msg.hasSearchEngine()) { msg.searchEngine } else { null }
             )
         }
         internal fun fromCollectionMessage(msg: MsgTypes.SearchResultList): List<SearchResult> {
@@ -1160,6 +1690,122 @@ data class SearchResult(
     }
 }
 
+/**
+ * The weights and cutoffs used to calculate frecency, named after the
+ * `places.frecency.*` preferences in Desktop Firefox. Any settings which are
+ * null take their default values.
+ */
+data class FrecencySettings(
+    val numVisits: Int? = null,
+    val firstBucketCutoffDays: Int? = null,
+    val secondBucketCutoffDays: Int? = null,
+    val thirdBucketCutoffDays: Int? = null,
+    val fourthBucketCutoffDays: Int? = null,
+    val firstBucketWeight: Int? = null,
+    val secondBucketWeight: Int? = null,
+    val thirdBucketWeight: Int? = null,
+    val fourthBucketWeight: Int? = null,
+    val defaultBucketWeight: Int? = null,
+    val embedVisitBonus: Int? = null,
+    val framedLinkVisitBonus: Int? = null,
+    val linkVisitBonus: Int? = null,
+    val typedVisitBonus: Int? = null,
+    val bookmarkVisitBonus: Int? = null,
+    val downloadVisitBonus: Int? = null,
+    val permanentRedirectVisitBonus: Int? = null,
+    val temporaryRedirectVisitBonus: Int? = null,
+    val redirectSourceVisitBonus: Int? = null,
+    val defaultVisitBonus: Int? = null,
+    val unvisitedBookmarkBonus: Int? = null,
+    val unvisitedTypedBonus: Int? = null,
+    val reloadVisitBonus: Int? = null
+) {
+    fun toJSON(): JSONObject {
+        val o = JSONObject()
+        this.numVisits?.let { o.put("num_visits", it) }
+        this.firstBucketCutoffDays?.let { o.put("first_bucket_cutoff_days", it) }
+        this.secondBucketCutoffDays?.let { o.put("second_bucket_cutoff_days", it) }
+        this.thirdBucketCutoffDays?.let { o.put("third_bucket_cutoff_days", it) }
+        this.fourthBucketCutoffDays?.let { o.put("fourth_bucket_cutoff_days", it) }
+        this.firstBucketWeight?.let { o.put("first_bucket_weight", it) }
+        this.secondBucketWeight?.let { o.put("second_bucket_weight", it) }
+        this.thirdBucketWeight?.let { o.put("third_bucket_weight", it) }
+        this.fourthBucketWeight?.let { o.put("fourth_bucket_weight", it) }
+        this.defaultBucketWeight?.let { o.put("default_bucket_weight", it) }
+        this.embedVisitBonus?.let { o.put("embed_visit_bonus", it) }
+        this.framedLinkVisitBonus?.let { o.put("framed_link_visit_bonus", it) }
+        this.linkVisitBonus?.let { o.put("link_visit_bonus", it) }
+        this.typedVisitBonus?.let { o.put("typed_visit_bonus", it) }
+        this.bookmarkVisitBonus?.let { o.put("bookmark_visit_bonus", it) }
+        this.downloadVisitBonus?.let { o.put("download_visit_bonus", it) }
+        this.permanentRedirectVisitBonus?.let { o.put("permanent_redirect_visit_bonus", it) }
+        this.temporaryRedirectVisitBonus?.let { o.put("temporary_redirect_visit_bonus", it) }
+        this.redirectSourceVisitBonus?.let { o.put("redirect_source_visit_bonus", it) }
+        this.defaultVisitBonus?.let { o.put("default_visit_bonus", it) }
+        this.unvisitedBookmarkBonus?.let { o.put("unvisited_bookmark_bonus", it) }
+        this.unvisitedTypedBonus?.let { o.put("unvisited_typed_bonus", it) }
+        this.reloadVisitBonus?.let { o.put("reload_visit_bonus", it) }
+        return o
+    }
+}
+
+/**
+ * A search engine, used to recognize visits to its result pages.
+ *
+ * @param name The name of the engine, which is returned along with search terms.
+ * @param urlTemplate The engine's search URL, with `{searchTerms}` as the value
+ * of the query parameter that holds the search terms. For example,
+ * `https://www.example.com/search?q={searchTerms}`.
+ */
+data class SearchEngine(
+    val name: String,
+    val urlTemplate: String
+) {
+    fun toJSON(): JSONObject {
+        val o = JSONObject()
+        o.put("name", this.name)
+        o.put("urlTemplate", this.urlTemplate)
+        return o
+    }
+}
+
+/**
+ * The number of pages and visits removed by `PlacesAPI.pruneDestructively`.
+ */
+data class PruneMetrics(
+    val pagesRemoved: Int,
+    val visitsRemoved: Int
+) {
+    companion object {
+        internal fun fromMessage(msg: MsgTypes.PruneMetrics): PruneMetrics {
+            return PruneMetrics(pagesRemoved = msg.pagesRemoved, visitsRemoved = msg.visitsRemoved)
+        }
+    }
+}
+
+/**
+ * A frecent page. Returned by `PlacesAPI.getTopFrecentSiteInfos`.
+ */
+data class TopFrecentSiteInfo(
+    val url: String,
+    val title: String?,
+    val frecency: Long,
+    val searchTerm: String? = null,
+    val searchEngine: String? = null
+) {
+    companion object {
+        internal fun fromMessage(msg: MsgTypes.TopFrecentSiteInfos): List<TopFrecentSiteInfo> {
+            return msg.infosList.map {
+                TopFrecentSiteInfo(url = it.url,
+                    title = if (it.hasTitle()) { it.title } else { null },
+                    frecency = it.frecency,
+                    searchTerm = if (it.hasSearchTerm()) { it.searchTerm } else { null },
+                    searchEngine = if (it.hasSearchEngine()) { it.searchEngine } else { null })
+            }
+        }
+    }
+}
+
 /**
  * Information about a history visit. Returned by `PlacesAPI.getVisitInfos`.
  */
@@ -1188,7 +1834,18 @@ data class VisitInfo(
      * Whether the page is hidden because it redirected to another page, or was
      * visited in a frame.
      */
-    val isHidden: Boolean
+    val isHidden: Boolean,
+
+    /**
+     * What the user searched for, if the page is a search result page for one
+     * of the engines passed to `PlacesApi.setSearchEngines`.
+     */
+    val searchTerm: String? = null,
+
+    /**
+     * The name of the search engine, if `searchTerm` is set.
+     */
+    val searchEngine: String? = null
 ) {
     companion object {
         internal fun fromMessage(msg: MsgTypes.HistoryVisitInfos): List<VisitInfo> {
@@ -1197,7 +1854,9 @@ data class VisitInfo(
                     title = it.title,
                     visitTime = it.timestamp,
                     visitType = intToVisitType[it.visitType]!!,
-                    isHidden = it.isHidden)
+                    isHidden = it.isHidden,
+                    searchTerm = if (it.hasSearchTerm()) { it.searchTerm } else { null },
+                    searchEngine = if (it.hasSearchEngine()) { it.searchEngine } else { null })
             }
         }
     }
@@ -1216,7 +1875,9 @@ data class VisitInfosWithBound(
                     title = it.title,
                     visitTime = it.timestamp,
                     visitType = intToVisitType[it.visitType]!!,
-                    isHidden = it.isHidden
+                    isHidden = it.isHidden,
+                    searchTerm = if (it.hasSearchTerm()) { it.searchTerm } else { null },
+                    searchEngine = if (it.hasSearchEngine()) { it.searchEngine } else { null }
                 )
             }
             return VisitInfosWithBound(
@@ -1228,6 +1889,64 @@ data class VisitInfosWithBound(
     }
 }
 
+/**
+ * A site's visits on one day.
+ *
+ * @property url The URL of the most recently visited page on the site.
+ * @property title The title of the most recently visited page on the site.
+ */
+data class HistorySite(
+    val host: String,
+    val visitCount: Int,
+    val url: String,
+    val title: String?,
+    val latestVisit: Long
+)
+
+/**
+ * The sites visited on one day, most recently visited first.
+ *
+ * @property day The start of the day, in milliseconds since the epoch.
+ */
+data class HistorySection(
+    val day: Long,
+    val sites: List<HistorySite>
+)
+
+/**
+ * A page of [HistorySection]s.
+ *
+ * @property cursor Pass this to [ReadableHistoryConnection.getHistorySections]
+ * to get the next page, or null if this is the last page.
+ */
+data class HistorySections(
+    val sections: List<HistorySection>,
+    val cursor: String?
+) {
+    companion object {
+        internal fun fromMessage(msg: MsgTypes.HistorySections): HistorySections {
+            val sections = msg.sectionsList.map { section ->
+                HistorySection(
+                    day = section.day,
+                    sites = section.sitesList.map {
+                        HistorySite(
+                            host = it.host,
+                            visitCount = it.visitCount,
+                            url = it.url,
+                            title = if (it.hasTitle()) { it.title } else { null },
+                            latestVisit = it.latestVisit
+                        )
+                    }
+                )
+            }
+            return HistorySections(
+                sections = sections,
+                cursor = if (msg.hasCursor()) { msg.cursor } else { null }
+            )
+        }
+    }
+}
+
 /**
  * A helper extension method for conveniently measuring execution time of a closure.
  *
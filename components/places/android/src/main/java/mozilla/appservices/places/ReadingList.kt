@@ -0,0 +1,124 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+package mozilla.appservices.places
+
+/**
+ * Which items [ReadableReadingListConnection.getReadingListItems] returns.
+ *
+ * Must match ReadingListFilter in the Rust code.
+ */
+enum class ReadingListFilter(val value: Int) {
+    ALL(1),
+    UNREAD(2),
+    READ(3),
+}
+
+/**
+ * A page saved to the reading list.
+ */
+data class ReadingListItem(
+    val guid: String,
+    val url: String,
+    val title: String?,
+    val excerpt: String?,
+
+    /**
+     * The name of the device the page was saved on, if known.
+     */
+    val sourceDevice: String?,
+
+    /**
+     * When the page was saved, in milliseconds since the unix epoch.
+     */
+    val addedAt: Long,
+
+    /**
+     * When the page was marked as read, in milliseconds since the unix epoch,
+     * or null if it hasn't been.
+     */
+    val readAt: Long?
+) {
+    val isRead: Boolean
+        get() = readAt != null
+
+    companion object {
+        internal fun fromMessage(msg: MsgTypes.ReadingListItem): ReadingListItem {
+            return ReadingListItem(
+                guid = msg.guid,
+                url = msg.url,
+                title = if (msg.hasTitle()) { msg.title } else { null },
+                excerpt = if (msg.hasExcerpt()) { msg.excerpt } else { null },
+                sourceDevice = if (msg.hasSourceDevice()) { msg.sourceDevice } else { null },
+                addedAt = msg.addedAt,
+                readAt = if (msg.hasReadAt()) { msg.readAt } else { null }
+            )
+        }
+
+        internal fun fromCollectionMessage(msg: MsgTypes.ReadingListItems): List<ReadingListItem> {
+            return msg.itemsList.map { fromMessage(it) }
+        }
+    }
+}
+
+/**
+ * An interface defining the set of read operations for the reading list.
+ */
+interface ReadableReadingListConnection : InterruptibleConnection {
+    /**
+     * Returns the reading list item for a URL.
+     *
+     * @param url The URL of the saved page.
+     * @return The item, or null if the page isn't in the reading list.
+     */
+    fun getReadingListItem(url: String): ReadingListItem?
+
+    /**
+     * Returns the pages in the reading list, most recently saved first.
+     *
+     * @param filter Whether to return all items, or only unread or read ones.
+     */
+    fun getReadingListItems(filter: ReadingListFilter = ReadingListFilter.ALL): List<ReadingListItem>
+}
+
+/**
+ * An interface defining the set of write operations for the reading list.
+ */
+interface WritableReadingListConnection : ReadableReadingListConnection {
+    /**
+     * Saves a page to the reading list. Saving a page doesn't add a visit to
+     * history.
+     *
+     * @param url The URL of the page.
+     * @param title The title of the page, if known.
+     * @param excerpt A short preview of the page, if known.
+     * @param sourceDevice The name of this device, if known.
+     * @return The new item, or the existing one if the page was already saved.
+     */
+    fun addReadingListItem(
+        url: String,
+        title: String? = null,
+        excerpt: String? = null,
+        sourceDevice: String? = null
+    ): ReadingListItem
+
+    /**
+     * Marks a saved page as read, or unread.
+     *
+     * @return Whether or not the page is in the reading list.
+     */
+    fun markReadingListItemRead(url: String, read: Boolean = true): Boolean
+
+    /**
+     * Removes a page from the reading list.
+     *
+     * @return Whether or not the page was in the reading list.
+     */
+    fun deleteReadingListItem(url: String): Boolean
+
+    /**
+     * Removes all pages from the reading list.
+     */
+    fun deleteAllReadingListItems()
+}
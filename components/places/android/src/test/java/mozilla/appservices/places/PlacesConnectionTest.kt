@@ -270,6 +270,39 @@ class PlacesConnectionTest {
         assertEquals(folder.parentGUID, BookmarkRoot.Unfiled.id)
     }
 
+    @Test
+    fun testReadingList() {
+        val item = db.addReadingListItem(
+                url = "https://www.example.com/article",
+                title = "An article",
+                sourceDevice = "Phone")
+        assertEquals("https://www.example.com/article", item.url)
+        assertEquals("An article", item.title)
+        assertEquals(null, item.excerpt)
+        assertEquals("Phone", item.sourceDevice)
+        assertEquals(false, item.isRead)
+
+        // Saving the same page again returns the existing item.
+        assertEquals(item, db.addReadingListItem(url = "https://www.example.com/article"))
+        db.addReadingListItem(url = "https://www.example.com/other")
+
+        assertEquals(true, db.markReadingListItemRead("https://www.example.com/article"))
+        assertEquals(false, db.markReadingListItemRead("https://www.example.com/missing"))
+        assertEquals(true, db.getReadingListItem("https://www.example.com/article")!!.isRead)
+        assertEquals(null, db.getReadingListItem("https://www.example.com/missing"))
+
+        assertEquals(2, db.getReadingListItems().size)
+        assertEquals(listOf("https://www.example.com/other"),
+                db.getReadingListItems(ReadingListFilter.UNREAD).map { it.url })
+        assertEquals(listOf("https://www.example.com/article"),
+                db.getReadingListItems(ReadingListFilter.READ).map { it.url })
+
+        assertEquals(true, db.deleteReadingListItem("https://www.example.com/article"))
+        assertEquals(false, db.deleteReadingListItem("https://www.example.com/article"))
+        db.deleteAllReadingListItems()
+        assertEquals(0, db.getReadingListItems().size)
+    }
+
     @Test
     fun testHistoryMetricsGathering() {
         assert(!PlacesManagerMetrics.writeQueryTime.testHasValue())
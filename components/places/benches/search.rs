@@ -3,7 +3,7 @@
 
 use criterion::{criterion_group, criterion_main, Criterion};
 use places::api::{
-    matcher::{match_url, search_frecent, SearchParams},
+    matcher::{match_url, search_frecent, SearchMode, SearchParams},
     places_api::ConnectionType,
 };
 use places::PlacesDb;
@@ -54,6 +54,9 @@ impl TestDb {
             ConnectionType::ReadWrite,
             0,
             Arc::new(Mutex::new(())),
+            Default::default(),
+            Default::default(),
+            Default::default(),
         )
         .unwrap();
         println!("Populating test database...");
@@ -81,6 +84,7 @@ fn bench_search_frecent(c: &mut Criterion) {
             SearchParams {
                 search_string: "mozilla".into(),
                 limit: 10,
+                mode: SearchMode::Match,
             },
         )
         .unwrap()
@@ -91,6 +95,7 @@ fn bench_search_frecent(c: &mut Criterion) {
             SearchParams {
                 search_string: "blog.mozilla.org".into(),
                 limit: 10,
+                mode: SearchMode::Match,
             },
         )
         .unwrap()
@@ -101,6 +106,44 @@ fn bench_search_frecent(c: &mut Criterion) {
             SearchParams {
                 search_string: "https://hg.mozilla.org/mozilla-central".into(),
                 limit: 10,
+                mode: SearchMode::Match,
+            },
+        )
+        .unwrap()
+    });
+}
+
+fn bench_search_frecent_full_text(c: &mut Criterion) {
+    let test_db = TestDb::new();
+    db_bench!(c, "search_frecent full text string", |db: test_db| {
+        search_frecent(
+            &db,
+            SearchParams {
+                search_string: "mozilla".into(),
+                limit: 10,
+                mode: SearchMode::FullText,
+            },
+        )
+        .unwrap()
+    });
+    db_bench!(c, "search_frecent full text words", |db: test_db| {
+        search_frecent(
+            &db,
+            SearchParams {
+                search_string: "mozilla central".into(),
+                limit: 10,
+                mode: SearchMode::FullText,
+            },
+        )
+        .unwrap()
+    });
+    db_bench!(c, "search_frecent full text origin", |db: test_db| {
+        search_frecent(
+            &db,
+            SearchParams {
+                search_string: "blog.mozilla.org".into(),
+                limit: 10,
+                mode: SearchMode::FullText,
             },
         )
         .unwrap()
@@ -120,5 +163,10 @@ fn bench_match_url(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, bench_search_frecent, bench_match_url);
+criterion_group!(
+    benches,
+    bench_search_frecent,
+    bench_search_frecent_full_text,
+    bench_match_url
+);
 criterion_main!(benches);
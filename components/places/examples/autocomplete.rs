@@ -249,7 +249,7 @@ where
 #[cfg(not(windows))]
 mod autocomplete {
     use super::*;
-    use places::api::matcher::{search_frecent, SearchParams, SearchResult};
+    use places::api::matcher::{search_frecent, SearchMode, SearchParams, SearchResult};
     use places::ErrorKind;
     use rusqlite::{Error as RusqlError, ErrorCode};
     use sql_support::SqlInterruptHandle;
@@ -551,6 +551,7 @@ mod autocomplete {
                             autocompleter.query(SearchParams {
                                 search_string: query_str.clone(),
                                 limit: 10,
+                                mode: SearchMode::Match,
                             })?;
                         }
                     }
@@ -633,6 +634,7 @@ mod autocomplete {
                         autocompleter.query(SearchParams {
                             search_string: query_str.clone(),
                             limit: 10,
+                            mode: SearchMode::Match,
                         })?;
                     } else {
                         pending_change = true;
@@ -661,6 +663,7 @@ mod autocomplete {
                     autocompleter.query(SearchParams {
                         search_string: query_str.clone(),
                         limit: 10,
+                        mode: SearchMode::Match,
                     })?;
                 }
             }
@@ -8,6 +8,7 @@
 use cli_support::fxa_creds::{get_cli_fxa, get_default_fxa_config};
 use places::bookmark_sync::store::BookmarksStore;
 use places::history_sync::store::HistoryStore;
+use places::history_sync::HistorySyncConfig;
 use places::storage::bookmarks::{
     fetch_tree, insert_tree, BookmarkNode, BookmarkRootGuid, BookmarkTreeNode, FetchDepth,
     FolderNode, SeparatorNode,
@@ -45,6 +46,8 @@ struct DesktopItem {
     last_modified: Option<u64>,
     title: Option<String>,
     uri: Option<Url>,
+    // Desktop stores tags as a comma-separated string.
+    tags: Option<String>,
     children: Vec<DesktopItem>,
 }
 
@@ -66,6 +69,10 @@ fn convert_node(dm: DesktopItem) -> Option<BookmarkTreeNode> {
                 last_modified: dm.last_modified.map(|v| Timestamp(v / 1000)),
                 title: dm.title,
                 url,
+                tags: dm
+                    .tags
+                    .map(|tags| tags.split(',').map(str::to_owned).collect())
+                    .unwrap_or_default(),
             }
             .into()
         }
@@ -204,7 +211,11 @@ fn sync(
     let stores: Vec<Box<dyn Store>> = if engine_names.is_empty() {
         vec![
             Box::new(BookmarksStore::new(&conn, &interruptee)),
-            Box::new(HistoryStore::new(&conn, &interruptee)),
+            Box::new(HistoryStore::new(
+                &conn,
+                &interruptee,
+                HistorySyncConfig::default(),
+            )),
         ]
     } else {
         engine_names.sort();
@@ -214,7 +225,11 @@ fn sync(
             .map(|name| -> Box<dyn Store> {
                 match name.as_str() {
                     "bookmarks" => Box::new(BookmarksStore::new(&conn, &interruptee)),
-                    "history" => Box::new(HistoryStore::new(&conn, &interruptee)),
+                    "history" => Box::new(HistoryStore::new(
+                        &conn,
+                        &interruptee,
+                        HistorySyncConfig::default(),
+                    )),
                     _ => unimplemented!("Can't sync unsupported engine {}", name),
                 }
             })
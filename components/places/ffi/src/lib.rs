@@ -10,28 +10,111 @@
 
 use ffi_support::{
     define_box_destructor, define_bytebuffer_destructor, define_handle_map_deleter,
-    define_string_destructor, ByteBuffer, ConcurrentHandleMap, ExternError, FfiStr,
+    define_string_destructor, ByteBuffer, ConcurrentHandleMap, ExternError, FfiStr, IntoFfi,
 };
+use places::db::PooledInterruptHandle;
 use places::error::*;
-use places::msg_types::{BookmarkNodeList, SearchResultList};
-use places::storage::bookmarks;
+use places::history_sync::HistorySyncConfig;
+use places::msg_types::{self, BookmarkNodeList, SearchResultList};
+use places::search_engines::SearchEngines;
+use places::storage::{bookmarks, history_metadata, history_view, reading_list, tags, top_sites};
 use places::types::VisitTransitionSet;
 use places::{storage, ConnectionType, PlacesApi, PlacesDb};
 use sql_support::SqlInterruptHandle;
 use std::os::raw::c_char;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use sync_guid::Guid as SyncGuid;
 
-use places::api::matcher::{self, match_url, search_frecent, SearchParams};
+use places::api::matcher::{
+    self, match_url, search_frecent, search_frecent_interruptible, SearchInterruptHandle,
+    SearchMode, SearchParams,
+};
 
 // indirection to help `?` figure out the target error type
 fn parse_url(url: &str) -> places::Result<url::Url> {
     Ok(url::Url::parse(url)?)
 }
 
+/// A connection opened over the FFI. Read-write connections are opened
+/// directly. Read-only connections check out a reader from the API's pool for
+/// each call, and return it afterward, so that an idle handle doesn't hold on
+/// to a pooled connection, and readers see schema changes.
+enum FfiConnection {
+    Direct(Box<PlacesDb>),
+    Reader(FfiReader),
+}
+
+/// A read-only connection opened over the FFI. It only holds its API, and the
+/// interrupt handle for the reader it has checked out, if it's in a call.
+struct FfiReader {
+    api: Arc<PlacesApi>,
+    current: Arc<Mutex<Option<PooledInterruptHandle>>>,
+}
+
+impl FfiConnection {
+    fn new_interrupt_handle(&self) -> ConnectionInterruptHandle {
+        match self {
+            FfiConnection::Direct(db) => {
+                ConnectionInterruptHandle::Direct(db.new_interrupt_handle())
+            }
+            FfiConnection::Reader(reader) => {
+                ConnectionInterruptHandle::Reader(reader.current.clone())
+            }
+        }
+    }
+
+    /// Calls `f` with the database, checking out a reader for the call if
+    /// this is a read-only connection.
+    fn with_db<R>(&self, f: impl FnOnce(&PlacesDb) -> places::Result<R>) -> places::Result<R> {
+        match self {
+            FfiConnection::Direct(db) => f(db),
+            FfiConnection::Reader(reader) => {
+                let db = reader.api.checkout_reader()?;
+                *reader.current.lock().unwrap() = Some(db.new_interrupt_handle());
+                let result = f(&db);
+                *reader.current.lock().unwrap() = None;
+                result
+            }
+        }
+    }
+}
+
+/// Calls `callback` with the database for a connection handle. See
+/// `FfiConnection::with_db`.
+fn call_with_connection<R, F>(error: &mut ExternError, handle: u64, callback: F) -> R::Value
+where
+    F: std::panic::UnwindSafe + FnOnce(&PlacesDb) -> places::Result<R>,
+    R: IntoFfi,
+{
+    CONNECTIONS.call_with_result(error, handle, |conn| conn.with_db(callback))
+}
+
+/// Interrupts queries on a connection. A handle for a read-only connection
+/// interrupts the reader it has checked out, if it's in a call, and does
+/// nothing otherwise, so that it can't interrupt the reader's next user.
+pub enum ConnectionInterruptHandle {
+    Direct(SqlInterruptHandle),
+    Reader(Arc<Mutex<Option<PooledInterruptHandle>>>),
+}
+
+impl ConnectionInterruptHandle {
+    fn interrupt(&self) {
+        match self {
+            ConnectionInterruptHandle::Direct(handle) => handle.interrupt(),
+            ConnectionInterruptHandle::Reader(current) => {
+                if let Some(handle) = &*current.lock().unwrap() {
+                    handle.interrupt();
+                }
+            }
+        }
+    }
+}
+
+ffi_support::implement_into_ffi_by_pointer!(ConnectionInterruptHandle);
+
 lazy_static::lazy_static! {
     pub static ref APIS: ConcurrentHandleMap<Arc<PlacesApi>> = ConcurrentHandleMap::new();
-    static ref CONNECTIONS: ConcurrentHandleMap<PlacesDb> = ConcurrentHandleMap::new();
+    static ref CONNECTIONS: ConcurrentHandleMap<FfiConnection> = ConcurrentHandleMap::new();
 }
 
 /// Instantiate a places API. Returned api must be freed with
@@ -45,15 +128,34 @@ pub extern "C" fn places_api_new(db_path: FfiStr<'_>, error: &mut ExternError) -
     })
 }
 
+/// Like `places_api_new`, but also changes the settings used to calculate
+/// frecencies. The settings are a `FrecencySettings` represented as JSON, and
+/// any which are missing take their default values.
+#[no_mangle]
+pub extern "C" fn places_api_new_with_frecency_settings(
+    db_path: FfiStr<'_>,
+    frecency_settings_json: FfiStr<'_>,
+    error: &mut ExternError,
+) -> u64 {
+    log::debug!("places_api_new_with_frecency_settings");
+    APIS.insert_with_result(error, || {
+        let path = db_path.as_str();
+        let settings = serde_json::from_str(frecency_settings_json.as_str())?;
+        PlacesApi::new_with_frecency_settings(path, settings)
+    })
+}
+
 /// Get an interrupt handle for the PlacesApi's sync connection.
 #[no_mangle]
 pub extern "C" fn places_new_sync_conn_interrupt_handle(
     handle: u64,
     error: &mut ExternError,
-) -> *mut SqlInterruptHandle {
+) -> *mut ConnectionInterruptHandle {
     log::debug!("places_new_sync_conn_interrupt_handle");
     APIS.call_with_result(error, handle, |api| -> places::Result<_> {
-        api.new_sync_conn_interrupt_handle()
+        Ok(ConnectionInterruptHandle::Direct(
+            api.new_sync_conn_interrupt_handle()?,
+        ))
     })
 }
 
@@ -72,7 +174,14 @@ pub extern "C" fn places_connection_new(
             }
             Some(val) => val,
         };
-        Ok(CONNECTIONS.insert(api.open_connection(conn_type)?))
+        let conn = match conn_type {
+            ConnectionType::ReadOnly => FfiConnection::Reader(FfiReader {
+                api: api.clone(),
+                current: Arc::new(Mutex::new(None)),
+            }),
+            _ => FfiConnection::Direct(Box::new(api.open_connection(conn_type)?)),
+        };
+        Ok(CONNECTIONS.insert(conn))
     })
 }
 
@@ -140,31 +249,48 @@ pub extern "C" fn places_api_return_write_conn(
 ) {
     log::debug!("places_api_return_write_conn");
     APIS.call_with_result(error, api_handle, |api| -> places::Result<_> {
-        let write_conn = if let Ok(Some(conn)) = CONNECTIONS.remove_u64(write_handle) {
-            conn
-        } else {
-            log::warn!("Can't return connection to PlacesApi because it does not exist");
-            return Ok(());
-        };
-        if let Err(e) = api.close_connection(write_conn) {
+        let write_conn =
+            if let Ok(Some(FfiConnection::Direct(conn))) = CONNECTIONS.remove_u64(write_handle) {
+                conn
+            } else {
+                log::warn!("Can't return connection to PlacesApi because it does not exist");
+                return Ok(());
+            };
+        if let Err(e) = api.close_connection(*write_conn) {
             log::warn!("Failed to close connection: {}", e);
         }
         Ok(())
     })
 }
 
+/// Sets the search engines used to extract search terms from visits. The
+/// engines are a JSON array of `{"name", "urlTemplate"}` objects.
+#[no_mangle]
+pub extern "C" fn places_api_set_search_engines(
+    api_handle: u64,
+    search_engines_json: FfiStr<'_>,
+    error: &mut ExternError,
+) {
+    log::debug!("places_api_set_search_engines");
+    APIS.call_with_result(error, api_handle, |api| -> places::Result<_> {
+        let engines = SearchEngines::from_json(search_engines_json.as_str())?;
+        api.set_search_engines(engines);
+        Ok(())
+    })
+}
+
 /// Get the interrupt handle for a connection. Must be destroyed with
 /// `places_interrupt_handle_destroy`.
 #[no_mangle]
 pub extern "C" fn places_new_interrupt_handle(
     handle: u64,
     error: &mut ExternError,
-) -> *mut SqlInterruptHandle {
+) -> *mut ConnectionInterruptHandle {
     CONNECTIONS.call_with_output(error, handle, |conn| conn.new_interrupt_handle())
 }
 
 #[no_mangle]
-pub extern "C" fn places_interrupt(handle: &SqlInterruptHandle, error: &mut ExternError) {
+pub extern "C" fn places_interrupt(handle: &ConnectionInterruptHandle, error: &mut ExternError) {
     ffi_support::call_with_output(error, || handle.interrupt())
 }
 
@@ -177,31 +303,80 @@ pub extern "C" fn places_note_observation(
     error: &mut ExternError,
 ) {
     log::debug!("places_note_observation");
-    CONNECTIONS.call_with_result_mut(error, handle, |conn| {
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
         let json = json_observation.as_str();
         let visit: places::VisitObservation = serde_json::from_str(&json)?;
-        places::api::apply_observation(conn, visit)
+        storage::history::apply_observation(conn, visit)?;
+        Ok(())
     })
 }
 
-/// Execute a query, returning a `Vec<SearchResult>` as a JSON string. Returned string must be freed
-/// using `places_destroy_string`. Returns null and logs on errors (for now).
+/// Changes the settings used to calculate frecencies. The settings are a
+/// `FrecencySettings` represented as JSON, and any which are missing take
+/// their default values.
+#[no_mangle]
+pub extern "C" fn places_set_frecency_settings(
+    handle: u64,
+    frecency_settings_json: FfiStr<'_>,
+    error: &mut ExternError,
+) {
+    log::debug!("places_set_frecency_settings");
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
+        let settings = serde_json::from_str(frecency_settings_json.as_str())?;
+        storage::set_frecency_settings(conn, &settings)?;
+        Ok(())
+    })
+}
+
+/// Get a handle which can be used to cancel a single autocomplete query. Must
+/// be destroyed with `places_autocomplete_interrupt_handle_destroy`, and only
+/// once the query it was passed to has returned.
+#[no_mangle]
+pub extern "C" fn places_new_autocomplete_interrupt_handle(
+    error: &mut ExternError,
+) -> *mut SearchInterruptHandle {
+    ffi_support::call_with_output(error, SearchInterruptHandle::new)
+}
+
+/// Cancel the autocomplete query that `handle` was passed to. The query fails
+/// with an interrupted error, or, if it hasn't started yet, will fail as soon
+/// as it does.
+#[no_mangle]
+pub extern "C" fn places_autocomplete_interrupt(
+    handle: &SearchInterruptHandle,
+    error: &mut ExternError,
+) {
+    ffi_support::call_with_output(error, || handle.interrupt())
+}
+
+/// Execute a query, returning a `msg_types::SearchResultList`. `mode` is a
+/// `SearchMode`; unknown modes fall back to `SearchMode::Match`. The
+/// `interrupt_handle` is optional, and can be used to cancel just this query,
+/// for example, once the user has typed another character.
 #[no_mangle]
 pub extern "C" fn places_query_autocomplete(
     handle: u64,
     search: FfiStr<'_>,
     limit: u32,
+    mode: u8,
+    interrupt_handle: Option<&SearchInterruptHandle>,
     error: &mut ExternError,
 ) -> ByteBuffer {
     log::debug!("places_query_autocomplete");
-    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
-        let results = search_frecent(
-            conn,
-            SearchParams {
-                search_string: search.into_string(),
-                limit,
-            },
-        )?
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
+        let mode = SearchMode::from_primitive(mode).unwrap_or_else(|| {
+            log::warn!("Unknown search mode {}; assuming match", mode);
+            SearchMode::Match
+        });
+        let params = SearchParams {
+            search_string: search.into_string(),
+            limit,
+            mode,
+        };
+        let results = match interrupt_handle {
+            Some(interrupt_handle) => search_frecent_interruptible(conn, params, interrupt_handle)?,
+            None => search_frecent(conn, params)?,
+        }
         .into_iter()
         .map(|r| r.into())
         .collect();
@@ -218,7 +393,7 @@ pub extern "C" fn places_match_url(
     error: &mut ExternError,
 ) -> *mut c_char {
     log::debug!("places_match_url");
-    CONNECTIONS.call_with_result(error, handle, |conn| match_url(conn, search.as_str()))
+    call_with_connection(error, handle, |conn| match_url(conn, search.as_str()))
 }
 
 /// # Safety
@@ -236,7 +411,7 @@ pub unsafe extern "C" fn places_get_visited(
 ) {
     log::debug!("places_get_visited");
     // This function has a dumb amount of overhead and copying...
-    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<()> {
+    call_with_connection(error, handle, |conn| -> places::Result<()> {
         assert!(
             urls_len >= 0,
             "Negative array length provided to places_get_visited {}",
@@ -267,7 +442,7 @@ pub extern "C" fn places_get_visited_urls_in_range(
     error: &mut ExternError,
 ) -> *mut c_char {
     log::debug!("places_get_visited_in_range");
-    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
         let visited = storage::history::get_visited_urls(
             conn,
             // Probably should allow into()...
@@ -282,7 +457,7 @@ pub extern "C" fn places_get_visited_urls_in_range(
 #[no_mangle]
 pub extern "C" fn places_delete_visits_for(handle: u64, url: FfiStr<'_>, error: &mut ExternError) {
     log::debug!("places_delete_visits_for");
-    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
         let url = parse_url(url.as_str())?;
         let guid = match parse_url(url.as_str()) {
             Ok(url) => storage::history::url_to_guid(conn, &url)?,
@@ -298,6 +473,20 @@ pub extern "C" fn places_delete_visits_for(handle: u64, url: FfiStr<'_>, error:
     })
 }
 
+/// Deletes all pages and visits for a host, and optionally its subdomains.
+#[no_mangle]
+pub extern "C" fn places_delete_visits_for_host(
+    handle: u64,
+    host: FfiStr<'_>,
+    include_subdomains: u8, // JNA has issues with bools...
+    error: &mut ExternError,
+) {
+    log::debug!("places_delete_visits_for_host");
+    call_with_connection(error, handle, |conn| {
+        storage::history::delete_visits_for_host(conn, host.as_str(), include_subdomains != 0)
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn places_delete_visits_between(
     handle: u64,
@@ -306,7 +495,7 @@ pub extern "C" fn places_delete_visits_between(
     error: &mut ExternError,
 ) {
     log::debug!("places_delete_visits_between");
-    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
         storage::history::delete_visits_between(
             conn,
             places::Timestamp(start.max(0) as u64),
@@ -324,7 +513,7 @@ pub extern "C" fn places_delete_visit(
     error: &mut ExternError,
 ) {
     log::debug!("places_delete_visit");
-    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
         match parse_url(url.as_str()) {
             Ok(url) => {
                 storage::history::delete_place_visit_at_time(
@@ -349,27 +538,31 @@ pub extern "C" fn places_delete_visit(
 #[no_mangle]
 pub extern "C" fn places_wipe_local(handle: u64, error: &mut ExternError) {
     log::debug!("places_wipe_local");
-    CONNECTIONS.call_with_result(error, handle, |conn| storage::history::wipe_local(conn))
+    call_with_connection(error, handle, |conn| storage::history::wipe_local(conn))
 }
 
 #[no_mangle]
 pub extern "C" fn places_run_maintenance(handle: u64, error: &mut ExternError) {
     log::debug!("places_run_maintenance");
-    CONNECTIONS.call_with_result(error, handle, |conn| storage::run_maintenance(conn))
+    call_with_connection(error, handle, |conn| storage::run_maintenance(conn))
 }
 
+/// Returns a `msg_types::PruneMetrics` with the number of pages and visits
+/// that were removed.
 #[no_mangle]
-pub extern "C" fn places_prune_destructively(handle: u64, error: &mut ExternError) {
+pub extern "C" fn places_prune_destructively(handle: u64, error: &mut ExternError) -> ByteBuffer {
     log::debug!("places_prune_destructively");
-    CONNECTIONS.call_with_result(error, handle, |conn| {
-        storage::history::prune_destructively(conn)
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
+        Ok(msg_types::PruneMetrics::from(
+            storage::history::prune_destructively(conn)?,
+        ))
     })
 }
 
 #[no_mangle]
 pub extern "C" fn places_delete_everything(handle: u64, error: &mut ExternError) {
     log::debug!("places_delete_everything");
-    CONNECTIONS.call_with_result(error, handle, |conn| {
+    call_with_connection(error, handle, |conn| {
         storage::history::delete_everything(conn)
     })
 }
@@ -383,7 +576,7 @@ pub extern "C" fn places_get_visit_infos(
     error: &mut ExternError,
 ) -> ByteBuffer {
     log::debug!("places_get_visit_infos");
-    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
         Ok(storage::history::get_visit_infos(
             conn,
             places::Timestamp(start_date.max(0) as u64),
@@ -401,7 +594,7 @@ pub extern "C" fn places_get_visit_count(
     error: &mut ExternError,
 ) -> i64 {
     log::debug!("places_get_visit_count");
-    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
         storage::history::get_visit_count(
             conn,
             // Note: it's a bug in our FFI android (or swift, eventually) code
@@ -412,6 +605,322 @@ pub extern "C" fn places_get_visit_count(
     })
 }
 
+/// Records a history metadata observation, passed as a JSON string.
+#[no_mangle]
+pub extern "C" fn places_note_history_metadata(
+    handle: u64,
+    json_observation: FfiStr<'_>,
+    error: &mut ExternError,
+) {
+    log::debug!("places_note_history_metadata");
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
+        let json = json_observation.as_str();
+        let observation: history_metadata::HistoryMetadataObservation = serde_json::from_str(json)?;
+        history_metadata::note_observation(conn, observation)
+    })
+}
+
+/// Returns a `msg_types::HistoryMetadata`, or an empty buffer if there is
+/// no metadata for the URL.
+#[no_mangle]
+pub extern "C" fn places_get_latest_history_metadata_for_url(
+    handle: u64,
+    url: FfiStr<'_>,
+    error: &mut ExternError,
+) -> ByteBuffer {
+    log::debug!("places_get_latest_history_metadata_for_url");
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
+        let url = parse_url(url.as_str())?;
+        Ok(history_metadata::get_latest_for_url(conn, &url)?.map(msg_types::HistoryMetadata::from))
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn places_get_history_metadata_between(
+    handle: u64,
+    start: i64,
+    end: i64,
+    error: &mut ExternError,
+) -> ByteBuffer {
+    log::debug!("places_get_history_metadata_between");
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
+        Ok(msg_types::HistoryMetadataList::from(
+            history_metadata::get_between(
+                conn,
+                places::Timestamp(start.max(0) as u64),
+                places::Timestamp(end.max(0) as u64),
+            )?,
+        ))
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn places_get_history_metadata_grouped_by_search_term(
+    handle: u64,
+    start: i64,
+    limit: i64,
+    error: &mut ExternError,
+) -> ByteBuffer {
+    log::debug!("places_get_history_metadata_grouped_by_search_term");
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
+        Ok(msg_types::HistoryMetadataSearchTermGroups::from(
+            history_metadata::get_grouped_by_search_term(
+                conn,
+                places::Timestamp(start.max(0) as u64),
+                limit.max(0) as u32,
+            )?,
+        ))
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn places_delete_history_metadata_older_than(
+    handle: u64,
+    older_than: i64,
+    error: &mut ExternError,
+) {
+    log::debug!("places_delete_history_metadata_older_than");
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
+        history_metadata::delete_older_than(conn, places::Timestamp(older_than.max(0) as u64))
+    })
+}
+
+/// Saves a page to the reading list, and returns the new item as a
+/// `msg_types::ReadingListItem`. If the page is already saved, returns the
+/// existing item unchanged. `title`, `excerpt` and `source_device` may be
+/// null.
+#[no_mangle]
+pub extern "C" fn places_reading_list_add_item(
+    handle: u64,
+    url: FfiStr<'_>,
+    title: FfiStr<'_>,
+    excerpt: FfiStr<'_>,
+    source_device: FfiStr<'_>,
+    error: &mut ExternError,
+) -> ByteBuffer {
+    log::debug!("places_reading_list_add_item");
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
+        let url = parse_url(url.as_str())?;
+        let item = reading_list::add_item(
+            conn,
+            &url,
+            title.as_opt_str(),
+            excerpt.as_opt_str(),
+            source_device.as_opt_str(),
+        )?;
+        Ok(msg_types::ReadingListItem::from(item))
+    })
+}
+
+/// Returns a `msg_types::ReadingListItem`, or an empty buffer if the page
+/// isn't in the reading list.
+#[no_mangle]
+pub extern "C" fn places_reading_list_get_item(
+    handle: u64,
+    url: FfiStr<'_>,
+    error: &mut ExternError,
+) -> ByteBuffer {
+    log::debug!("places_reading_list_get_item");
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
+        let url = parse_url(url.as_str())?;
+        Ok(reading_list::get_item(conn, &url)?.map(msg_types::ReadingListItem::from))
+    })
+}
+
+/// Returns a `msg_types::ReadingListItems` with the saved pages that match
+/// `filter`, which is a `reading_list::ReadingListFilter`, most recently saved
+/// first.
+#[no_mangle]
+pub extern "C" fn places_reading_list_get_items(
+    handle: u64,
+    filter: u8,
+    error: &mut ExternError,
+) -> ByteBuffer {
+    log::debug!("places_reading_list_get_items");
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
+        let filter = reading_list::ReadingListFilter::from_primitive(filter).unwrap_or_else(|| {
+            log::warn!(
+                "Unknown reading list filter {}; returning all items",
+                filter
+            );
+            reading_list::ReadingListFilter::All
+        });
+        Ok(msg_types::ReadingListItems::from(reading_list::get_items(
+            conn, filter,
+        )?))
+    })
+}
+
+/// Marks a saved page as read, or unread. Returns false if the page isn't in
+/// the reading list.
+#[no_mangle]
+pub extern "C" fn places_reading_list_mark_read(
+    handle: u64,
+    url: FfiStr<'_>,
+    read: u8,
+    error: &mut ExternError,
+) -> u8 {
+    log::debug!("places_reading_list_mark_read");
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
+        let url = parse_url(url.as_str())?;
+        reading_list::mark_read(conn, &url, read != 0)
+    })
+}
+
+/// Removes a page from the reading list. Returns false if it wasn't there.
+#[no_mangle]
+pub extern "C" fn places_reading_list_delete_item(
+    handle: u64,
+    url: FfiStr<'_>,
+    error: &mut ExternError,
+) -> u8 {
+    log::debug!("places_reading_list_delete_item");
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
+        let url = parse_url(url.as_str())?;
+        reading_list::delete_item(conn, &url)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn places_reading_list_delete_all_items(handle: u64, error: &mut ExternError) {
+    log::debug!("places_reading_list_delete_all_items");
+    call_with_connection(error, handle, |conn| reading_list::delete_all_items(conn))
+}
+
+/// Returns a `msg_types::TopFrecentSiteInfos` with up to `limit` of the most
+/// frecent pages, excluding any which have been blocked.
+#[no_mangle]
+pub extern "C" fn places_get_top_frecent_site_infos(
+    handle: u64,
+    limit: i32,
+    frecency_threshold: i64,
+    error: &mut ExternError,
+) -> ByteBuffer {
+    log::debug!("places_get_top_frecent_site_infos");
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
+        Ok(msg_types::TopFrecentSiteInfos::from(
+            top_sites::get_top_frecent_sites(conn, limit.max(0) as u32, frecency_threshold)?,
+        ))
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn places_block_top_site(handle: u64, url: FfiStr<'_>, error: &mut ExternError) {
+    log::debug!("places_block_top_site");
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
+        let url = parse_url(url.as_str())?;
+        top_sites::block_site(conn, &url)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn places_unblock_top_site(handle: u64, url: FfiStr<'_>, error: &mut ExternError) {
+    log::debug!("places_unblock_top_site");
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
+        let url = parse_url(url.as_str())?;
+        top_sites::unblock_site(conn, &url)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn places_clear_top_sites_blocklist(handle: u64, error: &mut ExternError) {
+    log::debug!("places_clear_top_sites_blocklist");
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
+        top_sites::clear_blocklist(conn)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn places_tag_url(
+    handle: u64,
+    url: FfiStr<'_>,
+    tag: FfiStr<'_>,
+    error: &mut ExternError,
+) {
+    log::debug!("places_tag_url");
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
+        let url = parse_url(url.as_str())?;
+        tags::tag_url(conn, &url, tag.as_str())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn places_untag_url(
+    handle: u64,
+    url: FfiStr<'_>,
+    tag: FfiStr<'_>,
+    error: &mut ExternError,
+) {
+    log::debug!("places_untag_url");
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
+        let url = parse_url(url.as_str())?;
+        tags::untag_url(conn, &url, tag.as_str())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn places_remove_tag(handle: u64, tag: FfiStr<'_>, error: &mut ExternError) {
+    log::debug!("places_remove_tag");
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
+        tags::remove_tag(conn, tag.as_str())
+    })
+}
+
+/// Renames a tag, merging it into an existing tag with the new name if there
+/// is one.
+#[no_mangle]
+pub extern "C" fn places_rename_tag(
+    handle: u64,
+    old_tag: FfiStr<'_>,
+    new_tag: FfiStr<'_>,
+    error: &mut ExternError,
+) {
+    log::debug!("places_rename_tag");
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
+        tags::rename_tag(conn, old_tag.as_str(), new_tag.as_str())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn places_get_all_tags(handle: u64, error: &mut ExternError) -> ByteBuffer {
+    log::debug!("places_get_all_tags");
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
+        Ok(msg_types::TagInfoList::from(tags::get_all_tags(conn)?))
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn places_get_tags_for_url(
+    handle: u64,
+    url: FfiStr<'_>,
+    error: &mut ExternError,
+) -> ByteBuffer {
+    log::debug!("places_get_tags_for_url");
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
+        let url = parse_url(url.as_str())?;
+        Ok(msg_types::StringList::from(tags::get_tags_for_url(
+            conn, &url,
+        )?))
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn places_get_urls_with_tag(
+    handle: u64,
+    tag: FfiStr<'_>,
+    error: &mut ExternError,
+) -> ByteBuffer {
+    log::debug!("places_get_urls_with_tag");
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
+        let urls = tags::get_urls_with_tag(conn, tag.as_str())?;
+        Ok(msg_types::StringList::from(
+            urls.into_iter()
+                .map(url::Url::into_string)
+                .collect::<Vec<_>>(),
+        ))
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn places_get_visit_page(
     handle: u64,
@@ -421,7 +930,7 @@ pub extern "C" fn places_get_visit_page(
     error: &mut ExternError,
 ) -> ByteBuffer {
     log::debug!("places_get_visit_page");
-    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
         storage::history::get_visit_page(
             conn,
             offset,
@@ -444,7 +953,7 @@ pub extern "C" fn places_get_visit_page_with_bound(
     error: &mut ExternError,
 ) -> ByteBuffer {
     log::debug!("places_get_visit_page");
-    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
         storage::history::get_visit_page_with_bound(
             conn,
             bound,
@@ -456,6 +965,37 @@ pub extern "C" fn places_get_visit_page_with_bound(
     })
 }
 
+/// Returns a `msg_types::HistorySections` with a page of visited sites,
+/// grouped by day. `search` and `cursor` may be null. `cursor` is the opaque
+/// string returned with the previous page.
+#[no_mangle]
+pub extern "C" fn places_get_history_sections(
+    handle: u64,
+    search: FfiStr<'_>,
+    exclude_types: i32,
+    utc_offset_minutes: i32,
+    limit: i32,
+    cursor: FfiStr<'_>,
+    error: &mut ExternError,
+) -> ByteBuffer {
+    log::debug!("places_get_history_sections");
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
+        let query = history_view::HistoryQuery {
+            search: search.as_opt_str().map(String::from),
+            exclude_types: VisitTransitionSet::from_u16(exclude_types as u16)
+                .expect("Bug: Invalid VisitTransitionSet"),
+            utc_offset_minutes,
+            limit: limit.max(0) as u32,
+            cursor: match cursor.as_opt_str() {
+                Some(cursor) => Some(serde_json::from_str(cursor)?),
+                None => None,
+            },
+        };
+        let page = history_view::get_history_sections(conn, &query)?;
+        Ok(msg_types::HistorySections::from(page))
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn places_accept_result(
     handle: u64,
@@ -464,7 +1004,7 @@ pub extern "C" fn places_accept_result(
     error: &mut ExternError,
 ) {
     log::debug!("places_accept_result");
-    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
         let search_string = search_string.as_str();
         let url = if let Ok(url) = parse_url(url.as_str()) {
             url
@@ -513,6 +1053,7 @@ pub extern "C" fn sync15_history_sync(
                 tokenserver_url: parse_url(tokenserver_url.as_str())?,
             },
             &sync15::KeyBundle::from_ksync_base64(sync_key.as_str())?,
+            &HistorySyncConfig::default(),
         )?;
         Ok(ping)
     })
@@ -548,7 +1089,7 @@ pub extern "C" fn bookmarks_get_tree(
     error: &mut ExternError,
 ) -> ByteBuffer {
     log::debug!("bookmarks_get_tree");
-    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
         let root_id = SyncGuid::from(guid.as_str());
         Ok(bookmarks::public_node::fetch_public_tree(conn, &root_id)?)
     })
@@ -557,7 +1098,7 @@ pub extern "C" fn bookmarks_get_tree(
 #[no_mangle]
 pub extern "C" fn bookmarks_delete_everything(handle: u64, error: &mut ExternError) {
     log::debug!("bookmarks_delete_everything");
-    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
         bookmarks::delete_everything(conn)?;
         Ok(())
     })
@@ -571,7 +1112,7 @@ pub extern "C" fn bookmarks_get_by_guid(
     error: &mut ExternError,
 ) -> ByteBuffer {
     log::debug!("bookmarks_get_by_guid");
-    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
         let guid = SyncGuid::from(guid.as_str());
         Ok(bookmarks::public_node::fetch_bookmark(
             conn,
@@ -602,7 +1143,7 @@ pub unsafe extern "C" fn bookmarks_insert(
 ) -> *mut c_char {
     log::debug!("bookmarks_insert");
     use places::msg_types::BookmarkNode;
-    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
         let buffer = get_buffer(data, len);
         let bookmark: BookmarkNode = prost::Message::decode(buffer)?;
         let insertable = bookmark.into_insertable()?;
@@ -622,7 +1163,7 @@ pub unsafe extern "C" fn bookmarks_update(
 ) {
     log::debug!("bookmarks_update");
     use places::msg_types::BookmarkNode;
-    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
         let buffer = get_buffer(data, len);
         let bookmark: BookmarkNode = prost::Message::decode(buffer)?;
         bookmarks::public_node::update_bookmark_from_message(conn, bookmark)?;
@@ -630,16 +1171,78 @@ pub unsafe extern "C" fn bookmarks_update(
     })
 }
 
+/// # Safety
+/// Deref pointer, thus unsafe
+#[no_mangle]
+pub unsafe extern "C" fn bookmarks_reorder_children(
+    handle: u64,
+    folder_guid: FfiStr<'_>,
+    data: *const u8,
+    len: i32,
+    error: &mut ExternError,
+) {
+    log::debug!("bookmarks_reorder_children");
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
+        let buffer = get_buffer(data, len);
+        let guids: msg_types::StringList = prost::Message::decode(buffer)?;
+        let guids: Vec<SyncGuid> = guids.values.into_iter().map(SyncGuid::from).collect();
+        bookmarks::reorder_children(conn, &SyncGuid::from(folder_guid.as_str()), &guids)
+    })
+}
+
+/// Moves the bookmarks in the protobuf-encoded `StringList` to
+/// `new_parent_guid`, inserting them at `position`, or appending them if
+/// `position` is negative.
+///
+/// # Safety
+/// Deref pointer, thus unsafe
+#[no_mangle]
+pub unsafe extern "C" fn bookmarks_move_many(
+    handle: u64,
+    data: *const u8,
+    len: i32,
+    new_parent_guid: FfiStr<'_>,
+    position: i32,
+    error: &mut ExternError,
+) {
+    log::debug!("bookmarks_move_many");
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
+        let buffer = get_buffer(data, len);
+        let guids: msg_types::StringList = prost::Message::decode(buffer)?;
+        let guids: Vec<SyncGuid> = guids.values.into_iter().map(SyncGuid::from).collect();
+        let position = if position < 0 {
+            bookmarks::BookmarkPosition::Append
+        } else {
+            bookmarks::BookmarkPosition::Specific(position as u32)
+        };
+        bookmarks::move_many(
+            conn,
+            &guids,
+            &SyncGuid::from(new_parent_guid.as_str()),
+            position,
+        )
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn bookmarks_delete(handle: u64, id: FfiStr<'_>, error: &mut ExternError) -> u8 {
     log::debug!("bookmarks_delete");
-    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
         let guid = SyncGuid::from(id.as_str());
         let did_delete = bookmarks::delete_bookmark(conn, &guid)?;
         Ok(did_delete)
     })
 }
 
+#[no_mangle]
+pub extern "C" fn bookmarks_undo(handle: u64, error: &mut ExternError) -> u8 {
+    log::debug!("bookmarks_undo");
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
+        let did_undo = bookmarks::undo::undo_last_change(conn)?;
+        Ok(did_undo)
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn bookmarks_get_all_with_url(
     handle: u64,
@@ -647,7 +1250,7 @@ pub extern "C" fn bookmarks_get_all_with_url(
     error: &mut ExternError,
 ) -> ByteBuffer {
     log::debug!("bookmarks_get_all_with_url");
-    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
         Ok(match parse_url(url.as_str()) {
             Ok(url) => {
                 BookmarkNodeList::from(bookmarks::public_node::fetch_bookmarks_by_url(conn, &url)?)
@@ -670,12 +1273,51 @@ pub unsafe extern "C" fn bookmarks_get_url_for_keyword(
     error: &mut ExternError,
 ) -> *mut c_char {
     log::debug!("bookmarks_get_url_for_keyword");
-    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
         let url = bookmarks::bookmarks_get_url_for_keyword(conn, keyword.as_str())?;
         Ok(url.map(url::Url::into_string))
     })
 }
 
+#[no_mangle]
+pub extern "C" fn bookmarks_get_keywords(handle: u64, error: &mut ExternError) -> ByteBuffer {
+    log::debug!("bookmarks_get_keywords");
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
+        Ok(msg_types::BookmarkKeywordList::from(
+            bookmarks::bookmarks_get_keywords(conn)?,
+        ))
+    })
+}
+
+/// Sets the keyword for a bookmarked URL. `post_data` may be null.
+#[no_mangle]
+pub extern "C" fn bookmarks_set_keyword(
+    handle: u64,
+    url: FfiStr<'_>,
+    keyword: FfiStr<'_>,
+    post_data: FfiStr<'_>,
+    error: &mut ExternError,
+) {
+    log::debug!("bookmarks_set_keyword");
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
+        let url = parse_url(url.as_str())?;
+        bookmarks::bookmarks_set_keyword(conn, &url, keyword.as_str(), post_data.as_opt_str())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn bookmarks_remove_keyword(
+    handle: u64,
+    url: FfiStr<'_>,
+    error: &mut ExternError,
+) -> u8 {
+    log::debug!("bookmarks_remove_keyword");
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
+        let url = parse_url(url.as_str())?;
+        bookmarks::bookmarks_remove_keyword(conn, &url)
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn bookmarks_search(
     handle: u64,
@@ -684,7 +1326,7 @@ pub extern "C" fn bookmarks_search(
     error: &mut ExternError,
 ) -> ByteBuffer {
     log::debug!("bookmarks_search");
-    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
         Ok(BookmarkNodeList::from(
             bookmarks::public_node::search_bookmarks(conn, query.as_str(), limit as u32)?,
         ))
@@ -698,7 +1340,7 @@ pub extern "C" fn bookmarks_get_recent(
     error: &mut ExternError,
 ) -> ByteBuffer {
     log::debug!("bookmarks_get_recent");
-    CONNECTIONS.call_with_result(error, handle, |conn| -> places::Result<_> {
+    call_with_connection(error, handle, |conn| -> places::Result<_> {
         Ok(BookmarkNodeList::from(
             bookmarks::public_node::recent_bookmarks(conn, limit as u32)?,
         ))
@@ -710,4 +1352,8 @@ define_bytebuffer_destructor!(places_destroy_bytebuffer);
 define_handle_map_deleter!(APIS, places_api_destroy);
 
 define_handle_map_deleter!(CONNECTIONS, places_connection_destroy);
-define_box_destructor!(SqlInterruptHandle, places_interrupt_handle_destroy);
+define_box_destructor!(ConnectionInterruptHandle, places_interrupt_handle_destroy);
+define_box_destructor!(
+    SearchInterruptHandle,
+    places_autocomplete_interrupt_handle_destroy
+);
@@ -516,6 +516,104 @@ public class PlacesReadConnection {
         }
     }
 
+    /**
+     * Returns history and bookmark suggestions for the location bar.
+     *
+     * The order of the results is unspecified.
+     *
+     * - Parameter query: The string to match results against.
+     * - Parameter limit: The maximum number of results to return.
+     * - Parameter mode: How to find matches. `.fullText` is much faster for
+     *                   large histories, but only matches words that start
+     *                   with a word in the query.
+     * - Returns: A list of suggestions matching the query.
+     * - Throws:
+     *     - `PlacesError.databaseInterrupted`: If a call is made to `interrupt()` on this
+     *                                          object from another thread.
+     *     - `PlacesError.connUseAfterAPIClosed`: If the PlacesAPI that returned this connection
+     *                                            object has been closed. This indicates API
+     *                                            misuse.
+     *     - `PlacesError.databaseBusy`: If this query times out with a SQLITE_BUSY error.
+     *     - `PlacesError.unexpected`: When an error that has not specifically been exposed
+     *                                 to Swift is encountered (for example IO errors from
+     *                                 the database code, etc).
+     *     - `PlacesError.panic`: If the rust code panics while completing this
+     *                            operation. (If this occurs, please let us know).
+     */
+    open func queryAutocomplete(query: String, limit: UInt, mode: SearchMode = .match) throws -> [SearchResult] {
+        return try queue.sync {
+            try self.checkApi()
+            let buffer = try PlacesError.unwrap { error in
+                places_query_autocomplete(self.handle, query, Int32(limit), mode.rawValue, nil, error)
+            }
+            defer { places_destroy_bytebuffer(buffer) }
+            let msg = try MsgTypes_SearchResultList(serializedData: Data(placesRustBuffer: buffer))
+            return msg.results.map { SearchResult(msg: $0) }
+        }
+    }
+
+    /**
+     * Returns the reading list item for a URL.
+     *
+     * - Parameter url: The URL of the saved page.
+     * - Returns: The item, or nil if the page isn't in the reading list.
+     * - Throws:
+     *     - `PlacesError.urlParseError`: If `url` is not a valid URL.
+     *     - `PlacesError.connUseAfterAPIClosed`: If the PlacesAPI that returned this connection
+     *                                            object has been closed. This indicates API
+     *                                            misuse.
+     *     - `PlacesError.databaseBusy`: If this query times out with a SQLITE_BUSY error.
+     *     - `PlacesError.unexpected`: When an error that has not specifically been exposed
+     *                                 to Swift is encountered (for example IO errors from
+     *                                 the database code, etc).
+     *     - `PlacesError.panic`: If the rust code panics while completing this
+     *                            operation. (If this occurs, please let us know).
+     */
+    open func getReadingListItem(url: String) throws -> ReadingListItem? {
+        return try queue.sync {
+            try self.checkApi()
+            let buffer = try PlacesError.unwrap { error in
+                places_reading_list_get_item(self.handle, url, error)
+            }
+            if buffer.data == nil {
+                return nil
+            }
+            defer { places_destroy_bytebuffer(buffer) }
+            let msg = try MsgTypes_ReadingListItem(serializedData: Data(placesRustBuffer: buffer))
+            return ReadingListItem(msg: msg)
+        }
+    }
+
+    /**
+     * Returns the pages in the reading list, most recently saved first.
+     *
+     * - Parameter filter: Whether to return all items, or only unread or read ones.
+     * - Returns: A list of reading list items.
+     * - Throws:
+     *     - `PlacesError.databaseInterrupted`: If a call is made to `interrupt()` on this
+     *                                          object from another thread.
+     *     - `PlacesError.connUseAfterAPIClosed`: If the PlacesAPI that returned this connection
+     *                                            object has been closed. This indicates API
+     *                                            misuse.
+     *     - `PlacesError.databaseBusy`: If this query times out with a SQLITE_BUSY error.
+     *     - `PlacesError.unexpected`: When an error that has not specifically been exposed
+     *                                 to Swift is encountered (for example IO errors from
+     *                                 the database code, etc).
+     *     - `PlacesError.panic`: If the rust code panics while completing this
+     *                            operation. (If this occurs, please let us know).
+     */
+    open func getReadingListItems(filter: ReadingListFilter = .all) throws -> [ReadingListItem] {
+        return try queue.sync {
+            try self.checkApi()
+            let buffer = try PlacesError.unwrap { error in
+                places_reading_list_get_items(self.handle, filter.rawValue, error)
+            }
+            defer { places_destroy_bytebuffer(buffer) }
+            let msg = try MsgTypes_ReadingListItems(serializedData: Data(placesRustBuffer: buffer))
+            return msg.items.map { ReadingListItem(msg: $0) }
+        }
+    }
+
     /**
      * Attempt to interrupt a long-running operation which may be
      * happening concurrently. If the operation is interrupted,
@@ -599,6 +697,123 @@ public class PlacesWriteConnection: PlacesReadConnection {
         }
     }
 
+    /**
+     * Saves a page to the reading list. Saving a page doesn't add a visit to
+     * history.
+     *
+     * - Parameter url: The URL of the page.
+     * - Parameter title: The title of the page, if known.
+     * - Parameter excerpt: A short preview of the page, if known.
+     * - Parameter sourceDevice: The name of this device, if known.
+     * - Returns: The new item, or the existing one if the page was already saved.
+     * - Throws:
+     *     - `PlacesError.urlParseError`: If `url` is not a valid URL.
+     *     - `PlacesError.connUseAfterAPIClosed`: If the PlacesAPI that returned this connection
+     *                                            object has been closed. This indicates API
+     *                                            misuse.
+     *     - `PlacesError.databaseBusy`: If this query times out with a SQLITE_BUSY error.
+     *     - `PlacesError.unexpected`: When an error that has not specifically been exposed
+     *                                 to Swift is encountered (for example IO errors from
+     *                                 the database code, etc).
+     *     - `PlacesError.panic`: If the rust code panics while completing this
+     *                            operation. (If this occurs, please let us know).
+     */
+    @discardableResult
+    open func addReadingListItem(url: String,
+                                 title: String? = nil,
+                                 excerpt: String? = nil,
+                                 sourceDevice: String? = nil) throws -> ReadingListItem {
+        return try queue.sync {
+            try self.checkApi()
+            let buffer = try PlacesError.unwrap { error in
+                places_reading_list_add_item(self.handle, url, title, excerpt, sourceDevice, error)
+            }
+            defer { places_destroy_bytebuffer(buffer) }
+            let msg = try MsgTypes_ReadingListItem(serializedData: Data(placesRustBuffer: buffer))
+            return ReadingListItem(msg: msg)
+        }
+    }
+
+    /**
+     * Marks a saved page as read, or unread.
+     *
+     * - Parameter url: The URL of the saved page.
+     * - Parameter read: Whether the page should be marked as read.
+     * - Returns: Whether or not the page is in the reading list.
+     * - Throws:
+     *     - `PlacesError.urlParseError`: If `url` is not a valid URL.
+     *     - `PlacesError.connUseAfterAPIClosed`: If the PlacesAPI that returned this connection
+     *                                            object has been closed. This indicates API
+     *                                            misuse.
+     *     - `PlacesError.databaseBusy`: If this query times out with a SQLITE_BUSY error.
+     *     - `PlacesError.unexpected`: When an error that has not specifically been exposed
+     *                                 to Swift is encountered (for example IO errors from
+     *                                 the database code, etc).
+     *     - `PlacesError.panic`: If the rust code panics while completing this
+     *                            operation. (If this occurs, please let us know).
+     */
+    @discardableResult
+    open func markReadingListItemRead(url: String, read: Bool = true) throws -> Bool {
+        return try queue.sync {
+            try self.checkApi()
+            let resByte = try PlacesError.unwrap { error in
+                places_reading_list_mark_read(self.handle, url, read ? 1 : 0, error)
+            }
+            return resByte != 0
+        }
+    }
+
+    /**
+     * Removes a page from the reading list.
+     *
+     * - Parameter url: The URL of the saved page.
+     * - Returns: Whether or not the page was in the reading list.
+     * - Throws:
+     *     - `PlacesError.urlParseError`: If `url` is not a valid URL.
+     *     - `PlacesError.connUseAfterAPIClosed`: If the PlacesAPI that returned this connection
+     *                                            object has been closed. This indicates API
+     *                                            misuse.
+     *     - `PlacesError.databaseBusy`: If this query times out with a SQLITE_BUSY error.
+     *     - `PlacesError.unexpected`: When an error that has not specifically been exposed
+     *                                 to Swift is encountered (for example IO errors from
+     *                                 the database code, etc).
+     *     - `PlacesError.panic`: If the rust code panics while completing this
+     *                            operation. (If this occurs, please let us know).
+     */
+    @discardableResult
+    open func deleteReadingListItem(url: String) throws -> Bool {
+        return try queue.sync {
+            try self.checkApi()
+            let resByte = try PlacesError.unwrap { error in
+                places_reading_list_delete_item(self.handle, url, error)
+            }
+            return resByte != 0
+        }
+    }
+
+    /**
+     * Removes all pages from the reading list.
+     *
+     * - Throws:
+     *     - `PlacesError.connUseAfterAPIClosed`: If the PlacesAPI that returned this connection
+     *                                            object has been closed. This indicates API
+     *                                            misuse.
+     *     - `PlacesError.databaseBusy`: If this query times out with a SQLITE_BUSY error.
+     *     - `PlacesError.unexpected`: When an error that has not specifically been exposed
+     *                                 to Swift is encountered (for example IO errors from
+     *                                 the database code, etc).
+     *     - `PlacesError.panic`: If the rust code panics while completing this
+     *                            operation. (If this occurs, please let us know).
+     */
+    open func deleteAllReadingListItems() throws {
+        return try queue.sync {
+            try self.checkApi()
+            try PlacesError.unwrap { error in
+                places_reading_list_delete_all_items(self.handle, error)
+            }
+        }
+    }
+
     /**
      * Create a bookmark folder, returning its guid.
      *
@@ -846,3 +1061,75 @@ private class InterruptHandle {
         }
     }
 }
+
+/**
+ * How `PlacesReadConnection.queryAutocomplete` finds matches.
+ */
+public enum SearchMode: UInt8 {
+    /// Match the query anywhere in the URL, title, or tags of every page.
+    case match = 1
+    /// Use the full-text index, which is much faster for large histories, but
+    /// only matches words that start with a word in the query.
+    case fullText = 2
+}
+
+/**
+ * A suggestion returned from `PlacesReadConnection.queryAutocomplete`.
+ */
+public struct SearchResult {
+    /// The URL to open when the suggestion is picked.
+    public let url: String
+    /// The title to show for the suggestion.
+    public let title: String
+    /// The frecency score for the suggestion.
+    public let frecency: Int64
+    /// What the user searched for, if this is a search result page.
+    public let searchTerm: String?
+
+    fileprivate init(msg: MsgTypes_SearchResultMessage) {
+        url = msg.url
+        title = msg.title
+        frecency = msg.frecency
+        searchTerm = msg.hasSearchTerm ? msg.searchTerm : nil
+    }
+}
+
+/**
+ * Which items `PlacesReadConnection.getReadingListItems` returns.
+ */
+public enum ReadingListFilter: UInt8 {
+    case all = 1
+    case unread = 2
+    case read = 3
+}
+
+/**
+ * A page saved to the reading list.
+ */
+public struct ReadingListItem {
+    public let guid: String
+    public let url: String
+    public let title: String?
+    public let excerpt: String?
+    /// The name of the device the page was saved on, if known.
+    public let sourceDevice: String?
+    /// When the page was saved, in milliseconds since the unix epoch.
+    public let addedAt: Int64
+    /// When the page was marked as read, in milliseconds since the unix
+    /// epoch, or nil if it hasn't been.
+    public let readAt: Int64?
+
+    public var isRead: Bool {
+        return readAt != nil
+    }
+
+    fileprivate init(msg: MsgTypes_ReadingListItem) {
+        guid = msg.guid
+        url = msg.url
+        title = msg.hasTitle ? msg.title : nil
+        excerpt = msg.hasExcerpt ? msg.excerpt : nil
+        sourceDevice = msg.hasSourceDevice ? msg.sourceDevice : nil
+        addedAt = msg.addedAt
+        readAt = msg.hasReadAt ? msg.readAt : nil
+    }
+}
@@ -35,6 +35,7 @@ typedef struct PlacesRustBuffer {
 } PlacesRustBuffer;
 
 typedef struct RawPlacesInterruptHandle RawPlacesInterruptHandle;
+typedef struct RawPlacesAutocompleteInterruptHandle RawPlacesAutocompleteInterruptHandle;
 
 // Not a named enum because we need int32_t ABI in `places_connection_new`,
 // and using a named enum would be `int` (which usually is 32 bits these
@@ -49,6 +50,10 @@ enum {
 PlacesAPIHandle places_api_new(const char *_Nonnull db_path,
                                PlacesRustError *_Nonnull out_err);
 
+PlacesAPIHandle places_api_new_with_frecency_settings(const char *_Nonnull db_path,
+                                                      const char *_Nonnull frecency_settings_json,
+                                                      PlacesRustError *_Nonnull out_err);
+
 
 PlacesConnectionHandle places_connection_new(PlacesAPIHandle handle,
                                              int32_t type,
@@ -60,10 +65,17 @@ void places_note_observation(PlacesConnectionHandle handle,
                              const char *_Nonnull observation_json,
                              PlacesRustError *_Nonnull out_err);
 
-char *_Nullable places_query_autocomplete(PlacesConnectionHandle handle,
-                                          const char *_Nonnull search,
-                                          int32_t limit,
-                                          PlacesRustError *_Nonnull out_err);
+PlacesRustBuffer places_query_autocomplete(PlacesConnectionHandle handle,
+                                           const char *_Nonnull search,
+                                           int32_t limit,
+                                           uint8_t mode,
+                                           RawPlacesAutocompleteInterruptHandle *_Nullable interrupt_handle,
+                                           PlacesRustError *_Nonnull out_err);
+
+RawPlacesAutocompleteInterruptHandle *_Nullable places_new_autocomplete_interrupt_handle(PlacesRustError *_Nonnull out_err);
+
+void places_autocomplete_interrupt(RawPlacesAutocompleteInterruptHandle *_Nonnull interrupt,
+                                   PlacesRustError *_Nonnull out_err);
 
 char *_Nullable places_match_url(PlacesConnectionHandle handle,
                                  const char *_Nonnull search,
@@ -97,6 +109,11 @@ void places_delete_visits_for(PlacesConnectionHandle handle,
                               const char *_Nonnull place_url,
                               PlacesRustError *_Nonnull out_err);
 
+void places_delete_visits_for_host(PlacesConnectionHandle handle,
+                                   const char *_Nonnull host,
+                                   uint8_t include_subdomains,
+                                   PlacesRustError *_Nonnull out_err);
+
 void places_delete_visit(PlacesConnectionHandle handle,
                          const char *_Nonnull place_url,
                          int64_t visit_timestamp,
@@ -113,8 +130,12 @@ void places_wipe_local(PlacesConnectionHandle handle,
 void places_run_maintenance(PlacesConnectionHandle handle,
                             PlacesRustError *_Nonnull out_err);
 
-void places_prune_destructively(PlacesConnectionHandle handle,
-                                PlacesRustError *_Nonnull out_err);
+void places_set_frecency_settings(PlacesConnectionHandle handle,
+                                  const char *_Nonnull frecency_settings_json,
+                                  PlacesRustError *_Nonnull out_err);
+
+PlacesRustBuffer places_prune_destructively(PlacesConnectionHandle handle,
+                                            PlacesRustError *_Nonnull out_err);
 
 void places_delete_everything(PlacesConnectionHandle handle,
                               PlacesRustError *_Nonnull out_err);
@@ -125,6 +146,41 @@ PlacesRustBuffer places_get_visit_infos(PlacesConnectionHandle handle,
                                         int32_t exclude_types,
                                         PlacesRustError *_Nonnull out_err);
 
+PlacesRustBuffer places_get_history_sections(PlacesConnectionHandle handle,
+                                             char const *_Nullable search,
+                                             int32_t exclude_types,
+                                             int32_t utc_offset_minutes,
+                                             int32_t limit,
+                                             char const *_Nullable cursor,
+                                             PlacesRustError *_Nonnull out_err);
+
+PlacesRustBuffer places_reading_list_add_item(PlacesConnectionHandle handle,
+                                              char const *_Nonnull url,
+                                              char const *_Nullable title,
+                                              char const *_Nullable excerpt,
+                                              char const *_Nullable source_device,
+                                              PlacesRustError *_Nonnull out_err);
+
+PlacesRustBuffer places_reading_list_get_item(PlacesConnectionHandle handle,
+                                              char const *_Nonnull url,
+                                              PlacesRustError *_Nonnull out_err);
+
+PlacesRustBuffer places_reading_list_get_items(PlacesConnectionHandle handle,
+                                               uint8_t filter,
+                                               PlacesRustError *_Nonnull out_err);
+
+uint8_t places_reading_list_mark_read(PlacesConnectionHandle handle,
+                                      char const *_Nonnull url,
+                                      uint8_t read,
+                                      PlacesRustError *_Nonnull out_err);
+
+uint8_t places_reading_list_delete_item(PlacesConnectionHandle handle,
+                                        char const *_Nonnull url,
+                                        PlacesRustError *_Nonnull out_err);
+
+void places_reading_list_delete_all_items(PlacesConnectionHandle handle,
+                                          PlacesRustError *_Nonnull out_err);
+
 void places_reset(PlacesAPIHandle handle,
                   PlacesRustError *_Nonnull out_err);
 
@@ -161,6 +217,49 @@ char *_Nullable bookmarks_get_url_for_keyword(PlacesConnectionHandle handle,
                                               char const *_Nonnull keyword,
                                               PlacesRustError *_Nonnull out_err);
 
+PlacesRustBuffer bookmarks_get_keywords(PlacesConnectionHandle handle,
+                                        PlacesRustError *_Nonnull out_err);
+
+void bookmarks_set_keyword(PlacesConnectionHandle handle,
+                           char const *_Nonnull url,
+                           char const *_Nonnull keyword,
+                           char const *_Nullable post_data,
+                           PlacesRustError *_Nonnull out_err);
+
+uint8_t bookmarks_remove_keyword(PlacesConnectionHandle handle,
+                                 char const *_Nonnull url,
+                                 PlacesRustError *_Nonnull out_err);
+
+void places_tag_url(PlacesConnectionHandle handle,
+                    char const *_Nonnull url,
+                    char const *_Nonnull tag,
+                    PlacesRustError *_Nonnull out_err);
+
+void places_untag_url(PlacesConnectionHandle handle,
+                      char const *_Nonnull url,
+                      char const *_Nonnull tag,
+                      PlacesRustError *_Nonnull out_err);
+
+void places_remove_tag(PlacesConnectionHandle handle,
+                       char const *_Nonnull tag,
+                       PlacesRustError *_Nonnull out_err);
+
+void places_rename_tag(PlacesConnectionHandle handle,
+                       char const *_Nonnull old_tag,
+                       char const *_Nonnull new_tag,
+                       PlacesRustError *_Nonnull out_err);
+
+PlacesRustBuffer places_get_all_tags(PlacesConnectionHandle handle,
+                                     PlacesRustError *_Nonnull out_err);
+
+PlacesRustBuffer places_get_tags_for_url(PlacesConnectionHandle handle,
+                                         char const *_Nonnull url,
+                                         PlacesRustError *_Nonnull out_err);
+
+PlacesRustBuffer places_get_urls_with_tag(PlacesConnectionHandle handle,
+                                          char const *_Nonnull tag,
+                                          PlacesRustError *_Nonnull out_err);
+
 PlacesRustBuffer bookmarks_search(PlacesConnectionHandle handle,
                                   char const *_Nonnull query,
                                   int32_t limit,
@@ -184,10 +283,26 @@ void bookmarks_update(PlacesConnectionHandle handle,
                       int32_t len,
                       PlacesRustError *_Nonnull out_err);
 
+void bookmarks_reorder_children(PlacesConnectionHandle handle,
+                                char const *_Nonnull folder_guid,
+                                uint8_t const *_Nonnull data,
+                                int32_t len,
+                                PlacesRustError *_Nonnull out_err);
+
+void bookmarks_move_many(PlacesConnectionHandle handle,
+                         uint8_t const *_Nonnull data,
+                         int32_t len,
+                         char const *_Nonnull new_parent_guid,
+                         int32_t position,
+                         PlacesRustError *_Nonnull out_err);
+
 uint8_t bookmarks_delete(PlacesConnectionHandle handle,
                          char const *_Nonnull guid_to_delete,
                          PlacesRustError *_Nonnull out_err);
 
+uint8_t bookmarks_undo(PlacesConnectionHandle handle,
+                       PlacesRustError *_Nonnull out_err);
+
 void bookmarks_reset(PlacesAPIHandle handle,
                      PlacesRustError *_Nonnull out_err);
 
@@ -203,6 +318,8 @@ void places_destroy_string(char const *_Nonnull s);
 
 void places_interrupt_handle_destroy(RawPlacesInterruptHandle *_Nonnull handle);
 
+void places_autocomplete_interrupt_handle_destroy(RawPlacesAutocompleteInterruptHandle *_Nonnull handle);
+
 void places_connection_destroy(PlacesConnectionHandle conn,
                                PlacesRustError *_Nonnull out_err);
 
@@ -48,3 +48,15 @@ BEGIN
         syncChangeCounter = syncChangeCounter + 1
     WHERE fk = OLD.place_id;
 END;
+
+-- Renaming a tag changes the tags for all URLs with that tag, so we bump the
+-- change counter for their bookmarks, too.
+CREATE TEMP TRIGGER moz_tags_afterupdate_sync_trigger
+AFTER UPDATE OF tag ON moz_tags
+FOR EACH ROW WHEN OLD.tag <> NEW.tag
+BEGIN
+    UPDATE moz_bookmarks SET
+        syncChangeCounter = syncChangeCounter + 1
+    WHERE fk IN (SELECT place_id FROM moz_tags_relation
+                 WHERE tag_id = NEW.id);
+END;
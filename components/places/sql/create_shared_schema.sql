@@ -66,6 +66,7 @@ CREATE TABLE IF NOT EXISTS moz_historyvisits (
     place_id INTEGER NOT NULL,
     visit_date INTEGER NOT NULL,
     visit_type INTEGER NOT NULL,
+    is_error INTEGER NOT NULL DEFAULT 0, -- XXX - not in desktop - set for visits to pages that failed to load.
     -- session INTEGER, -- XXX - what is 'session'? Appears unused.
 
     FOREIGN KEY(place_id) REFERENCES moz_places(id) ON DELETE CASCADE,
@@ -227,11 +228,93 @@ CREATE TABLE IF NOT EXISTS moz_bookmarks_synced_tag_relation(
 -- these with custom search engines eventually (bug 648398); however, we
 -- must still round-trip keywords imported via Sync or migrated from Fennec.
 -- Since none of the `moz_bookmarks_synced_*` tables are durable, we store
--- keywords for URLs in a separate table. Unlike Desktop, we don't support
--- custom POST data, since we don't sync it (bug 1345417), and Fennec
--- doesn't write it.
+-- keywords for URLs in a separate table. Like Desktop, we store custom POST
+-- data for keywords set locally, but we don't sync it (bug 1345417), and
+-- Fennec doesn't write it.
 CREATE TABLE IF NOT EXISTS moz_keywords(
     place_id INTEGER PRIMARY KEY REFERENCES moz_places(id)
                      ON DELETE RESTRICT,
-    keyword TEXT NOT NULL UNIQUE
+    keyword TEXT NOT NULL UNIQUE,
+    post_data TEXT
 );
+
+-- This table holds history metadata: how the user engaged with a page, and
+-- how they got there. Unlike visits, a row here is updated as the user keeps
+-- interacting with the page, and is keyed by the page, its referrer and the
+-- search term which led to it. None of this is synced.
+CREATE TABLE IF NOT EXISTS moz_places_metadata (
+    id INTEGER PRIMARY KEY,
+    place_id INTEGER NOT NULL REFERENCES moz_places(id)
+                              ON DELETE CASCADE,
+    referrer_place_id INTEGER REFERENCES moz_places(id)
+                              ON DELETE CASCADE,
+    search_query_id INTEGER REFERENCES moz_places_metadata_search_queries(id)
+                            ON DELETE CASCADE,
+    created_at INTEGER NOT NULL DEFAULT 0,
+    updated_at INTEGER NOT NULL DEFAULT 0,
+    -- In milliseconds.
+    total_view_time INTEGER NOT NULL DEFAULT 0,
+    document_type INTEGER NOT NULL DEFAULT 0 -- DocumentType::Regular
+);
+
+CREATE INDEX IF NOT EXISTS moz_places_metadata_placeindex ON moz_places_metadata(place_id);
+CREATE INDEX IF NOT EXISTS moz_places_metadata_updatedindex ON moz_places_metadata(updated_at);
+
+CREATE TABLE IF NOT EXISTS moz_places_metadata_search_queries (
+    id INTEGER PRIMARY KEY,
+    term TEXT NOT NULL UNIQUE
+);
+
+-- A full-text index over page URLs, titles and tags, used to find autocomplete
+-- candidates without scanning every row in `moz_places`. The `rowid` of each
+-- entry is the `id` of its page, and the index is kept up to date by
+-- triggers.
+CREATE VIRTUAL TABLE IF NOT EXISTS moz_places_fts USING fts5(
+    url,
+    title,
+    tags
+);
+
+-- Pages which the user removed from their top sites, and which shouldn't be
+-- suggested again. This stores URLs instead of referencing `moz_places`, so
+-- that pages stay blocked even if they're removed from history.
+CREATE TABLE IF NOT EXISTS moz_top_sites_blocklist (
+    url TEXT NOT NULL PRIMARY KEY,
+    url_hash INTEGER NOT NULL,
+    blocked_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS moz_top_sites_blocklist_url_hashindex ON moz_top_sites_blocklist(url_hash);
+
+-- Pages the user saved to read later. These aren't part of history, and are
+-- synced as their own collection.
+CREATE TABLE IF NOT EXISTS moz_reading_list (
+    id INTEGER PRIMARY KEY,
+    guid TEXT NOT NULL UNIQUE,
+    url TEXT NOT NULL UNIQUE,
+    title TEXT,
+    excerpt TEXT,
+    -- The device the page was saved on, if known.
+    source_device TEXT,
+    added_at INTEGER NOT NULL, -- In milliseconds.
+    read_at INTEGER, -- In milliseconds. NULL if the page hasn't been read.
+    sync_status TINYINT NOT NULL DEFAULT 1, -- 1 is SyncStatus::New
+    sync_change_counter INTEGER NOT NULL DEFAULT 1
+);
+
+CREATE TABLE IF NOT EXISTS moz_reading_list_tombstones (
+    guid TEXT PRIMARY KEY
+) WITHOUT ROWID;
+
+-- What the user searched for, for pages that are search result pages for
+-- one of the search engines passed to `PlacesApi::set_search_engines`. These
+-- are extracted from the URL when the page is visited, and aren't synced.
+CREATE TABLE IF NOT EXISTS moz_places_search_terms (
+    place_id INTEGER PRIMARY KEY NOT NULL REFERENCES moz_places(id)
+                                          ON DELETE CASCADE,
+    search_term TEXT NOT NULL,
+    -- The name of the search engine.
+    engine TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS moz_places_search_terms_termindex ON moz_places_search_terms(search_term);
@@ -338,3 +338,69 @@ BEGIN
         foreign_count = foreign_count - 1
     WHERE id = OLD.place_id;
 END;
+
+-- Search terms are shared between metadata entries, so we clean them up once
+-- the last entry which refers to them is gone.
+CREATE TEMP TRIGGER moz_places_metadata_afterdelete_trigger
+AFTER DELETE ON moz_places_metadata
+FOR EACH ROW WHEN OLD.search_query_id NOT NULL
+BEGIN
+    DELETE FROM moz_places_metadata_search_queries
+    WHERE id = OLD.search_query_id AND
+          NOT EXISTS(SELECT 1 FROM moz_places_metadata
+                     WHERE search_query_id = OLD.search_query_id);
+END;
+
+-- These triggers keep the full-text index used for autocomplete in sync with
+-- the URLs, titles and tags of pages. We use `INSERT OR REPLACE` because
+-- `REPLACE`ing a row in `moz_places` doesn't fire the delete trigger.
+CREATE TEMP TRIGGER moz_places_afterinsert_trigger_fts
+AFTER INSERT ON moz_places
+FOR EACH ROW
+BEGIN
+    INSERT OR REPLACE INTO moz_places_fts(rowid, url, title, tags)
+    VALUES(NEW.id, NEW.url, NEW.title, NULL);
+END;
+
+CREATE TEMP TRIGGER moz_places_afterupdate_trigger_fts
+AFTER UPDATE OF url, title ON moz_places
+FOR EACH ROW WHEN OLD.url <> NEW.url OR OLD.title IS NOT NEW.title
+BEGIN
+    UPDATE moz_places_fts SET
+        url = NEW.url,
+        title = NEW.title
+    WHERE rowid = NEW.id;
+END;
+
+CREATE TEMP TRIGGER moz_places_afterdelete_trigger_fts
+AFTER DELETE ON moz_places
+FOR EACH ROW
+BEGIN
+    DELETE FROM moz_places_fts WHERE rowid = OLD.id;
+END;
+
+CREATE TEMP TRIGGER moz_tags_relations_afterinsert_trigger_fts
+AFTER INSERT ON moz_tags_relation
+BEGIN
+    {update_new_place_fts_tags};
+END;
+
+CREATE TEMP TRIGGER moz_tags_relations_afterupdate_trigger_fts
+AFTER UPDATE ON moz_tags_relation
+BEGIN
+    {update_old_place_fts_tags};
+    {update_new_place_fts_tags};
+END;
+
+CREATE TEMP TRIGGER moz_tags_relations_afterdelete_trigger_fts
+AFTER DELETE ON moz_tags_relation
+BEGIN
+    {update_old_place_fts_tags};
+END;
+
+CREATE TEMP TRIGGER moz_tags_afterupdate_trigger_fts
+AFTER UPDATE OF tag ON moz_tags
+FOR EACH ROW WHEN OLD.tag <> NEW.tag
+BEGIN
+    {update_renamed_tag_fts_tags};
+END;
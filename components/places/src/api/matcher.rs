@@ -3,12 +3,16 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use crate::db::PlacesDb;
-use crate::error::Result;
+use crate::error::{ErrorKind, Result};
 pub use crate::match_impl::{MatchBehavior, SearchBehavior};
 use crate::msg_types::{SearchResultMessage, SearchResultReason};
+use crate::search_engines::SearchTerm;
+use crate::storage::search_terms::search_term_from_row;
+use interrupt_support::{Interrupted, Interruptee};
 use rusqlite::{types::ToSql, Row};
 use serde_derive::*;
-use sql_support::{maybe_log_plan, ConnExt};
+use sql_support::{maybe_log_plan, ConnExt, SqlInterruptHandle, SqlInterruptScope};
+use std::sync::Mutex;
 use url::Url;
 
 // A helper to log, cache and execute a query, returning a vector of flattened rows.
@@ -16,6 +20,7 @@ fn query_flat_rows_and_then_named<T, F>(
     conn: &PlacesDb,
     sql: &str,
     params: &[(&str, &dyn ToSql)],
+    scope: &SqlInterruptScope,
     mapper: F,
 ) -> Result<Vec<T>>
 where
@@ -24,33 +29,152 @@ where
     maybe_log_plan(conn, sql, params);
     let mut stmt = conn.prepare_maybe_cached(sql, true)?;
     let iter = stmt.query_and_then_named(params, mapper)?;
-    Ok(iter
-        .inspect(|r| {
-            if let Err(ref e) = *r {
+    let mut results = Vec::new();
+    for result in iter {
+        // Check before looking at the row, since an interrupted query fails
+        // with an error that we don't want to report as a failed search.
+        scope.err_if_interrupted()?;
+        match result {
+            Ok(result) => results.push(result),
+            Err(e) => {
                 log::warn!("Failed to perform a search: {}", e);
                 if cfg!(debug_assertions) {
                     panic!("Failed to perform a search: {}", e);
                 }
             }
-        })
-        .flatten()
-        .collect::<Vec<_>>())
+        }
+    }
+    Ok(results)
 }
 
 #[derive(Debug, Clone)]
 pub struct SearchParams {
     pub search_string: String,
     pub limit: u32,
+    pub mode: SearchMode,
+}
+
+/// How `search_frecent` finds history and bookmark suggestions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchMode {
+    /// Match every page against the query with `AUTOCOMPLETE_MATCH`. This
+    /// can find matches anywhere in a word, like desktop, but needs to look
+    /// at every page in the database.
+    Match = 1,
+    /// Use the full-text index to find candidate pages, then rank them by
+    /// frecency. This is much faster for large histories, but only matches
+    /// words in the URL, title and tags that start with a word in the query.
+    FullText = 2,
+}
+
+impl SearchMode {
+    pub fn from_primitive(p: u8) -> Option<Self> {
+        match p {
+            1 => Some(SearchMode::Match),
+            2 => Some(SearchMode::FullText),
+            _ => None,
+        }
+    }
+}
+
+/// Cancels a single autocomplete search.
+///
+/// Unlike an `SqlInterruptHandle`, which interrupts whatever its connection
+/// happens to be doing, this only interrupts the search it's passed to. This
+/// means that a consumer can cancel a stale search when the user types
+/// another character, without racing with the search that replaces it.
+/// Interrupting a search that hasn't started yet makes it fail as soon as it
+/// starts.
+#[derive(Default)]
+pub struct SearchInterruptHandle {
+    state: Mutex<SearchInterruptState>,
+}
+
+#[derive(Default)]
+struct SearchInterruptState {
+    interrupted: bool,
+    // The interrupt handle for the connection running the search, if the
+    // search is in progress.
+    running: Option<SqlInterruptHandle>,
+}
+
+impl SearchInterruptHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn interrupt(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.interrupted = true;
+        if let Some(handle) = &state.running {
+            handle.interrupt();
+        }
+    }
+
+    fn begin_search(&self, conn: &PlacesDb) -> Result<RunningSearch<'_>> {
+        let mut state = self.state.lock().unwrap();
+        if state.interrupted {
+            return Err(ErrorKind::InterruptedError(Interrupted).into());
+        }
+        // The scope must start before the handle is published, so that it
+        // sees an `interrupt` that happens before the first statement runs.
+        let scope = conn.begin_interrupt_scope();
+        state.running = Some(conn.new_interrupt_handle());
+        Ok(RunningSearch {
+            handle: self,
+            scope,
+        })
+    }
+}
+
+impl Interruptee for SearchInterruptHandle {
+    fn was_interrupted(&self) -> bool {
+        self.state.lock().unwrap().interrupted
+    }
+}
+
+// Detaches a `SearchInterruptHandle` from its connection once the search
+// finishes, so that interrupting it later can't affect anything else.
+struct RunningSearch<'a> {
+    handle: &'a SearchInterruptHandle,
+    // The scope that the search checks.
+    scope: SqlInterruptScope,
+}
+
+impl<'a> Drop for RunningSearch<'a> {
+    fn drop(&mut self) {
+        self.handle.state.lock().unwrap().running = None;
+    }
+}
+
+/// Like `search_frecent`, but the search can be canceled with `interrupt`,
+/// in which case an `InterruptedError` is returned instead of the matches
+/// found so far.
+pub fn search_frecent_interruptible(
+    conn: &PlacesDb,
+    params: SearchParams,
+    interrupt: &SearchInterruptHandle,
+) -> Result<Vec<SearchResult>> {
+    let running = interrupt.begin_search(conn)?;
+    search_frecent_in_scope(conn, params, &running.scope)
 }
 
 /// Synchronously queries all providers for autocomplete matches, then filters
-/// the matches. This isn't cancelable yet; once a search is started, it can't
-/// be interrupted, even if the user moves on (see
-/// https://github.com/mozilla/application-services/issues/265).
+/// the matches. The search can be interrupted using the connection's
+/// interrupt handle, or, for just this search, with
+/// `search_frecent_interruptible`.
 ///
 /// A provider can be anything that returns URL suggestions: Places history
 /// and bookmarks, synced tabs, search engine suggestions, and search keywords.
 pub fn search_frecent(conn: &PlacesDb, params: SearchParams) -> Result<Vec<SearchResult>> {
+    search_frecent_in_scope(conn, params, &conn.begin_interrupt_scope())
+}
+
+fn search_frecent_in_scope(
+    conn: &PlacesDb,
+    params: SearchParams,
+    scope: &SqlInterruptScope,
+) -> Result<Vec<SearchResult>> {
     // TODO: Tokenize the query.
 
     // Try to find the first heuristic result. Desktop tries extensions,
@@ -59,6 +183,17 @@ pub fn search_frecent(conn: &PlacesDb, params: SearchParams) -> Result<Vec<Searc
     // and a search if all else fails. We only try origins and URLs for
     // heuristic matches, since that's all we support.
 
+    let match_suggestions = Suggestions::with_behavior(
+        &params.search_string,
+        MatchBehavior::Anywhere,
+        SearchBehavior::default(),
+    );
+    let full_text_suggestions = FullTextSuggestions::new(&params.search_string);
+    let suggestions: &dyn Matcher = match params.mode {
+        SearchMode::Match => &match_suggestions,
+        SearchMode::FullText => &full_text_suggestions,
+    };
+
     let mut matches = match_with_limit(
         conn,
         &[
@@ -70,13 +205,10 @@ pub fn search_frecent(conn: &PlacesDb, params: SearchParams) -> Result<Vec<Searc
                 MatchBehavior::Anywhere,
                 SearchBehavior::default(),
             ),
-            &Suggestions::with_behavior(
-                &params.search_string,
-                MatchBehavior::Anywhere,
-                SearchBehavior::default(),
-            ),
+            suggestions,
         ],
         params.limit,
+        scope,
     )?;
 
     matches.sort_unstable_by(|a, b| a.url.cmp(&b.url));
@@ -89,7 +221,7 @@ pub fn match_url(conn: &PlacesDb, query: impl AsRef<str>) -> Result<Option<Strin
     let scope = conn.begin_interrupt_scope();
     let matcher = OriginOrUrl::new(query.as_ref());
     // Note: The matcher ignores the limit argument (it's a trait method)
-    let results = matcher.search(conn, 1)?;
+    let results = matcher.search(conn, 1, &scope)?;
     scope.err_if_interrupted()?;
     // Doing it like this lets us move the result, avoiding a copy (which almost
     // certainly doesn't matter but whatever)
@@ -104,19 +236,22 @@ fn match_with_limit(
     conn: &PlacesDb,
     matchers: &[&dyn Matcher],
     max_results: u32,
+    scope: &SqlInterruptScope,
 ) -> Result<Vec<SearchResult>> {
     let mut results = Vec::new();
     let mut rem_results = max_results;
-    let scope = conn.begin_interrupt_scope();
     for m in matchers {
         if rem_results == 0 {
             break;
         }
         scope.err_if_interrupted()?;
-        let matches = m.search(conn, rem_results)?;
+        let matches = m.search(conn, rem_results, scope)?;
         results.extend(matches);
         rem_results = rem_results.saturating_sub(results.len() as u32);
     }
+    // Don't return partial results if we were interrupted while running the
+    // last matcher.
+    scope.err_if_interrupted()?;
     Ok(results)
 }
 
@@ -208,6 +343,10 @@ pub struct SearchResult {
 
     /// A list of reasons why this matched.
     pub reasons: Vec<MatchReason>,
+
+    /// What the user searched for, if this is a search result page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search_term: Option<SearchTerm>,
 }
 
 impl SearchResult {
@@ -234,6 +373,7 @@ impl SearchResult {
             reasons.push(MatchReason::Bookmark);
         }
         let url = Url::parse(&url)?;
+        let search_term = search_term_from_row(row)?;
 
         Ok(Self {
             search_string,
@@ -242,6 +382,7 @@ impl SearchResult {
             icon_url: None,
             frecency,
             reasons,
+            search_term,
         })
     }
 
@@ -262,6 +403,7 @@ impl SearchResult {
         let url = Url::parse(&url)?;
 
         let frecency = row.get::<_, i64>("frecency")?;
+        let search_term = search_term_from_row(row)?;
 
         Ok(Self {
             search_string,
@@ -270,6 +412,7 @@ impl SearchResult {
             icon_url: None,
             frecency,
             reasons,
+            search_term,
         })
     }
 
@@ -288,6 +431,7 @@ impl SearchResult {
             icon_url: None,
             frecency,
             reasons: vec![MatchReason::Origin],
+            search_term: None,
         })
     }
 
@@ -321,6 +465,13 @@ impl SearchResult {
                 (url, stripped_url)
             }
         };
+        // The match is usually truncated to the next path segment, so it's
+        // only a search result page if we kept the whole URL.
+        let search_term = if url.as_str() == href {
+            search_term_from_row(row)?
+        } else {
+            None
+        };
 
         Ok(Self {
             search_string,
@@ -329,12 +480,17 @@ impl SearchResult {
             icon_url: None,
             frecency,
             reasons,
+            search_term,
         })
     }
 }
 
 impl From<SearchResult> for SearchResultMessage {
     fn from(res: SearchResult) -> Self {
+        let (search_term, search_engine) = match res.search_term {
+            Some(SearchTerm { term, engine }) => (Some(term), Some(engine)),
+            None => (None, None),
+        };
         Self {
             url: res.url.into_string(),
             title: res.title,
@@ -344,6 +500,8 @@ impl From<SearchResult> for SearchResultMessage {
                 .into_iter()
                 .map(|r| Into::<SearchResultReason>::into(r) as i32)
                 .collect::<Vec<i32>>(),
+            search_term,
+            search_engine,
         }
     }
 }
@@ -362,7 +520,12 @@ impl From<MatchReason> for SearchResultReason {
 }
 
 trait Matcher {
-    fn search(&self, conn: &PlacesDb, max_results: u32) -> Result<Vec<SearchResult>>;
+    fn search(
+        &self,
+        conn: &PlacesDb,
+        max_results: u32,
+        scope: &SqlInterruptScope,
+    ) -> Result<Vec<SearchResult>>;
 }
 
 struct OriginOrUrl<'query> {
@@ -381,9 +544,11 @@ const URL_SQL: &str = "
             h.frecency as frecency,
             h.foreign_count > 0 AS bookmarked,
             h.id as id,
+            t.search_term, t.engine AS search_engine,
             :searchString AS searchString
     FROM moz_places h
     JOIN moz_origins o ON o.id = h.origin_id
+    LEFT JOIN moz_places_search_terms t ON t.place_id = h.id
     WHERE o.rev_host = reverse_host(:host)
             AND MAX(h.frecency, 0) >= :frecencyThreshold
             AND h.hidden = 0
@@ -394,9 +559,11 @@ const URL_SQL: &str = "
             h.frecency as frecency,
             h.foreign_count > 0 AS bookmarked,
             h.id as id,
+            t.search_term, t.engine AS search_engine,
             :searchString AS searchString
     FROM moz_places h
     JOIN moz_origins o ON o.id = h.origin_id
+    LEFT JOIN moz_places_search_terms t ON t.place_id = h.id
     WHERE o.rev_host = reverse_host(:host) || 'www.'
             AND MAX(h.frecency, 0) >= :frecencyThreshold
             AND h.hidden = 0
@@ -436,7 +603,12 @@ const ORIGIN_SQL: &str = "
 ";
 
 impl<'query> Matcher for OriginOrUrl<'query> {
-    fn search(&self, conn: &PlacesDb, _: u32) -> Result<Vec<SearchResult>> {
+    fn search(
+        &self,
+        conn: &PlacesDb,
+        _: u32,
+        scope: &SqlInterruptScope,
+    ) -> Result<Vec<SearchResult>> {
         Ok(if looks_like_origin(self.query) {
             query_flat_rows_and_then_named(
                 conn,
@@ -446,6 +618,7 @@ impl<'query> Matcher for OriginOrUrl<'query> {
                     (":searchString", &self.query),
                     (":frecencyThreshold", &-1i64),
                 ],
+                scope,
                 SearchResult::from_origin_row,
             )?
         } else if self.query.contains(|c| c == '/' || c == ':' || c == '?') {
@@ -470,6 +643,7 @@ impl<'query> Matcher for OriginOrUrl<'query> {
                     (":remainder", &remainder),
                     (":frecencyThreshold", &-1i64),
                 ],
+                scope,
                 SearchResult::from_url_row,
             )?
         } else {
@@ -499,7 +673,12 @@ impl<'query> Adaptive<'query> {
 }
 
 impl<'query> Matcher for Adaptive<'query> {
-    fn search(&self, conn: &PlacesDb, max_results: u32) -> Result<Vec<SearchResult>> {
+    fn search(
+        &self,
+        conn: &PlacesDb,
+        max_results: u32,
+        scope: &SqlInterruptScope,
+    ) -> Result<Vec<SearchResult>> {
         Ok(query_flat_rows_and_then_named(
             conn,
             "
@@ -518,6 +697,7 @@ impl<'query> Matcher for Adaptive<'query> {
                    h.id as id,
                    NULL AS open_count,
                    h.frecency as frecency,
+                   t.search_term, t.engine AS search_engine,
                    :searchString AS searchString
             FROM (
               SELECT ROUND(MAX(use_count) * (1 + (input = :searchString)), 1) AS rank,
@@ -527,6 +707,7 @@ impl<'query> Matcher for Adaptive<'query> {
               GROUP BY place_id
             ) AS i
             JOIN moz_places h ON h.id = i.place_id
+            LEFT JOIN moz_places_search_terms t ON t.place_id = h.id
             WHERE AUTOCOMPLETE_MATCH(:searchString, h.url,
                                      IFNULL(btitle, h.title), tags,
                                      visit_count, h.typed, bookmarked,
@@ -539,6 +720,7 @@ impl<'query> Matcher for Adaptive<'query> {
                 (":searchBehavior", &self.search_behavior),
                 (":maxResults", &max_results),
             ],
+            scope,
             SearchResult::from_adaptive_row,
         )?)
     }
@@ -565,7 +747,12 @@ impl<'query> Suggestions<'query> {
 }
 
 impl<'query> Matcher for Suggestions<'query> {
-    fn search(&self, conn: &PlacesDb, max_results: u32) -> Result<Vec<SearchResult>> {
+    fn search(
+        &self,
+        conn: &PlacesDb,
+        max_results: u32,
+        scope: &SqlInterruptScope,
+    ) -> Result<Vec<SearchResult>> {
         Ok(query_flat_rows_and_then_named(
             conn,
             "
@@ -581,8 +768,11 @@ impl<'query> Matcher for Suggestions<'query> {
                    h.visit_count_local + h.visit_count_remote AS visit_count,
                    h.typed as typed,
                    h.id as id,
-                   NULL AS open_count, h.frecency, :searchString AS searchString
+                   NULL AS open_count, h.frecency,
+                   t.search_term, t.engine AS search_engine,
+                   :searchString AS searchString
             FROM moz_places h
+            LEFT JOIN moz_places_search_terms t ON t.place_id = h.id
             WHERE h.frecency > 0
               AND AUTOCOMPLETE_MATCH(:searchString, h.url,
                                      IFNULL(btitle, h.title), tags,
@@ -598,6 +788,76 @@ impl<'query> Matcher for Suggestions<'query> {
                 (":searchBehavior", &self.search_behavior),
                 (":maxResults", &max_results),
             ],
+            scope,
+            SearchResult::from_suggestion_row,
+        )?)
+    }
+}
+
+struct FullTextSuggestions<'query> {
+    query: &'query str,
+}
+
+impl<'query> FullTextSuggestions<'query> {
+    pub fn new(query: &'query str) -> FullTextSuggestions<'query> {
+        FullTextSuggestions { query }
+    }
+}
+
+/// Converts a search string into an FTS5 query that matches pages
+/// containing a word that starts with each word in the string. Each word is
+/// quoted, so that punctuation and FTS5 operators in the search string are
+/// matched literally. Returns `None` if there's nothing to search for.
+fn fts_query(search_string: &str) -> Option<String> {
+    let words = search_string
+        .split_whitespace()
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect::<Vec<_>>();
+    if words.is_empty() {
+        None
+    } else {
+        Some(words.join(" "))
+    }
+}
+
+impl<'query> Matcher for FullTextSuggestions<'query> {
+    fn search(
+        &self,
+        conn: &PlacesDb,
+        max_results: u32,
+        scope: &SqlInterruptScope,
+    ) -> Result<Vec<SearchResult>> {
+        let fts_query = match fts_query(self.query) {
+            Some(fts_query) => fts_query,
+            None => return Ok(vec![]),
+        };
+        Ok(query_flat_rows_and_then_named(
+            conn,
+            "
+            SELECT h.url, h.title,
+                   (SELECT title FROM moz_bookmarks
+                    WHERE fk = h.id AND
+                          title NOT NULL
+                    ORDER BY lastModified DESC
+                    LIMIT 1) AS btitle,
+                   f.tags AS tags,
+                   h.frecency,
+                   t.search_term, t.engine AS search_engine,
+                   :searchString AS searchString
+            FROM moz_places_fts f
+            JOIN moz_places h ON h.id = f.rowid
+            LEFT JOIN moz_places_search_terms t ON t.place_id = h.id
+            WHERE moz_places_fts MATCH :ftsQuery
+              AND h.frecency > 0
+              AND (+h.visit_count_local > 0 OR +h.visit_count_remote > 0)
+            ORDER BY h.frecency DESC, h.id DESC
+            LIMIT :maxResults",
+            &[
+                (":searchString", &self.query),
+                (":ftsQuery", &fts_query),
+                (":maxResults", &max_results),
+            ],
+            scope,
             SearchResult::from_suggestion_row,
         )?)
     }
@@ -610,6 +870,7 @@ mod tests {
     use crate::observation::VisitObservation;
     use crate::storage::history::apply_observation;
     use crate::types::{Timestamp, VisitTransition};
+    use sync_guid::Guid as SyncGuid;
 
     #[test]
     fn split() {
@@ -655,6 +916,7 @@ mod tests {
             SearchParams {
                 search_string: "example.com".into(),
                 limit: 10,
+                mode: SearchMode::Match,
             },
         )
         .expect("Should search by origin");
@@ -670,6 +932,7 @@ mod tests {
             SearchParams {
                 search_string: "http://example.com".into(),
                 limit: 10,
+                mode: SearchMode::Match,
             },
         )
         .expect("Should search by URL without path");
@@ -684,6 +947,7 @@ mod tests {
             SearchParams {
                 search_string: "http://example.com/1".into(),
                 limit: 10,
+                mode: SearchMode::Match,
             },
         )
         .expect("Should search by URL with path");
@@ -700,6 +964,7 @@ mod tests {
             SearchParams {
                 search_string: "ample".into(),
                 limit: 10,
+                mode: SearchMode::Match,
             },
         )
         .expect("Should search by adaptive input history");
@@ -714,6 +979,7 @@ mod tests {
             SearchParams {
                 search_string: "example".into(),
                 limit: 1,
+                mode: SearchMode::Match,
             },
         )
         .expect("Should search until reaching limit");
@@ -726,6 +992,7 @@ mod tests {
                 icon_url: None,
                 frecency: 1999,
                 reasons: vec![MatchReason::Origin],
+                search_term: None,
             }]
         );
     }
@@ -746,6 +1013,7 @@ mod tests {
             SearchParams {
                 search_string: "http://exämple.com".into(),
                 limit: 10,
+                mode: SearchMode::Match,
             },
         )
         .expect("Should search by URL without path");
@@ -761,6 +1029,7 @@ mod tests {
             SearchParams {
                 search_string: "http://exämple.com/1".into(),
                 limit: 10,
+                mode: SearchMode::Match,
             },
         )
         .expect("Should search by URL with path");
@@ -785,10 +1054,192 @@ mod tests {
             SearchParams {
                 search_string: ball_of_yarn_about_blank.into(),
                 limit: 10,
+                mode: SearchMode::Match,
             },
         )
         .unwrap();
     }
+
+    #[test]
+    fn test_fts_query() {
+        assert_eq!(fts_query(""), None);
+        assert_eq!(fts_query("  "), None);
+        assert_eq!(fts_query("moz"), Some("\"moz\"*".into()));
+        assert_eq!(
+            fts_query(" mozilla  org "),
+            Some("\"mozilla\"* \"org\"*".into())
+        );
+        assert_eq!(
+            fts_query("a\"b OR NEAR(c)"),
+            Some("\"a\"\"b\"* \"OR\"* \"NEAR(c)\"*".into())
+        );
+    }
+
+    fn full_text_search(conn: &PlacesDb, search_string: &str) -> Vec<SearchResult> {
+        search_frecent(
+            conn,
+            SearchParams {
+                search_string: search_string.into(),
+                limit: 10,
+                mode: SearchMode::FullText,
+            },
+        )
+        .expect("Should search using full-text index")
+    }
+
+    #[test]
+    fn search_full_text() {
+        use crate::storage::{history::delete_visits_for, tags};
+
+        let conn = new_mem_connection();
+
+        let url = Url::parse("https://www.mozilla.org/firefox/new").unwrap();
+        let visit = VisitObservation::new(url.clone())
+            .with_title("Download Firefox".to_string())
+            .with_visit_type(VisitTransition::Link)
+            .with_at(Timestamp::now());
+        apply_observation(&conn, visit).expect("Should apply visit");
+
+        let suggestion = |results: &[SearchResult]| {
+            results
+                .iter()
+                .find(|result| result.url == url)
+                .map(|result| (result.title.clone(), result.reasons.clone()))
+        };
+
+        // Words in the title and URL should match, as should prefixes of
+        // those words, but not words in the middle of them. Punctuation
+        // isn't indexed, so it shouldn't be treated as an FTS5 operator.
+        for query in &[
+            "firefox",
+            "down",
+            "mozilla",
+            "MOZILLA.org new",
+            "fire dow",
+            "\"firefox",
+        ] {
+            assert_eq!(
+                suggestion(&full_text_search(&conn, query)),
+                Some(("Download Firefox".into(), vec![MatchReason::Bookmark])),
+                "Should match {}",
+                query
+            );
+        }
+        for query in &["", "irefox", "chrome", "firefox chrome", "moz.org"] {
+            assert_eq!(
+                suggestion(&full_text_search(&conn, query)),
+                None,
+                "Shouldn't match {}",
+                query
+            );
+        }
+
+        // Title changes should be reflected in the index.
+        let visit = VisitObservation::new(url.clone())
+            .with_title("Get the browser".to_string())
+            .with_visit_type(VisitTransition::Link)
+            .with_at(Timestamp::now());
+        apply_observation(&conn, visit).expect("Should apply visit");
+        assert_eq!(suggestion(&full_text_search(&conn, "download")), None);
+        assert!(suggestion(&full_text_search(&conn, "browser")).is_some());
+
+        // So should tags, including when they're removed.
+        tags::tag_url(&conn, &url, "foxy").expect("Should tag URL");
+        assert_eq!(
+            suggestion(&full_text_search(&conn, "foxy")),
+            Some((
+                "Get the browser".into(),
+                vec![MatchReason::Bookmark, MatchReason::Tags("foxy".into())]
+            ))
+        );
+        tags::untag_url(&conn, &url, "foxy").expect("Should untag URL");
+        assert_eq!(suggestion(&full_text_search(&conn, "foxy")), None);
+
+        // And removed pages.
+        let guid = conn
+            .query_row_and_then_named(
+                "SELECT guid FROM moz_places WHERE url_hash = hash(:url) AND url = :url",
+                &[(":url", &url.as_str())],
+                |row| row.get::<_, SyncGuid>(0),
+                false,
+            )
+            .expect("Should fetch page GUID");
+        delete_visits_for(&conn, &guid).expect("Should delete page");
+        assert_eq!(suggestion(&full_text_search(&conn, "browser")), None);
+        let count: u32 = conn
+            .query_one("SELECT COUNT(*) FROM moz_places_fts")
+            .expect("Should count index entries");
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn search_interruptible() {
+        let conn = new_mem_connection();
+
+        let url = Url::parse("http://example.com/123").unwrap();
+        let visit = VisitObservation::new(url)
+            .with_title("Example page 123".to_string())
+            .with_visit_type(VisitTransition::Typed)
+            .with_at(Timestamp::now());
+        apply_observation(&conn, visit).expect("Should apply visit");
+
+        let params = SearchParams {
+            search_string: "example".into(),
+            limit: 10,
+            mode: SearchMode::Match,
+        };
+
+        // A search that finishes without being interrupted should return
+        // matches, and interrupting it afterward shouldn't affect anything
+        // else running on the connection.
+        let finished = SearchInterruptHandle::new();
+        let results = search_frecent_interruptible(&conn, params.clone(), &finished)
+            .expect("Should search without interruption");
+        assert!(!results.is_empty());
+        let scope = conn.begin_interrupt_scope();
+        finished.interrupt();
+        assert!(!scope.was_interrupted());
+
+        // Interrupting a search before it starts should fail it.
+        let early = SearchInterruptHandle::new();
+        early.interrupt();
+        match search_frecent_interruptible(&conn, params.clone(), &early)
+            .expect_err("Should fail interrupted search")
+            .kind()
+        {
+            ErrorKind::InterruptedError(_) => {}
+            kind => panic!("Unexpected error: {:?}", kind),
+        }
+
+        // Interrupting a running search should interrupt its connection.
+        let running = SearchInterruptHandle::new();
+        {
+            let _running = running.begin_search(&conn).expect("Should begin search");
+            let scope = conn.begin_interrupt_scope();
+            running.interrupt();
+            assert!(scope.was_interrupted());
+        }
+
+        // Interrupting a search after it starts, but before it runs its first
+        // statement, should fail it.
+        let starting = SearchInterruptHandle::new();
+        {
+            let running = starting.begin_search(&conn).expect("Should begin search");
+            starting.interrupt();
+            match search_frecent_in_scope(&conn, params.clone(), &running.scope)
+                .expect_err("Should fail search interrupted before it ran")
+                .kind()
+            {
+                ErrorKind::InterruptedError(_) => {}
+                kind => panic!("Unexpected error: {:?}", kind),
+            }
+        }
+
+        // And other searches should be unaffected.
+        search_frecent_interruptible(&conn, params, &SearchInterruptHandle::new())
+            .expect("Should search with a new interrupt handle");
+    }
+
     // This panics in tests but not for "real" consumers. In an effort to ensure
     // we are panicing where we think we are, note the 'expected' string.
     // (Not really clear this test offers much value, but seems worth having...)
@@ -813,6 +1264,7 @@ mod tests {
             SearchParams {
                 search_string: "not-a-url".into(),
                 limit: 10,
+                mode: SearchMode::Match,
             },
         );
     }
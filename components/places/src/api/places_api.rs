@@ -3,22 +3,30 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use crate::bookmark_sync::store::BookmarksStore;
+use crate::bookmark_sync::validation::{self, ValidationProblems};
 use crate::db::db::PlacesDb;
+use crate::db::reader_pool::ReaderPool;
+use crate::db::recovery;
+use crate::db::{PooledConnection, DEFAULT_MAX_READERS};
 use crate::error::*;
+use crate::frecency::FrecencySettings;
 use crate::history_sync::store::HistoryStore;
+use crate::history_sync::HistorySyncConfig;
+use crate::observer::{ObserverHandle, PlacesObserver, PlacesObservers};
+use crate::reading_list_sync::{self, store::ReadingListStore};
+use crate::search_engines::SearchEngines;
 use crate::storage::{self, delete_meta, get_meta, put_meta};
 use crate::util::normalize_path;
 use lazy_static::lazy_static;
 use rusqlite::OpenFlags;
-use sql_support::SqlInterruptHandle;
+use sql_support::{RecoveryReport, SqlInterruptHandle};
 use std::cell::Cell;
 use std::collections::HashMap;
-use std::fs;
 use std::mem;
 use std::path::{Path, PathBuf};
 use std::sync::{
     atomic::{AtomicBool, AtomicUsize, Ordering},
-    Arc, Mutex, Weak,
+    Arc, Mutex, RwLock, Weak,
 };
 use sync15::{sync_multiple, telemetry, MemoryCachedState, SyncResult};
 
@@ -61,6 +69,16 @@ impl ConnectionType {
     }
 }
 
+/// Returns true if opening a database failed because it's corrupt, or
+/// because we couldn't upgrade it, so we should try to recover it.
+fn is_recoverable(err: &Error) -> bool {
+    match err.kind() {
+        ErrorKind::DatabaseUpgradeError => true,
+        ErrorKind::SqlError(e) => sql_support::is_corruption_error(e),
+        _ => false,
+    }
+}
+
 // We only allow a single PlacesApi per filename.
 lazy_static! {
     static ref APIS: Mutex<HashMap<PathBuf, Weak<PlacesApi>>> = Mutex::new(HashMap::new());
@@ -81,14 +99,36 @@ pub struct PlacesApi {
     write_connection: Mutex<Option<PlacesDb>>,
     sync_state: Mutex<Option<SyncState>>,
     coop_tx_lock: Arc<Mutex<()>>,
+    observers: Arc<PlacesObservers>,
+    frecency_settings: Arc<RwLock<FrecencySettings>>,
+    search_engines: Arc<RwLock<SearchEngines>>,
     sync_conn_active: AtomicBool,
+    recovery_report: Mutex<Option<RecoveryReport>>,
+    reader_pool: Arc<ReaderPool>,
     id: usize,
 }
 impl PlacesApi {
     /// Create a new, or fetch an already open, PlacesApi backed by a file on disk.
     pub fn new(db_name: impl AsRef<Path>) -> Result<Arc<Self>> {
         let db_name = normalize_path(db_name)?;
-        Self::new_or_existing(db_name)
+        Self::new_or_existing(db_name, None)
+    }
+
+    /// Like `new`, but also changes the settings used to calculate
+    /// frecencies. The settings are persisted, so they'll also be used the
+    /// next time the database is opened with `new`. If they're different
+    /// from the current settings, all frecencies are marked as stale, and
+    /// recalculated in the background.
+    ///
+    /// If the API is already open, its write connection is used to change
+    /// the settings, and this fails with `ConnectionAlreadyOpen` if that
+    /// connection has been handed out.
+    pub fn new_with_frecency_settings(
+        db_name: impl AsRef<Path>,
+        frecency_settings: FrecencySettings,
+    ) -> Result<Arc<Self>> {
+        let db_name = normalize_path(db_name)?;
+        Self::new_or_existing(db_name, Some(frecency_settings))
     }
 
     /// Create a new, or fetch an already open, memory-based PlacesApi. You must
@@ -96,58 +136,117 @@ impl PlacesApi {
     ///  reader connections to the same memory DB open.
     pub fn new_memory(db_name: &str) -> Result<Arc<Self>> {
         let name = PathBuf::from(format!("file:{}?mode=memory&cache=shared", db_name));
-        Self::new_or_existing(name)
+        Self::new_or_existing(name, None)
     }
     fn new_or_existing_into(
         target: &mut HashMap<PathBuf, Weak<PlacesApi>>,
         db_name: PathBuf,
-        delete_on_fail: bool,
+        frecency_settings: Option<FrecencySettings>,
+        recover_on_fail: bool,
     ) -> Result<Arc<Self>> {
         let id = ID_COUNTER.fetch_add(1, Ordering::SeqCst);
         match target.get(&db_name).and_then(Weak::upgrade) {
-            Some(existing) => Ok(existing),
+            Some(existing) => {
+                if let Some(settings) = frecency_settings {
+                    let guard = existing.write_connection.lock().unwrap();
+                    match &*guard {
+                        Some(connection) => {
+                            storage::set_frecency_settings(connection, &settings)?;
+                        }
+                        None => return Err(ErrorKind::ConnectionAlreadyOpen.into()),
+                    }
+                }
+                Ok(existing)
+            }
             None => {
                 // We always create a new read-write connection for an initial open so
                 // we can create the schema and/or do version upgrades.
                 let coop_tx_lock = Arc::new(Mutex::new(()));
-                match PlacesDb::open(
+                let observers = Arc::new(PlacesObservers::default());
+                let shared_frecency_settings = Arc::new(RwLock::new(FrecencySettings::default()));
+                let search_engines = Arc::new(RwLock::new(SearchEngines::default()));
+                let opened = PlacesDb::open(
                     &db_name,
                     ConnectionType::ReadWrite,
                     id,
                     coop_tx_lock.clone(),
-                ) {
+                    observers.clone(),
+                    shared_frecency_settings.clone(),
+                    search_engines.clone(),
+                )
+                .and_then(|connection| {
+                    // Load the persisted settings, or replace them with the
+                    // ones we were given.
+                    match &frecency_settings {
+                        Some(settings) => {
+                            storage::set_frecency_settings(&connection, settings)?;
+                        }
+                        None => storage::load_frecency_settings(&connection)?,
+                    }
+                    Ok(connection)
+                });
+                match opened {
                     Ok(connection) => {
                         let new = PlacesApi {
                             db_name: db_name.clone(),
                             write_connection: Mutex::new(Some(connection)),
                             sync_state: Mutex::new(None),
                             sync_conn_active: AtomicBool::new(false),
+                            recovery_report: Mutex::new(None),
+                            reader_pool: Arc::new(ReaderPool::new(DEFAULT_MAX_READERS)),
                             id,
                             coop_tx_lock,
+                            observers,
+                            frecency_settings: shared_frecency_settings,
+                            search_engines,
                         };
                         let arc = Arc::new(new);
                         target.insert(db_name, Arc::downgrade(&arc));
                         Ok(arc)
                     }
                     Err(e) => {
-                        if !delete_on_fail {
+                        if !recover_on_fail || !is_recoverable(&e) || !db_name.exists() {
                             return Err(e);
                         }
-                        if let ErrorKind::DatabaseUpgradeError = e.kind() {
-                            fs::remove_file(&db_name)?;
-                            Self::new_or_existing_into(target, db_name, false)
-                        } else {
-                            Err(e)
-                        }
+                        log::error!("Failed to open places database, recovering: {}", e);
+                        // Move the broken database aside instead of deleting
+                        // it, start over with an empty one, and copy in
+                        // whatever we can still read.
+                        let corrupt_path = sql_support::move_aside(&db_name)?;
+                        let api =
+                            Self::new_or_existing_into(target, db_name, frecency_settings, false)?;
+                        let report = {
+                            let guard = api.write_connection.lock().unwrap();
+                            let connection = guard.as_ref().unwrap();
+                            recovery::salvage(connection, &corrupt_path)
+                        };
+                        let report = report.unwrap_or_else(|e| {
+                            log::error!("Failed to salvage the corrupt database: {}", e);
+                            RecoveryReport::new(corrupt_path)
+                        });
+                        *api.recovery_report.lock().unwrap() = Some(report);
+                        Ok(api)
                     }
                 }
             }
         }
     }
 
-    fn new_or_existing(db_name: PathBuf) -> Result<Arc<Self>> {
+    fn new_or_existing(
+        db_name: PathBuf,
+        frecency_settings: Option<FrecencySettings>,
+    ) -> Result<Arc<Self>> {
         let mut guard = APIS.lock().unwrap();
-        Self::new_or_existing_into(&mut guard, db_name, true)
+        Self::new_or_existing_into(&mut guard, db_name, frecency_settings, true)
+    }
+
+    /// Returns a report describing what was recovered, if the database was
+    /// corrupt, or couldn't be upgraded, when this API was opened. The
+    /// broken database is moved aside, rather than deleted, and whatever
+    /// history and bookmarks could be read from it are copied into a fresh
+    /// database.
+    pub fn recovery_report(&self) -> Option<RecoveryReport> {
+        self.recovery_report.lock().unwrap().clone()
     }
 
     /// Open a connection to the database.
@@ -160,6 +259,9 @@ impl PlacesApi {
                     ConnectionType::ReadOnly,
                     self.id,
                     self.coop_tx_lock.clone(),
+                    self.observers.clone(),
+                    self.frecency_settings.clone(),
+                    self.search_engines.clone(),
                 )
             }
             ConnectionType::ReadWrite => {
@@ -176,6 +278,40 @@ impl PlacesApi {
         }
     }
 
+    /// Checks out a read-only connection from this API's pool, opening a new
+    /// one if none are idle. The connection goes back into the pool when it's
+    /// dropped, so that its prepared statements can be reused by the next
+    /// reader. If `max_readers` connections are already checked out, this
+    /// blocks until one is returned.
+    ///
+    /// Pooled connections are closed, instead of reused, if the schema has
+    /// changed since they were opened.
+    pub fn checkout_reader(&self) -> Result<PooledConnection> {
+        self.reader_pool
+            .checkout(|| self.open_connection(ConnectionType::ReadOnly))
+    }
+
+    /// Like `checkout_reader`, but returns `None` instead of blocking if
+    /// `max_readers` connections are already checked out. This is useful for
+    /// callers that hold on to their connection, and would rather open one
+    /// outside the pool than wait.
+    pub fn try_checkout_reader(&self) -> Result<Option<PooledConnection>> {
+        self.reader_pool
+            .try_checkout(|| self.open_connection(ConnectionType::ReadOnly))
+    }
+
+    /// Changes the maximum number of read-only connections in the pool. The
+    /// default is `DEFAULT_MAX_READERS`.
+    pub fn set_max_readers(&self, max_readers: usize) {
+        self.reader_pool.set_max_readers(max_readers);
+    }
+
+    /// Closes all idle pooled connections, and makes sure that the ones
+    /// currently checked out are closed when they're returned.
+    pub fn clear_reader_pool(&self) {
+        self.reader_pool.clear();
+    }
+
     pub fn open_sync_connection(&self) -> Result<SyncConn<'_>> {
         let prev_value = self
             .sync_conn_active
@@ -188,6 +324,9 @@ impl PlacesApi {
                 ConnectionType::Sync,
                 self.id,
                 self.coop_tx_lock.clone(),
+                self.observers.clone(),
+                self.frecency_settings.clone(),
+                self.search_engines.clone(),
             )?;
             Ok(SyncConn {
                 db,
@@ -211,6 +350,27 @@ impl PlacesApi {
         Ok(())
     }
 
+    /// Register an observer to be told about changes made through any
+    /// connection opened by this API. The returned handle can be passed to
+    /// `remove_observer`.
+    pub fn add_observer(&self, observer: Arc<dyn PlacesObserver>) -> ObserverHandle {
+        self.observers.add(observer)
+    }
+
+    /// Unregister an observer. Returns false if it wasn't registered.
+    pub fn remove_observer(&self, handle: ObserverHandle) -> bool {
+        self.observers.remove(handle)
+    }
+
+    /// Sets the search engines used to recognize search result pages. When
+    /// a visit to one of these pages is observed, we store what the user
+    /// searched for, so that it can be shown instead of the URL. Engines
+    /// aren't persisted, so this should be called each time the API is
+    /// opened; until then, no search terms are extracted.
+    pub fn set_search_engines(&self, engines: SearchEngines) {
+        *self.search_engines.write().unwrap() = engines;
+    }
+
     fn get_disk_persisted_state(&self, conn: &PlacesDb) -> Result<Option<String>> {
         Ok(get_meta::<String>(&conn, GLOBAL_STATE_META_KEY)?)
     }
@@ -229,12 +389,13 @@ impl PlacesApi {
         &self,
         client_init: &sync15::Sync15StorageClientInit,
         key_bundle: &sync15::KeyBundle,
+        config: &HistorySyncConfig,
     ) -> Result<telemetry::SyncTelemetryPing> {
         self.do_sync_one(
             "history",
             move |conn, mem_cached_state, disk_cached_state| {
                 let interruptee = conn.begin_interrupt_scope();
-                let store = HistoryStore::new(&conn, &interruptee);
+                let store = HistoryStore::new(&conn, &interruptee, config.clone());
                 sync_multiple(
                     &[&store],
                     disk_cached_state,
@@ -271,6 +432,29 @@ impl PlacesApi {
         )
     }
 
+    pub fn sync_reading_list(
+        &self,
+        client_init: &sync15::Sync15StorageClientInit,
+        key_bundle: &sync15::KeyBundle,
+    ) -> Result<telemetry::SyncTelemetryPing> {
+        self.do_sync_one(
+            reading_list_sync::COLLECTION_NAME,
+            move |conn, mem_cached_state, disk_cached_state| {
+                let interruptee = conn.begin_interrupt_scope();
+                let store = ReadingListStore::new(conn, &interruptee);
+                sync_multiple(
+                    &[&store],
+                    disk_cached_state,
+                    mem_cached_state,
+                    client_init,
+                    key_bundle,
+                    &interruptee,
+                    None,
+                )
+            },
+        )
+    }
+
     pub fn do_sync_one<F>(
         &self,
         name: &'static str,
@@ -324,6 +508,7 @@ impl PlacesApi {
         &self,
         client_init: &sync15::Sync15StorageClientInit,
         key_bundle: &sync15::KeyBundle,
+        history_config: &HistorySyncConfig,
     ) -> Result<SyncResult> {
         let mut guard = self.sync_state.lock().unwrap();
         let conn = self.open_sync_connection()?;
@@ -341,7 +526,7 @@ impl PlacesApi {
 
         let interruptee = conn.begin_interrupt_scope();
         let bm_store = BookmarksStore::new(&conn, &interruptee);
-        let history_store = HistoryStore::new(&conn, &interruptee);
+        let history_store = HistoryStore::new(&conn, &interruptee, history_config.clone());
         let mut mem_cached_state = sync_state.mem_cached_state.take();
         let mut disk_cached_state = sync_state.disk_cached_state.take();
 
@@ -399,6 +584,41 @@ impl PlacesApi {
         Ok(())
     }
 
+    pub fn wipe_reading_list(&self) -> Result<()> {
+        // Take the lock to prevent syncing while we're doing this.
+        let _guard = self.sync_state.lock().unwrap();
+        let conn = self.open_sync_connection()?;
+        let scope = conn.begin_interrupt_scope();
+        let store = ReadingListStore::new(&conn, &scope);
+        store.do_wipe()
+    }
+
+    pub fn reset_reading_list(&self) -> Result<()> {
+        // Take the lock to prevent syncing while we're doing this.
+        let _guard = self.sync_state.lock().unwrap();
+        let conn = self.open_sync_connection()?;
+        let scope = conn.begin_interrupt_scope();
+        let store = ReadingListStore::new(&conn, &scope);
+        store.do_reset(&sync15::StoreSyncAssociation::Disconnected)
+    }
+
+    /// Checks the local bookmark tree and the synced mirror for problems,
+    /// and, if `repair` is true, fixes any problems with the local tree.
+    /// Returns the problems that were found, which can be reported with
+    /// `ValidationProblems::to_telemetry`.
+    pub fn validate_bookmarks(&self, repair: bool) -> Result<ValidationProblems> {
+        // Take the lock to prevent syncing while we're doing this, since
+        // syncing changes the mirror.
+        let _guard = self.sync_state.lock().unwrap();
+        let conn = self.open_sync_connection()?;
+        let scope = conn.begin_interrupt_scope();
+        if repair {
+            validation::repair(&conn, &scope)
+        } else {
+            validation::validate(&conn, &scope)
+        }
+    }
+
     pub fn wipe_history(&self) -> Result<()> {
         // Take the lock to prevent syncing while we're doing this.
         let _guard = self.sync_state.lock().unwrap();
@@ -426,7 +646,7 @@ impl PlacesApi {
         // We'd rather you didn't interrupt this, but it's a required arg for
         // HistoryStore
         let scope = conn.begin_interrupt_scope();
-        let store = HistoryStore::new(&conn, &scope);
+        let store = HistoryStore::new(&conn, &scope, HistorySyncConfig::default());
         store.do_reset(&sync15::StoreSyncAssociation::Disconnected)
     }
 
@@ -560,6 +780,29 @@ mod tests {
         assert_eq!(val, 999);
     }
 
+    #[test]
+    fn test_pooled_readers() -> Result<()> {
+        let api = new_mem_api();
+        api.set_max_readers(1);
+        let reader = api.checkout_reader()?;
+        let id = reader.api_id();
+        drop(reader);
+
+        let writer = api.open_connection(ConnectionType::ReadWrite)?;
+        writer.execute_batch(
+            "CREATE TABLE test_table (test_value INTEGER);
+             INSERT INTO test_table VALUES (999)",
+        )?;
+
+        // The pooled reader was opened before the table was created, so it
+        // should be replaced with one that can see it.
+        let reader = api.checkout_reader()?;
+        assert_eq!(reader.api_id(), id);
+        let val = reader.query_one::<i64>("SELECT test_value FROM test_table")?;
+        assert_eq!(val, 999);
+        Ok(())
+    }
+
     #[test]
     fn test_wrong_writer_close() {
         let api = new_mem_api();
@@ -609,6 +852,38 @@ mod tests {
         assert_ne!(id, api2.id);
         let conn = api2.open_connection(ConnectionType::ReadWrite)?;
         assert_ne!(1, conn.db.query_one::<i64>("PRAGMA user_version")?);
+
+        // The old database should be moved aside, and its bookmark roots
+        // salvaged.
+        let report = api2.recovery_report().expect("should recover");
+        assert_eq!(report.corrupt_path, dirname.path().join("temp.db.corrupt"));
+        assert!(report.corrupt_path.exists());
+        assert!(report.integrity_ok);
+        assert!(report.is_complete());
+        assert_eq!(report.recovered(), 5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_corrupt_db() -> Result<()> {
+        let dirname = tempfile::tempdir().unwrap();
+        let db_name = dirname.path().join("temp.db");
+        std::fs::write(&db_name, vec![b'x'; 4096])?;
+
+        let api = PlacesApi::new(&db_name)?;
+        let report = api.recovery_report().expect("should recover");
+        assert!(!report.integrity_ok);
+        assert_eq!(report.recovered(), 0);
+        assert_eq!(
+            std::fs::read(&report.corrupt_path)?,
+            vec![b'x'; 4096],
+            "Should keep the corrupt database"
+        );
+        let conn = api.open_connection(ConnectionType::ReadWrite)?;
+        assert_eq!(
+            conn.query_one::<i64>("SELECT COUNT(*) FROM moz_bookmarks")?,
+            5
+        );
         Ok(())
     }
 }
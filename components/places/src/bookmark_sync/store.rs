@@ -7,17 +7,20 @@ use super::record::{
     BookmarkItemRecord, BookmarkRecord, BookmarkRecordId, FolderRecord, QueryRecord,
     SeparatorRecord,
 };
+use super::validation;
 use super::{SyncedBookmarkKind, SyncedBookmarkValidity};
 use crate::api::places_api::ConnectionType;
 use crate::db::PlacesDb;
 use crate::error::*;
-use crate::frecency::{calculate_frecency, DEFAULT_FRECENCY_SETTINGS};
+use crate::observer::{PlacesEvent, SyncSummary, SyncedCollection};
 use crate::storage::{
     bookmarks::{
         bookmark_sync::{create_synced_bookmark_roots, reset, reset_meta},
         BookmarkRootGuid,
     },
-    delete_pending_temp_tables, get_meta, put_meta,
+    delete_pending_temp_tables, get_meta,
+    history::{update_stale_frecencies, STALE_FRECENCIES_BATCH_SIZE},
+    put_meta,
 };
 use crate::types::{BookmarkType, SyncStatus, Timestamp};
 use dogear::{
@@ -27,7 +30,7 @@ use dogear::{
 use rusqlite::{Row, NO_PARAMS};
 use sql_support::{self, ConnExt, SqlInterruptScope};
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::fmt;
 use std::result;
@@ -42,12 +45,6 @@ pub const LAST_SYNC_META_KEY: &str = "bookmarks_last_sync_time";
 pub const GLOBAL_SYNCID_META_KEY: &str = "bookmarks_global_sync_id";
 pub const COLLECTION_SYNCID_META_KEY: &str = "bookmarks_sync_id";
 
-/// The maximum number of URLs for which to recalculate frecencies at once.
-/// This is a trade-off between write efficiency and transaction time: higher
-/// maximums mean fewer write statements, but longer transactions, possibly
-/// blocking writes from other connections.
-const MAX_FRECENCIES_TO_RECALCULATE_PER_CHUNK: usize = 400;
-
 /// Adapts an interruptee to a Dogear abort signal.
 struct MergeInterruptee<'a, I>(&'a I);
 
@@ -453,19 +450,24 @@ impl<'a> BookmarksStore<'a> {
 
     fn apply_remote_items(&self, now: Timestamp) -> Result<()> {
         // Remove all keywords from old and new URLs, and remove new keywords
-        // from all existing URLs. The `NOT NULL` conditions are important; they
-        // ensure that SQLite uses our partial indexes on `itemsToApply`,
-        // instead of a table scan.
+        // from all existing URLs. Keywords that still point to the same URL
+        // are kept, since records don't carry their POST data. The `NOT NULL`
+        // conditions are important; they ensure that SQLite uses our partial
+        // indexes on `itemsToApply`, instead of a table scan.
         log::debug!("Removing old keywords");
         self.interruptee.err_if_interrupted()?;
         self.db.execute_batch(
             "DELETE FROM moz_keywords
-             WHERE place_id IN (SELECT oldPlaceId FROM itemsToApply
-                                WHERE oldPlaceId NOT NULL) OR
-                   place_id IN (SELECT newPlaceId FROM itemsToApply
-                                WHERE newPlaceId NOT NULL) OR
-                   keyword IN (SELECT newKeyword FROM itemsToApply
-                               WHERE newKeyword NOT NULL)",
+             WHERE (place_id IN (SELECT oldPlaceId FROM itemsToApply
+                                 WHERE oldPlaceId NOT NULL) OR
+                    place_id IN (SELECT newPlaceId FROM itemsToApply
+                                 WHERE newPlaceId NOT NULL) OR
+                    keyword IN (SELECT newKeyword FROM itemsToApply
+                                WHERE newKeyword NOT NULL)) AND
+                   NOT EXISTS(SELECT 1 FROM itemsToApply
+                              WHERE newKeyword NOT NULL AND
+                                    newKeyword = moz_keywords.keyword AND
+                                    newPlaceId = moz_keywords.place_id)",
         )?;
 
         log::debug!("Removing old tags");
@@ -828,77 +830,34 @@ impl<'a> BookmarksStore<'a> {
         Ok(())
     }
 
-    pub(crate) fn update_frecencies(&self) -> Result<()> {
-        let mut tx = self.db.begin_transaction()?;
-
-        let mut frecencies = Vec::with_capacity(MAX_FRECENCIES_TO_RECALCULATE_PER_CHUNK);
-        loop {
-            let sql = format!(
-                "SELECT place_id FROM moz_places_stale_frecencies
-                 ORDER BY stale_at DESC
-                 LIMIT {}",
-                MAX_FRECENCIES_TO_RECALCULATE_PER_CHUNK
-            );
-            let mut stmt = self.db.prepare_maybe_cached(&sql, true)?;
-            let mut results = stmt.query(NO_PARAMS)?;
-            while let Some(row) = results.next()? {
-                let place_id = row.get("place_id")?;
-                // Frecency recalculation runs several statements, so check to
-                // make sure we aren't interrupted before each calculation.
-                self.interruptee.err_if_interrupted()?;
-                let frecency = calculate_frecency(
-                    &self.db,
-                    &DEFAULT_FRECENCY_SETTINGS,
-                    place_id,
-                    Some(false),
-                )?;
-                frecencies.push((place_id, frecency));
-            }
-            if frecencies.is_empty() {
-                break;
-            }
-
-            // Update all frecencies in one fell swoop...
-            self.db.execute_batch(&format!(
-                "WITH frecencies(id, frecency) AS (
-                   VALUES {}
-                 )
-                 UPDATE moz_places SET
-                   frecency = (SELECT frecency FROM frecencies f
-                               WHERE f.id = id)
-                 WHERE id IN (SELECT f.id FROM frecencies f)",
-                sql_support::repeat_display(frecencies.len(), ",", |index, f| {
-                    let (id, frecency) = frecencies[index];
-                    write!(f, "({}, {})", id, frecency)
-                })
-            ))?;
-            tx.maybe_commit()?;
-            self.interruptee.err_if_interrupted()?;
+    /// Recalculates one batch of stale frecencies, and returns the number
+    /// that are still stale.
+    fn update_frecencies_batch(&self) -> Result<u32> {
+        // The stale queue is shared with history, so it can hold redirect
+        // sources as well as bookmarked URLs.
+        update_stale_frecencies(self.db, STALE_FRECENCIES_BATCH_SIZE, self.interruptee)
+    }
 
-            // ...And remove them from the stale table.
-            self.db.execute_batch(&format!(
-                "DELETE FROM moz_places_stale_frecencies
-                 WHERE place_id IN ({})",
-                sql_support::repeat_display(frecencies.len(), ",", |index, f| {
-                    let (id, _) = frecencies[index];
-                    write!(f, "{}", id)
-                })
-            ))?;
-            tx.maybe_commit()?;
-            self.interruptee.err_if_interrupted()?;
+    /// Recalculates all stale frecencies, a batch at a time. Importers use
+    /// this; syncs only recalculate one batch, and leave the rest to
+    /// `run_maintenance`.
+    pub(crate) fn update_frecencies(&self) -> Result<()> {
+        while self.update_frecencies_batch()? > 0 {}
+        Ok(())
+    }
 
-            // If the query returned fewer URLs than the maximum, we're done.
-            // Otherwise, we might have more, so clear the ones we just
-            // recalculated and fetch the next chunk.
-            if frecencies.len() < MAX_FRECENCIES_TO_RECALCULATE_PER_CHUNK {
-                break;
+    /// Validates the local and synced trees, if there are at most `max_items`
+    /// local items, and returns the problems as a telemetry record. Returns
+    /// `None` if the tree is too big, or if validation fails; a failed
+    /// validation shouldn't fail the sync.
+    fn validate_for_telemetry(&self, max_items: usize) -> Option<telemetry::Validation> {
+        match validation::validate_if_small(self.db, self.interruptee, max_items) {
+            Ok(problems) => problems.map(|problems| problems.to_telemetry()),
+            Err(e) => {
+                log::warn!("Failed to validate bookmarks after syncing: {}", e);
+                None
             }
-            frecencies.clear();
         }
-
-        tx.commit()?;
-
-        Ok(())
     }
 
     /// Removes all sync metadata, such that the next sync is treated as a
@@ -960,17 +919,20 @@ impl<'a> Store for BookmarksStore<'a> {
         records_synced: Vec<SyncGuid>,
     ) -> result::Result<(), failure::Error> {
         self.push_synced_items(new_timestamp, records_synced)?;
-        self.update_frecencies()?;
+        self.update_frecencies_batch()?;
         self.db.pragma_update(None, "wal_checkpoint", &"PASSIVE")?;
         Ok(())
     }
 
+    fn get_last_sync(&self) -> result::Result<Option<ServerTimestamp>, failure::Error> {
+        Ok(get_meta::<i64>(self.db, LAST_SYNC_META_KEY)?.map(ServerTimestamp))
+    }
+
     fn get_collection_requests(
         &self,
         server_timestamp: ServerTimestamp,
     ) -> result::Result<Vec<CollectionRequest>, failure::Error> {
-        let since =
-            ServerTimestamp(get_meta::<i64>(self.db, LAST_SYNC_META_KEY)?.unwrap_or_default());
+        let since = self.get_last_sync()?.unwrap_or_default();
         Ok(if since == server_timestamp {
             vec![]
         } else {
@@ -1136,9 +1098,18 @@ impl<'a> Merger<'a> {
         let result = self.merge_with_driver(&driver, &MergeInterruptee(self.store.interruptee));
         log::debug!("merge completed");
 
-        // Record telemetry in all cases, even if the merge fails.
+        // Record telemetry in all cases, even if the merge fails. If the merge
+        // succeeded, and the tree isn't too big, we run the validator, which
+        // checks the entire local and synced trees, and report its problems
+        // instead of just the ones Dogear found in the incoming records.
         if let Some(ref mut telem) = self.telem {
-            telem.validation(driver.validation.into_inner());
+            let validation = match result {
+                Ok(()) => self
+                    .store
+                    .validate_for_telemetry(validation::MAX_ITEMS_TO_VALIDATE_AFTER_SYNC),
+                Err(_) => None,
+            };
+            telem.validation(validation.unwrap_or_else(|| driver.validation.into_inner()));
         }
         result
     }
@@ -1146,9 +1117,9 @@ impl<'a> Merger<'a> {
     /// Prepares synced bookmarks for merging.
     fn prepare(&self) -> Result<()> {
         // Sync and Fennec associate keywords with bookmarks, and don't sync
-        // POST data; Rust Places associates them with URLs, and also doesn't
-        // support POST data; Desktop associates keywords with (URL, POST data)
-        // pairs, and multiple bookmarks may have the same URL.
+        // POST data; Rust Places associates them with URLs, and stores, but
+        // doesn't sync, POST data; Desktop associates keywords with (URL, POST
+        // data) pairs, and multiple bookmarks may have the same URL.
         //
         // When a keyword changes, clients should reupload all bookmarks with
         // the affected URL (bug 1328737). Just in case, we flag any synced
@@ -1526,6 +1497,31 @@ impl<'a> dogear::Store for Merger<'a> {
             .stage_items_to_upload(&ops.upload_items, &ops.upload_tombstones)?;
 
         self.store.db.execute_batch("DELETE FROM itemsToApply;")?;
+
+        // Items can have both new values and a new position, so we only
+        // count each one once.
+        let changed = ops
+            .apply_remote_items
+            .iter()
+            .map(|op| &op.merged_node.guid)
+            .chain(
+                ops.apply_new_local_structure
+                    .iter()
+                    .map(|op| &op.merged_node.guid),
+            )
+            .collect::<HashSet<_>>()
+            .len();
+        let removed = ops.delete_local_items.len();
+        if changed > 0 || removed > 0 {
+            self.store
+                .db
+                .note_event(PlacesEvent::SyncApplied(SyncSummary {
+                    collection: SyncedCollection::Bookmarks,
+                    changed: changed as u32,
+                    removed: removed as u32,
+                }));
+        }
+
         if let Some(tx) = tx {
             tx.commit()?;
         }
@@ -1705,7 +1701,7 @@ impl fmt::Display for UrlOrPlaceIdFragment {
 
 /// A helper that interpolates a SQL list containing the given bookmark
 /// root GUIDs.
-struct RootsFragment<'a>(&'a [BookmarkRootGuid]);
+pub(super) struct RootsFragment<'a>(pub(super) &'a [BookmarkRootGuid]);
 
 impl<'a> fmt::Display for RootsFragment<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -1935,6 +1931,50 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_apply_notifies_observers() -> Result<()> {
+        use crate::observer::test::RecordingObserver;
+        use std::sync::Arc;
+
+        let api = new_mem_api();
+        let observer = Arc::new(RecordingObserver::default());
+        api.add_observer(observer.clone());
+        let conn = api.open_sync_connection()?;
+
+        apply_incoming(
+            &conn,
+            ServerTimestamp(0),
+            json!([{
+                "id": "qqVTRWhLBOu3",
+                "type": "bookmark",
+                "parentid": "unfiled",
+                "parentName": "Unfiled Bookmarks",
+                "dateAdded": 1_381_542_355_843u64,
+                "title": "The title",
+                "bmkUri": "https://example.com",
+                "tags": [],
+            }, {
+                "id": "unfiled",
+                "type": "folder",
+                "parentid": "places",
+                "parentName": "",
+                "dateAdded": 0,
+                "title": "Unfiled Bookmarks",
+                "children": ["qqVTRWhLBOu3"],
+                "tags": [],
+            }]),
+        );
+        assert_eq!(
+            observer.take(),
+            vec![PlacesEvent::SyncApplied(SyncSummary {
+                collection: SyncedCollection::Bookmarks,
+                changed: 1,
+                removed: 0,
+            })]
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_fetch_local_tree() -> Result<()> {
         let now = SystemTime::now();
@@ -2338,14 +2378,17 @@ mod tests {
                         "guid": "bookmarkCCC2",
                         "title": "C2",
                         "url": "http://example.com/c",
+                        "tags": ["five", "four", "seven", "six"],
                     }, {
                         "guid": "bookmarkCCC3",
                         "title": "C3",
                         "url": "http://example.com/c",
+                        "tags": ["five", "four", "seven", "six"],
                     }, {
                         "guid": "bookmarkAAA2",
                         "title": "A2",
                         "url": "http://example.com/a",
+                        "tags": ["one", "two"],
                     }],
                 }, {
                     "guid": &BookmarkRootGuid::Toolbar.as_guid(),
@@ -2353,10 +2396,12 @@ mod tests {
                         "guid": "bookmarkEEE1",
                         "title": "E1",
                         "url": "http://example.com/e",
+                        "tags": ["eleven", "nine", "ten"],
                     }, {
                         "guid": "bookmarkFFF1",
                         "title": "F1",
                         "url": "http://example.com/f",
+                        "tags": ["twelve"],
                     }],
                 }, {
                     "guid": &BookmarkRootGuid::Unfiled.as_guid(),
@@ -2364,18 +2409,22 @@ mod tests {
                         "guid": "bookmarkBBBB",
                         "title": "B",
                         "url": "http://example.com/b",
+                        "tags": ["eight", "three", "two"],
                     }, {
                         "guid": "bookmarkCCC1",
                         "title": "C1",
                         "url": "http://example.com/c",
+                        "tags": ["five", "four", "seven", "six"],
                     }, {
                         "guid": "bookmarkDDDD",
                         "title": "D",
                         "url": "http://example.com/d",
+                        "tags": ["five", "four", "six"],
                     }, {
                         "guid": "bookmarkAAA1",
                         "title": "A1",
                         "url": "http://example.com/a",
+                        "tags": ["one", "two"],
                     }],
                 }, {
                     "guid": &BookmarkRootGuid::Mobile.as_guid(),
@@ -2383,10 +2432,12 @@ mod tests {
                         "guid": "bookmarkEEE2",
                         "title": "E2",
                         "url": "http://example.com/e",
+                        "tags": ["eleven", "nine", "ten"],
                     }, {
                         "guid": "bookmarkFFF2",
                         "title": "F2",
                         "url": "http://example.com/f",
+                        "tags": ["twelve"],
                     }],
                 }],
             }),
@@ -2580,9 +2631,9 @@ mod tests {
             }]),
             &BookmarkRootGuid::Unfiled.as_guid(),
             json!({"children" : [
-                  {"guid": "bookmarkBBBB", "url": "http://example.com/b"},
-                  {"guid": "bookmarkCCCC", "url": "http://example.com/c"},
-                  {"guid": "bookmarkAAAA", "url": "http://example.com/a"},
+                  {"guid": "bookmarkBBBB", "url": "http://example.com/b", "tags": ["one", "two"]},
+                  {"guid": "bookmarkCCCC", "url": "http://example.com/c", "tags": ["three"]},
+                  {"guid": "bookmarkAAAA", "url": "http://example.com/a", "tags": ["one"]},
             ]}),
         );
 
@@ -2849,6 +2900,7 @@ mod tests {
                                 "guid": "bookmarkCCCC",
                                 "title": "C",
                                 "url": "http://example.com/c",
+                                "tags": ["bar", "foo"],
                                 "date_added": Timestamp(1_552_183_116_885),
                             },
                         ],
@@ -2864,6 +2916,7 @@ mod tests {
                                 "guid": "bookmarkAAAA",
                                 "title": "A",
                                 "url": "http://example.com/a",
+                                "tags": ["baz"],
                             },
                             {
                                 "guid": "bookmarkBBBB",
@@ -2925,7 +2978,10 @@ mod tests {
 
     #[test]
     fn test_keywords() -> Result<()> {
-        use crate::storage::bookmarks::bookmarks_get_url_for_keyword;
+        use crate::storage::bookmarks::{
+            bookmarks_get_keyword_for_url, bookmarks_get_url_for_keyword, bookmarks_remove_keyword,
+            bookmarks_set_keyword,
+        };
 
         let api = new_mem_api();
         let writer = api.open_connection(ConnectionType::ReadWrite)?;
@@ -3039,6 +3095,84 @@ mod tests {
             _ => panic!("Wanted constraint violation error; got {:?}", err),
         }
 
+        store
+            .sync_finished(ServerTimestamp(1000), vec!["bookmarkAAAA".into()])
+            .expect("Should push synced changes back to the store");
+
+        // Changing the keyword locally should reupload the bookmark with the
+        // new keyword, but not the POST data.
+        bookmarks_set_keyword(
+            &writer,
+            &Url::parse("http://example.com/a/%s")?,
+            "b",
+            Some("q=%s"),
+        )?;
+        assert_eq!(bookmarks_get_url_for_keyword(&writer, "a")?, None);
+        let outgoing = store
+            .apply_incoming(
+                vec![IncomingChangeset::new(
+                    store.collection_name(),
+                    ServerTimestamp(2000),
+                )],
+                &mut telemetry::Engine::new("bookmarks"),
+            )
+            .expect("Should fetch outgoing records after setting keyword");
+        assert_eq!(outgoing.changes.len(), 1);
+        assert_eq!(outgoing.changes[0].id, "bookmarkAAAA");
+        assert_eq!(outgoing.changes[0].data["keyword"], "b");
+        assert!(outgoing.changes[0].data.get("postData").is_none());
+        store
+            .sync_finished(ServerTimestamp(2000), vec!["bookmarkAAAA".into()])
+            .expect("Should push synced changes back to the store");
+
+        // A remote change that keeps the keyword and URL shouldn't drop the
+        // POST data.
+        let mut incoming = IncomingChangeset::new(store.collection_name(), ServerTimestamp(2500));
+        incoming.changes.push((
+            Payload::from_json(json!({
+                "id": "bookmarkAAAA",
+                "type": "bookmark",
+                "parentid": "toolbar",
+                "parentName": "toolbar",
+                "dateAdded": 1_552_183_116_885u64,
+                "title": "A (remote)",
+                "bmkUri": "http://example.com/a/%s",
+                "keyword": "b",
+            }))
+            .unwrap(),
+            ServerTimestamp(2500),
+        ));
+        let outgoing = store
+            .apply_incoming(vec![incoming], &mut telemetry::Engine::new("bookmarks"))
+            .expect("Should apply remote title change");
+        assert!(outgoing.changes.is_empty());
+        let keyword =
+            bookmarks_get_keyword_for_url(&writer, &Url::parse("http://example.com/a/%s")?)?
+                .expect("Should keep keyword after remote change");
+        assert_eq!(keyword.keyword, "b");
+        assert_eq!(keyword.post_data.as_deref(), Some("q=%s"));
+        store
+            .sync_finished(ServerTimestamp(2500), vec![])
+            .expect("Should push synced changes back to the store");
+
+        // Removing it should reupload the bookmark without a keyword.
+        assert!(bookmarks_remove_keyword(
+            &writer,
+            &Url::parse("http://example.com/a/%s")?
+        )?);
+        let outgoing = store
+            .apply_incoming(
+                vec![IncomingChangeset::new(
+                    store.collection_name(),
+                    ServerTimestamp(3000),
+                )],
+                &mut telemetry::Engine::new("bookmarks"),
+            )
+            .expect("Should fetch outgoing records after removing keyword");
+        assert_eq!(outgoing.changes.len(), 1);
+        assert_eq!(outgoing.changes[0].id, "bookmarkAAAA");
+        assert!(outgoing.changes[0].data.get("keyword").is_none());
+
         Ok(())
     }
 
@@ -3046,8 +3180,8 @@ mod tests {
     fn test_apply_complex_bookmark_keywords() -> Result<()> {
         use crate::storage::bookmarks::bookmarks_get_url_for_keyword;
 
-        // We don't provide an API for setting keywords locally, but we'll
-        // still round-trip and fix up keywords on the server.
+        // Keywords set locally are covered by `test_keywords`; this test
+        // checks that we round-trip and fix up keywords on the server.
 
         let api = new_mem_api();
         let writer = api.open_connection(ConnectionType::ReadWrite)?;
@@ -3302,6 +3436,119 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_validation_telemetry() -> Result<()> {
+        use crate::bookmark_sync::validation::VALIDATION_VERSION;
+
+        let api = new_mem_api();
+        let syncer = api.open_sync_connection()?;
+        let interrupt_scope = syncer.begin_interrupt_scope();
+        let store = BookmarksStore::new(&syncer, &interrupt_scope);
+
+        let mut incoming = IncomingChangeset::new(store.collection_name(), ServerTimestamp(0));
+        incoming.changes.push((
+            Payload::from_json(json!({
+                "id": "bookmarkAAAA",
+                "type": "bookmark",
+                "parentid": "unfiled",
+                "parentName": "Unfiled",
+                "title": "A",
+                "bmkUri": "http://example.com/a",
+            }))
+            .unwrap(),
+            ServerTimestamp(0),
+        ));
+        let mut telem = telemetry::Engine::new("bookmarks");
+        store
+            .apply_incoming(vec![incoming], &mut telem)
+            .expect("Should apply incoming records");
+
+        // The tree is small, so we should report the validator's results
+        // instead of Dogear's.
+        let mut sync_telem = telemetry::SyncTelemetry::new();
+        sync_telem.engine(telem);
+        sync_telem.finished();
+        let sync_json = serde_json::to_value(&sync_telem).unwrap();
+        assert_eq!(
+            sync_json["engines"][0]["validation"]["version"],
+            json!(VALIDATION_VERSION)
+        );
+
+        // Trees with too many items shouldn't be validated.
+        assert!(store.validate_for_telemetry(1).is_none());
+        assert!(store.validate_for_telemetry(100).is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_after_sync() -> Result<()> {
+        use crate::bookmark_sync::validation::ValidationProblems;
+
+        let api = new_mem_api();
+        let writer = api.open_connection(ConnectionType::ReadWrite)?;
+        insert_local_json_tree(
+            &writer,
+            json!({
+                "guid": &BookmarkRootGuid::Menu.as_guid(),
+                "children": [
+                    {
+                        "guid": "bookmarkAAAA",
+                        "title": "A",
+                        "url": "http://example.com/a",
+                    },
+                    {
+                        "guid": "folderBBBBBB",
+                        "title": "B",
+                        "children": [
+                            {
+                                "guid": "bookmarkCCCC",
+                                "title": "C",
+                                "url": "http://example.com/c",
+                            },
+                        ],
+                    },
+                ],
+            }),
+        );
+        {
+            let syncer = api.open_sync_connection()?;
+            apply_incoming(&syncer, ServerTimestamp(0), json!([]));
+        }
+
+        // After syncing, the local tree and mirror should match.
+        assert!(api.validate_bookmarks(false)?.is_empty());
+
+        // Change the mirror and the local tree without flagging anything for
+        // upload.
+        writer.execute_batch(
+            "UPDATE moz_bookmarks_synced SET
+               title = 'Not A'
+             WHERE guid = 'bookmarkAAAA';
+
+             UPDATE moz_bookmarks_synced SET
+               placeId = NULL
+             WHERE guid = 'bookmarkCCCC';
+
+             UPDATE moz_bookmarks SET
+               position = 1 - position
+             WHERE guid IN ('bookmarkAAAA', 'folderBBBBBB');",
+        )?;
+        let problems = api.validate_bookmarks(true)?;
+        assert_eq!(
+            problems,
+            ValidationProblems {
+                url_mismatches: 1,
+                structure_differences: 2,
+                content_differences: 1,
+                ..ValidationProblems::default()
+            }
+        );
+        assert!(!problems.has_local_problems());
+
+        Ok(())
+    }
+
     #[test]
     fn test_wipe() -> Result<()> {
         let api = new_mem_api();
@@ -0,0 +1,663 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A bookmark validator, which checks the local tree in `moz_bookmarks` and
+//! the synced tree in `moz_bookmarks_synced` for problems, and can repair
+//! problems that only affect the local tree.
+//!
+//! Dogear already reports problems with the remote tree when it merges, but
+//! only for incoming records. The validator looks at everything, including
+//! items that haven't changed since the last sync, and compares unchanged
+//! local items with their synced counterparts.
+
+use super::store::RootsFragment;
+use super::{SyncedBookmarkKind, SyncedBookmarkValidity};
+use crate::db::PlacesDb;
+use crate::error::*;
+use crate::storage::bookmarks::{
+    create_missing_bookmark_roots, BookmarkRootGuid, USER_CONTENT_ROOTS,
+};
+use crate::storage::RowId;
+use crate::types::{BookmarkType, SyncStatus};
+use sql_support::{ConnExt, SqlInterruptScope};
+use sync15::telemetry;
+
+/// The version of the validator, which we report in telemetry. This should be
+/// bumped whenever the checks change.
+pub const VALIDATION_VERSION: u32 = 1;
+
+/// The maximum number of local items for which we validate after every sync.
+/// Validation checks the entire tree, so we skip it for larger trees to keep
+/// syncs fast.
+pub const MAX_ITEMS_TO_VALIDATE_AFTER_SYNC: usize = 1000;
+
+/// The number of problems of each kind that the validator found.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ValidationProblems {
+    /// Roots that are missing from the local tree.
+    pub missing_roots: usize,
+    /// Local roots that aren't children of the Places root, or, for the
+    /// Places root, that have a parent.
+    pub misparented_roots: usize,
+    /// Local items whose parents don't exist.
+    pub orphans: usize,
+    /// Local items whose parents aren't folders.
+    pub non_folder_parents: usize,
+    /// Local folders whose children have gaps or duplicates in their
+    /// positions.
+    pub position_gaps: usize,
+
+    /// Synced items that aren't in any synced folder's `children`.
+    pub mirror_orphans: usize,
+    /// Synced items that are in more than one synced folder's `children`.
+    pub multiple_parents: usize,
+    /// Synced items whose `parentid` doesn't match the folder that has them
+    /// in its `children`.
+    pub parent_child_disagreements: usize,
+    /// Entries in synced folders' `children` that don't exist, or are
+    /// deleted.
+    pub missing_children: usize,
+
+    /// Unchanged local bookmarks whose URLs don't match their synced URLs.
+    pub url_mismatches: usize,
+    /// Unchanged local items with a different parent or position than their
+    /// synced counterparts.
+    pub structure_differences: usize,
+    /// Unchanged local items with a different kind or title than their
+    /// synced counterparts.
+    pub content_differences: usize,
+}
+
+impl ValidationProblems {
+    /// Returns `true` if the validator didn't find any problems.
+    pub fn is_empty(&self) -> bool {
+        *self == ValidationProblems::default()
+    }
+
+    /// Returns `true` if there are problems with the local tree, which
+    /// `repair` can fix. Problems with the synced tree, and differences
+    /// between the two trees, can only be fixed by syncing.
+    pub fn has_local_problems(&self) -> bool {
+        self.missing_roots > 0
+            || self.misparented_roots > 0
+            || self.orphans > 0
+            || self.non_folder_parents > 0
+            || self.position_gaps > 0
+    }
+
+    /// Converts the problems into a validation record for the engine's
+    /// telemetry.
+    pub fn to_telemetry(&self) -> telemetry::Validation {
+        let mut validation = telemetry::Validation::with_version(VALIDATION_VERSION);
+        validation
+            .problem("missingRoots", self.missing_roots)
+            .problem("misparentedRoots", self.misparented_roots)
+            .problem("orphans", self.orphans)
+            .problem("nonFolderParents", self.non_folder_parents)
+            .problem("positionGaps", self.position_gaps)
+            .problem("mirrorOrphans", self.mirror_orphans)
+            .problem("multipleParents", self.multiple_parents)
+            .problem("parentChildDisagreements", self.parent_child_disagreements)
+            .problem("missingChildren", self.missing_children)
+            .problem("urlMismatches", self.url_mismatches)
+            .problem("structuralDifferences", self.structure_differences)
+            .problem("contentDifferences", self.content_differences);
+        validation
+    }
+}
+
+const ALL_ROOTS: &[BookmarkRootGuid] = &[
+    BookmarkRootGuid::Root,
+    BookmarkRootGuid::Menu,
+    BookmarkRootGuid::Toolbar,
+    BookmarkRootGuid::Unfiled,
+    BookmarkRootGuid::Mobile,
+];
+
+/// Checks the local and synced bookmark trees for problems, but only if the
+/// local tree has at most `max_items` items. Returns `None` if the tree is too
+/// big.
+pub fn validate_if_small(
+    db: &PlacesDb,
+    scope: &SqlInterruptScope,
+    max_items: usize,
+) -> Result<Option<ValidationProblems>> {
+    let items = count(db, "SELECT COUNT(*) FROM moz_bookmarks")?;
+    if items > max_items {
+        log::debug!(
+            "Skipping validation for {} items; the limit is {}",
+            items,
+            max_items
+        );
+        return Ok(None);
+    }
+    Ok(Some(validate(db, scope)?))
+}
+
+/// Checks the local and synced bookmark trees for problems.
+pub fn validate(db: &PlacesDb, scope: &SqlInterruptScope) -> Result<ValidationProblems> {
+    let mut problems = ValidationProblems::default();
+
+    let existing_roots: usize = count(
+        db,
+        &format!(
+            "SELECT COUNT(*) FROM moz_bookmarks WHERE guid IN {roots}",
+            roots = RootsFragment(ALL_ROOTS)
+        ),
+    )?;
+    problems.missing_roots = ALL_ROOTS.len() - existing_roots;
+    problems.misparented_roots = count(db, &misparented_roots_sql("COUNT(*)"))?;
+    problems.orphans = count(db, &orphans_sql("COUNT(*)"))?;
+    problems.non_folder_parents = count(db, &non_folder_parents_sql("COUNT(*)"))?;
+    problems.position_gaps = count(
+        db,
+        &format!(
+            "SELECT COUNT(*) FROM ({})",
+            folders_with_position_gaps_sql()
+        ),
+    )?;
+    scope.err_if_interrupted()?;
+
+    problems.mirror_orphans = count(
+        db,
+        "SELECT COUNT(*) FROM moz_bookmarks_synced v
+         WHERE NOT v.isDeleted AND
+               NOT EXISTS(SELECT 1 FROM moz_bookmarks_synced_structure s
+                          WHERE s.guid = v.guid)",
+    )?;
+    problems.multiple_parents = count(
+        db,
+        "SELECT COUNT(*) FROM (
+           SELECT guid FROM moz_bookmarks_synced_structure
+           GROUP BY guid
+           HAVING COUNT(*) > 1
+         )",
+    )?;
+    problems.parent_child_disagreements = count(
+        db,
+        "SELECT COUNT(*) FROM moz_bookmarks_synced v
+         WHERE NOT v.isDeleted AND
+               EXISTS(SELECT 1 FROM moz_bookmarks_synced_structure s
+                      WHERE s.guid = v.guid) AND
+               NOT EXISTS(SELECT 1 FROM moz_bookmarks_synced_structure s
+                          WHERE s.guid = v.guid AND
+                                s.parentGuid = v.parentGuid)",
+    )?;
+    problems.missing_children = count(
+        db,
+        "SELECT COUNT(*) FROM moz_bookmarks_synced_structure s
+         WHERE NOT EXISTS(SELECT 1 FROM moz_bookmarks_synced v
+                          WHERE v.guid = s.guid AND
+                                NOT v.isDeleted)",
+    )?;
+    scope.err_if_interrupted()?;
+
+    problems.url_mismatches = count(
+        db,
+        &format!(
+            "SELECT COUNT(*) {from_unchanged}
+         AND b.type = {bookmark_type} AND
+             b.fk IS NOT v.placeId",
+            from_unchanged = from_unchanged_items_sql(),
+            bookmark_type = BookmarkType::Bookmark as u8
+        ),
+    )?;
+    problems.structure_differences = count(
+        db,
+        &format!(
+            "SELECT COUNT(*) {from_unchanged}
+         AND b.parent NOT NULL AND (p.guid IS NOT v.parentGuid OR (
+           /* If the local parent changed, its children's positions might
+              have changed, too. */
+           p.syncChangeCounter = 0 AND
+           b.position IS NOT (SELECT s.position
+                              FROM moz_bookmarks_synced_structure s
+                              WHERE s.guid = b.guid AND
+                                    s.parentGuid = p.guid)
+         ))",
+            from_unchanged = from_unchanged_items_sql(),
+        ),
+    )?;
+    problems.content_differences = count(
+        db,
+        &format!(
+            "SELECT COUNT(*) {from_unchanged}
+         AND b.guid NOT IN {roots} AND (
+           IFNULL(b.title, '') <> IFNULL(v.title, '') OR
+           CASE b.type
+           WHEN {bookmark_type} THEN v.kind NOT IN ({bookmark_kind}, {query_kind})
+           WHEN {folder_type} THEN v.kind <> {folder_kind}
+           ELSE v.kind <> {separator_kind}
+           END
+         )",
+            from_unchanged = from_unchanged_items_sql(),
+            roots = RootsFragment(ALL_ROOTS),
+            bookmark_type = BookmarkType::Bookmark as u8,
+            folder_type = BookmarkType::Folder as u8,
+            bookmark_kind = SyncedBookmarkKind::Bookmark as u8,
+            query_kind = SyncedBookmarkKind::Query as u8,
+            folder_kind = SyncedBookmarkKind::Folder as u8,
+            separator_kind = SyncedBookmarkKind::Separator as u8
+        ),
+    )?;
+
+    if !problems.is_empty() {
+        log::warn!("Bookmark validation found problems: {:?}", problems);
+    }
+    Ok(problems)
+}
+
+/// Checks the local and synced bookmark trees for problems, and repairs any
+/// problems with the local tree. Missing roots are recreated, misparented
+/// roots are moved back to the Places root, orphans and children of
+/// non-folders are moved to the end of the unfiled root, and positions are
+/// renumbered to remove gaps. All changed items are flagged for upload.
+///
+/// Returns the problems that were found before repairing.
+pub fn repair(db: &PlacesDb, scope: &SqlInterruptScope) -> Result<ValidationProblems> {
+    let problems = validate(db, scope)?;
+    if problems.has_local_problems() {
+        let tx = db.begin_transaction()?;
+        repair_local_tree(db, scope)?;
+        tx.commit()?;
+    }
+    Ok(problems)
+}
+
+fn repair_local_tree(db: &PlacesDb, scope: &SqlInterruptScope) -> Result<()> {
+    let created = create_missing_bookmark_roots(db)?;
+    if created > 0 {
+        log::info!("Recreated {} missing bookmark roots", created);
+    }
+
+    let root_id = get_row_id(db, BookmarkRootGuid::Root)?;
+    db.execute_named_cached(
+        "UPDATE moz_bookmarks SET
+           parent = NULL,
+           syncChangeCounter = syncChangeCounter + 1
+         WHERE id = :root_id AND
+               parent NOT NULL",
+        &[(":root_id", &root_id)],
+    )?;
+    let misparented_roots: Vec<RowId> =
+        db.query_rows_and_then_named(&misparented_roots_sql("b.id"), &[], |row| row.get(0))?;
+    for item_id in misparented_roots {
+        move_to_end(db, item_id, root_id)?;
+    }
+    scope.err_if_interrupted()?;
+
+    let unfiled_id = get_row_id(db, BookmarkRootGuid::Unfiled)?;
+    let homeless: Vec<RowId> = db.query_rows_and_then_named(
+        &format!(
+            "{} UNION {}",
+            orphans_sql("b.id"),
+            non_folder_parents_sql("b.id")
+        ),
+        &[],
+        |row| row.get(0),
+    )?;
+    if !homeless.is_empty() {
+        log::info!("Moving {} bookmarks to unfiled", homeless.len());
+    }
+    for item_id in homeless {
+        move_to_end(db, item_id, unfiled_id)?;
+    }
+    scope.err_if_interrupted()?;
+
+    let folders: Vec<RowId> =
+        db.query_rows_and_then_named(&folders_with_position_gaps_sql(), &[], |row| row.get(0))?;
+    for folder_id in folders {
+        renumber_children(db, folder_id)?;
+        scope.err_if_interrupted()?;
+    }
+    Ok(())
+}
+
+fn count(db: &PlacesDb, sql: &str) -> Result<usize> {
+    Ok(db.query_one::<i64>(sql)? as usize)
+}
+
+fn get_row_id(db: &PlacesDb, guid: BookmarkRootGuid) -> Result<RowId> {
+    Ok(db.query_row_and_then_named(
+        "SELECT id FROM moz_bookmarks WHERE guid = :guid",
+        &[(":guid", &guid.as_guid())],
+        |row| row.get(0),
+        true,
+    )?)
+}
+
+/// Moves an item to the end of a folder, and flags both for upload.
+fn move_to_end(db: &PlacesDb, item_id: RowId, folder_id: RowId) -> Result<()> {
+    db.execute_named_cached(
+        "UPDATE moz_bookmarks SET
+           parent = :folder_id,
+           position = (SELECT IFNULL(MAX(position) + 1, 0) FROM moz_bookmarks
+                       WHERE parent = :folder_id),
+           syncChangeCounter = syncChangeCounter + 1
+         WHERE id = :item_id",
+        &[(":folder_id", &folder_id), (":item_id", &item_id)],
+    )?;
+    db.execute_named_cached(
+        "UPDATE moz_bookmarks SET
+           syncChangeCounter = syncChangeCounter + 1
+         WHERE id = :folder_id",
+        &[(":folder_id", &folder_id)],
+    )?;
+    Ok(())
+}
+
+/// Renumbers a folder's children, keeping their current order, and flags the
+/// folder for upload. Positions aren't part of the child's record, so the
+/// children don't need to be reuploaded.
+fn renumber_children(db: &PlacesDb, folder_id: RowId) -> Result<()> {
+    let children: Vec<RowId> = db.query_rows_and_then_named(
+        "SELECT id FROM moz_bookmarks
+         WHERE parent = :folder_id
+         ORDER BY position, id",
+        &[(":folder_id", &folder_id)],
+        |row| row.get(0),
+    )?;
+    for (position, child_id) in children.into_iter().enumerate() {
+        db.execute_named_cached(
+            "UPDATE moz_bookmarks SET
+               position = :position
+             WHERE id = :child_id",
+            &[(":position", &(position as u32)), (":child_id", &child_id)],
+        )?;
+    }
+    db.execute_named_cached(
+        "UPDATE moz_bookmarks SET
+           syncChangeCounter = syncChangeCounter + 1
+         WHERE id = :folder_id",
+        &[(":folder_id", &folder_id)],
+    )?;
+    Ok(())
+}
+
+fn misparented_roots_sql(columns: &str) -> String {
+    format!(
+        "SELECT {columns} FROM moz_bookmarks b
+         WHERE (b.guid = '{root}' AND b.parent NOT NULL) OR
+               (b.guid IN {user_roots} AND
+                b.parent IS NOT (SELECT id FROM moz_bookmarks
+                                 WHERE guid = '{root}'))",
+        columns = columns,
+        root = BookmarkRootGuid::Root.as_str(),
+        user_roots = RootsFragment(USER_CONTENT_ROOTS)
+    )
+}
+
+fn orphans_sql(columns: &str) -> String {
+    format!(
+        "SELECT {columns} FROM moz_bookmarks b
+         WHERE b.guid NOT IN {roots} AND
+               NOT EXISTS(SELECT 1 FROM moz_bookmarks p
+                          WHERE p.id = b.parent)",
+        columns = columns,
+        roots = RootsFragment(ALL_ROOTS)
+    )
+}
+
+fn non_folder_parents_sql(columns: &str) -> String {
+    format!(
+        "SELECT {columns} FROM moz_bookmarks b
+         JOIN moz_bookmarks p ON p.id = b.parent
+         WHERE b.guid NOT IN {roots} AND
+               p.type <> {folder_type}",
+        columns = columns,
+        roots = RootsFragment(ALL_ROOTS),
+        folder_type = BookmarkType::Folder as u8
+    )
+}
+
+fn folders_with_position_gaps_sql() -> String {
+    format!(
+        "SELECT b.parent FROM moz_bookmarks b
+         JOIN moz_bookmarks p ON p.id = b.parent
+         WHERE p.type = {folder_type}
+         GROUP BY b.parent
+         HAVING MIN(b.position) <> 0 OR
+                MAX(b.position) <> COUNT(*) - 1 OR
+                COUNT(DISTINCT b.position) <> COUNT(*)",
+        folder_type = BookmarkType::Folder as u8
+    )
+}
+
+/// Joins local items that haven't changed since the last sync with their
+/// synced counterparts. Callers add conditions for the differences they're
+/// looking for.
+fn from_unchanged_items_sql() -> String {
+    format!(
+        "FROM moz_bookmarks b
+         JOIN moz_bookmarks_synced v ON v.guid = b.guid
+         LEFT JOIN moz_bookmarks p ON p.id = b.parent
+         WHERE b.syncStatus = {sync_status} AND
+               b.syncChangeCounter = 0 AND
+               NOT v.needsMerge AND
+               NOT v.isDeleted AND
+               v.validity = {valid}",
+        sync_status = SyncStatus::Normal as u8,
+        valid = SyncedBookmarkValidity::Valid as u8
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::{test::new_mem_api, ConnectionType};
+    use crate::tests::{assert_json_tree, insert_json_tree};
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    #[test]
+    fn test_local_problems() -> Result<()> {
+        let api = new_mem_api();
+        let writer = api.open_connection(ConnectionType::ReadWrite)?;
+        insert_json_tree(
+            &writer,
+            json!({
+                "guid": String::from(BookmarkRootGuid::Unfiled.as_str()),
+                "children": [
+                    { "guid": "bookmarkAAAA", "url": "http://example.com/a" },
+                    { "guid": "bookmarkBBBB", "url": "http://example.com/b" },
+                    {
+                        "guid": "folderCCCCCC",
+                        "children": [
+                            { "guid": "bookmarkDDDD", "url": "http://example.com/d" },
+                            { "guid": "bookmarkEEEE", "url": "http://example.com/e" },
+                        ],
+                    },
+                ],
+            }),
+        );
+        insert_json_tree(
+            &writer,
+            json!({
+                "guid": String::from(BookmarkRootGuid::Menu.as_str()),
+                "children": [
+                    { "guid": "bookmarkFFFF", "url": "http://example.com/f" },
+                ],
+            }),
+        );
+        assert!(api.validate_bookmarks(false)?.is_empty());
+
+        writer.execute_batch(
+            "DELETE FROM moz_bookmarks WHERE guid = 'toolbar_____';
+
+             UPDATE moz_bookmarks SET
+               parent = (SELECT id FROM moz_bookmarks WHERE guid = 'menu________'),
+               position = 1
+             WHERE guid = 'mobile______';
+
+             UPDATE moz_bookmarks SET
+               parent = (SELECT id FROM moz_bookmarks WHERE guid = 'bookmarkAAAA'),
+               position = 0
+             WHERE guid = 'bookmarkBBBB';
+
+             UPDATE moz_bookmarks SET
+               position = 5
+             WHERE guid = 'bookmarkFFFF';
+
+             UPDATE moz_bookmarks SET
+               syncChangeCounter = 0;
+
+             PRAGMA foreign_keys = OFF;
+             UPDATE moz_bookmarks SET
+               parent = 9999
+             WHERE guid = 'bookmarkEEEE';",
+        )?;
+        writer.execute_batch("PRAGMA foreign_keys = ON")?;
+
+        let problems = api.validate_bookmarks(false)?;
+        assert_eq!(
+            problems,
+            ValidationProblems {
+                missing_roots: 1,
+                misparented_roots: 1,
+                orphans: 1,
+                non_folder_parents: 1,
+                // The Places root, menu, and unfiled.
+                position_gaps: 3,
+                ..ValidationProblems::default()
+            }
+        );
+        assert!(problems.has_local_problems());
+        assert_eq!(
+            serde_json::to_value(problems.to_telemetry()).unwrap(),
+            json!({
+                "version": VALIDATION_VERSION,
+                "problems": [
+                    { "name": "missingRoots", "count": 1 },
+                    { "name": "misparentedRoots", "count": 1 },
+                    { "name": "orphans", "count": 1 },
+                    { "name": "nonFolderParents", "count": 1 },
+                    { "name": "positionGaps", "count": 3 },
+                ],
+            })
+        );
+
+        // Repairing returns the problems it found, and fixes them.
+        assert_eq!(api.validate_bookmarks(true)?, problems);
+        assert!(api.validate_bookmarks(false)?.is_empty());
+
+        assert_json_tree(
+            &writer,
+            &BookmarkRootGuid::Root.into(),
+            json!({
+                "guid": String::from(BookmarkRootGuid::Root.as_str()),
+                "children": [
+                    {
+                        "guid": String::from(BookmarkRootGuid::Menu.as_str()),
+                        "children": [
+                            { "guid": "bookmarkFFFF", "url": "http://example.com/f" },
+                        ],
+                    },
+                    {
+                        "guid": String::from(BookmarkRootGuid::Unfiled.as_str()),
+                        "children": [
+                            { "guid": "bookmarkAAAA", "url": "http://example.com/a" },
+                            {
+                                "guid": "folderCCCCCC",
+                                "children": [
+                                    { "guid": "bookmarkDDDD", "url": "http://example.com/d" },
+                                ],
+                            },
+                            { "guid": "bookmarkBBBB", "url": "http://example.com/b" },
+                            { "guid": "bookmarkEEEE", "url": "http://example.com/e" },
+                        ],
+                    },
+                    {
+                        "guid": String::from(BookmarkRootGuid::Toolbar.as_str()),
+                        "children": [],
+                    },
+                    {
+                        "guid": String::from(BookmarkRootGuid::Mobile.as_str()),
+                        "children": [],
+                    },
+                ],
+            }),
+        );
+
+        // Everything we changed should be flagged for upload.
+        let mut changed: Vec<String> = writer.query_rows_and_then_named(
+            "SELECT guid FROM moz_bookmarks WHERE syncChangeCounter > 0",
+            &[],
+            |row| row.get(0),
+        )?;
+        changed.sort();
+        assert_eq!(
+            changed,
+            vec![
+                "bookmarkBBBB",
+                "bookmarkEEEE",
+                "menu________",
+                "mobile______",
+                "root________",
+                "toolbar_____",
+                "unfiled_____",
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mirror_problems() -> Result<()> {
+        let api = new_mem_api();
+        let syncer = api.open_sync_connection()?;
+        syncer.execute_batch(
+            "INSERT INTO moz_bookmarks_synced(guid, parentGuid, kind)
+             VALUES('folderAAAAAA', 'menu________', 3),
+                   ('bookmarkBBBB', 'unfiled_____', 1),
+                   ('bookmarkDDDD', 'menu________', 1),
+                   ('bookmarkEEEE', 'folderAAAAAA', 1);
+
+             INSERT INTO moz_bookmarks_synced_structure(guid, parentGuid, position)
+             VALUES('folderAAAAAA', 'menu________', 0),
+                   ('bookmarkEEEE', 'menu________', 1),
+                   ('bookmarkBBBB', 'folderAAAAAA', 0),
+                   ('bookmarkCCCC', 'folderAAAAAA', 1),
+                   ('bookmarkEEEE', 'folderAAAAAA', 2);",
+        )?;
+        drop(syncer);
+
+        let problems = api.validate_bookmarks(true)?;
+        assert_eq!(
+            problems,
+            ValidationProblems {
+                // D isn't in any folder's children.
+                mirror_orphans: 1,
+                // E is in both the menu and A.
+                multiple_parents: 1,
+                // B says its parent is unfiled, but it's in A.
+                parent_child_disagreements: 1,
+                // C doesn't exist.
+                missing_children: 1,
+                ..ValidationProblems::default()
+            }
+        );
+        assert!(!problems.has_local_problems());
+
+        // We can't repair the mirror.
+        assert_eq!(api.validate_bookmarks(false)?, problems);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_interrupted() -> Result<()> {
+        let api = new_mem_api();
+        let syncer = api.open_sync_connection()?;
+        let scope = syncer.begin_interrupt_scope();
+        syncer.new_interrupt_handle().interrupt();
+        match validate(&syncer, &scope)
+            .expect_err("Should fail to validate after interrupting")
+            .kind()
+        {
+            ErrorKind::InterruptedError(_) => {}
+            kind => panic!("Unexpected error {:?}", kind),
+        }
+        Ok(())
+    }
+}
@@ -5,12 +5,17 @@
 use super::schema;
 use crate::api::places_api::ConnectionType;
 use crate::error::*;
+use crate::frecency::FrecencySettings;
+use crate::observer::{PlacesEvent, PlacesObservers};
+use crate::search_engines::SearchEngines;
+use crate::storage::bookmarks::undo::UndoLog;
 use rusqlite::Connection;
 use sql_support::{ConnExt, SqlInterruptHandle, SqlInterruptScope};
+use std::cell::RefCell;
 use std::ops::Deref;
 use std::path::Path;
 
-use std::sync::{atomic::AtomicUsize, Arc, Mutex};
+use std::sync::{atomic::AtomicUsize, Arc, Mutex, RwLock, RwLockReadGuard};
 
 pub const MAX_VARIABLE_NUMBER: usize = 999;
 
@@ -21,6 +26,18 @@ pub struct PlacesDb {
     interrupt_counter: Arc<AtomicUsize>,
     api_id: usize,
     pub(super) coop_tx_lock: Arc<Mutex<()>>,
+    observers: Arc<PlacesObservers>,
+    // Shared by all connections for the same API, so that changing the
+    // settings on one connection affects the others.
+    frecency_settings: Arc<RwLock<FrecencySettings>>,
+    // Also shared, so that we can extract search terms from visits made on
+    // any connection.
+    search_engines: Arc<RwLock<SearchEngines>>,
+    // Events recorded by the current transaction, which are delivered to
+    // the observers when it commits.
+    pending_events: RefCell<Vec<PlacesEvent>>,
+    // Inverses of recent bookmark changes made on this connection.
+    bookmark_undo_log: RefCell<UndoLog>,
 }
 
 impl PlacesDb {
@@ -29,6 +46,9 @@ impl PlacesDb {
         conn_type: ConnectionType,
         api_id: usize,
         coop_tx_lock: Arc<Mutex<()>>,
+        observers: Arc<PlacesObservers>,
+        frecency_settings: Arc<RwLock<FrecencySettings>>,
+        search_engines: Arc<RwLock<SearchEngines>>,
     ) -> Result<Self> {
         let initial_pragmas = "
             -- The value we use was taken from Desktop Firefox, and seems necessary to
@@ -76,6 +96,11 @@ impl PlacesDb {
             api_id,
             interrupt_counter: Arc::new(AtomicUsize::new(0)),
             coop_tx_lock,
+            observers,
+            frecency_settings,
+            search_engines,
+            pending_events: RefCell::new(Vec::new()),
+            bookmark_undo_log: RefCell::default(),
         };
         match res.conn_type() {
             // For read-only connections, we can avoid opening a transaction,
@@ -98,12 +123,18 @@ impl PlacesDb {
         conn_type: ConnectionType,
         api_id: usize,
         coop_tx_lock: Arc<Mutex<()>>,
+        observers: Arc<PlacesObservers>,
+        frecency_settings: Arc<RwLock<FrecencySettings>>,
+        search_engines: Arc<RwLock<SearchEngines>>,
     ) -> Result<Self> {
         Ok(Self::with_connection(
             Connection::open_with_flags(path, conn_type.rusqlite_flags())?,
             conn_type,
             api_id,
             coop_tx_lock,
+            observers,
+            frecency_settings,
+            search_engines,
         )?)
     }
 
@@ -116,6 +147,9 @@ impl PlacesDb {
             conn_ty,
             0,
             Arc::new(Mutex::new(())),
+            Default::default(),
+            Default::default(),
+            Default::default(),
         )?)
     }
 
@@ -140,6 +174,54 @@ impl PlacesDb {
     pub fn api_id(&self) -> usize {
         self.api_id
     }
+
+    /// Returns the settings used to calculate frecencies.
+    pub fn frecency_settings(&self) -> FrecencySettings {
+        self.frecency_settings.read().unwrap().clone()
+    }
+
+    /// Replaces the settings used to calculate frecencies by all connections
+    /// for this API. This doesn't persist the settings; use
+    /// `storage::set_frecency_settings` for that.
+    pub(crate) fn replace_frecency_settings(&self, settings: FrecencySettings) {
+        *self.frecency_settings.write().unwrap() = settings;
+    }
+
+    /// Returns the search engines used to extract search terms from visits.
+    pub fn search_engines(&self) -> RwLockReadGuard<'_, SearchEngines> {
+        self.search_engines.read().unwrap()
+    }
+
+    /// Records an event to be delivered to the observers. If we're in a
+    /// transaction, the event is held until it commits; otherwise the change
+    /// has already been written, so it's delivered immediately.
+    pub(crate) fn note_event(&self, event: PlacesEvent) {
+        if self.observers.is_empty() {
+            return;
+        }
+        if self.db.is_autocommit() {
+            self.observers.notify(&[event]);
+        } else {
+            self.pending_events.borrow_mut().push(event);
+        }
+    }
+
+    /// Returns the log of recent bookmark changes made on this connection,
+    /// which `undo_last_change` uses to reverse them.
+    pub(crate) fn bookmark_undo_log(&self) -> &RefCell<UndoLog> {
+        &self.bookmark_undo_log
+    }
+
+    /// Delivers the events recorded by a transaction which just committed.
+    pub(crate) fn flush_events(&self) {
+        let events = self.pending_events.replace(Vec::new());
+        self.observers.notify(&events);
+    }
+
+    /// Forgets any events recorded by a transaction which didn't commit.
+    pub(crate) fn discard_events(&self) {
+        self.pending_events.borrow_mut().clear();
+    }
 }
 
 impl Drop for PlacesDb {
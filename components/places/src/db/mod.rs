@@ -5,8 +5,11 @@
 // We don't want 'db.rs' as a sub-module. We could move the contents here? Or something else?
 #[allow(clippy::module_inception)] // FIXME
 pub mod db;
+pub(crate) mod reader_pool;
+pub(crate) mod recovery;
 mod schema;
 mod tx;
+pub use self::reader_pool::{PooledConnection, PooledInterruptHandle, DEFAULT_MAX_READERS};
 pub use self::tx::PlacesTransaction;
 
 pub use crate::db::db::PlacesDb;
@@ -0,0 +1,376 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A bounded pool of read-only connections, shared by everything that reads
+//! from the same `PlacesApi`. Opening a connection is relatively expensive,
+//! and each connection has its own cache of prepared statements, so reusing
+//! them makes things like autocomplete and history views much faster than
+//! opening a new connection for each.
+
+use super::PlacesDb;
+use crate::api::places_api::ConnectionType;
+use crate::error::*;
+use rusqlite::NO_PARAMS;
+use sql_support::SqlInterruptHandle;
+use std::ops::Deref;
+use std::sync::{Arc, Condvar, Mutex};
+
+/// The maximum number of read-only connections a pool will open by default.
+pub const DEFAULT_MAX_READERS: usize = 4;
+
+struct IdleReader {
+    db: PlacesDb,
+    // The schema version when the connection was opened, so that we can
+    // tell if the schema has changed since.
+    schema_version: i64,
+    // The pool generation when the connection was opened. See
+    // `ReaderPool::clear`.
+    generation: usize,
+}
+
+#[derive(Default)]
+struct PoolState {
+    idle: Vec<IdleReader>,
+    // The number of connections that are either idle or checked out.
+    open: usize,
+    max_readers: usize,
+    generation: usize,
+}
+
+pub(crate) struct ReaderPool {
+    state: Mutex<PoolState>,
+    returned: Condvar,
+}
+
+impl ReaderPool {
+    pub(crate) fn new(max_readers: usize) -> Self {
+        Self {
+            state: Mutex::new(PoolState {
+                max_readers: max_readers.max(1),
+                ..PoolState::default()
+            }),
+            returned: Condvar::new(),
+        }
+    }
+
+    /// Changes the maximum number of connections. If there are more than that
+    /// many open, the extras are closed as they're returned.
+    pub(crate) fn set_max_readers(&self, max_readers: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.max_readers = max_readers.max(1);
+        while state.open > state.max_readers && !state.idle.is_empty() {
+            state.idle.pop();
+            state.open -= 1;
+        }
+        self.returned.notify_all();
+    }
+
+    /// Closes all idle connections. Checked out connections are closed when
+    /// they're returned, instead of going back into the pool.
+    pub(crate) fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.open -= state.idle.len();
+        state.idle.clear();
+        state.generation += 1;
+        self.returned.notify_all();
+    }
+
+    /// Checks out an idle connection, or calls `open` to open a new one if
+    /// there aren't any, and the pool isn't full. If it's full, this blocks
+    /// until another connection is returned.
+    pub(crate) fn checkout(
+        self: &Arc<Self>,
+        open: impl Fn() -> Result<PlacesDb>,
+    ) -> Result<PooledConnection> {
+        Ok(self
+            .checkout_or_wait(open, true)?
+            .expect("Blocking checkout should return a connection"))
+    }
+
+    /// Like `checkout`, but returns `None` instead of blocking if the pool is
+    /// full.
+    pub(crate) fn try_checkout(
+        self: &Arc<Self>,
+        open: impl Fn() -> Result<PlacesDb>,
+    ) -> Result<Option<PooledConnection>> {
+        self.checkout_or_wait(open, false)
+    }
+
+    fn checkout_or_wait(
+        self: &Arc<Self>,
+        open: impl Fn() -> Result<PlacesDb>,
+        wait: bool,
+    ) -> Result<Option<PooledConnection>> {
+        loop {
+            let (idle, generation) = {
+                let mut state = self.state.lock().unwrap();
+                loop {
+                    if let Some(idle) = state.idle.pop() {
+                        break (Some(idle), state.generation);
+                    }
+                    if state.open < state.max_readers {
+                        state.open += 1;
+                        break (None, state.generation);
+                    }
+                    if !wait {
+                        return Ok(None);
+                    }
+                    state = self.returned.wait(state).unwrap();
+                }
+            };
+            match idle {
+                Some(idle) => {
+                    // Statements prepared before a schema change would need to
+                    // be prepared again anyway, so we close connections that
+                    // have seen one, instead of keeping a cache of stale
+                    // statements around.
+                    if matches!(schema_version(&idle.db), Ok(v) if v == idle.schema_version) {
+                        return Ok(Some(PooledConnection::new(self.clone(), idle)));
+                    }
+                    log::debug!("Schema changed; recycling pooled reader");
+                    drop(idle);
+                    self.forget_one();
+                }
+                None => {
+                    let opened = open().and_then(|db| {
+                        assert_eq!(db.conn_type(), ConnectionType::ReadOnly);
+                        let schema_version = schema_version(&db)?;
+                        Ok(IdleReader {
+                            db,
+                            schema_version,
+                            generation,
+                        })
+                    });
+                    return match opened {
+                        Ok(idle) => Ok(Some(PooledConnection::new(self.clone(), idle))),
+                        Err(e) => {
+                            self.forget_one();
+                            Err(e)
+                        }
+                    };
+                }
+            }
+        }
+    }
+
+    fn checkin(&self, reader: IdleReader) {
+        let mut state = self.state.lock().unwrap();
+        // A reader left in a transaction, or returned after the pool shrank
+        // or was cleared, is closed instead of being reused.
+        if reader.db.is_autocommit()
+            && state.open <= state.max_readers
+            && reader.generation == state.generation
+        {
+            state.idle.push(reader);
+        } else {
+            state.open -= 1;
+        }
+        self.returned.notify_one();
+    }
+
+    // Notes that a checked out connection was closed, instead of returned.
+    fn forget_one(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.open -= 1;
+        self.returned.notify_one();
+    }
+
+    #[cfg(test)]
+    fn counts(&self) -> (usize, usize) {
+        let state = self.state.lock().unwrap();
+        (state.idle.len(), state.open)
+    }
+}
+
+fn schema_version(db: &PlacesDb) -> Result<i64> {
+    Ok(db.query_row("PRAGMA schema_version", NO_PARAMS, |row| row.get(0))?)
+}
+
+/// A read-only connection checked out from a `PlacesApi`'s reader pool. It
+/// goes back into the pool when it's dropped.
+pub struct PooledConnection {
+    pool: Arc<ReaderPool>,
+    reader: Option<IdleReader>,
+    checked_out: Arc<Mutex<bool>>,
+}
+
+impl PooledConnection {
+    fn new(pool: Arc<ReaderPool>, reader: IdleReader) -> Self {
+        Self {
+            pool,
+            reader: Some(reader),
+            checked_out: Arc::new(Mutex::new(true)),
+        }
+    }
+
+    /// Returns a handle that interrupts queries on this connection, for as
+    /// long as it's checked out. Once the connection is returned to the pool,
+    /// the handle does nothing, so it can't interrupt whoever checks out the
+    /// connection next.
+    pub fn new_interrupt_handle(&self) -> PooledInterruptHandle {
+        PooledInterruptHandle {
+            handle: self.deref().new_interrupt_handle(),
+            checked_out: self.checked_out.clone(),
+        }
+    }
+}
+
+impl Deref for PooledConnection {
+    type Target = PlacesDb;
+    #[inline]
+    fn deref(&self) -> &PlacesDb {
+        &self.reader.as_ref().unwrap().db
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        // Holding the lock while we return the connection means that an
+        // interrupt is either delivered before we return it, or not at all.
+        let mut checked_out = self.checked_out.lock().unwrap();
+        *checked_out = false;
+        if let Some(reader) = self.reader.take() {
+            self.pool.checkin(reader);
+        }
+    }
+}
+
+/// Interrupts queries on a pooled connection. See
+/// `PooledConnection::new_interrupt_handle`.
+pub struct PooledInterruptHandle {
+    handle: SqlInterruptHandle,
+    checked_out: Arc<Mutex<bool>>,
+}
+
+impl PooledInterruptHandle {
+    pub fn interrupt(&self) {
+        let checked_out = self.checked_out.lock().unwrap();
+        if *checked_out {
+            self.handle.interrupt();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_api;
+    use crate::api::places_api::PlacesApi;
+    use interrupt_support::Interruptee;
+    use std::cell::Cell;
+    use std::thread;
+    use std::time::Duration;
+
+    fn open_reader(api: &PlacesApi) -> impl Fn() -> Result<PlacesDb> + '_ {
+        move || api.open_connection(ConnectionType::ReadOnly)
+    }
+
+    #[test]
+    fn test_reuse() -> Result<()> {
+        let api = new_mem_api();
+        let pool = Arc::new(ReaderPool::new(2));
+        let first = pool.checkout(open_reader(&api))?;
+        let second = pool.checkout(open_reader(&api))?;
+        assert_eq!(pool.counts(), (0, 2));
+        drop(first);
+        drop(second);
+        assert_eq!(pool.counts(), (2, 2));
+
+        // Checking out again should reuse an idle connection.
+        let reader = pool.checkout(|| panic!("Shouldn't open a new connection"))?;
+        assert_eq!(pool.counts(), (1, 2));
+        drop(reader);
+
+        pool.set_max_readers(1);
+        assert_eq!(pool.counts(), (1, 1));
+
+        let reader = pool.checkout(open_reader(&api))?;
+        pool.clear();
+        assert_eq!(pool.counts(), (0, 1));
+        // Connections checked out before clearing aren't reused.
+        drop(reader);
+        assert_eq!(pool.counts(), (0, 0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocks_when_full() -> Result<()> {
+        let api = new_mem_api();
+        let pool = Arc::new(ReaderPool::new(1));
+        let reader = pool.checkout(open_reader(&api))?;
+        let waiter = {
+            let pool = pool.clone();
+            thread::spawn(move || {
+                let reader = pool.checkout(|| panic!("Shouldn't open a new connection"));
+                reader.is_ok()
+            })
+        };
+        thread::sleep(Duration::from_millis(50));
+        drop(reader);
+        assert!(waiter.join().unwrap());
+        assert_eq!(pool.counts(), (1, 1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_try_checkout() -> Result<()> {
+        let api = new_mem_api();
+        let pool = Arc::new(ReaderPool::new(1));
+        let reader = pool
+            .try_checkout(open_reader(&api))?
+            .expect("Should open a connection");
+        // The pool is full, so we shouldn't wait for the connection to be
+        // returned.
+        assert!(pool
+            .try_checkout(|| panic!("Shouldn't open a new connection"))?
+            .is_none());
+        drop(reader);
+        assert!(pool
+            .try_checkout(|| panic!("Shouldn't open a new connection"))?
+            .is_some());
+        assert_eq!(pool.counts(), (1, 1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_recycle_after_schema_change() -> Result<()> {
+        let api = new_mem_api();
+        let pool = Arc::new(ReaderPool::new(2));
+        drop(pool.checkout(open_reader(&api))?);
+
+        let writer = api.open_connection(ConnectionType::ReadWrite)?;
+        writer.execute_batch("CREATE TABLE moz_test(id INTEGER PRIMARY KEY)")?;
+
+        // The idle connection has seen the old schema, so we should close it,
+        // and open a new one.
+        let opened = Cell::new(0);
+        let reader = pool.checkout(|| {
+            opened.set(opened.get() + 1);
+            api.open_connection(ConnectionType::ReadOnly)
+        })?;
+        assert_eq!(opened.get(), 1);
+        drop(reader);
+        assert_eq!(pool.counts(), (1, 1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_interrupt_handle() -> Result<()> {
+        let api = new_mem_api();
+        let pool = Arc::new(ReaderPool::new(1));
+        let reader = pool.checkout(open_reader(&api))?;
+        let handle = reader.new_interrupt_handle();
+        let scope = reader.begin_interrupt_scope();
+        handle.interrupt();
+        assert!(scope.was_interrupted());
+        drop(reader);
+
+        // Once returned, the handle shouldn't affect the next user.
+        let reader = pool.checkout(open_reader(&api))?;
+        let scope = reader.begin_interrupt_scope();
+        handle.interrupt();
+        assert!(!scope.was_interrupted());
+        Ok(())
+    }
+}
@@ -0,0 +1,580 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Salvages history and bookmarks from a corrupt database, or one that we
+//! couldn't upgrade, into a fresh database. `PlacesApi` does this when
+//! opening a database fails, after moving the broken file aside.
+//!
+//! We only copy local data: pages, visits, search terms, tags, keywords,
+//! history metadata, the local bookmark tree, and the reading list. Sync
+//! metadata, the bookmark mirror, and tombstones are left behind, so all
+//! salvaged items are flagged as new, and the next sync will merge them with
+//! what's on the server, as if this were a first sync.
+
+use crate::bookmark_sync::validation;
+use crate::db::PlacesDb;
+use crate::error::*;
+use crate::storage::bookmarks::BookmarkRootGuid;
+use crate::storage::{delete_pending_temp_tables, RowId};
+use crate::types::SyncStatus;
+use rusqlite::types::Value;
+use rusqlite::{Connection, OpenFlags};
+use sql_support::{self, ConnExt, RecoveryReport, TableRecovery};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// Copies what we can read from the database at `corrupt_path` into `db`,
+/// which should be a freshly created database, and returns a report
+/// describing what was recovered.
+pub fn salvage(db: &PlacesDb, corrupt_path: &Path) -> Result<RecoveryReport> {
+    let mut report = RecoveryReport::new(corrupt_path);
+    // We read the corrupt database with a plain connection, since
+    // `PlacesDb` would try to upgrade it.
+    let src = match Connection::open_with_flags(
+        corrupt_path,
+        OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    ) {
+        Ok(src) => src,
+        Err(e) => {
+            log::warn!("Can't open the corrupt database: {}", e);
+            return Ok(report);
+        }
+    };
+    report.integrity_ok = sql_support::check_integrity(&src).unwrap_or(false);
+
+    let tx = db.begin_transaction()?;
+    report.tables.push(salvage_places(db, &src)?);
+    report.tables.push(salvage_visits(db, &src));
+    // Places keep their ids, so search terms, tags, keywords, and history
+    // metadata can be copied as is.
+    report.tables.push(sql_support::salvage_table(
+        &src,
+        db,
+        "moz_places_search_terms",
+        &["place_id", "search_term", "engine"],
+    ));
+    report.tables.push(sql_support::salvage_table(
+        &src,
+        db,
+        "moz_tags",
+        &["id", "tag", "lastModified"],
+    ));
+    report.tables.push(sql_support::salvage_table(
+        &src,
+        db,
+        "moz_tags_relation",
+        &["tag_id", "place_id"],
+    ));
+    report.tables.push(sql_support::salvage_table(
+        &src,
+        db,
+        "moz_keywords",
+        &["place_id", "keyword", "post_data"],
+    ));
+    report.tables.push(sql_support::salvage_table(
+        &src,
+        db,
+        "moz_places_metadata_search_queries",
+        &["id", "term"],
+    ));
+    report.tables.push(sql_support::salvage_table(
+        &src,
+        db,
+        "moz_places_metadata",
+        &[
+            "id",
+            "place_id",
+            "referrer_place_id",
+            "search_query_id",
+            "created_at",
+            "updated_at",
+            "total_view_time",
+            "document_type",
+        ],
+    ));
+    report.tables.push(salvage_bookmarks(db, &src)?);
+    report.tables.push(salvage_reading_list(db, &src));
+    tx.commit()?;
+
+    // Salvaging can leave gaps in folders, if we couldn't read some of their
+    // children, or add orphans to the unfiled root, so we run the same
+    // repair as bookmark validation to tidy up the tree.
+    let scope = db.begin_interrupt_scope();
+    validation::repair(db, &scope)?;
+
+    log::info!(
+        "Recovered {} rows from the corrupt database ({} failed)",
+        report.recovered(),
+        report.failed()
+    );
+    Ok(report)
+}
+
+fn salvage_places(db: &PlacesDb, src: &Connection) -> Result<TableRecovery> {
+    // Visit counts, last visit dates, and foreign counts are maintained by
+    // triggers as we copy visits and bookmarks, so we don't copy them.
+    let result = sql_support::salvage_rows(
+        src,
+        "moz_places",
+        &["id", "url", "title", "hidden", "typed", "frecency", "guid"],
+        |row| {
+            db.execute_named_cached(
+                "INSERT INTO moz_places(id, url, title, hidden, typed, frecency,
+                                        guid, url_hash, sync_status)
+                 VALUES(:id, :url, :title, :hidden, :typed, :frecency,
+                        :guid, hash(:url), :sync_status)",
+                &[
+                    (":id", &row.get::<_, Value>(0)?),
+                    (":url", &row.get::<_, Value>(1)?),
+                    (":title", &row.get::<_, Value>(2)?),
+                    (":hidden", &row.get::<_, Value>(3)?),
+                    (":typed", &row.get::<_, Value>(4)?),
+                    (":frecency", &row.get::<_, Value>(5)?),
+                    (":guid", &row.get::<_, Value>(6)?),
+                    (":sync_status", &(SyncStatus::New as u8)),
+                ],
+            )?;
+            Ok(())
+        },
+    );
+    delete_pending_temp_tables(db)?;
+    Ok(result)
+}
+
+fn salvage_visits(db: &PlacesDb, src: &Connection) -> TableRecovery {
+    sql_support::salvage_rows(
+        src,
+        "moz_historyvisits",
+        &[
+            "id",
+            "is_local",
+            "from_visit",
+            "place_id",
+            "visit_date",
+            "visit_type",
+        ],
+        |row| {
+            // Visits are copied in order, so the visit we came from should
+            // already be here, unless we couldn't recover it.
+            db.execute_named_cached(
+                "INSERT INTO moz_historyvisits(id, is_local, from_visit, place_id,
+                                               visit_date, visit_type)
+                 VALUES(:id, :is_local,
+                        (SELECT id FROM moz_historyvisits WHERE id = :from_visit),
+                        :place_id, :visit_date, :visit_type)",
+                &[
+                    (":id", &row.get::<_, Value>(0)?),
+                    (":is_local", &row.get::<_, Value>(1)?),
+                    (":from_visit", &row.get::<_, Value>(2)?),
+                    (":place_id", &row.get::<_, Value>(3)?),
+                    (":visit_date", &row.get::<_, Value>(4)?),
+                    (":visit_type", &row.get::<_, Value>(5)?),
+                ],
+            )?;
+            Ok(())
+        },
+    )
+}
+
+fn salvage_reading_list(db: &PlacesDb, src: &Connection) -> TableRecovery {
+    sql_support::salvage_rows(
+        src,
+        "moz_reading_list",
+        &[
+            "guid",
+            "url",
+            "title",
+            "excerpt",
+            "source_device",
+            "added_at",
+            "read_at",
+        ],
+        |row| {
+            db.execute_named_cached(
+                "INSERT INTO moz_reading_list(guid, url, title, excerpt, source_device,
+                                              added_at, read_at)
+                 VALUES(:guid, :url, :title, :excerpt, :source_device,
+                        :added_at, :read_at)",
+                &[
+                    (":guid", &row.get::<_, Value>(0)?),
+                    (":url", &row.get::<_, Value>(1)?),
+                    (":title", &row.get::<_, Value>(2)?),
+                    (":excerpt", &row.get::<_, Value>(3)?),
+                    (":source_device", &row.get::<_, Value>(4)?),
+                    (":added_at", &row.get::<_, Value>(5)?),
+                    (":read_at", &row.get::<_, Value>(6)?),
+                ],
+            )?;
+            Ok(())
+        },
+    )
+}
+
+/// A bookmark read from the corrupt database.
+struct SalvagedItem {
+    id: i64,
+    fk: Value,
+    kind: Value,
+    parent: Option<i64>,
+    position: Value,
+    title: Value,
+    date_added: Value,
+    last_modified: Value,
+    guid: String,
+}
+
+fn salvage_bookmarks(db: &PlacesDb, src: &Connection) -> Result<TableRecovery> {
+    // Children can come before their parents in rowid order, so we read all
+    // the items we can first, then insert them parents-first.
+    let mut items = Vec::new();
+    let mut result = sql_support::salvage_rows(
+        src,
+        "moz_bookmarks",
+        &[
+            "id",
+            "fk",
+            "type",
+            "parent",
+            "position",
+            "title",
+            "dateAdded",
+            "lastModified",
+            "guid",
+        ],
+        |row| {
+            items.push(SalvagedItem {
+                id: row.get(0)?,
+                fk: row.get(1)?,
+                kind: row.get(2)?,
+                parent: row.get(3)?,
+                position: row.get(4)?,
+                title: row.get(5)?,
+                date_added: row.get(6)?,
+                last_modified: row.get(7)?,
+                guid: row.get(8)?,
+            });
+            Ok(())
+        },
+    );
+    let read = result.recovered + result.failed;
+
+    // The fresh database already has the roots, so we map the old roots to
+    // the new ones, instead of copying them.
+    let mut new_ids: HashMap<i64, RowId> = HashMap::new();
+    let mut pending = Vec::with_capacity(items.len());
+    for item in items {
+        if BookmarkRootGuid::well_known(&item.guid).is_some() {
+            let new_id = db.query_row_and_then_named(
+                "SELECT id FROM moz_bookmarks WHERE guid = :guid",
+                &[(":guid", &item.guid)],
+                |row| row.get::<_, RowId>(0),
+                true,
+            )?;
+            new_ids.insert(item.id, new_id);
+        } else {
+            pending.push(item);
+        }
+    }
+    let mut recovered = new_ids.len();
+
+    let unfiled_id = db.query_row_and_then_named(
+        "SELECT id FROM moz_bookmarks WHERE guid = :guid",
+        &[(":guid", &BookmarkRootGuid::Unfiled.as_guid())],
+        |row| row.get::<_, RowId>(0),
+        true,
+    )?;
+    while !pending.is_empty() {
+        let (ready, waiting): (Vec<_>, Vec<_>) = pending
+            .into_iter()
+            .partition(|item| matches!(item.parent, Some(p) if new_ids.contains_key(&p)));
+        pending = waiting;
+        if ready.is_empty() {
+            // Everything left is either an orphan, a descendant of an item
+            // we couldn't copy, or part of a cycle. Move the topmost one to
+            // unfiled, which unblocks its descendants.
+            let pending_ids = pending.iter().map(|item| item.id).collect::<HashSet<_>>();
+            let index = pending
+                .iter()
+                .position(|item| !matches!(item.parent, Some(p) if pending_ids.contains(&p)))
+                .unwrap_or(0);
+            let item = pending.remove(index);
+            if insert_item(db, &item, unfiled_id, None, &mut new_ids) {
+                recovered += 1;
+            }
+            continue;
+        }
+        for item in ready {
+            let parent_id = new_ids[&item.parent.unwrap()];
+            if insert_item(db, &item, parent_id, Some(&item.position), &mut new_ids) {
+                recovered += 1;
+            }
+        }
+    }
+
+    result.recovered = recovered;
+    result.failed = read - recovered;
+    Ok(result)
+}
+
+/// Inserts a salvaged item into `parent_id`, at `position` if given, or at
+/// the end of the folder otherwise. Returns false if the item couldn't be
+/// inserted; for example, if it's a bookmark whose page we couldn't recover.
+fn insert_item(
+    db: &PlacesDb,
+    item: &SalvagedItem,
+    parent_id: RowId,
+    position: Option<&Value>,
+    new_ids: &mut HashMap<i64, RowId>,
+) -> bool {
+    let inserted = db.execute_named_cached(
+        "INSERT INTO moz_bookmarks(fk, type, parent, position, title, dateAdded,
+                                   lastModified, guid, syncStatus, syncChangeCounter)
+         VALUES(:fk, :kind, :parent,
+                IFNULL(:position, (SELECT IFNULL(MAX(position) + 1, 0)
+                                   FROM moz_bookmarks WHERE parent = :parent)),
+                :title, :date_added, :last_modified, :guid, :sync_status, 1)",
+        &[
+            (":fk", &item.fk),
+            (":kind", &item.kind),
+            (":parent", &parent_id),
+            (":position", &position),
+            (":title", &item.title),
+            (":date_added", &item.date_added),
+            (":last_modified", &item.last_modified),
+            (":guid", &item.guid),
+            (":sync_status", &(SyncStatus::New as u8)),
+        ],
+    );
+    match inserted {
+        Ok(_) => {
+            new_ids.insert(item.id, RowId(db.conn().last_insert_rowid()));
+            true
+        }
+        Err(e) => {
+            log::debug!("Failed to copy bookmark {}: {}", item.guid, e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::{ConnectionType, PlacesApi};
+    use crate::observation::VisitObservation;
+    use crate::storage::bookmarks::{insert_bookmark, InsertableItem};
+    use crate::tests::{assert_json_tree, insert_json_tree};
+    use crate::types::{Timestamp, VisitTransition};
+    use serde_json::json;
+    use url::Url;
+
+    #[test]
+    fn test_salvage() -> Result<()> {
+        let _ = env_logger::try_init();
+        let dir = tempfile::tempdir().unwrap();
+        let corrupt_path = dir.path().join("corrupt.sqlite");
+        {
+            let api = PlacesApi::new(&corrupt_path)?;
+            let mut conn = api.open_connection(ConnectionType::ReadWrite)?;
+            let now = Timestamp::now();
+            for (i, url) in ["https://example.com/a", "https://example.com/b"]
+                .iter()
+                .enumerate()
+            {
+                crate::apply_observation(
+                    &mut conn,
+                    VisitObservation::new(Url::parse(url).unwrap())
+                        .with_title(Some(format!("Page {}", i)))
+                        .with_visit_type(VisitTransition::Link)
+                        .with_at(now),
+                )?;
+            }
+            insert_json_tree(
+                &conn,
+                json!({
+                    "guid": &BookmarkRootGuid::Unfiled.as_guid(),
+                    "children": [
+                        {
+                            "guid": "folder1_____",
+                            "title": "A folder",
+                            "children": [
+                                {
+                                    "guid": "bookmark1___",
+                                    "title": "A",
+                                    "url": "https://example.com/a",
+                                },
+                                {
+                                    "guid": "bookmark2___",
+                                    "title": "C",
+                                    "url": "https://example.com/c",
+                                },
+                            ],
+                        },
+                    ],
+                }),
+            );
+            // Make the folder an orphan, and reparent a bookmark into it. The
+            // folder is inserted after its child, so salvaging has to
+            // reorder them.
+            insert_bookmark(
+                &conn,
+                &InsertableItem::Separator(crate::storage::bookmarks::InsertableSeparator {
+                    parent_guid: BookmarkRootGuid::Toolbar.as_guid(),
+                    position: crate::storage::bookmarks::BookmarkPosition::Append,
+                    date_added: None,
+                    last_modified: None,
+                    guid: Some("separator1__".into()),
+                }),
+            )?;
+            conn.execute_batch(
+                "PRAGMA foreign_keys = OFF;
+                 UPDATE moz_bookmarks SET id = 1000 WHERE guid = 'folder1_____';
+                 UPDATE moz_bookmarks SET parent = 1000
+                 WHERE guid IN ('bookmark1___', 'bookmark2___');
+                 UPDATE moz_bookmarks SET parent = 999 WHERE guid = 'folder1_____';
+                 DELETE FROM moz_historyvisits
+                 WHERE place_id = (SELECT id FROM moz_places
+                                   WHERE url = 'https://example.com/b');
+                 PRAGMA foreign_keys = ON;",
+            )?;
+            // Tag and add a keyword to a bookmarked page, and add history
+            // metadata for the other page.
+            conn.execute_batch(
+                "INSERT INTO moz_tags(id, tag, lastModified) VALUES(1, 'later', 1);
+                 INSERT INTO moz_tags_relation(tag_id, place_id)
+                 SELECT 1, id FROM moz_places WHERE url = 'https://example.com/a';
+                 INSERT INTO moz_keywords(place_id, keyword, post_data)
+                 SELECT id, 'ex', 'q=%s' FROM moz_places
+                 WHERE url = 'https://example.com/a';
+                 INSERT INTO moz_places_metadata_search_queries(id, term)
+                 VALUES(1, 'example');
+                 INSERT INTO moz_places_metadata(place_id, search_query_id, created_at,
+                                                 updated_at, total_view_time)
+                 SELECT id, 1, 1, 2, 3 FROM moz_places
+                 WHERE url = 'https://example.com/b';",
+            )?;
+            crate::storage::reading_list::add_item(
+                &conn,
+                &Url::parse("https://example.com/later")?,
+                Some("Read me later"),
+                None,
+                None,
+            )?;
+            api.close_connection(conn)?;
+        }
+
+        let api = PlacesApi::new(dir.path().join("fresh.sqlite"))?;
+        let conn = api.open_connection(ConnectionType::ReadWrite)?;
+        let report = salvage(&conn, &corrupt_path)?;
+        assert!(report.integrity_ok);
+        let summary = report
+            .tables
+            .iter()
+            .map(|t| (t.table.as_str(), t.recovered, t.failed, t.complete))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("moz_places", 3, 0, true),
+                ("moz_historyvisits", 1, 0, true),
+                ("moz_places_search_terms", 0, 0, true),
+                ("moz_tags", 1, 0, true),
+                ("moz_tags_relation", 1, 0, true),
+                ("moz_keywords", 1, 0, true),
+                ("moz_places_metadata_search_queries", 1, 0, true),
+                ("moz_places_metadata", 1, 0, true),
+                ("moz_bookmarks", 9, 0, true),
+                ("moz_reading_list", 1, 0, true),
+            ]
+        );
+
+        let (visit_count, last_visit, foreign_count): (i64, i64, i64) = conn.query_row_and_then(
+            "SELECT visit_count_local, last_visit_date_local, foreign_count
+             FROM moz_places WHERE url = 'https://example.com/a'",
+            rusqlite::NO_PARAMS,
+            |row| -> rusqlite::Result<_> { Ok((row.get(0)?, row.get(1)?, row.get(2)?)) },
+        )?;
+        assert_eq!(visit_count, 1);
+        assert!(last_visit > 0);
+        // One each for the bookmark, the tag, and the keyword.
+        assert_eq!(foreign_count, 3);
+        assert_eq!(
+            crate::storage::tags::get_tags_for_url(&conn, &Url::parse("https://example.com/a")?)?,
+            vec!["later".to_string()]
+        );
+        assert_eq!(
+            conn.query_one::<String>("SELECT post_data FROM moz_keywords WHERE keyword = 'ex'")?,
+            "q=%s"
+        );
+        assert_eq!(
+            conn.query_one::<String>(
+                "SELECT q.term FROM moz_places_metadata m
+                 JOIN moz_places h ON h.id = m.place_id
+                 JOIN moz_places_metadata_search_queries q ON q.id = m.search_query_id
+                 WHERE h.url = 'https://example.com/b' AND m.total_view_time = 3"
+            )?,
+            "example"
+        );
+        assert_eq!(
+            conn.query_one::<i64>(
+                "SELECT COUNT(*) FROM moz_bookmarks
+                 WHERE syncStatus <> 1 AND guid NOT IN (SELECT guid FROM moz_bookmarks
+                                                       WHERE parent IS NULL)"
+            )?,
+            0
+        );
+
+        // The orphaned folder should be moved to unfiled, with its children.
+        assert_json_tree(
+            &conn,
+            &BookmarkRootGuid::Unfiled.as_guid(),
+            json!({
+                "guid": &BookmarkRootGuid::Unfiled.as_guid(),
+                "children": [
+                    {
+                        "guid": "folder1_____",
+                        "title": "A folder",
+                        "children": [
+                            {
+                                "guid": "bookmark1___",
+                                "title": "A",
+                                "url": "https://example.com/a",
+                                "tags": ["later"],
+                            },
+                            {
+                                "guid": "bookmark2___",
+                                "title": "C",
+                                "url": "https://example.com/c",
+                            },
+                        ],
+                    },
+                ],
+            }),
+        );
+        assert_json_tree(
+            &conn,
+            &BookmarkRootGuid::Toolbar.as_guid(),
+            json!({
+                "guid": &BookmarkRootGuid::Toolbar.as_guid(),
+                "children": [{"guid": "separator1__", "type": 3}],
+            }),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_salvage_garbage() -> Result<()> {
+        let dir = tempfile::tempdir().unwrap();
+        let corrupt_path = dir.path().join("corrupt.sqlite");
+        std::fs::write(&corrupt_path, vec![b'x'; 4096]).unwrap();
+
+        let api = PlacesApi::new(dir.path().join("fresh.sqlite"))?;
+        let conn = api.open_connection(ConnectionType::ReadWrite)?;
+        let report = salvage(&conn, &corrupt_path)?;
+        assert!(!report.integrity_ok);
+        assert_eq!(report.recovered(), 0);
+        assert!(report.tables.iter().all(|t| !t.complete));
+        Ok(())
+    }
+}
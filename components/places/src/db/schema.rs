@@ -18,7 +18,7 @@ use crate::types::SyncStatus;
 use rusqlite::NO_PARAMS;
 use sql_support::ConnExt;
 
-const VERSION: i64 = 11;
+const VERSION: i64 = 17;
 
 // Shared schema and temp tables for the read-write and Sync connections.
 const CREATE_SHARED_SCHEMA_SQL: &str = include_str!("../../sql/create_shared_schema.sql");
@@ -38,6 +38,11 @@ lazy_static::lazy_static! {
             include_str!("../../sql/create_shared_triggers.sql"),
             increase_frecency_stats = update_origin_frecency_stats("+"),
             decrease_frecency_stats = update_origin_frecency_stats("-"),
+            update_new_place_fts_tags = update_places_fts_tags("NEW.place_id"),
+            update_old_place_fts_tags = update_places_fts_tags("OLD.place_id"),
+            update_renamed_tag_fts_tags = update_places_fts_tags(
+                "SELECT place_id FROM moz_tags_relation WHERE tag_id = NEW.id"
+            ),
         )
     };
 }
@@ -76,6 +81,21 @@ fn update_origin_frecency_stats(op: &str) -> String {
     )
 }
 
+/// Returns a statement which refreshes the tags stored in the full-text index
+/// for the pages in `place_ids`, which is either a single id or a subquery.
+fn update_places_fts_tags(place_ids: &str) -> String {
+    format!(
+        "
+        UPDATE moz_places_fts SET
+            tags = (SELECT group_concat(t.tag, ' ')
+                    FROM moz_tags_relation r
+                    JOIN moz_tags t ON t.id = r.tag_id
+                    WHERE r.place_id = moz_places_fts.rowid)
+        WHERE rowid IN ({place_ids})",
+        place_ids = place_ids,
+    )
+}
+
 fn get_current_schema_version(db: &PlacesDb) -> Result<i64> {
     Ok(db.query_one::<i64>("PRAGMA user_version")?)
 }
@@ -167,6 +187,36 @@ where
     Ok(())
 }
 
+/// Adds the `is_error` column to `moz_historyvisits`. `ALTER TABLE` isn't
+/// idempotent, so we check for the column first, in case the table was
+/// already created with the current schema.
+fn add_visit_is_error_column(db: &PlacesDb) -> Result<()> {
+    let has_column: bool = db.query_one(
+        "SELECT EXISTS(SELECT 1 FROM pragma_table_info('moz_historyvisits')
+                       WHERE name = 'is_error')",
+    )?;
+    if !has_column {
+        db.execute_batch(
+            "ALTER TABLE moz_historyvisits ADD COLUMN is_error INTEGER NOT NULL DEFAULT 0",
+        )?;
+    }
+    Ok(())
+}
+
+/// Adds the `post_data` column to `moz_keywords`. `ALTER TABLE` isn't
+/// idempotent, so we check for the column first, in case the table was
+/// already created with the current schema.
+fn add_keyword_post_data_column(db: &PlacesDb) -> Result<()> {
+    let has_column: bool = db.query_one(
+        "SELECT EXISTS(SELECT 1 FROM pragma_table_info('moz_keywords')
+                       WHERE name = 'post_data')",
+    )?;
+    if !has_column {
+        db.execute_batch("ALTER TABLE moz_keywords ADD COLUMN post_data TEXT")?;
+    }
+    Ok(())
+}
+
 fn upgrade(db: &PlacesDb, from: i64) -> Result<()> {
     log::debug!("Upgrading schema from {} to {}", from, VERSION);
     if from == VERSION {
@@ -255,7 +305,33 @@ fn upgrade(db: &PlacesDb, from: i64) -> Result<()> {
         ],
         || Ok(()),
     )?;
-    // Add more migrations here...
+    migration(db, 11, 12, &[CREATE_SHARED_SCHEMA_SQL], || Ok(()))?; // history metadata.
+    migration(
+        db,
+        12,
+        13,
+        &[
+            // Add the full-text index used for autocomplete, and fill it
+            // with existing pages.
+            CREATE_SHARED_SCHEMA_SQL,
+            "INSERT OR REPLACE INTO moz_places_fts(rowid, url, title, tags)
+             SELECT h.id, h.url, h.title,
+                    (SELECT group_concat(t.tag, ' ')
+                     FROM moz_tags_relation r
+                     JOIN moz_tags t ON t.id = r.tag_id
+                     WHERE r.place_id = h.id)
+             FROM moz_places h",
+        ],
+        || Ok(()),
+    )?;
+    // Top sites blocklist, and visits to pages that failed to load.
+    migration(db, 13, 14, &[CREATE_SHARED_SCHEMA_SQL], || {
+        add_visit_is_error_column(db)
+    })?;
+    migration(db, 14, 15, &[], || add_keyword_post_data_column(db))?;
+    migration(db, 15, 16, &[CREATE_SHARED_SCHEMA_SQL], || Ok(()))?; // reading list.
+    migration(db, 16, 17, &[CREATE_SHARED_SCHEMA_SQL], || Ok(()))?; // search terms.
+                                                                    // Add more migrations here...
 
     if get_current_schema_version(db)? == VERSION {
         return Ok(());
@@ -737,6 +813,64 @@ mod tests {
         .expect_err("changing the guid should fail");
     }
 
+    #[test]
+    fn test_fts_migration() -> Result<()> {
+        let path = "file:fts_migration?mode=memory&cache=shared";
+
+        // Add a tagged page, then remove the full-text index and roll back
+        // to the version before it was added.
+        let old = PlacesDb::open(
+            path,
+            ConnectionType::ReadWrite,
+            0,
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+        )
+        .expect("Should open first in-memory database with shared cache");
+        old.execute_all(&[
+            "INSERT INTO moz_places(id, guid, url, url_hash, title)
+             VALUES(1, 'page_guid___', 'https://example.com/',
+                    hash('https://example.com/'), 'Example')",
+            "INSERT INTO moz_tags(id, tag, lastModified) VALUES(1, 'one', 0), (2, 'two', 0)",
+            "INSERT INTO moz_tags_relation(tag_id, place_id) VALUES(1, 1), (2, 1)",
+            "DROP TABLE moz_places_fts",
+            "PRAGMA user_version = 12",
+        ])?;
+
+        // Opening a second connection should add the index back and fill it.
+        let upgrade = PlacesDb::open(
+            path,
+            ConnectionType::ReadWrite,
+            0,
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+        )
+        .expect("Should open second in-memory database with shared cache");
+        let (url, title, tags) = upgrade.query_row_and_then_named(
+            "SELECT url, title, tags FROM moz_places_fts WHERE rowid = 1",
+            &[],
+            |row| -> Result<_> {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            },
+            false,
+        )?;
+        assert_eq!(url, "https://example.com/");
+        assert_eq!(title, "Example");
+        let mut tags = tags.split(' ').collect::<Vec<_>>();
+        tags.sort();
+        assert_eq!(tags, vec!["one", "two"]);
+
+        Ok(())
+    }
+
     #[test]
     fn test_downgrade_schema() -> Result<()> {
         // This test uses SQLite's URI filenames and shared cache features to
@@ -747,8 +881,16 @@ mod tests {
         // first one that we support for migrations. We don't actually roll
         // back any of the schema changes; we just want to make sure that
         // running through all our migration routines doesn't trigger errors.
-        let downgrade = PlacesDb::open(path, ConnectionType::ReadWrite, 0, Default::default())
-            .expect("Should open first in-memory database with shared cache");
+        let downgrade = PlacesDb::open(
+            path,
+            ConnectionType::ReadWrite,
+            0,
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+        )
+        .expect("Should open first in-memory database with shared cache");
         downgrade.execute_batch("PRAGMA user_version = 2")?;
         assert_eq!(
             get_current_schema_version(&downgrade)?,
@@ -758,8 +900,16 @@ mod tests {
 
         // Now open a second connection to the same named in-memory database.
         // This should run through all our migrations.
-        let upgrade = PlacesDb::open(path, ConnectionType::ReadWrite, 0, Default::default())
-            .expect("Should open second in-memory database with shared cache");
+        let upgrade = PlacesDb::open(
+            path,
+            ConnectionType::ReadWrite,
+            0,
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+        )
+        .expect("Should open second in-memory database with shared cache");
         assert_eq!(
             get_current_schema_version(&upgrade)?,
             VERSION,
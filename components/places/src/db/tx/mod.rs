@@ -20,7 +20,7 @@ macro_rules! debug_complaint {
 }
 /// High level transaction type which "does the right thing" for you.
 /// Construct one with `PlacesDb::begin_transaction()`.
-pub struct PlacesTransaction<'conn>(PlacesTransactionRepr<'conn>);
+pub struct PlacesTransaction<'conn>(PlacesTransactionRepr<'conn>, &'conn super::PlacesDb);
 
 /// Only separated from PlacesTransaction so that the internals of the former
 /// are private (so that it can't be `matched` on, for example)
@@ -58,13 +58,15 @@ impl<'conn> PlacesTransaction<'conn> {
         Ok(())
     }
 
-    /// Consumes and commits a PlacesTransaction transaction.
+    /// Consumes and commits a PlacesTransaction transaction, then delivers
+    /// any events recorded while it was open to the observers.
     pub fn commit(self) -> Result<()> {
         match self.0 {
             PlacesTransactionRepr::ChunkedWrite(t) => t.commit()?,
             PlacesTransactionRepr::UnchunkedWrite(t) => t.commit()?,
             PlacesTransactionRepr::ReadOnly(t) => t.commit()?,
         };
+        self.1.flush_events();
         Ok(())
     }
 
@@ -72,6 +74,7 @@ impl<'conn> PlacesTransaction<'conn> {
     /// maybe_commit has been called, this may only roll back as far as that
     /// call.
     pub fn rollback(self) -> Result<()> {
+        self.1.discard_events();
         match self.0 {
             PlacesTransactionRepr::ChunkedWrite(t) => t.rollback()?,
             PlacesTransactionRepr::UnchunkedWrite(t) => t.rollback()?,
@@ -88,7 +91,10 @@ impl super::PlacesDb {
     /// - for ReadWrite connections, begins a normal coop transaction
     /// - for ReadOnly connections, begins an unchecked transaction.
     pub fn begin_transaction(&self) -> Result<PlacesTransaction<'_>> {
-        Ok(PlacesTransaction(match self.conn_type() {
+        // Anything left over is from a transaction which was dropped without
+        // committing.
+        self.discard_events();
+        let repr = match self.conn_type() {
             ConnectionType::Sync => {
                 PlacesTransactionRepr::ChunkedWrite(self.chunked_coop_trransaction()?)
             }
@@ -99,7 +105,8 @@ impl super::PlacesDb {
                 // Use an unchecked transaction with no locking.
                 PlacesTransactionRepr::ReadOnly(self.unchecked_transaction()?)
             }
-        }))
+        };
+        Ok(PlacesTransaction(repr, self))
     }
 }
 
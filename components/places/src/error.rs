@@ -77,6 +77,9 @@ pub enum ErrorKind {
 
     #[fail(display = "Database version {} is not supported", _0)]
     UnsupportedDatabaseVersion(i64),
+
+    #[fail(display = "Invalid search engine definition: {}", _0)]
+    InvalidSearchEngine(String),
 }
 
 error_support::define_error! {
@@ -130,6 +133,11 @@ pub enum InvalidPlaceInfo {
     // Like Urls, a tag is considered private info, so the value isn't in the error.
     #[fail(display = "The tag value is invalid")]
     InvalidTag,
+
+    // Keywords are also private, for the same reason.
+    #[fail(display = "The keyword value is invalid")]
+    InvalidKeyword,
+
     #[fail(
         display = "Cannot change the '{}' property of a bookmark of type {:?}",
         _0, _1
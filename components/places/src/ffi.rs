@@ -7,7 +7,8 @@
 use crate::error::{Error, ErrorKind, InvalidPlaceInfo};
 use crate::msg_types;
 use ffi_support::{
-    implement_into_ffi_by_delegation, implement_into_ffi_by_protobuf, ErrorCode, ExternError,
+    implement_into_ffi_by_delegation, implement_into_ffi_by_pointer,
+    implement_into_ffi_by_protobuf, ErrorCode, ExternError,
 };
 
 pub mod error_codes {
@@ -140,9 +141,21 @@ impl From<Error> for ExternError {
 implement_into_ffi_by_protobuf!(msg_types::SearchResultList);
 implement_into_ffi_by_protobuf!(msg_types::HistoryVisitInfos);
 implement_into_ffi_by_protobuf!(msg_types::HistoryVisitInfosWithBound);
+implement_into_ffi_by_protobuf!(msg_types::HistorySections);
+implement_into_ffi_by_protobuf!(msg_types::HistoryMetadata);
+implement_into_ffi_by_protobuf!(msg_types::HistoryMetadataList);
+implement_into_ffi_by_protobuf!(msg_types::HistoryMetadataSearchTermGroups);
+implement_into_ffi_by_protobuf!(msg_types::TopFrecentSiteInfos);
+implement_into_ffi_by_protobuf!(msg_types::PruneMetrics);
 implement_into_ffi_by_protobuf!(msg_types::BookmarkNode);
 implement_into_ffi_by_protobuf!(msg_types::BookmarkNodeList);
+implement_into_ffi_by_protobuf!(msg_types::BookmarkKeywordList);
+implement_into_ffi_by_protobuf!(msg_types::TagInfoList);
+implement_into_ffi_by_protobuf!(msg_types::StringList);
+implement_into_ffi_by_protobuf!(msg_types::ReadingListItem);
+implement_into_ffi_by_protobuf!(msg_types::ReadingListItems);
 implement_into_ffi_by_delegation!(
     crate::storage::bookmarks::PublicNode,
     msg_types::BookmarkNode
 );
+implement_into_ffi_by_pointer!(crate::api::matcher::SearchInterruptHandle);
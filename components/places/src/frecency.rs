@@ -5,6 +5,7 @@
 use crate::error::*;
 use crate::types::VisitTransition;
 use rusqlite::Connection;
+use serde_derive::*;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 enum RedirectBonus {
@@ -13,7 +14,12 @@ enum RedirectBonus {
     Normal,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// The weights and cutoffs used to calculate frecency. These can be changed
+/// with `storage::set_frecency_settings`, and are persisted in the database.
+/// When deserializing, missing fields take their default values, so callers
+/// only need to specify the settings they want to change.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct FrecencySettings {
     // TODO: These probably should not all be i32s...
     pub num_visits: i32,                     // from "places.frecency.numVisits"
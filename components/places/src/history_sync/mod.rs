@@ -5,16 +5,63 @@
 use crate::types::Timestamp;
 use serde_derive::*;
 use std::fmt;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 mod plan;
 pub mod record;
 pub mod store;
 
 const MAX_INCOMING_PLACES: usize = 5000;
+const INCOMING_PAGE_SIZE: usize = 500;
 const MAX_OUTGOING_PLACES: usize = 5000;
 const MAX_VISITS: usize = 20;
-pub const HISTORY_TTL: u32 = 5_184_000; // 60 days in milliseconds
+pub const HISTORY_TTL: u32 = 5_184_000; // 60 days in seconds
+
+/// Limits for syncing history. The defaults match desktop, but devices with
+/// little storage may want to sync less, and larger devices more.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistorySyncConfig {
+    /// The maximum number of pages to download in each sync.
+    pub max_incoming_places: usize,
+    /// How many pages to download and apply at a time. If a sync is
+    /// interrupted, the next one continues from the last page it applied.
+    /// Zero downloads everything in one request.
+    pub incoming_page_size: usize,
+    /// The maximum number of changed pages to upload in each sync.
+    pub max_outgoing_places: usize,
+    /// The maximum number of visits to upload for each page. Incoming visits
+    /// older than a page's last `max_visits` local visits are ignored.
+    pub max_visits: usize,
+    /// How long the server keeps tombstones for deleted pages, in seconds.
+    pub tombstone_ttl: u32,
+    /// If set, only visits in this window before the current time are
+    /// downloaded and applied. Pages without any visits in the window are
+    /// skipped. Widening the window later doesn't download pages that were
+    /// skipped, unless they change on the server, or history is reset.
+    pub max_age: Option<Duration>,
+}
+
+impl Default for HistorySyncConfig {
+    fn default() -> Self {
+        Self {
+            max_incoming_places: MAX_INCOMING_PLACES,
+            incoming_page_size: INCOMING_PAGE_SIZE,
+            max_outgoing_places: MAX_OUTGOING_PLACES,
+            max_visits: MAX_VISITS,
+            tombstone_ttl: HISTORY_TTL,
+            max_age: None,
+        }
+    }
+}
+
+impl HistorySyncConfig {
+    /// Returns the earliest visit date in the sync window, or `None` if all
+    /// visits should be synced.
+    pub(crate) fn earliest_visit_date(&self) -> Option<Timestamp> {
+        self.max_age
+            .map(|age| Timestamp::now().checked_sub(age).unwrap_or_default())
+    }
+}
 
 /// Visit timestamps on the server are *microseconds* since the epoch.
 #[derive(
@@ -3,23 +3,25 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use super::record::{HistoryRecord, HistoryRecordVisit, HistorySyncRecord};
-use super::{HISTORY_TTL, MAX_OUTGOING_PLACES, MAX_VISITS};
+use super::HistorySyncConfig;
 use crate::api::history::can_add_url;
 use crate::db::PlacesDb;
 use crate::error::*;
+use crate::observer::{PlacesEvent, SyncSummary, SyncedCollection};
 use crate::storage::{
     delete_pending_temp_tables,
     history::history_sync::{
         apply_synced_deletion, apply_synced_reconciliation, apply_synced_visits, fetch_outgoing,
         fetch_visits, finish_outgoing, FetchedVisit, FetchedVisitPage, OutgoingInfo,
     },
+    history::{update_stale_frecencies, STALE_FRECENCIES_BATCH_SIZE},
 };
 use crate::types::{Timestamp, VisitTransition};
 use interrupt_support::Interruptee;
 use std::collections::HashSet;
 use std::time::{SystemTime, UNIX_EPOCH};
 use sync15::telemetry;
-use sync15::{IncomingChangeset, OutgoingChangeset, Payload};
+use sync15::{IncomingChangeset, OutgoingChangeset, Payload, ServerTimestamp};
 use sync_guid::Guid as SyncGuid;
 use url::Url;
 
@@ -172,15 +174,61 @@ fn plan_incoming_record(conn: &PlacesDb, record: HistoryRecord, max_visits: usiz
     }
 }
 
+/// Drops incoming visits that are older than `earliest`. Returns false if
+/// none of the record's visits are in the window, so the record should be
+/// skipped.
+fn retain_visits_since(record: &mut HistoryRecord, earliest: Timestamp) -> bool {
+    record
+        .visits
+        .retain(|visit| Timestamp::from(visit.date) >= earliest);
+    !record.visits.is_empty()
+}
+
 pub fn apply_plan(
     db: &PlacesDb,
     inbound: IncomingChangeset,
+    config: &HistorySyncConfig,
     telem: &mut telemetry::EngineIncoming,
     interruptee: &impl Interruptee,
 ) -> Result<OutgoingChangeset> {
+    apply_incoming_records(db, inbound.changes, config, telem, interruptee)?;
+    let mut outgoing = OutgoingChangeset::new("history", inbound.timestamp);
+    // It might make sense for fetch_outgoing to manage its own
+    // begin_transaction - even though doesn't seem a large bottleneck
+    // at this time, the fact we hold a single transaction for the entire call
+    // really is used only for performance, so it's certainly a candidate.
+    let tx = db.begin_transaction()?;
+    let mut out_infos = fetch_outgoing(db, config.max_outgoing_places, config.max_visits)?;
+
+    for (guid, out_record) in out_infos.drain() {
+        let payload = match out_record {
+            OutgoingInfo::Record(record) => Payload::from_record(record)?,
+            OutgoingInfo::Tombstone => {
+                Payload::new_tombstone_with_ttl(guid.as_str().to_string(), config.tombstone_ttl)
+            }
+        };
+        log::trace!("outgoing {:?}", payload);
+        outgoing.changes.push(payload);
+    }
+    tx.commit()?;
+
+    log::info!("incoming: {}", serde_json::to_string(&telem).unwrap());
+    Ok(outgoing)
+}
+
+/// Applies incoming records without building the outgoing changeset. This is
+/// used to apply each page of a paged download as it arrives.
+pub fn apply_incoming_records(
+    db: &PlacesDb,
+    records: Vec<(Payload, ServerTimestamp)>,
+    config: &HistorySyncConfig,
+    telem: &mut telemetry::EngineIncoming,
+    interruptee: &impl Interruptee,
+) -> Result<()> {
+    let earliest = config.earliest_visit_date();
     // for a first-cut, let's do this in the most naive way possible...
-    let mut plans: Vec<(SyncGuid, IncomingPlan)> = Vec::with_capacity(inbound.changes.len());
-    for incoming in inbound.changes {
+    let mut plans: Vec<(SyncGuid, IncomingPlan)> = Vec::with_capacity(records.len());
+    for incoming in records {
         interruptee.err_if_interrupted()?;
         let item = match HistorySyncRecord::from_payload(incoming.0) {
             Ok(item) => item,
@@ -193,7 +241,10 @@ pub fn apply_plan(
             }
         };
         let plan = match item.record {
-            Some(record) => plan_incoming_record(db, record, MAX_VISITS),
+            Some(mut record) => match earliest {
+                Some(earliest) if !retain_visits_since(&mut record, earliest) => IncomingPlan::Skip,
+                _ => plan_incoming_record(db, record, config.max_visits),
+            },
             None => IncomingPlan::Delete,
         };
         let guid = item.guid.clone();
@@ -202,7 +253,11 @@ pub fn apply_plan(
 
     let mut tx = db.begin_transaction()?;
 
-    let mut outgoing = OutgoingChangeset::new("history", inbound.timestamp);
+    let mut summary = SyncSummary {
+        collection: SyncedCollection::History,
+        changed: 0,
+        removed: 0,
+    };
     for (guid, plan) in plans {
         interruptee.err_if_interrupted()?;
         match &plan {
@@ -226,6 +281,7 @@ pub fn apply_plan(
                 log::trace!("incoming: deleting {:?}", guid);
                 apply_synced_deletion(&db, &guid)?;
                 telem.applied(1);
+                summary.removed += 1;
             }
             IncomingPlan::Apply {
                 url,
@@ -241,6 +297,7 @@ pub fn apply_plan(
                 );
                 apply_synced_visits(&db, &guid, &url, new_title, visits)?;
                 telem.applied(1);
+                summary.changed += 1;
             }
             IncomingPlan::Reconciled => {
                 telem.reconciled(1);
@@ -259,28 +316,16 @@ pub fn apply_plan(
     // ...And commit the final chunk of plans, making sure we trigger
     // frecency and origin updates.
     delete_pending_temp_tables(db)?;
-    tx.commit()?;
-    // It might make sense for fetch_outgoing to manage its own
-    // begin_transaction - even though doesn't seem a large bottleneck
-    // at this time, the fact we hold a single transaction for the entire call
-    // really is used only for performance, so it's certainly a candidate.
-    let tx = db.begin_transaction()?;
-    let mut out_infos = fetch_outgoing(db, MAX_OUTGOING_PLACES, MAX_VISITS)?;
-
-    for (guid, out_record) in out_infos.drain() {
-        let payload = match out_record {
-            OutgoingInfo::Record(record) => Payload::from_record(record)?,
-            OutgoingInfo::Tombstone => {
-                Payload::new_tombstone_with_ttl(guid.as_str().to_string(), HISTORY_TTL)
-            }
-        };
-        log::trace!("outgoing {:?}", payload);
-        outgoing.changes.push(payload);
+    if summary.changed > 0 || summary.removed > 0 {
+        db.note_event(PlacesEvent::SyncApplied(summary));
     }
     tx.commit()?;
-
-    log::info!("incoming: {}", serde_json::to_string(&telem).unwrap());
-    Ok(outgoing)
+    // Recalculate frecencies for the pages we changed, outside the
+    // transaction so that we don't block other writers. We only do one batch
+    // here, so that a large first sync doesn't spend too long on it;
+    // `run_maintenance` recalculates any that are left.
+    update_stale_frecencies(db, STALE_FRECENCIES_BATCH_SIZE, interruptee)?;
+    Ok(())
 }
 
 pub fn finish_plan(db: &PlacesDb) -> Result<()> {
@@ -294,7 +339,7 @@ pub fn finish_plan(db: &PlacesDb) -> Result<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::api::matcher::{search_frecent, SearchParams};
+    use crate::api::matcher::{search_frecent, SearchMode, SearchParams};
     use crate::api::places_api::ConnectionType;
     use crate::db::PlacesDb;
     use crate::history_sync::ServerVisitTimestamp;
@@ -519,6 +564,7 @@ mod tests {
         let outgoing = apply_plan(
             &db,
             incoming,
+            &HistorySyncConfig::default(),
             &mut telemetry::EngineIncoming::new(),
             &NeverInterrupts,
         )?;
@@ -585,6 +631,7 @@ mod tests {
         let outgoing = apply_plan(
             &db,
             incoming,
+            &HistorySyncConfig::default(),
             &mut telemetry::EngineIncoming::new(),
             &NeverInterrupts,
         )?;
@@ -647,6 +694,7 @@ mod tests {
         let outgoing = apply_plan(
             &db,
             incoming,
+            &HistorySyncConfig::default(),
             &mut telemetry::EngineIncoming::new(),
             &NeverInterrupts,
         )?;
@@ -683,6 +731,7 @@ mod tests {
         let outgoing = apply_plan(
             &db,
             result,
+            &HistorySyncConfig::default(),
             &mut telemetry::EngineIncoming::new(),
             &NeverInterrupts,
         )?;
@@ -718,6 +767,7 @@ mod tests {
         let outgoing = apply_plan(
             &db,
             result,
+            &HistorySyncConfig::default(),
             &mut telemetry::EngineIncoming::new(),
             &NeverInterrupts,
         )?;
@@ -770,6 +820,7 @@ mod tests {
         let outgoing = apply_plan(
             &db,
             result,
+            &HistorySyncConfig::default(),
             &mut telemetry::EngineIncoming::new(),
             &NeverInterrupts,
         )?;
@@ -790,6 +841,7 @@ mod tests {
             SearchParams {
                 search_string: "http://example.com".into(),
                 limit: 2,
+                mode: SearchMode::Match,
             },
         )?;
         assert_eq!(found.len(), 1);
@@ -801,6 +853,61 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_apply_plan_incoming_max_age() -> Result<()> {
+        let _ = env_logger::try_init();
+        let now: Timestamp = SystemTime::now().into();
+        let day = Duration::from_secs(24 * 60 * 60);
+        let recent = now.checked_sub(day).unwrap();
+        let old = now.checked_sub(day * 30).unwrap();
+        let mut result = IncomingChangeset::new("history", ServerTimestamp(0i64));
+        for json in &[
+            json!({
+                "id": "aaaaaaaaaaaa",
+                "title": "mixed",
+                "histUri": "http://example.com/mixed",
+                "visits": [
+                    {"date": ServerVisitTimestamp::from(recent), "type": 1},
+                    {"date": ServerVisitTimestamp::from(old), "type": 1},
+                ]
+            }),
+            json!({
+                "id": "bbbbbbbbbbbb",
+                "title": "old",
+                "histUri": "http://example.com/old",
+                "visits": [ {"date": ServerVisitTimestamp::from(old), "type": 1}]
+            }),
+        ] {
+            let payload = Payload::from_json(json.clone()).unwrap();
+            result.changes.push((payload, ServerTimestamp(0i64)));
+        }
+
+        let db = PlacesDb::open_in_memory(ConnectionType::Sync)?;
+        let config = HistorySyncConfig {
+            max_age: Some(day * 7),
+            ..HistorySyncConfig::default()
+        };
+        let outgoing = apply_plan(
+            &db,
+            result,
+            &config,
+            &mut telemetry::EngineIncoming::new(),
+            &NeverInterrupts,
+        )?;
+
+        // Only the visit in the window should be applied.
+        let (_, visits) =
+            fetch_visits(&db, &Url::parse("http://example.com/mixed")?, 2)?.expect("page exists");
+        assert_eq!(visits.len(), 1);
+        assert_eq!(visits[0].visit_date, recent);
+
+        // And the page without any visits in the window should be skipped.
+        assert!(fetch_visits(&db, &Url::parse("http://example.com/old")?, 2)?.is_none());
+
+        assert_eq!(outgoing.changes.len(), 0);
+        Ok(())
+    }
+
     #[test]
     fn test_apply_plan_outgoing_new() -> Result<()> {
         let _ = env_logger::try_init();
@@ -816,6 +923,7 @@ mod tests {
         let outgoing = apply_plan(
             &db,
             incoming,
+            &HistorySyncConfig::default(),
             &mut telemetry::EngineIncoming::new(),
             &NeverInterrupts,
         )?;
@@ -858,6 +966,7 @@ mod tests {
         apply_plan(
             &db,
             incoming,
+            &HistorySyncConfig::default(),
             &mut telemetry::EngineIncoming::new(),
             &NeverInterrupts,
         )?;
@@ -904,6 +1013,7 @@ mod tests {
         let outgoing = apply_plan(
             &db,
             incoming,
+            &HistorySyncConfig::default(),
             &mut telemetry::EngineIncoming::new(),
             &NeverInterrupts,
         )?;
@@ -957,6 +1067,7 @@ mod tests {
         let outgoing = apply_plan(
             &db,
             incoming,
+            &HistorySyncConfig::default(),
             &mut telemetry::EngineIncoming::new(),
             &NeverInterrupts,
         )?;
@@ -980,6 +1091,7 @@ mod tests {
         apply_plan(
             &db,
             IncomingChangeset::new("history", ServerTimestamp(0i64)),
+            &HistorySyncConfig::default(),
             &mut telemetry::EngineIncoming::new(),
             &NeverInterrupts,
         )?;
@@ -999,6 +1111,7 @@ mod tests {
         let outgoing = apply_plan(
             &db,
             incoming,
+            &HistorySyncConfig::default(),
             &mut telemetry::EngineIncoming::new(),
             &NeverInterrupts,
         )?;
@@ -1021,6 +1134,7 @@ mod tests {
         apply_plan(
             &db,
             IncomingChangeset::new("history", ServerTimestamp(0i64)),
+            &HistorySyncConfig::default(),
             &mut telemetry::EngineIncoming::new(),
             &NeverInterrupts,
         )?;
@@ -1036,6 +1150,7 @@ mod tests {
         let outgoing = apply_plan(
             &db,
             IncomingChangeset::new("history", ServerTimestamp(0i64)),
+            &HistorySyncConfig::default(),
             &mut telemetry::EngineIncoming::new(),
             &NeverInterrupts,
         )?;
@@ -1055,4 +1170,50 @@ mod tests {
         let ts = Timestamp::now();
         assert_eq!(clamp_visit_date(ts), Ok(ts));
     }
+
+    #[test]
+    fn test_apply_plan_notifies_observers() -> Result<()> {
+        use crate::api::places_api::test::new_mem_api;
+        use crate::observer::test::RecordingObserver;
+        use std::sync::Arc;
+
+        let api = new_mem_api();
+        let observer = Arc::new(RecordingObserver::default());
+        api.add_observer(observer.clone());
+        let db = api.open_sync_connection()?;
+
+        let mut incoming = IncomingChangeset::new("history", ServerTimestamp(0i64));
+        for i in 0..2 {
+            let payload = Payload::from_json(json!({
+                "id": SyncGuid::random(),
+                "title": "title",
+                "histUri": format!("https://example.com/{}", i),
+                "sortindex": 0,
+                "ttl": 100,
+                "visits": [ {"date": ServerVisitTimestamp::from(Timestamp::now()), "type": 1}]
+            }))?;
+            incoming.changes.push((payload, ServerTimestamp(0i64)));
+        }
+        incoming.changes.push((
+            Payload::new_tombstone(SyncGuid::random()),
+            ServerTimestamp(0i64),
+        ));
+
+        apply_plan(
+            &db,
+            incoming,
+            &HistorySyncConfig::default(),
+            &mut telemetry::EngineIncoming::new(),
+            &NeverInterrupts,
+        )?;
+        assert_eq!(
+            observer.take(),
+            vec![PlacesEvent::SyncApplied(SyncSummary {
+                collection: SyncedCollection::History,
+                changed: 2,
+                removed: 1,
+            })]
+        );
+        Ok(())
+    }
 }
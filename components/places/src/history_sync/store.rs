@@ -16,15 +16,17 @@ use std::ops::Deref;
 use std::result;
 use sync15::telemetry;
 use sync15::{
-    extract_v1_state, CollSyncIds, CollectionRequest, IncomingChangeset, OutgoingChangeset,
-    ServerTimestamp, Store, StoreSyncAssociation,
+    extract_v1_state, CollSyncIds, CollectionRequest, DownloadOffset, IncomingChangeset,
+    OutgoingChangeset, ServerTimestamp, Store, StoreSyncAssociation,
 };
 use sync_guid::Guid;
 
-use super::plan::{apply_plan, finish_plan};
-use super::MAX_INCOMING_PLACES;
+use super::plan::{apply_incoming_records, apply_plan, finish_plan};
+use super::HistorySyncConfig;
 
 pub const LAST_SYNC_META_KEY: &str = "history_last_sync_time";
+// Where to continue an interrupted download, as JSON.
+pub const DOWNLOAD_OFFSET_META_KEY: &str = "history_download_offset";
 // Note that all engines in this crate should use a *different* meta key
 // for the global sync ID, because engines are reset individually.
 pub const GLOBAL_SYNCID_META_KEY: &str = "history_global_sync_id";
@@ -35,12 +37,21 @@ pub const COLLECTION_SYNCID_META_KEY: &str = "history_sync_id";
 pub struct HistoryStore<'a> {
     pub db: &'a PlacesDb,
     interruptee: &'a SqlInterruptScope,
+    config: HistorySyncConfig,
 }
 
 impl<'a> HistoryStore<'a> {
-    pub fn new(db: &'a PlacesDb, interruptee: &'a SqlInterruptScope) -> Self {
+    pub fn new(
+        db: &'a PlacesDb,
+        interruptee: &'a SqlInterruptScope,
+        config: HistorySyncConfig,
+    ) -> Self {
         assert_eq!(db.conn_type(), ConnectionType::Sync);
-        Self { db, interruptee }
+        Self {
+            db,
+            interruptee,
+            config,
+        }
     }
 
     fn put_meta(&self, key: &str, value: &dyn ToSql) -> Result<()> {
@@ -59,16 +70,58 @@ impl<'a> HistoryStore<'a> {
         let timestamp = inbound.timestamp;
         let outgoing = {
             let mut incoming_telemetry = telemetry::EngineIncoming::new();
-            let result = apply_plan(&self.db, inbound, &mut incoming_telemetry, self.interruptee);
+            let result = apply_plan(
+                &self.db,
+                inbound,
+                &self.config,
+                &mut incoming_telemetry,
+                self.interruptee,
+            );
             telem.incoming(incoming_telemetry);
             result
         }?;
         // write the timestamp now, so if we are interrupted creating outgoing
-        // changesets we don't need to re-reconcile what we just did.
+        // changesets we don't need to re-reconcile what we just did. The
+        // download is complete, so the offset is no longer valid.
+        let tx = self.db.begin_transaction()?;
         self.put_meta(LAST_SYNC_META_KEY, &(timestamp.as_millis() as i64))?;
+        crate::storage::delete_meta(self.db, DOWNLOAD_OFFSET_META_KEY)?;
+        tx.commit()?;
         Ok(outgoing)
     }
 
+    fn do_apply_incoming_page(
+        &self,
+        page: &IncomingChangeset,
+        next: Option<&DownloadOffset>,
+        telem: &mut telemetry::Engine,
+    ) -> Result<()> {
+        let mut incoming_telemetry = telemetry::EngineIncoming::new();
+        let result = apply_incoming_records(
+            &self.db,
+            page.changes.clone(),
+            &self.config,
+            &mut incoming_telemetry,
+            self.interruptee,
+        );
+        telem.incoming(incoming_telemetry);
+        result?;
+        // If this was the last page, keep the old offset until
+        // `do_apply_incoming` bumps the last sync time, so that we don't
+        // start over if we're interrupted before then.
+        if let Some(next) = next {
+            self.put_meta(DOWNLOAD_OFFSET_META_KEY, &serde_json::to_string(next)?)?;
+        }
+        Ok(())
+    }
+
+    fn get_download_offset(&self) -> Result<Option<DownloadOffset>> {
+        Ok(match self.get_meta::<String>(DOWNLOAD_OFFSET_META_KEY)? {
+            Some(json) => Some(serde_json::from_str(&json)?),
+            None => None,
+        })
+    }
+
     fn do_sync_finished(
         &self,
         new_timestamp: ServerTimestamp,
@@ -157,6 +210,16 @@ impl<'a> Store for HistoryStore<'a> {
         Ok(self.do_apply_incoming(inbound, telem)?)
     }
 
+    fn apply_incoming_page(
+        &self,
+        page: &IncomingChangeset,
+        next: Option<&DownloadOffset>,
+        telem: &mut telemetry::Engine,
+    ) -> result::Result<bool, failure::Error> {
+        self.do_apply_incoming_page(page, next, telem)?;
+        Ok(true)
+    }
+
     fn sync_finished(
         &self,
         new_timestamp: ServerTimestamp,
@@ -166,21 +229,40 @@ impl<'a> Store for HistoryStore<'a> {
         Ok(())
     }
 
+    fn get_last_sync(&self) -> result::Result<Option<ServerTimestamp>, failure::Error> {
+        Ok(self
+            .get_meta::<i64>(LAST_SYNC_META_KEY)?
+            .map(ServerTimestamp))
+    }
+
     fn get_collection_requests(
         &self,
         server_timestamp: ServerTimestamp,
     ) -> result::Result<Vec<CollectionRequest>, failure::Error> {
-        let since = ServerTimestamp(
-            self.get_meta::<i64>(LAST_SYNC_META_KEY)?
-                .unwrap_or_default(),
-        );
-        Ok(if since == server_timestamp {
+        let since = self.get_last_sync()?.unwrap_or_default();
+        let resume = self.get_download_offset()?;
+        Ok(if since == server_timestamp && resume.is_none() {
             vec![]
         } else {
+            // Adding a visit changes a record's modified time, so records
+            // last changed before the window can't have any visits in it.
+            let newer_than = match self.config.earliest_visit_date() {
+                Some(earliest) => {
+                    let earliest = ServerTimestamp::from_millis(earliest.0 as i64);
+                    if earliest > since {
+                        earliest
+                    } else {
+                        since
+                    }
+                }
+                None => since,
+            };
             vec![CollectionRequest::new("history")
                 .full()
-                .newer_than(since)
-                .limit(MAX_INCOMING_PLACES)]
+                .newer_than(newer_than)
+                .limit(self.config.max_incoming_places)
+                .page_size(self.config.incoming_page_size)
+                .resume_from(resume)]
         })
     }
 
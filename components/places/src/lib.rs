@@ -19,6 +19,9 @@ pub mod history_sync;
 pub mod import;
 pub mod match_impl;
 pub mod observation;
+pub mod observer;
+pub mod reading_list_sync;
+pub mod search_engines;
 pub mod storage;
 #[cfg(test)]
 mod tests;
@@ -36,6 +39,8 @@ pub use crate::api::places_api::{ConnectionType, PlacesApi};
 pub use crate::db::PlacesDb;
 pub use crate::error::*;
 pub use crate::observation::VisitObservation;
+pub use crate::observer::{PlacesEvent, PlacesObserver};
 pub use crate::storage::PageInfo;
 pub use crate::storage::RowId;
 pub use crate::types::*;
+pub use sql_support::{RecoveryReport, TableRecovery};
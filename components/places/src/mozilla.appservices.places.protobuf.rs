@@ -10,6 +10,11 @@ pub struct HistoryVisitInfo {
     pub visit_type: i32,
     #[prost(bool, required, tag="5")]
     pub is_hidden: bool,
+    /// Set if the page is a search result page for a known search engine.
+    #[prost(string, optional, tag="6")]
+    pub search_term: ::std::option::Option<std::string::String>,
+    #[prost(string, optional, tag="7")]
+    pub search_engine: ::std::option::Option<std::string::String>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct HistoryVisitInfos {
@@ -25,6 +30,97 @@ pub struct HistoryVisitInfosWithBound {
     #[prost(int64, required, tag="3")]
     pub offset: i64,
 }
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct HistoryMetadata {
+    #[prost(string, required, tag="1")]
+    pub url: std::string::String,
+    #[prost(string, optional, tag="2")]
+    pub title: ::std::option::Option<std::string::String>,
+    #[prost(int64, required, tag="3")]
+    pub created_at: i64,
+    #[prost(int64, required, tag="4")]
+    pub updated_at: i64,
+    /// In milliseconds.
+    #[prost(int64, required, tag="5")]
+    pub total_view_time: i64,
+    #[prost(string, optional, tag="6")]
+    pub search_term: ::std::option::Option<std::string::String>,
+    /// A `DocumentType` (from `types.rs`).
+    #[prost(int32, required, tag="7")]
+    pub document_type: i32,
+    #[prost(string, optional, tag="8")]
+    pub referrer_url: ::std::option::Option<std::string::String>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct HistoryMetadataList {
+    #[prost(message, repeated, tag="1")]
+    pub metadata: ::std::vec::Vec<HistoryMetadata>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct HistoryMetadataSearchTermGroup {
+    #[prost(string, required, tag="1")]
+    pub search_term: std::string::String,
+    #[prost(message, repeated, tag="2")]
+    pub metadata: ::std::vec::Vec<HistoryMetadata>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct HistoryMetadataSearchTermGroups {
+    #[prost(message, repeated, tag="1")]
+    pub groups: ::std::vec::Vec<HistoryMetadataSearchTermGroup>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct HistorySite {
+    #[prost(string, required, tag="1")]
+    pub host: std::string::String,
+    #[prost(uint32, required, tag="2")]
+    pub visit_count: u32,
+    #[prost(string, required, tag="3")]
+    pub url: std::string::String,
+    #[prost(string, optional, tag="4")]
+    pub title: ::std::option::Option<std::string::String>,
+    #[prost(int64, required, tag="5")]
+    pub latest_visit: i64,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct HistorySection {
+    #[prost(int64, required, tag="1")]
+    pub day: i64,
+    #[prost(message, repeated, tag="2")]
+    pub sites: ::std::vec::Vec<HistorySite>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct HistorySections {
+    #[prost(message, repeated, tag="1")]
+    pub sections: ::std::vec::Vec<HistorySection>,
+    /// An opaque cursor for fetching the next page, if there is one.
+    #[prost(string, optional, tag="2")]
+    pub cursor: ::std::option::Option<std::string::String>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TopFrecentSiteInfo {
+    #[prost(string, required, tag="1")]
+    pub url: std::string::String,
+    #[prost(string, optional, tag="2")]
+    pub title: ::std::option::Option<std::string::String>,
+    #[prost(int64, required, tag="3")]
+    pub frecency: i64,
+    #[prost(string, optional, tag="4")]
+    pub search_term: ::std::option::Option<std::string::String>,
+    #[prost(string, optional, tag="5")]
+    pub search_engine: ::std::option::Option<std::string::String>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TopFrecentSiteInfos {
+    #[prost(message, repeated, tag="1")]
+    pub infos: ::std::vec::Vec<TopFrecentSiteInfo>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PruneMetrics {
+    #[prost(uint32, required, tag="1")]
+    pub pages_removed: u32,
+    #[prost(uint32, required, tag="2")]
+    pub visits_removed: u32,
+}
 ///*
 /// A bookmark node.
 ///
@@ -154,8 +250,61 @@ pub struct BookmarkNode {
     /// Leaving this out is equivalent to false.
     #[prost(bool, optional, tag="11")]
     pub have_child_nodes: ::std::option::Option<bool>,
+    ///*
+    /// The keyword for this bookmark's URL. Only present for type =
+    /// `BookmarkType::Bookmark`.
+    ///
+    /// - Returned on reads if the URL has a keyword.
+    /// - Ignored for insertion and update. Use the keyword functions instead.
+    #[prost(string, optional, tag="12")]
+    pub keyword: ::std::option::Option<std::string::String>,
+    ///*
+    /// The tags for this bookmark's URL, sorted by tag. Only present for
+    /// type = `BookmarkType::Bookmark`.
+    ///
+    /// - Returned on reads.
+    /// - Ignored for insertion and update. Use the tag functions instead.
+    #[prost(string, repeated, tag="13")]
+    pub tags: ::std::vec::Vec<std::string::String>,
+}
+///* A search keyword for a bookmarked URL. 
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct BookmarkKeyword {
+    #[prost(string, required, tag="1")]
+    pub keyword: std::string::String,
+    #[prost(string, required, tag="2")]
+    pub url: std::string::String,
+    #[prost(string, optional, tag="3")]
+    pub post_data: ::std::option::Option<std::string::String>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct BookmarkKeywordList {
+    #[prost(message, repeated, tag="1")]
+    pub keywords: ::std::vec::Vec<BookmarkKeyword>,
+}
+//* An array of bookmark nodes, since we can't represent that directly 
+
+///* A tag, and the number of URLs with that tag. 
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TagInfo {
+    #[prost(string, required, tag="1")]
+    pub tag: std::string::String,
+    #[prost(uint32, required, tag="2")]
+    pub url_count: u32,
+    #[prost(int64, required, tag="3")]
+    pub last_modified: i64,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TagInfoList {
+    #[prost(message, repeated, tag="1")]
+    pub tags: ::std::vec::Vec<TagInfo>,
+}
+///* A list of tags or URLs. 
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct StringList {
+    #[prost(string, repeated, tag="1")]
+    pub values: ::std::vec::Vec<std::string::String>,
 }
-///* An array of bookmark nodes, since we can't represent that directly 
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct BookmarkNodeList {
     #[prost(message, repeated, tag="1")]
@@ -171,12 +320,38 @@ pub struct SearchResultMessage {
     pub frecency: i64,
     #[prost(enumeration="SearchResultReason", repeated, tag="4")]
     pub reasons: ::std::vec::Vec<i32>,
+    #[prost(string, optional, tag="5")]
+    pub search_term: ::std::option::Option<std::string::String>,
+    #[prost(string, optional, tag="6")]
+    pub search_engine: ::std::option::Option<std::string::String>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct SearchResultList {
     #[prost(message, repeated, tag="1")]
     pub results: ::std::vec::Vec<SearchResultMessage>,
 }
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ReadingListItem {
+    #[prost(string, required, tag="1")]
+    pub guid: std::string::String,
+    #[prost(string, required, tag="2")]
+    pub url: std::string::String,
+    #[prost(string, optional, tag="3")]
+    pub title: ::std::option::Option<std::string::String>,
+    #[prost(string, optional, tag="4")]
+    pub excerpt: ::std::option::Option<std::string::String>,
+    #[prost(string, optional, tag="5")]
+    pub source_device: ::std::option::Option<std::string::String>,
+    #[prost(int64, required, tag="6")]
+    pub added_at: i64,
+    #[prost(int64, optional, tag="7")]
+    pub read_at: ::std::option::Option<i64>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ReadingListItems {
+    #[prost(message, repeated, tag="1")]
+    pub items: ::std::vec::Vec<ReadingListItem>,
+}
 /// Protobuf allows nesting these, but prost behaves weirdly if we do.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
 #[repr(i32)]
@@ -0,0 +1,235 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Support for notifying consumers about changes to history and bookmarks.
+//!
+//! Observers are registered on a `PlacesApi` and are shared by every
+//! connection it opens. Storage functions record events on the connection
+//! as they make changes, and the events are only delivered once the
+//! transaction that made them commits - observers never hear about changes
+//! which were rolled back. Changes made while syncing are not reported
+//! item-by-item; instead, a single `PlacesEvent::SyncApplied` summary is
+//! delivered for each collection.
+
+use crate::types::{BookmarkType, Timestamp, VisitTransition};
+use std::fmt;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+use sync_guid::Guid as SyncGuid;
+use url::Url;
+
+/// A change made to the places database.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlacesEvent {
+    /// A visit was added to a page.
+    VisitAdded {
+        guid: SyncGuid,
+        url: Url,
+        visit_date: Timestamp,
+        visit_type: VisitTransition,
+        is_local: bool,
+    },
+    /// The title of a page changed.
+    TitleChanged {
+        guid: SyncGuid,
+        url: Url,
+        title: String,
+    },
+    /// Some or all of the visits to a page were removed.
+    PageRemoved {
+        guid: SyncGuid,
+        url: Url,
+        /// True if the page itself was removed. False if the page was kept,
+        /// usually because it is bookmarked or tagged.
+        is_removed_from_store: bool,
+        /// True if the page still has visits left.
+        is_partial_visit_removal: bool,
+    },
+    /// All local history was removed.
+    HistoryCleared,
+    /// A bookmark, folder or separator was inserted.
+    BookmarkAdded {
+        guid: SyncGuid,
+        parent_guid: SyncGuid,
+        position: u32,
+        node_type: BookmarkType,
+        url: Option<Url>,
+    },
+    /// A bookmark, folder or separator was moved, either within the same
+    /// folder or to a new one.
+    BookmarkMoved {
+        guid: SyncGuid,
+        old_parent_guid: SyncGuid,
+        old_position: u32,
+        new_parent_guid: SyncGuid,
+        new_position: u32,
+    },
+    /// A property of a bookmark or folder changed.
+    BookmarkChanged {
+        guid: SyncGuid,
+        property: BookmarkProperty,
+    },
+    /// A bookmark, folder or separator was removed. Removing a folder also
+    /// removes its descendants, but only the folder itself is reported.
+    BookmarkRemoved {
+        guid: SyncGuid,
+        parent_guid: SyncGuid,
+        position: u32,
+        node_type: BookmarkType,
+        url: Option<Url>,
+    },
+    /// Incoming records from a sync were applied.
+    SyncApplied(SyncSummary),
+}
+
+/// The property of a bookmark reported by `PlacesEvent::BookmarkChanged`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BookmarkProperty {
+    /// The new title. `None` means the title was removed.
+    Title(Option<String>),
+    Url(Url),
+    /// The new keyword for the bookmark's URL. `None` means the keyword was
+    /// removed.
+    Keyword(Option<String>),
+}
+
+/// Which collection a `SyncSummary` is for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SyncedCollection {
+    History,
+    Bookmarks,
+}
+
+/// A summary of the local changes made while applying incoming records.
+/// Observers should treat this as a hint to refresh any views of the
+/// collection, since individual changes aren't reported during a sync.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncSummary {
+    pub collection: SyncedCollection,
+    /// The number of items which were added or updated locally.
+    pub changed: u32,
+    /// The number of items which were removed locally.
+    pub removed: u32,
+}
+
+/// Implemented by consumers who want to hear about changes.
+///
+/// Events are delivered on whatever thread committed the change, in the
+/// order they were made. Observers should return quickly, and must not
+/// block on places connections which could be waiting on the caller.
+pub trait PlacesObserver: Send + Sync {
+    fn on_events(&self, events: &[PlacesEvent]);
+}
+
+/// Identifies a registered observer, so that it can later be removed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ObserverHandle(usize);
+
+/// The set of observers registered with a `PlacesApi`.
+#[derive(Default)]
+pub struct PlacesObservers {
+    next_id: AtomicUsize,
+    observers: Mutex<Vec<(ObserverHandle, Arc<dyn PlacesObserver>)>>,
+}
+
+impl PlacesObservers {
+    pub fn add(&self, observer: Arc<dyn PlacesObserver>) -> ObserverHandle {
+        let handle = ObserverHandle(self.next_id.fetch_add(1, Ordering::SeqCst));
+        self.observers.lock().unwrap().push((handle, observer));
+        handle
+    }
+
+    /// Returns true if the observer was registered and has been removed.
+    pub fn remove(&self, handle: ObserverHandle) -> bool {
+        let mut observers = self.observers.lock().unwrap();
+        let len = observers.len();
+        observers.retain(|(h, _)| *h != handle);
+        observers.len() != len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.observers.lock().unwrap().is_empty()
+    }
+
+    pub fn notify(&self, events: &[PlacesEvent]) {
+        if events.is_empty() {
+            return;
+        }
+        // Take a copy of the list so that observers are free to add or
+        // remove observers while being notified.
+        let observers: Vec<Arc<dyn PlacesObserver>> = self
+            .observers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(_, o)| o.clone())
+            .collect();
+        for observer in observers {
+            observer.on_events(events);
+        }
+    }
+}
+
+impl fmt::Debug for PlacesObservers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PlacesObservers")
+            .field("count", &self.observers.lock().unwrap().len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    /// An observer which just remembers everything it was told.
+    #[derive(Default)]
+    pub struct RecordingObserver {
+        events: Mutex<Vec<PlacesEvent>>,
+    }
+
+    impl RecordingObserver {
+        pub fn take(&self) -> Vec<PlacesEvent> {
+            std::mem::take(&mut *self.events.lock().unwrap())
+        }
+    }
+
+    impl PlacesObserver for RecordingObserver {
+        fn on_events(&self, events: &[PlacesEvent]) {
+            self.events.lock().unwrap().extend_from_slice(events);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test::RecordingObserver;
+    use super::*;
+
+    #[test]
+    fn test_add_remove() {
+        let observers = PlacesObservers::default();
+        assert!(observers.is_empty());
+        let first = Arc::new(RecordingObserver::default());
+        let second = Arc::new(RecordingObserver::default());
+        let first_handle = observers.add(first.clone());
+        let second_handle = observers.add(second.clone());
+        assert_ne!(first_handle, second_handle);
+
+        observers.notify(&[PlacesEvent::HistoryCleared]);
+        assert_eq!(first.take(), vec![PlacesEvent::HistoryCleared]);
+        assert_eq!(second.take(), vec![PlacesEvent::HistoryCleared]);
+
+        assert!(observers.remove(first_handle));
+        assert!(!observers.remove(first_handle));
+        observers.notify(&[PlacesEvent::HistoryCleared]);
+        assert_eq!(first.take(), vec![]);
+        assert_eq!(second.take(), vec![PlacesEvent::HistoryCleared]);
+
+        assert!(observers.remove(second_handle));
+        assert!(observers.is_empty());
+    }
+}
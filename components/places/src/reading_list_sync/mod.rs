@@ -0,0 +1,11 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Syncs the reading list, so that pages saved on one device can be read on
+//! another. See `storage::reading_list` for the local store.
+
+pub mod record;
+pub mod store;
+
+pub const COLLECTION_NAME: &str = "readinglist";
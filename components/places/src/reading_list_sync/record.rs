@@ -0,0 +1,69 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::error::*;
+use crate::storage::reading_list::ReadingListItem;
+use crate::types::Timestamp;
+use serde_derive::*;
+use sync_guid::Guid as SyncGuid;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadingListRecord {
+    pub id: SyncGuid,
+
+    pub url: String,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub excerpt: Option<String>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_device: Option<String>,
+
+    /// Milliseconds since the epoch.
+    pub added_at: Timestamp,
+
+    /// Milliseconds since the epoch, or `None` if the page hasn't been read.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_at: Option<Timestamp>,
+}
+
+impl From<ReadingListItem> for ReadingListRecord {
+    fn from(item: ReadingListItem) -> Self {
+        Self {
+            id: item.guid,
+            url: item.url.into_string(),
+            title: item.title,
+            excerpt: item.excerpt,
+            source_device: item.source_device,
+            added_at: item.added_at,
+            read_at: item.read_at,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ReadingListSyncRecord {
+    pub guid: SyncGuid,
+    pub record: Option<ReadingListRecord>,
+}
+
+impl ReadingListSyncRecord {
+    pub fn from_payload(payload: sync15::Payload) -> Result<Self> {
+        let guid = payload.id.clone();
+        let record = if payload.is_tombstone() {
+            None
+        } else {
+            Some(payload.into_record::<ReadingListRecord>()?)
+        };
+        Ok(Self { guid, record })
+    }
+}
@@ -0,0 +1,702 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use super::record::{ReadingListRecord, ReadingListSyncRecord};
+use super::COLLECTION_NAME;
+use crate::api::places_api::ConnectionType;
+use crate::db::PlacesDb;
+use crate::error::*;
+use crate::storage::reading_list::ReadingListItem;
+use crate::storage::{delete_meta, get_meta, put_meta, RowId};
+use crate::types::SyncStatus;
+use rusqlite::Row;
+use sql_support::{ConnExt, SqlInterruptScope};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::result;
+use sync15::telemetry;
+use sync15::{
+    CollSyncIds, CollectionRequest, IncomingChangeset, OutgoingChangeset, Payload, ServerTimestamp,
+    Store, StoreSyncAssociation,
+};
+use sync_guid::Guid as SyncGuid;
+use url::Url;
+
+pub const LAST_SYNC_META_KEY: &str = "reading_list_last_sync_time";
+pub const GLOBAL_SYNCID_META_KEY: &str = "reading_list_global_sync_id";
+pub const COLLECTION_SYNCID_META_KEY: &str = "reading_list_sync_id";
+
+/// A reading list item, along with its sync metadata.
+struct LocalItem {
+    id: RowId,
+    item: ReadingListItem,
+    sync_status: SyncStatus,
+    sync_change_counter: i64,
+}
+
+impl LocalItem {
+    fn from_row(row: &Row<'_>) -> Result<Self> {
+        Ok(Self {
+            id: row.get("id")?,
+            item: ReadingListItem::from_row(row)?,
+            sync_status: SyncStatus::from_u8(row.get("sync_status")?),
+            sync_change_counter: row.get("sync_change_counter")?,
+        })
+    }
+}
+
+const LOCAL_ITEM_COLUMNS: &str = "id, guid, url, title, excerpt, source_device, added_at,
+                                  read_at, sync_status, sync_change_counter";
+
+// Like `HistoryStore`, a `ReadingListStore` is short-lived, and constructed
+// each sync.
+pub struct ReadingListStore<'a> {
+    pub db: &'a PlacesDb,
+    interruptee: &'a SqlInterruptScope,
+    // The change counters of the items we're uploading, so that changes made
+    // while we're uploading them aren't lost.
+    uploading: RefCell<HashMap<SyncGuid, i64>>,
+}
+
+impl<'a> ReadingListStore<'a> {
+    pub fn new(db: &'a PlacesDb, interruptee: &'a SqlInterruptScope) -> Self {
+        assert_eq!(db.conn_type(), ConnectionType::Sync);
+        Self {
+            db,
+            interruptee,
+            uploading: RefCell::default(),
+        }
+    }
+
+    fn do_apply_incoming(
+        &self,
+        inbound: IncomingChangeset,
+        telem: &mut telemetry::Engine,
+    ) -> Result<OutgoingChangeset> {
+        let mut incoming_telemetry = telemetry::EngineIncoming::new();
+        let tx = self.db.begin_transaction()?;
+        for (payload, _) in inbound.changes {
+            self.interruptee.err_if_interrupted()?;
+            let applied = ReadingListSyncRecord::from_payload(payload).and_then(|incoming| {
+                self.in_savepoint(|| match incoming.record {
+                    Some(record) => self.apply_record(record),
+                    None => self.apply_tombstone(&incoming.guid),
+                })
+            });
+            match applied {
+                Ok(()) => incoming_telemetry.applied(1),
+                Err(e) => {
+                    log::warn!("Failed to apply incoming reading list record: {}", e);
+                    incoming_telemetry.failed(1);
+                }
+            }
+        }
+        telem.incoming(incoming_telemetry);
+        put_meta(self.db, LAST_SYNC_META_KEY, &inbound.timestamp.as_millis())?;
+        tx.commit()?;
+
+        let mut outgoing = OutgoingChangeset::new(COLLECTION_NAME, inbound.timestamp);
+        outgoing.changes = self.fetch_outgoing()?;
+        Ok(outgoing)
+    }
+
+    // Applying a record takes several statements, so we run them in a
+    // savepoint. If one fails, we roll back the others, instead of committing
+    // a half-applied record with the rest of the batch.
+    fn in_savepoint(&self, apply: impl FnOnce() -> Result<()>) -> Result<()> {
+        self.db.execute_batch("SAVEPOINT apply_record")?;
+        match apply() {
+            Ok(()) => {
+                self.db.execute_batch("RELEASE apply_record")?;
+                Ok(())
+            }
+            Err(e) => {
+                self.db
+                    .execute_batch("ROLLBACK TO apply_record; RELEASE apply_record")?;
+                Err(e)
+            }
+        }
+    }
+
+    fn fetch_local(&self, guid: &SyncGuid, url: &Url) -> Result<Vec<LocalItem>> {
+        self.db.query_rows_and_then_named_cached(
+            &format!(
+                "SELECT {} FROM moz_reading_list
+                 WHERE guid = :guid OR url = :url
+                 ORDER BY guid = :guid DESC",
+                LOCAL_ITEM_COLUMNS
+            ),
+            &[(":guid", guid), (":url", &url.as_str())],
+            LocalItem::from_row,
+        )
+    }
+
+    // Removes a local item that was replaced by an incoming one. If we've
+    // already uploaded it, we also upload a tombstone for it.
+    fn replace_local(&self, local: &LocalItem) -> Result<()> {
+        if local.sync_status == SyncStatus::Normal {
+            self.db.execute_named_cached(
+                "INSERT OR IGNORE INTO moz_reading_list_tombstones(guid) VALUES(:guid)",
+                &[(":guid", &local.item.guid)],
+            )?;
+        }
+        self.db.execute_named_cached(
+            "DELETE FROM moz_reading_list WHERE id = :id",
+            &[(":id", &local.id)],
+        )?;
+        Ok(())
+    }
+
+    fn apply_record(&self, record: ReadingListRecord) -> Result<()> {
+        let url = Url::parse(&record.url)?;
+        let mut matches = self.fetch_local(&record.id, &url)?.into_iter();
+        // If we have an item with the same GUID, that's the one we merge
+        // with. Otherwise, we dedupe by URL.
+        let local = matches.next();
+        for other in matches {
+            // Another item has the incoming URL, but we're already merging
+            // with the one that has the incoming GUID.
+            self.replace_local(&other)?;
+        }
+        let local = match local {
+            Some(local) => local,
+            None => {
+                self.db.execute_named_cached(
+                    "INSERT INTO moz_reading_list(guid, url, title, excerpt, source_device,
+                                                  added_at, read_at, sync_status,
+                                                  sync_change_counter)
+                     VALUES(:guid, :url, :title, :excerpt, :source_device,
+                            :added_at, :read_at, :normal, 0)",
+                    &[
+                        (":guid", &record.id),
+                        (":url", &url.as_str()),
+                        (":title", &record.title),
+                        (":excerpt", &record.excerpt),
+                        (":source_device", &record.source_device),
+                        (":added_at", &record.added_at),
+                        (":read_at", &record.read_at),
+                        (":normal", &(SyncStatus::Normal as u8)),
+                    ],
+                )?;
+                return Ok(());
+            }
+        };
+        if local.item.guid != record.id && local.sync_status == SyncStatus::Normal {
+            // We uploaded the local item with a different GUID, so we need to
+            // delete it from the server.
+            self.db.execute_named_cached(
+                "INSERT OR IGNORE INTO moz_reading_list_tombstones(guid) VALUES(:guid)",
+                &[(":guid", &local.item.guid)],
+            )?;
+        }
+        // If the item hasn't changed locally since we last synced, we take
+        // the incoming item as is. Otherwise, the local changes are newer, so
+        // we keep them, fill in anything that's missing locally, and leave the
+        // change counter alone so that we upload the merged item. An item
+        // that's been read on either side stays read, so that merging doesn't
+        // mark it unread everywhere.
+        let merged = if local.sync_change_counter == 0 {
+            ReadingListItem {
+                guid: record.id,
+                url,
+                title: record.title,
+                excerpt: record.excerpt,
+                source_device: record.source_device,
+                added_at: record.added_at,
+                read_at: record.read_at,
+            }
+        } else {
+            let item = local.item;
+            ReadingListItem {
+                guid: record.id,
+                url,
+                title: item.title.or(record.title),
+                excerpt: item.excerpt.or(record.excerpt),
+                source_device: item.source_device.or(record.source_device),
+                added_at: item.added_at.min(record.added_at),
+                read_at: item.read_at.or(record.read_at),
+            }
+        };
+        self.db.execute_named_cached(
+            "UPDATE moz_reading_list SET
+               guid = :guid,
+               url = :url,
+               title = :title,
+               excerpt = :excerpt,
+               source_device = :source_device,
+               added_at = :added_at,
+               read_at = :read_at,
+               sync_status = :normal
+             WHERE id = :id",
+            &[
+                (":guid", &merged.guid),
+                (":url", &merged.url.as_str()),
+                (":title", &merged.title),
+                (":excerpt", &merged.excerpt),
+                (":source_device", &merged.source_device),
+                (":added_at", &merged.added_at),
+                (":read_at", &merged.read_at),
+                (":normal", &(SyncStatus::Normal as u8)),
+                (":id", &local.id),
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn apply_tombstone(&self, guid: &SyncGuid) -> Result<()> {
+        // Deletions always win, even if the item changed locally, to match
+        // history.
+        self.db.execute_named_cached(
+            "DELETE FROM moz_reading_list WHERE guid = :guid",
+            &[(":guid", guid)],
+        )?;
+        self.db.execute_named_cached(
+            "DELETE FROM moz_reading_list_tombstones WHERE guid = :guid",
+            &[(":guid", guid)],
+        )?;
+        Ok(())
+    }
+
+    fn fetch_outgoing(&self) -> Result<Vec<Payload>> {
+        let mut uploading = self.uploading.borrow_mut();
+        uploading.clear();
+        let mut changes = Vec::new();
+        let tombstones = self.db.query_rows_and_then_named_cached(
+            "SELECT guid FROM moz_reading_list_tombstones",
+            &[],
+            |row| -> rusqlite::Result<SyncGuid> { Ok(row.get::<_, String>(0)?.into()) },
+        )?;
+        for guid in tombstones {
+            log::trace!("outgoing tombstone {:?}", &guid);
+            changes.push(Payload::new_tombstone(guid));
+        }
+        let items = self.db.query_rows_and_then_named_cached(
+            &format!(
+                "SELECT {} FROM moz_reading_list
+                 WHERE sync_change_counter > 0 OR sync_status != {}",
+                LOCAL_ITEM_COLUMNS,
+                SyncStatus::Normal as u8
+            ),
+            &[],
+            LocalItem::from_row,
+        )?;
+        for local in items {
+            log::trace!("outgoing record {:?}", &local.item.guid);
+            uploading.insert(local.item.guid.clone(), local.sync_change_counter);
+            changes.push(Payload::from_record(ReadingListRecord::from(local.item))?);
+        }
+        Ok(changes)
+    }
+
+    fn do_sync_finished(
+        &self,
+        new_timestamp: ServerTimestamp,
+        records_synced: Vec<SyncGuid>,
+    ) -> Result<()> {
+        log::info!(
+            "reading list sync completed after uploading {} records",
+            records_synced.len()
+        );
+        let uploading = self.uploading.replace(HashMap::new());
+        let tx = self.db.begin_transaction()?;
+        for guid in &records_synced {
+            match uploading.get(guid) {
+                Some(counter) => {
+                    // Leave the item flagged if it changed again while we
+                    // were uploading it.
+                    self.db.execute_named_cached(
+                        "UPDATE moz_reading_list SET
+                           sync_status = :normal,
+                           sync_change_counter = MAX(sync_change_counter - :counter, 0)
+                         WHERE guid = :guid",
+                        &[
+                            (":normal", &(SyncStatus::Normal as u8)),
+                            (":counter", counter),
+                            (":guid", guid),
+                        ],
+                    )?;
+                }
+                None => {
+                    self.db.execute_named_cached(
+                        "DELETE FROM moz_reading_list_tombstones WHERE guid = :guid",
+                        &[(":guid", guid)],
+                    )?;
+                }
+            }
+        }
+        put_meta(self.db, LAST_SYNC_META_KEY, &new_timestamp.as_millis())?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn reset_meta(&self) -> Result<()> {
+        // Marking every item as new makes the next sync reconcile and upload
+        // all of them. We keep the change counters, so that local changes
+        // that weren't uploaded yet still win over incoming records.
+        self.db.execute_all(&[
+            &format!(
+                "UPDATE moz_reading_list SET sync_status = {}",
+                SyncStatus::New as u8
+            ),
+            "DELETE FROM moz_reading_list_tombstones",
+        ])?;
+        put_meta(self.db, LAST_SYNC_META_KEY, &0)?;
+        Ok(())
+    }
+
+    pub(crate) fn do_reset(&self, assoc: &StoreSyncAssociation) -> Result<()> {
+        let tx = self.db.begin_transaction()?;
+        self.reset_meta()?;
+        match assoc {
+            StoreSyncAssociation::Disconnected => {
+                delete_meta(self.db, GLOBAL_SYNCID_META_KEY)?;
+                delete_meta(self.db, COLLECTION_SYNCID_META_KEY)?;
+            }
+            StoreSyncAssociation::Connected(ids) => {
+                put_meta(self.db, GLOBAL_SYNCID_META_KEY, &ids.global)?;
+                put_meta(self.db, COLLECTION_SYNCID_META_KEY, &ids.coll)?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub(crate) fn do_wipe(&self) -> Result<()> {
+        self.db.execute_all(&[
+            "DELETE FROM moz_reading_list",
+            "DELETE FROM moz_reading_list_tombstones",
+        ])?;
+        Ok(())
+    }
+}
+
+impl<'a> Store for ReadingListStore<'a> {
+    fn collection_name(&self) -> std::borrow::Cow<'static, str> {
+        COLLECTION_NAME.into()
+    }
+
+    fn apply_incoming(
+        &self,
+        inbound: Vec<IncomingChangeset>,
+        telem: &mut telemetry::Engine,
+    ) -> result::Result<OutgoingChangeset, failure::Error> {
+        assert_eq!(inbound.len(), 1, "reading list only requests one item");
+        let inbound = inbound.into_iter().next().unwrap();
+        Ok(self.do_apply_incoming(inbound, telem)?)
+    }
+
+    fn sync_finished(
+        &self,
+        new_timestamp: ServerTimestamp,
+        records_synced: Vec<SyncGuid>,
+    ) -> result::Result<(), failure::Error> {
+        self.do_sync_finished(new_timestamp, records_synced)?;
+        Ok(())
+    }
+
+    fn get_last_sync(&self) -> result::Result<Option<ServerTimestamp>, failure::Error> {
+        Ok(get_meta::<i64>(self.db, LAST_SYNC_META_KEY)?.map(ServerTimestamp))
+    }
+
+    fn get_collection_requests(
+        &self,
+        server_timestamp: ServerTimestamp,
+    ) -> result::Result<Vec<CollectionRequest>, failure::Error> {
+        let since = self.get_last_sync()?.unwrap_or_default();
+        Ok(if since == server_timestamp {
+            vec![]
+        } else {
+            vec![CollectionRequest::new(COLLECTION_NAME)
+                .full()
+                .newer_than(since)]
+        })
+    }
+
+    fn get_sync_assoc(&self) -> result::Result<StoreSyncAssociation, failure::Error> {
+        let global = get_meta(self.db, GLOBAL_SYNCID_META_KEY)?;
+        let coll = get_meta(self.db, COLLECTION_SYNCID_META_KEY)?;
+        Ok(if let (Some(global), Some(coll)) = (global, coll) {
+            StoreSyncAssociation::Connected(CollSyncIds { global, coll })
+        } else {
+            StoreSyncAssociation::Disconnected
+        })
+    }
+
+    fn reset(&self, assoc: &StoreSyncAssociation) -> result::Result<(), failure::Error> {
+        self.do_reset(assoc)?;
+        Ok(())
+    }
+
+    fn wipe(&self) -> result::Result<(), failure::Error> {
+        self.do_wipe()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_api;
+    use crate::storage::reading_list::{self, ReadingListFilter};
+    use crate::types::Timestamp;
+    use serde_json::json;
+
+    fn incoming(records: Vec<serde_json::Value>) -> IncomingChangeset {
+        let mut changeset = IncomingChangeset::new(COLLECTION_NAME, ServerTimestamp(1000));
+        for record in records {
+            let payload = Payload::from_json(record).unwrap();
+            changeset.changes.push((payload, ServerTimestamp(1000)));
+        }
+        changeset
+    }
+
+    fn apply(store: &ReadingListStore<'_>, changeset: IncomingChangeset) -> Vec<Payload> {
+        let mut telem = telemetry::Engine::new(COLLECTION_NAME);
+        store
+            .apply_incoming(vec![changeset], &mut telem)
+            .expect("Should apply incoming records")
+            .changes
+    }
+
+    #[test]
+    fn test_upload_and_finish() -> Result<()> {
+        let api = new_mem_api();
+        let writer = api.open_connection(ConnectionType::ReadWrite)?;
+        let url = Url::parse("https://example.com/article")?;
+        let item = reading_list::add_item(&writer, &url, Some("Title"), None, Some("Phone"))?;
+        let deleted = Url::parse("https://example.com/deleted")?;
+        reading_list::add_item(&writer, &deleted, None, None, None)?;
+
+        let conn = api.open_sync_connection()?;
+        let scope = conn.begin_interrupt_scope();
+        let store = ReadingListStore::new(&conn, &scope);
+        let outgoing = apply(&store, incoming(vec![]));
+        assert_eq!(outgoing.len(), 2);
+        let guids = outgoing.iter().map(|p| p.id.clone()).collect::<Vec<_>>();
+        store.do_sync_finished(ServerTimestamp(2000), guids)?;
+
+        // Nothing should be uploaded again...
+        assert!(apply(&store, incoming(vec![])).is_empty());
+
+        // ...until it changes.
+        reading_list::mark_read(&writer, &url, true)?;
+        reading_list::delete_item(&writer, &deleted)?;
+        let outgoing = apply(&store, incoming(vec![]));
+        assert_eq!(outgoing.len(), 2);
+        let record = outgoing
+            .into_iter()
+            .find(|p| !p.is_tombstone())
+            .unwrap()
+            .into_record::<ReadingListRecord>()?;
+        assert_eq!(record.id, item.guid);
+        assert_eq!(record.title, Some("Title".to_string()));
+        assert_eq!(record.source_device, Some("Phone".to_string()));
+        assert!(record.read_at.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_incoming() -> Result<()> {
+        let api = new_mem_api();
+        let writer = api.open_connection(ConnectionType::ReadWrite)?;
+        let local = Url::parse("https://example.com/local")?;
+        reading_list::add_item(&writer, &local, None, None, None)?;
+        reading_list::mark_read(&writer, &local, true)?;
+        let read_at = reading_list::get_item(&writer, &local)?.unwrap().read_at;
+
+        let conn = api.open_sync_connection()?;
+        let scope = conn.begin_interrupt_scope();
+        let store = ReadingListStore::new(&conn, &scope);
+        let outgoing = apply(
+            &store,
+            incoming(vec![
+                json!({
+                    "id": "remoteaaaaaa",
+                    "url": "https://example.com/remote",
+                    "title": "Remote",
+                    "addedAt": 1000,
+                }),
+                // Dupes the local item, which changed locally, so the local
+                // read date should win.
+                json!({
+                    "id": "dupebbbbbbbb",
+                    "url": "https://example.com/local",
+                    "title": "Local",
+                    "excerpt": "An excerpt",
+                    "addedAt": 500,
+                }),
+                json!({
+                    "id": "deletedccccc",
+                    "deleted": true,
+                }),
+            ]),
+        );
+
+        let remote = reading_list::get_item(&writer, &Url::parse("https://example.com/remote")?)?
+            .expect("Should apply new item");
+        assert_eq!(remote.guid, "remoteaaaaaa");
+        assert_eq!(remote.title, Some("Remote".to_string()));
+        assert_eq!(remote.added_at, Timestamp(1000));
+
+        let deduped = reading_list::get_item(&writer, &local)?.unwrap();
+        assert_eq!(deduped.guid, "dupebbbbbbbb");
+        assert_eq!(deduped.title, Some("Local".to_string()));
+        assert_eq!(deduped.added_at, Timestamp(500));
+        assert_eq!(deduped.read_at, read_at);
+
+        // We should upload the merged item, but not the new one.
+        assert_eq!(outgoing.len(), 1);
+        assert_eq!(outgoing[0].id, "dupebbbbbbbb");
+
+        // Incoming tombstones delete local items.
+        apply(
+            &store,
+            incoming(vec![json!({"id": "remoteaaaaaa", "deleted": true})]),
+        );
+        assert_eq!(
+            reading_list::get_items(&writer, ReadingListFilter::All)?.len(),
+            1
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_incoming_failure() -> Result<()> {
+        let api = new_mem_api();
+        let writer = api.open_connection(ConnectionType::ReadWrite)?;
+        let first = Url::parse("https://example.com/first")?;
+        let item = reading_list::add_item(&writer, &first, None, None, None)?;
+        let second = Url::parse("https://example.com/second")?;
+        reading_list::add_item(&writer, &second, None, None, None)?;
+
+        let conn = api.open_sync_connection()?;
+        let scope = conn.begin_interrupt_scope();
+        let store = ReadingListStore::new(&conn, &scope);
+        conn.execute_batch(
+            "CREATE TEMP TRIGGER failUpdate
+             BEFORE UPDATE ON moz_reading_list
+             BEGIN
+               SELECT RAISE(ABORT, 'Simulated failure');
+             END",
+        )?;
+
+        // The incoming record has the first item's GUID, and the second
+        // item's URL. We replace the second item before updating the first,
+        // which fails, so the replacement should be rolled back, too.
+        let mut telem = telemetry::Engine::new(COLLECTION_NAME);
+        store
+            .apply_incoming(
+                vec![incoming(vec![json!({
+                    "id": item.guid,
+                    "url": "https://example.com/second",
+                    "addedAt": 1000,
+                })])],
+                &mut telem,
+            )
+            .expect("Should apply incoming records");
+        assert_eq!(telem.get_incoming().map(|i| i.get_failed()), Some(1));
+        assert!(reading_list::get_item(&writer, &first)?.is_some());
+        assert!(reading_list::get_item(&writer, &second)?.is_some());
+        let tombstones: i64 =
+            writer.query_one("SELECT COUNT(*) FROM moz_reading_list_tombstones")?;
+        assert_eq!(tombstones, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reset_and_wipe() -> Result<()> {
+        let api = new_mem_api();
+        let writer = api.open_connection(ConnectionType::ReadWrite)?;
+        let url = Url::parse("https://example.com/article")?;
+        reading_list::add_item(&writer, &url, None, None, None)?;
+
+        let conn = api.open_sync_connection()?;
+        let scope = conn.begin_interrupt_scope();
+        let store = ReadingListStore::new(&conn, &scope);
+        let outgoing = apply(&store, incoming(vec![]));
+        let guids = outgoing.iter().map(|p| p.id.clone()).collect::<Vec<_>>();
+        store.do_sync_finished(ServerTimestamp(2000), guids)?;
+        assert!(apply(&store, incoming(vec![])).is_empty());
+
+        // After a reset, everything should be uploaded again.
+        store.do_reset(&StoreSyncAssociation::Disconnected)?;
+        assert_eq!(
+            store.get_sync_assoc().unwrap(),
+            StoreSyncAssociation::Disconnected
+        );
+        assert_eq!(apply(&store, incoming(vec![])).len(), 1);
+
+        store.do_wipe()?;
+        assert!(reading_list::get_items(&writer, ReadingListFilter::All)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_dedupe_keeps_read() -> Result<()> {
+        let api = new_mem_api();
+        let writer = api.open_connection(ConnectionType::ReadWrite)?;
+        let url = Url::parse("https://example.com/article")?;
+        reading_list::add_item(&writer, &url, None, None, None)?;
+
+        let conn = api.open_sync_connection()?;
+        let scope = conn.begin_interrupt_scope();
+        let store = ReadingListStore::new(&conn, &scope);
+        // The local item is unread, but another device already read it.
+        let outgoing = apply(
+            &store,
+            incoming(vec![json!({
+                "id": "remoteaaaaaa",
+                "url": "https://example.com/article",
+                "addedAt": 1000,
+                "readAt": 2000,
+            })]),
+        );
+
+        let merged = reading_list::get_item(&writer, &url)?.unwrap();
+        assert_eq!(merged.guid, "remoteaaaaaa");
+        assert_eq!(merged.read_at, Some(Timestamp(2000)));
+        assert_eq!(outgoing.len(), 1);
+        let record = outgoing[0].clone().into_record::<ReadingListRecord>()?;
+        assert_eq!(record.read_at, Some(Timestamp(2000)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_reset_keeps_local_changes() -> Result<()> {
+        let api = new_mem_api();
+        let writer = api.open_connection(ConnectionType::ReadWrite)?;
+        let url = Url::parse("https://example.com/article")?;
+        let item = reading_list::add_item(&writer, &url, None, None, None)?;
+
+        let conn = api.open_sync_connection()?;
+        let scope = conn.begin_interrupt_scope();
+        let store = ReadingListStore::new(&conn, &scope);
+        let outgoing = apply(&store, incoming(vec![]));
+        let guids = outgoing.iter().map(|p| p.id.clone()).collect::<Vec<_>>();
+        store.do_sync_finished(ServerTimestamp(2000), guids)?;
+
+        // Mark the item as read, but reset before uploading it. The incoming
+        // copy of the item, which is still unread, shouldn't undo the change.
+        reading_list::mark_read(&writer, &url, true)?;
+        let read_at = reading_list::get_item(&writer, &url)?.unwrap().read_at;
+        store.do_reset(&StoreSyncAssociation::Disconnected)?;
+        let outgoing = apply(
+            &store,
+            incoming(vec![json!({
+                "id": item.guid,
+                "url": "https://example.com/article",
+                "addedAt": 1000,
+            })]),
+        );
+
+        assert_eq!(
+            reading_list::get_item(&writer, &url)?.unwrap().read_at,
+            read_at
+        );
+        assert_eq!(outgoing.len(), 1);
+        let record = outgoing[0].clone().into_record::<ReadingListRecord>()?;
+        assert_eq!(record.read_at, read_at);
+        Ok(())
+    }
+}
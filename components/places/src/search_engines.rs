@@ -0,0 +1,163 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Recognizes search result pages, so that we can show what the user
+//! searched for, instead of a URL that's meaningless to them.
+//!
+//! Engines are defined by a URL template, like
+//! `https://www.example.com/search?q={searchTerms}`, in the same format as
+//! desktop's search engine definitions. A visited URL is a result page for
+//! an engine if it has the same host and path as the template, and a value
+//! for the query parameter that holds `{searchTerms}`.
+
+use crate::error::*;
+use serde_derive::*;
+use url::Url;
+
+const SEARCH_TERMS_PLACEHOLDER: &str = "{searchTerms}";
+
+/// A search engine definition, as it appears in the JSON list passed to
+/// `SearchEngines::from_json`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchEngineDefinition {
+    pub name: String,
+    pub url_template: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct SearchEngine {
+    name: String,
+    host: String,
+    path: String,
+    // The query parameter that holds the search terms.
+    param: String,
+}
+
+impl SearchEngine {
+    fn new(definition: SearchEngineDefinition) -> Result<Self> {
+        let invalid = || ErrorKind::InvalidSearchEngine(definition.name.clone());
+        let template = Url::parse(&definition.url_template).map_err(|_| invalid())?;
+        let host = template.host_str().ok_or_else(invalid)?.to_string();
+        let param = template
+            .query_pairs()
+            .find(|(_, value)| value == SEARCH_TERMS_PLACEHOLDER)
+            .map(|(name, _)| name.into_owned())
+            .ok_or_else(invalid)?;
+        Ok(Self {
+            name: definition.name,
+            host,
+            path: template.path().to_string(),
+            param,
+        })
+    }
+
+    fn extract(&self, url: &Url) -> Option<String> {
+        if url.host_str() != Some(&self.host) || url.path() != self.path {
+            return None;
+        }
+        url.query_pairs()
+            .find(|(name, _)| name == self.param.as_str())
+            .map(|(_, value)| value.trim().to_string())
+            .filter(|term| !term.is_empty())
+    }
+}
+
+/// What the user searched for, and where.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SearchTerm {
+    pub term: String,
+    /// The name of the search engine.
+    pub engine: String,
+}
+
+/// The search engines we know about. This is empty by default, so no search
+/// terms are extracted until `PlacesApi::set_search_engines` is called.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchEngines {
+    engines: Vec<SearchEngine>,
+}
+
+impl SearchEngines {
+    pub fn new(definitions: Vec<SearchEngineDefinition>) -> Result<Self> {
+        Ok(Self {
+            engines: definitions
+                .into_iter()
+                .map(SearchEngine::new)
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    /// Parses a JSON array of engine definitions, like
+    /// `[{"name": "Example", "urlTemplate": "https://example.com/?q={searchTerms}"}]`.
+    pub fn from_json(json: &str) -> Result<Self> {
+        Self::new(serde_json::from_str(json)?)
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.engines.is_empty()
+    }
+
+    /// Returns the search term in `url`, if it's a result page for one of our
+    /// engines. If more than one engine matches, the first one wins.
+    pub fn extract(&self, url: &Url) -> Option<SearchTerm> {
+        self.engines.iter().find_map(|engine| {
+            engine.extract(url).map(|term| SearchTerm {
+                term,
+                engine: engine.name.clone(),
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract() -> Result<()> {
+        let engines = SearchEngines::from_json(
+            r#"[
+                {"name": "Example", "urlTemplate": "https://www.example.com/search?q={searchTerms}&client=firefox"},
+                {"name": "Other", "urlTemplate": "https://other.example.org/?query={searchTerms}"}
+            ]"#,
+        )?;
+        let extract = |url: &str| engines.extract(&Url::parse(url).unwrap());
+        assert_eq!(
+            extract("https://www.example.com/search?client=firefox&q=cute+kittens"),
+            Some(SearchTerm {
+                term: "cute kittens".to_string(),
+                engine: "Example".to_string(),
+            })
+        );
+        assert_eq!(
+            extract("https://other.example.org/?query=%E2%9D%A4%20rust").map(|t| t.term),
+            Some("\u{2764} rust".to_string())
+        );
+        // Wrong path, missing or empty terms, and unknown hosts don't match.
+        assert_eq!(extract("https://www.example.com/images?q=kittens"), None);
+        assert_eq!(
+            extract("https://www.example.com/search?client=firefox"),
+            None
+        );
+        assert_eq!(extract("https://www.example.com/search?q=+"), None);
+        assert_eq!(extract("https://example.net/search?q=kittens"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_engines() {
+        let err = SearchEngines::from_json(
+            r#"[{"name": "No terms", "urlTemplate": "https://example.com/search"}]"#,
+        )
+        .unwrap_err();
+        match err.kind() {
+            ErrorKind::InvalidSearchEngine(name) => assert_eq!(name, "No terms"),
+            _ => panic!("Unexpected error: {}", err),
+        }
+        assert!(SearchEngines::from_json("{}").is_err());
+        assert!(SearchEngines::from_json("[]").unwrap().is_empty());
+    }
+}
@@ -2,6 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use super::tags::{tag_url_in_tx, validate_tag};
 use super::RowId;
 use super::{delete_meta, put_meta};
 use super::{fetch_page_info, new_page_info};
@@ -10,6 +11,7 @@ use crate::bookmark_sync::store::{
 };
 use crate::db::PlacesDb;
 use crate::error::*;
+use crate::observer::{BookmarkProperty, PlacesEvent};
 use crate::types::{BookmarkType, SyncStatus, Timestamp};
 use rusqlite::types::ToSql;
 use rusqlite::{Connection, Row};
@@ -22,8 +24,9 @@ use serde_derive::*;
 use serde_json::{self, json};
 use sql_support::{self, ConnExt};
 use std::cmp::{max, min};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use sync_guid::Guid as SyncGuid;
+use undo::UndoEntry;
 use url::Url;
 
 pub use public_node::PublicNode;
@@ -32,6 +35,7 @@ pub use root_guid::{BookmarkRootGuid, USER_CONTENT_ROOTS};
 mod conversions;
 pub mod public_node;
 mod root_guid;
+pub mod undo;
 
 fn create_root(
     db: &Connection,
@@ -75,6 +79,54 @@ pub fn create_bookmark_roots(db: &Connection) -> Result<()> {
     Ok(())
 }
 
+/// Recreates any bookmark roots that are missing from the local tree. Missing
+/// user content roots are appended to the Places root, which is flagged for
+/// upload, since its children changed. Returns the number of roots created.
+pub(crate) fn create_missing_bookmark_roots(db: &PlacesDb) -> Result<usize> {
+    let now = Timestamp::now();
+    let mut created = 0;
+    let root_exists = |guid: BookmarkRootGuid| -> Result<bool> {
+        Ok(db.query_row_and_then_named(
+            "SELECT EXISTS(SELECT 1 FROM moz_bookmarks WHERE guid = :guid)",
+            &[(":guid", &guid.as_guid())],
+            |row| row.get(0),
+            true,
+        )?)
+    };
+    if !root_exists(BookmarkRootGuid::Root)? {
+        create_root(db, "root", &BookmarkRootGuid::Root.into(), 0, now)?;
+        created += 1;
+    }
+    for (guid, title) in &[
+        (BookmarkRootGuid::Menu, "menu"),
+        (BookmarkRootGuid::Toolbar, "toolbar"),
+        (BookmarkRootGuid::Unfiled, "unfiled"),
+        (BookmarkRootGuid::Mobile, "mobile"),
+    ] {
+        if root_exists(*guid)? {
+            continue;
+        }
+        let position: u32 = db.query_row_and_then_named(
+            "SELECT COUNT(*) FROM moz_bookmarks
+             WHERE parent = (SELECT id FROM moz_bookmarks WHERE guid = :root_guid)",
+            &[(":root_guid", &BookmarkRootGuid::Root.as_guid())],
+            |row| row.get(0),
+            true,
+        )?;
+        create_root(db, title, &(*guid).into(), position, now)?;
+        created += 1;
+    }
+    if created > 0 {
+        db.execute_named_cached(
+            "UPDATE moz_bookmarks SET
+               syncChangeCounter = syncChangeCounter + 1
+             WHERE guid = :root_guid",
+            &[(":root_guid", &BookmarkRootGuid::Root.as_guid())],
+        )?;
+    }
+    Ok(created)
+}
+
 #[derive(Debug, Copy, Clone)]
 pub enum BookmarkPosition {
     Specific(u32),
@@ -250,7 +302,12 @@ pub fn insert_bookmark(db: &PlacesDb, bm: &InsertableItem) -> Result<SyncGuid> {
     let result = insert_bookmark_in_tx(db, bm);
     super::delete_pending_temp_tables(db)?;
     match result {
-        Ok(_) => tx.commit()?,
+        Ok(ref guid) => {
+            tx.commit()?;
+            db.bookmark_undo_log()
+                .borrow_mut()
+                .push(UndoEntry::Insert { guid: guid.clone() });
+        }
         Err(_) => tx.rollback()?,
     }
     result
@@ -363,6 +420,16 @@ fn insert_bookmark_in_tx(db: &PlacesDb, bm: &InsertableItem) -> Result<SyncGuid>
         WHERE id = :parent_id";
     db.execute_named_cached(sql_counter, &[(":parent_id", &parent.row_id)])?;
 
+    db.note_event(PlacesEvent::BookmarkAdded {
+        guid: guid.clone(),
+        parent_guid: parent.guid,
+        position,
+        node_type: bookmark_type,
+        url: match bm {
+            InsertableItem::Bookmark(b) => Some(b.url.clone()),
+            _ => None,
+        },
+    });
     Ok(guid)
 }
 
@@ -370,9 +437,15 @@ fn insert_bookmark_in_tx(db: &PlacesDb, bm: &InsertableItem) -> Result<SyncGuid>
 /// existed and was deleted, false otherwise.
 pub fn delete_bookmark(db: &PlacesDb, guid: &SyncGuid) -> Result<bool> {
     let tx = db.begin_transaction()?;
+    let undo_entry = UndoEntry::for_delete(db, guid)?;
     let result = delete_bookmark_in_tx(db, guid);
     match result {
-        Ok(_) => tx.commit()?,
+        Ok(deleted) => {
+            tx.commit()?;
+            if let (true, Some(entry)) = (deleted, undo_entry) {
+                db.bookmark_undo_log().borrow_mut().push(entry);
+            }
+        }
         Err(_) => tx.rollback()?,
     }
     result
@@ -404,6 +477,15 @@ fn delete_bookmark_in_tx(db: &PlacesDb, guid: &SyncGuid) -> Result<bool> {
         &[(":id", &record.row_id)],
     )?;
     super::delete_pending_temp_tables(db)?;
+    if let Some(parent_guid) = record.parent_guid {
+        db.note_event(PlacesEvent::BookmarkRemoved {
+            guid: record.guid,
+            parent_guid,
+            position: record.position,
+            node_type: record.bookmark_type,
+            url: record.url,
+        });
+    }
     Ok(true)
 }
 
@@ -493,10 +575,14 @@ pub fn update_bookmark(db: &PlacesDb, guid: &SyncGuid, item: &UpdatableItem) ->
     let tx = db.begin_transaction()?;
     let existing = get_raw_bookmark(db, guid)?
         .ok_or_else(|| InvalidPlaceInfo::NoSuchGuid(guid.to_string()))?;
+    let undo_entry = UndoEntry::for_update(&existing);
     let result = update_bookmark_in_tx(db, guid, item, existing);
     super::delete_pending_temp_tables(db)?;
     // Note: `tx` automatically rolls back on drop if we don't commit
     tx.commit()?;
+    if let (Ok(_), Some(entry)) = (&result, undo_entry) {
+        db.bookmark_undo_log().borrow_mut().push(entry);
+    }
     result
 }
 
@@ -533,6 +619,7 @@ fn update_bookmark_in_tx(
     // no value is specified.
     let parent_id;
     let position;
+    let mut parent_guid = existing_parent_guid;
     match item.location() {
         UpdateTreeLocation::None => {
             parent_id = existing_parent_id;
@@ -558,6 +645,7 @@ fn update_bookmark_in_tx(
             if new_parent.bookmark_type != BookmarkType::Folder {
                 return Err(InvalidPlaceInfo::InvalidParent(new_parent_guid.to_string()).into());
             }
+            parent_guid = new_parent_guid;
             parent_id = new_parent.row_id;
             update_old_parent_status = true;
             update_new_parent_status = true;
@@ -651,6 +739,317 @@ fn update_bookmark_in_tx(
         set_ancestors_last_modified(db, parent_id, now)?;
         db.execute_named_cached(sql_counter, &[(":parent_id", &parent_id)])?;
     }
+
+    if parent_id != existing_parent_id || position != raw.position {
+        db.note_event(PlacesEvent::BookmarkMoved {
+            guid: guid.clone(),
+            old_parent_guid: existing_parent_guid.clone(),
+            old_position: raw.position,
+            new_parent_guid: parent_guid.clone(),
+            new_position: position,
+        });
+    }
+    if title != raw.title {
+        db.note_event(PlacesEvent::BookmarkChanged {
+            guid: guid.clone(),
+            property: BookmarkProperty::Title(
+                maybe_truncate_title(&title.as_deref()).map(String::from),
+            ),
+        });
+    }
+    if place_id != raw.place_id {
+        if let UpdatableItem::Bookmark(UpdatableBookmark { url: Some(url), .. }) = item {
+            db.note_event(PlacesEvent::BookmarkChanged {
+                guid: guid.clone(),
+                property: BookmarkProperty::Url(url.clone()),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Moves the children of a folder into the given order, in a single
+/// transaction. This matches desktop's `PlacesUtils.bookmarks.reorder`: GUIDs
+/// that aren't children of the folder are ignored, and children that aren't
+/// in `ordered_guids` are moved after the ones that are, keeping their
+/// current relative order.
+pub fn reorder_children(
+    db: &PlacesDb,
+    folder_guid: &SyncGuid,
+    ordered_guids: &[SyncGuid],
+) -> Result<()> {
+    let tx = db.begin_transaction()?;
+    let result = reorder_children_in_tx(db, folder_guid, ordered_guids);
+    match result {
+        Ok(ref old_guids) => {
+            tx.commit()?;
+            if let Some(guids) = old_guids {
+                db.bookmark_undo_log()
+                    .borrow_mut()
+                    .push(UndoEntry::Reorder {
+                        folder_guid: folder_guid.clone(),
+                        guids: guids.clone(),
+                    });
+            }
+        }
+        Err(_) => tx.rollback()?,
+    }
+    result.map(|_| ())
+}
+
+/// Reorders the children of a folder, returning the GUIDs of the children in
+/// their old order, or `None` if the order didn't change.
+pub(super) fn reorder_children_in_tx(
+    db: &PlacesDb,
+    folder_guid: &SyncGuid,
+    ordered_guids: &[SyncGuid],
+) -> Result<Option<Vec<SyncGuid>>> {
+    let folder = get_raw_folder(db, folder_guid)?;
+    let children = get_raw_children(db, folder.row_id)?;
+    let mut unordered: HashMap<&SyncGuid, &RawBookmark> =
+        children.iter().map(|child| (&child.guid, child)).collect();
+    let mut new_children = Vec::with_capacity(children.len());
+    for guid in ordered_guids {
+        if let Some(child) = unordered.remove(guid) {
+            new_children.push(child);
+        }
+    }
+    new_children.extend(
+        children
+            .iter()
+            .filter(|child| unordered.contains_key(&child.guid)),
+    );
+    if new_children
+        .iter()
+        .enumerate()
+        .all(|(position, child)| child.position as usize == position)
+    {
+        return Ok(None);
+    }
+
+    set_child_positions(db, folder.row_id, &new_children)?;
+    // Desktop only bumps the change counter for the folder, since the
+    // positions of its children are part of its record, not theirs.
+    let now = Timestamp::now();
+    set_ancestors_last_modified(db, folder.row_id, now)?;
+    db.execute_named_cached(
+        "UPDATE moz_bookmarks SET syncChangeCounter = syncChangeCounter + 1
+         WHERE id = :id",
+        &[(":id", &folder.row_id)],
+    )?;
+    for (position, child) in new_children.iter().enumerate() {
+        if child.position as usize != position {
+            db.note_event(PlacesEvent::BookmarkMoved {
+                guid: child.guid.clone(),
+                old_parent_guid: folder.guid.clone(),
+                old_position: child.position,
+                new_parent_guid: folder.guid.clone(),
+                new_position: position as u32,
+            });
+        }
+    }
+    Ok(Some(children.into_iter().map(|child| child.guid).collect()))
+}
+
+/// Moves several items into a folder in a single transaction. The items are
+/// inserted at `position` in the order given, and the children of each
+/// affected folder are renumbered once. `position` is an index into the
+/// children of the new parent that aren't being moved.
+pub fn move_many(
+    db: &PlacesDb,
+    guids: &[SyncGuid],
+    new_parent_guid: &SyncGuid,
+    position: BookmarkPosition,
+) -> Result<()> {
+    let tx = db.begin_transaction()?;
+    let result = move_many_in_tx(db, guids, new_parent_guid, position);
+    match result {
+        Ok(ref old_children) => {
+            tx.commit()?;
+            if !old_children.is_empty() {
+                db.bookmark_undo_log().borrow_mut().push(UndoEntry::Move {
+                    guids: guids.to_vec(),
+                    old_children: old_children.clone(),
+                });
+            }
+        }
+        Err(_) => tx.rollback()?,
+    }
+    result.map(|_| ())
+}
+
+/// Moves several items into a folder, returning the GUIDs of each old parent
+/// and its children in their old order.
+pub(super) fn move_many_in_tx(
+    db: &PlacesDb,
+    guids: &[SyncGuid],
+    new_parent_guid: &SyncGuid,
+    position: BookmarkPosition,
+) -> Result<Vec<(SyncGuid, Vec<SyncGuid>)>> {
+    if new_parent_guid == BookmarkRootGuid::Root {
+        return Err(InvalidPlaceInfo::CannotUpdateRoot(BookmarkRootGuid::Root).into());
+    }
+    let new_parent = get_raw_folder(db, new_parent_guid)?;
+    let new_parent_ancestors = get_ancestor_ids(db, new_parent.row_id)?;
+
+    let mut items: Vec<RawBookmark> = Vec::with_capacity(guids.len());
+    let mut moved_ids = HashSet::new();
+    for guid in guids {
+        if let Some(root) = BookmarkRootGuid::well_known(&guid.as_str()) {
+            return Err(InvalidPlaceInfo::CannotUpdateRoot(root).into());
+        }
+        let item = get_raw_bookmark(db, guid)?
+            .ok_or_else(|| InvalidPlaceInfo::NoSuchGuid(guid.to_string()))?;
+        // A folder can't be moved into itself, or one of its descendants.
+        if new_parent_ancestors.contains(&item.row_id) {
+            return Err(InvalidPlaceInfo::InvalidParent(new_parent_guid.to_string()).into());
+        }
+        if item.parent_id.is_none() {
+            return Err(Corruption::NonRootWithoutParent(guid.to_string()).into());
+        }
+        if moved_ids.insert(item.row_id) {
+            items.push(item);
+        }
+    }
+    if items.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // Renumber the old parents' remaining children first, then splice the
+    // items into the new parent.
+    let mut old_children = Vec::new();
+    let mut old_parent_ids = HashSet::new();
+    for item in &items {
+        let (parent_id, parent_guid) = match (item.parent_id, &item.parent_guid) {
+            (Some(id), Some(guid)) => (id, guid),
+            _ => continue,
+        };
+        if !old_parent_ids.insert(parent_id) {
+            continue;
+        }
+        let children = get_raw_children(db, parent_id)?;
+        if parent_id != new_parent.row_id {
+            let remaining: Vec<_> = children
+                .iter()
+                .filter(|child| !moved_ids.contains(&child.row_id))
+                .collect();
+            set_child_positions(db, parent_id, &remaining)?;
+        }
+        old_children.push((
+            parent_guid.clone(),
+            children.into_iter().map(|child| child.guid).collect(),
+        ));
+    }
+    let siblings = get_raw_children(db, new_parent.row_id)?;
+    let siblings: Vec<_> = siblings
+        .iter()
+        .filter(|child| !moved_ids.contains(&child.row_id))
+        .collect();
+    let index = match position {
+        BookmarkPosition::Specific(specified) => min(specified as usize, siblings.len()),
+        BookmarkPosition::Append => siblings.len(),
+    };
+    let mut new_children = Vec::with_capacity(siblings.len() + items.len());
+    new_children.extend_from_slice(&siblings[..index]);
+    new_children.extend(&items);
+    new_children.extend_from_slice(&siblings[index..]);
+    set_child_positions(db, new_parent.row_id, &new_children)?;
+
+    let now = Timestamp::now();
+    for item in &items {
+        db.execute_named_cached(
+            "UPDATE moz_bookmarks SET lastModified = :now WHERE id = :id",
+            &[(":now", &now), (":id", &item.row_id)],
+        )?;
+    }
+    old_parent_ids.insert(new_parent.row_id);
+    for parent_id in &old_parent_ids {
+        set_ancestors_last_modified(db, *parent_id, now)?;
+        db.execute_named_cached(
+            "UPDATE moz_bookmarks SET syncChangeCounter = syncChangeCounter + 1
+             WHERE id = :id",
+            &[(":id", parent_id)],
+        )?;
+    }
+    for (offset, item) in items.iter().enumerate() {
+        let new_position = (index + offset) as u32;
+        if item.parent_id == Some(new_parent.row_id) && item.position == new_position {
+            continue;
+        }
+        if let Some(old_parent_guid) = &item.parent_guid {
+            db.note_event(PlacesEvent::BookmarkMoved {
+                guid: item.guid.clone(),
+                old_parent_guid: old_parent_guid.clone(),
+                old_position: item.position,
+                new_parent_guid: new_parent.guid.clone(),
+                new_position,
+            });
+        }
+    }
+    Ok(old_children)
+}
+
+/// Returns the folder with the given GUID, or an error if it doesn't exist,
+/// or isn't a folder.
+fn get_raw_folder(db: &PlacesDb, guid: &SyncGuid) -> Result<RawBookmark> {
+    let folder = get_raw_bookmark(db, guid)?
+        .ok_or_else(|| InvalidPlaceInfo::NoSuchGuid(guid.to_string()))?;
+    if folder.bookmark_type != BookmarkType::Folder {
+        return Err(InvalidPlaceInfo::InvalidParent(guid.to_string()).into());
+    }
+    Ok(folder)
+}
+
+/// Returns the children of a folder, ordered by position.
+fn get_raw_children(db: &PlacesDb, parent_id: RowId) -> Result<Vec<RawBookmark>> {
+    Ok(db.query_rows_into_cached(
+        &format!(
+            "{} WHERE b.parent = :parent_id ORDER BY b.position",
+            RAW_BOOKMARK_SQL
+        ),
+        &[(":parent_id", &parent_id)],
+        RawBookmark::from_row,
+    )?)
+}
+
+/// Returns the row IDs of a folder and all its ancestors.
+fn get_ancestor_ids(db: &PlacesDb, folder_id: RowId) -> Result<HashSet<RowId>> {
+    let sql = "
+        WITH RECURSIVE
+        ancestors(aid) AS (
+            SELECT :folder_id
+            UNION
+            SELECT parent FROM moz_bookmarks
+            JOIN ancestors ON id = aid
+            WHERE parent NOT NULL
+        )
+        SELECT aid FROM ancestors
+    ";
+    Ok(
+        db.query_rows_into_cached(sql, &[(":folder_id", &folder_id)], |row| {
+            row.get::<_, RowId>(0)
+        })?,
+    )
+}
+
+/// Moves `children` into the folder with the given row ID, and sets their
+/// positions to match their order. Children that are already in the right
+/// place aren't changed.
+fn set_child_positions(db: &PlacesDb, parent_id: RowId, children: &[&RawBookmark]) -> Result<()> {
+    for (position, child) in children.iter().enumerate() {
+        if child.parent_id == Some(parent_id) && child.position as usize == position {
+            continue;
+        }
+        db.execute_named_cached(
+            "UPDATE moz_bookmarks SET parent = :parent_id, position = :position
+             WHERE id = :id",
+            &[
+                (":parent_id", &parent_id),
+                (":position", &(position as u32)),
+                (":id", &child.row_id),
+            ],
+        )?;
+    }
     Ok(())
 }
 
@@ -703,6 +1102,9 @@ pub struct BookmarkNode {
     pub last_modified: Option<Timestamp>,
     pub title: Option<String>,
     pub url: Url,
+    // Tags belong to the URL, so all bookmarks with the same URL have the
+    // same tags.
+    pub tags: Vec<String>,
 }
 
 impl From<BookmarkNode> for BookmarkTreeNode {
@@ -719,6 +1121,7 @@ impl PartialEq for BookmarkNode {
             && cmp_options(&self.last_modified, &other.last_modified)
             && cmp_options(&self.title, &other.title)
             && self.url == other.url
+            && self.tags == other.tags
     }
 }
 
@@ -826,6 +1229,11 @@ impl Serialize for BookmarkTreeNode {
                 state.serialize_field("last_modified", &b.last_modified)?;
                 state.serialize_field("title", &b.title)?;
                 state.serialize_field("url", &b.url.to_string())?;
+                if b.tags.is_empty() {
+                    state.skip_field("tags")?;
+                } else {
+                    state.serialize_field("tags", &b.tags)?;
+                }
             }
             BookmarkTreeNode::Separator(s) => {
                 state.serialize_field("type", &BookmarkType::Separator)?;
@@ -862,6 +1270,7 @@ impl<'de> Deserialize<'de> for BookmarkTreeNode {
             last_modified: Option<Timestamp>,
             title: Option<String>,
             url: Option<String>,
+            tags: Vec<String>,
             children: Vec<BookmarkTreeNode>,
         }
         let m = Mapping::deserialize(deserializer)?;
@@ -886,6 +1295,7 @@ impl<'de> Deserialize<'de> for BookmarkTreeNode {
                 last_modified: m.last_modified,
                 title: m.title,
                 url: url.unwrap(),
+                tags: m.tags,
             }
             .into(),
             BookmarkType::Separator => SeparatorNode {
@@ -923,6 +1333,198 @@ pub fn bookmarks_get_url_for_keyword(db: &PlacesDb, keyword: &str) -> Result<Opt
     }
 }
 
+/// A search keyword for a URL. Sync associates keywords with bookmarks, so
+/// we only allow setting keywords for bookmarked URLs, but every bookmark for
+/// a URL shares its keyword.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BookmarkKeyword {
+    pub keyword: String,
+    pub url: Url,
+    /// POST data to submit when using the keyword. This is stored locally,
+    /// but isn't synced.
+    pub post_data: Option<String>,
+}
+
+impl BookmarkKeyword {
+    pub(crate) fn from_row(row: &Row<'_>) -> Result<Self> {
+        Ok(Self {
+            keyword: row.get("keyword")?,
+            url: Url::parse(&row.get::<_, String>("url")?)?,
+            post_data: row.get("post_data")?,
+        })
+    }
+}
+
+const KEYWORD_SQL: &str = "
+    SELECT k.keyword, h.url, k.post_data
+    FROM moz_keywords k
+    JOIN moz_places h ON h.id = k.place_id";
+
+/// Trims and lowercases a keyword, matching Desktop.
+fn normalize_keyword(keyword: &str) -> Result<String> {
+    let keyword = keyword.trim();
+    if keyword.is_empty() {
+        return Err(InvalidPlaceInfo::InvalidKeyword.into());
+    }
+    Ok(keyword.to_lowercase())
+}
+
+/// Get the keyword for a URL, if it has one.
+pub fn bookmarks_get_keyword_for_url(db: &PlacesDb, url: &Url) -> Result<Option<BookmarkKeyword>> {
+    Ok(db.try_query_row(
+        &format!(
+            "{} WHERE h.url_hash = hash(:url) AND h.url = :url",
+            KEYWORD_SQL
+        ),
+        &[(":url", &url.as_str())],
+        BookmarkKeyword::from_row,
+        true,
+    )?)
+}
+
+/// Get all keywords, ordered by keyword.
+pub fn bookmarks_get_keywords(db: &PlacesDb) -> Result<Vec<BookmarkKeyword>> {
+    db.query_rows_and_then_named_cached(
+        &format!("{} ORDER BY k.keyword", KEYWORD_SQL),
+        &[],
+        BookmarkKeyword::from_row,
+    )
+}
+
+/// Sets the keyword for a bookmarked URL, replacing its existing keyword.
+/// Keywords are unique, so if another URL already has this keyword, it's
+/// removed from that URL. Fails with `InvalidPlaceInfo::NoSuchUrl` if the
+/// URL isn't bookmarked.
+pub fn bookmarks_set_keyword(
+    db: &PlacesDb,
+    url: &Url,
+    keyword: &str,
+    post_data: Option<&str>,
+) -> Result<()> {
+    let keyword = normalize_keyword(keyword)?;
+    let tx = db.begin_transaction()?;
+    set_keyword_in_tx(db, url, &keyword, post_data)?;
+    tx.commit()?;
+    Ok(())
+}
+
+fn set_keyword_in_tx(
+    db: &PlacesDb,
+    url: &Url,
+    keyword: &str,
+    post_data: Option<&str>,
+) -> Result<()> {
+    let place_id = db
+        .try_query_row(
+            "SELECT h.id FROM moz_places h
+             WHERE h.url_hash = hash(:url) AND h.url = :url AND
+                   EXISTS(SELECT 1 FROM moz_bookmarks b WHERE b.fk = h.id)",
+            &[(":url", &url.as_str())],
+            |row| row.get::<_, RowId>(0),
+            true,
+        )?
+        .ok_or(InvalidPlaceInfo::NoSuchUrl)?;
+
+    let existing = db.try_query_row(
+        "SELECT keyword, post_data FROM moz_keywords WHERE place_id = :place_id",
+        &[(":place_id", &place_id)],
+        |row| -> rusqlite::Result<_> {
+            Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
+        },
+        true,
+    )?;
+    if let Some((existing_keyword, existing_post_data)) = &existing {
+        if existing_keyword == keyword && existing_post_data.as_deref() == post_data {
+            return Ok(());
+        }
+    }
+
+    // Take the keyword away from the URL that currently has it.
+    let other_place_id = db.try_query_row(
+        "SELECT place_id FROM moz_keywords
+         WHERE keyword = :keyword AND place_id <> :place_id",
+        &[(":keyword", &keyword), (":place_id", &place_id)],
+        |row| row.get::<_, RowId>(0),
+        true,
+    )?;
+    if let Some(other_place_id) = other_place_id {
+        db.execute_named_cached(
+            "DELETE FROM moz_keywords WHERE place_id = :place_id",
+            &[(":place_id", &other_place_id)],
+        )?;
+        note_keyword_changed(db, other_place_id, None)?;
+    }
+
+    let sql = if existing.is_some() {
+        "UPDATE moz_keywords SET
+           keyword = :keyword,
+           post_data = :post_data
+         WHERE place_id = :place_id"
+    } else {
+        "INSERT INTO moz_keywords(place_id, keyword, post_data)
+         VALUES(:place_id, :keyword, :post_data)"
+    };
+    db.execute_named_cached(
+        sql,
+        &[
+            (":place_id", &place_id),
+            (":keyword", &keyword),
+            (":post_data", &post_data),
+        ],
+    )?;
+    note_keyword_changed(db, place_id, Some(keyword))?;
+    Ok(())
+}
+
+/// Removes the keyword for a URL, returning whether it had one.
+pub fn bookmarks_remove_keyword(db: &PlacesDb, url: &Url) -> Result<bool> {
+    let tx = db.begin_transaction()?;
+    let place_id = db.try_query_row(
+        "SELECT k.place_id FROM moz_keywords k
+         JOIN moz_places h ON h.id = k.place_id
+         WHERE h.url_hash = hash(:url) AND h.url = :url",
+        &[(":url", &url.as_str())],
+        |row| row.get::<_, RowId>(0),
+        true,
+    )?;
+    let removed = match place_id {
+        Some(place_id) => {
+            db.execute_named_cached(
+                "DELETE FROM moz_keywords WHERE place_id = :place_id",
+                &[(":place_id", &place_id)],
+            )?;
+            note_keyword_changed(db, place_id, None)?;
+            true
+        }
+        None => false,
+    };
+    tx.commit()?;
+    Ok(removed)
+}
+
+/// Sync stores keywords on bookmarks, so changing the keyword for a URL
+/// means we need to reupload all bookmarks with that URL.
+fn note_keyword_changed(db: &PlacesDb, place_id: RowId, keyword: Option<&str>) -> Result<()> {
+    let guids = db.query_rows_and_then_named_cached(
+        "SELECT guid FROM moz_bookmarks WHERE fk = :place_id",
+        &[(":place_id", &place_id)],
+        |row| row.get::<_, SyncGuid>(0),
+    )?;
+    db.execute_named_cached(
+        "UPDATE moz_bookmarks SET
+           syncChangeCounter = syncChangeCounter + 1
+         WHERE fk = :place_id",
+        &[(":place_id", &place_id)],
+    )?;
+    for guid in guids {
+        db.note_event(PlacesEvent::BookmarkChanged {
+            guid,
+            property: BookmarkProperty::Keyword(keyword.map(str::to_owned)),
+        });
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod test_serialize {
     use super::*;
@@ -941,6 +1543,7 @@ mod test_serialize {
                 last_modified: None,
                 title: Some("the bookmark".into()),
                 url: Url::parse("https://www.example.com")?,
+                tags: vec![],
             })],
         });
         // round-trip the tree via serde.
@@ -1073,11 +1676,30 @@ fn add_subtree_infos(parent: &SyncGuid, tree: &FolderNode, insert_infos: &mut Ve
     }
 }
 
+fn add_subtree_tags(db: &PlacesDb, tree: &FolderNode) -> Result<()> {
+    for child in &tree.children {
+        match child {
+            BookmarkTreeNode::Bookmark(b) => {
+                for tag in &b.tags {
+                    match validate_tag(tag).ensure_valid() {
+                        Ok(tag) => tag_url_in_tx(db, &b.url, tag)?,
+                        Err(_) => log::warn!("ignoring invalid tag for {:?}", b.guid),
+                    }
+                }
+            }
+            BookmarkTreeNode::Separator(_) => {}
+            BookmarkTreeNode::Folder(f) => add_subtree_tags(db, f)?,
+        }
+    }
+    Ok(())
+}
+
 /// Erases all bookmarks and resets all Sync metadata.
 pub fn delete_everything(db: &PlacesDb) -> Result<()> {
     let tx = db.begin_transaction()?;
     delete_everything_in_tx(db)?;
     tx.commit()?;
+    db.bookmark_undo_log().borrow_mut().clear();
     Ok(())
 }
 
@@ -1121,6 +1743,7 @@ pub fn insert_tree(db: &PlacesDb, tree: &FolderNode) -> Result<()> {
     for insertable in insert_infos {
         insert_bookmark_in_tx(db, &insertable)?;
     }
+    add_subtree_tags(db, tree)?;
     super::delete_pending_temp_tables(db)?;
     tx.commit()?;
     Ok(())
@@ -1140,6 +1763,7 @@ struct FetchedTreeRow {
     title: Option<String>,
     date_added: Timestamp,
     last_modified: Timestamp,
+    place_id: Option<RowId>,
     url: Option<String>,
 }
 
@@ -1161,11 +1785,41 @@ impl FetchedTreeRow {
             title: row.get::<_, Option<String>>("title")?,
             date_added: row.get("dateAdded")?,
             last_modified: row.get("lastModified")?,
+            place_id: row.get("placeId")?,
             url,
         })
     }
 }
 
+/// Fetches the tags for the given places, keyed by place ID, so that we can
+/// fill in the tags for a list or tree of bookmarks with a query per chunk of
+/// places, instead of per bookmark. Each place's tags are sorted by name.
+pub(crate) fn get_tags_by_place_id(
+    db: &PlacesDb,
+    place_ids: &[i64],
+) -> Result<HashMap<i64, Vec<String>>> {
+    let mut tags_by_place_id: HashMap<i64, Vec<String>> = HashMap::new();
+    sql_support::each_chunk(place_ids, |chunk, _| -> Result<()> {
+        let mut stmt = db.prepare(&format!(
+            "SELECT r.place_id, t.tag
+             FROM moz_tags_relation r
+             JOIN moz_tags t ON t.id = r.tag_id
+             WHERE r.place_id IN ({})
+             ORDER BY t.tag",
+            sql_support::repeat_sql_vars(chunk.len())
+        ))?;
+        let mut rows = stmt.query(chunk)?;
+        while let Some(row) = rows.next()? {
+            tags_by_place_id
+                .entry(row.get::<_, i64>(0)?)
+                .or_default()
+                .push(row.get::<_, String>(1)?);
+        }
+        Ok(())
+    })?;
+    Ok(tags_by_place_id)
+}
+
 fn inflate(
     parent: &mut BookmarkTreeNode,
     pseudo_tree: &mut HashMap<SyncGuid, Vec<BookmarkTreeNode>>,
@@ -1192,8 +1846,8 @@ pub fn fetch_tree(
     item_guid: &SyncGuid,
     target_depth: &FetchDepth,
 ) -> Result<Option<(BookmarkTreeNode, Option<SyncGuid>, u32)>> {
-    // XXX - this needs additional work for tags - unlike desktop, there's no
-    // "tags" folder, but instead a couple of tables to join on.
+    // Unlike desktop, there's no "tags" folder, so we fetch the tags for each
+    // bookmark's URL separately.
     let sql = r#"
         WITH RECURSIVE
         descendants(fk, level, type, id, guid, parent, parentGuid, position,
@@ -1210,17 +1864,12 @@ pub fn fetch_tree(
           JOIN descendants ON b2.parent = descendants.id) -- AND b2.id <> :tags_folder)
         SELECT d.level, d.id, d.guid, d.parent, d.parentGuid, d.type,
                d.position, NULLIF(d.title, '') AS title, d.dateAdded,
-               d.lastModified, h.url
+               d.lastModified, h.id AS placeId, h.url
 --               (SELECT icon_url FROM moz_icons i
 --                      JOIN moz_icons_to_pages ON icon_id = i.id
 --                      JOIN moz_pages_w_icons pi ON page_id = pi.id
 --                      WHERE pi.page_url_hash = hash(h.url) AND pi.page_url = h.url
 --                      ORDER BY width DESC LIMIT 1) AS iconuri,
---               (SELECT GROUP_CONCAT(t.title, ',')
---                FROM moz_bookmarks b2
---                JOIN moz_bookmarks t ON t.id = +b2.parent AND t.parent = :tags_folder
---                WHERE b2.fk = h.id
---               ) AS tags,
 --               EXISTS (SELECT 1 FROM moz_items_annos
 --                       WHERE item_id = d.id LIMIT 1) AS has_annos,
 --               (SELECT a.content FROM moz_annos a
@@ -1235,17 +1884,41 @@ pub fn fetch_tree(
     let scope = db.begin_interrupt_scope();
 
     let mut stmt = db.conn().prepare(sql)?;
+    let mut rows = Vec::new();
+    for result in
+        stmt.query_and_then_named(&[(":item_guid", item_guid)], FetchedTreeRow::from_row)?
+    {
+        let row = result?;
+        scope.err_if_interrupted()?;
+        // Check if we have done fetching the asked depth
+        if let FetchDepth::Specific(d) = *target_depth {
+            if row.level as usize > d + 1 {
+                break;
+            }
+        }
+        rows.push(row);
+    }
 
-    let mut results =
-        stmt.query_and_then_named(&[(":item_guid", item_guid)], FetchedTreeRow::from_row)?;
+    // Only fetch the tags for the bookmarks we're returning.
+    let place_ids = rows
+        .iter()
+        .filter_map(|row| row.place_id.map(|place_id| place_id.0))
+        .collect::<Vec<_>>();
+    let tags_by_place_id = get_tags_by_place_id(db, &place_ids)?;
+    let tags_for_row = |row: &FetchedTreeRow| {
+        row.place_id
+            .and_then(|place_id| tags_by_place_id.get(&place_id.0).cloned())
+            .unwrap_or_default()
+    };
+
+    let mut results = rows.into_iter();
 
     let parent_guid: Option<SyncGuid>;
     let position: u32;
 
     // The first row in the result set is always the root of our tree.
     let mut root = match results.next() {
-        Some(result) => {
-            let row = result?;
+        Some(row) => {
             parent_guid = row.parent_guid.clone();
             position = row.position;
             match row.node_type {
@@ -1261,6 +1934,7 @@ pub fn fetch_tree(
                     guid: Some(row.guid.clone()),
                     date_added: Some(row.date_added),
                     last_modified: Some(row.last_modified),
+                    tags: tags_for_row(&row),
                     title: row.title,
                     url: Url::parse(row.url.unwrap().as_str())?,
                 }
@@ -1287,15 +1961,7 @@ pub fn fetch_tree(
     // results in level order, so we'll see a node's siblings and cousins (same
     // level, but different parents) before any of their descendants.
     let mut pseudo_tree: HashMap<SyncGuid, Vec<BookmarkTreeNode>> = HashMap::new();
-    for result in results {
-        let row = result?;
-        scope.err_if_interrupted()?;
-        // Check if we have done fetching the asked depth
-        if let FetchDepth::Specific(d) = *target_depth {
-            if row.level as usize > d + 1 {
-                break;
-            }
-        }
+    for row in results {
         let node = match row.node_type {
             BookmarkType::Bookmark => match &row.url {
                 Some(url_str) => match Url::parse(&url_str) {
@@ -1305,6 +1971,7 @@ pub fn fetch_tree(
                         last_modified: Some(row.last_modified),
                         title: row.title.clone(),
                         url,
+                        tags: tags_for_row(&row),
                     }
                     .into(),
                     Err(e) => {
@@ -1596,6 +2263,215 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_tree_tags() -> Result<()> {
+        let _ = env_logger::try_init();
+        let conn = new_mem_connection();
+
+        // Tags in the tree are applied to the URL, so they show up for all
+        // bookmarks with that URL.
+        insert_json_tree(
+            &conn,
+            json!({
+                "guid": String::from(BookmarkRootGuid::Unfiled.as_str()),
+                "children": [
+                    {
+                        "guid": "bookmarkAAA1",
+                        "url": "https://example.com/a",
+                        "tags": ["two", "one"],
+                    },
+                    {
+                        "guid": "folderAAAAAA",
+                        "children": [
+                            { "guid": "bookmarkAAA2", "url": "https://example.com/a" },
+                            { "guid": "bookmarkBBBB", "url": "https://example.com/b" },
+                        ]
+                    },
+                ]
+            }),
+        );
+        assert_json_tree(
+            &conn,
+            &BookmarkRootGuid::Unfiled.into(),
+            json!({
+                "guid": String::from(BookmarkRootGuid::Unfiled.as_str()),
+                "children": [
+                    {
+                        "guid": "bookmarkAAA1",
+                        "url": "https://example.com/a",
+                        "tags": ["one", "two"],
+                    },
+                    {
+                        "guid": "folderAAAAAA",
+                        "children": [
+                            {
+                                "guid": "bookmarkAAA2",
+                                "url": "https://example.com/a",
+                                "tags": ["one", "two"],
+                            },
+                            { "guid": "bookmarkBBBB", "url": "https://example.com/b" },
+                        ]
+                    },
+                ]
+            }),
+        );
+
+        let node = public_node::fetch_bookmark(&conn, &"bookmarkAAA2".into(), false)?
+            .expect("Should fetch bookmark");
+        assert_eq!(node.tags, vec!["one".to_string(), "two".to_string()]);
+        let nodes = public_node::recent_bookmarks(&conn, 10)?;
+        assert_eq!(
+            nodes
+                .into_iter()
+                .map(|node| (node.guid.into_string(), node.tags.len()))
+                .collect::<HashSet<_>>(),
+            vec![
+                ("bookmarkAAA1".to_string(), 2),
+                ("bookmarkAAA2".to_string(), 2),
+                ("bookmarkBBBB".to_string(), 0),
+            ]
+            .into_iter()
+            .collect()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_keyword() -> Result<()> {
+        let _ = env_logger::try_init();
+        let conn = new_mem_connection();
+
+        let url_a = Url::parse("https://example.com/a?q=%s")?;
+        let url_b = Url::parse("https://example.com/b")?;
+        insert_json_tree(
+            &conn,
+            json!({
+                "guid": String::from(BookmarkRootGuid::Unfiled.as_str()),
+                "children": [
+                    { "guid": "bookmarkAAA1", "url": url_a.as_str() },
+                    { "guid": "bookmarkAAA2", "url": url_a.as_str() },
+                    { "guid": "bookmarkBBBB", "url": url_b.as_str() },
+                ]
+            }),
+        );
+        conn.execute("UPDATE moz_bookmarks SET syncChangeCounter = 0", NO_PARAMS)?;
+
+        let get_change_counters = || -> Result<Vec<(String, u32)>> {
+            conn.query_rows_and_then_named(
+                "SELECT guid, syncChangeCounter FROM moz_bookmarks
+                 WHERE fk NOT NULL
+                 ORDER BY guid",
+                &[],
+                |row| -> Result<_> { Ok((row.get(0)?, row.get(1)?)) },
+            )
+        };
+
+        // Only bookmarked URLs can have keywords, and empty keywords
+        // aren't allowed.
+        match bookmarks_set_keyword(&conn, &Url::parse("https://example.com/c")?, "c", None)
+            .expect_err("Should not set keyword for unbookmarked URL")
+            .kind()
+        {
+            ErrorKind::InvalidPlaceInfo(InvalidPlaceInfo::NoSuchUrl) => {}
+            e => panic!("Wanted NoSuchUrl error; got {:?}", e),
+        }
+        match bookmarks_set_keyword(&conn, &url_a, "  ", None)
+            .expect_err("Should not set empty keyword")
+            .kind()
+        {
+            ErrorKind::InvalidPlaceInfo(InvalidPlaceInfo::InvalidKeyword) => {}
+            e => panic!("Wanted InvalidKeyword error; got {:?}", e),
+        }
+
+        // Keywords are normalized, and bump the change counters for all
+        // bookmarks with the URL.
+        bookmarks_set_keyword(&conn, &url_a, " Search ", Some("q=%s"))?;
+        assert_eq!(
+            bookmarks_get_keyword_for_url(&conn, &url_a)?,
+            Some(BookmarkKeyword {
+                keyword: "search".into(),
+                url: url_a.clone(),
+                post_data: Some("q=%s".into()),
+            })
+        );
+        assert_eq!(
+            get_change_counters()?,
+            vec![
+                ("bookmarkAAA1".to_string(), 1),
+                ("bookmarkAAA2".to_string(), 1),
+                ("bookmarkBBBB".to_string(), 0),
+            ]
+        );
+
+        // Setting the same keyword again is a no-op.
+        bookmarks_set_keyword(&conn, &url_a, "search", Some("q=%s"))?;
+        assert_eq!(get_change_counters()?[0], ("bookmarkAAA1".to_string(), 1));
+
+        // Keywords are unique, so moving it to B removes it from A, and
+        // bumps the change counters for both.
+        bookmarks_set_keyword(&conn, &url_b, "search", None)?;
+        assert_eq!(bookmarks_get_keyword_for_url(&conn, &url_a)?, None);
+        assert_eq!(
+            bookmarks_get_url_for_keyword(&conn, "search")?,
+            Some(url_b.clone())
+        );
+        assert_eq!(
+            get_change_counters()?,
+            vec![
+                ("bookmarkAAA1".to_string(), 2),
+                ("bookmarkAAA2".to_string(), 2),
+                ("bookmarkBBBB".to_string(), 1),
+            ]
+        );
+
+        // Replacing B's keyword should update it in place.
+        bookmarks_set_keyword(&conn, &url_a, "a", None)?;
+        bookmarks_set_keyword(&conn, &url_b, "b", None)?;
+        assert_eq!(
+            bookmarks_get_keywords(&conn)?
+                .into_iter()
+                .map(|k| (k.keyword, k.url))
+                .collect::<Vec<_>>(),
+            vec![
+                ("a".to_string(), url_a.clone()),
+                ("b".to_string(), url_b.clone())
+            ]
+        );
+
+        // Keywords are surfaced on public nodes.
+        let node = public_node::fetch_bookmark(&conn, &"bookmarkAAA2".into(), false)?
+            .expect("Should fetch bookmark");
+        assert_eq!(node.keyword, Some("a".to_string()));
+        let unfiled = public_node::fetch_public_tree(&conn, &BookmarkRootGuid::Unfiled.into())?
+            .expect("Should fetch unfiled");
+        let keywords = unfiled
+            .child_nodes
+            .expect("Should have children")
+            .into_iter()
+            .map(|child| child.keyword)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keywords,
+            vec![
+                Some("a".to_string()),
+                Some("a".to_string()),
+                Some("b".to_string())
+            ]
+        );
+        assert!(public_node::fetch_bookmarks_by_url(&conn, &url_b)?
+            .into_iter()
+            .all(|node| node.keyword == Some("b".to_string())));
+
+        // Removing the keyword should only bump the change counter for B.
+        assert!(bookmarks_remove_keyword(&conn, &url_b)?);
+        assert!(!bookmarks_remove_keyword(&conn, &url_b)?);
+        assert_eq!(bookmarks_get_keyword_for_url(&conn, &url_b)?, None);
+        assert_eq!(get_change_counters()?[2], ("bookmarkBBBB".to_string(), 3));
+
+        Ok(())
+    }
+
     #[test]
     fn test_insert() -> Result<()> {
         let _ = env_logger::try_init();
@@ -1859,6 +2735,229 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_reorder_children() -> Result<()> {
+        let _ = env_logger::try_init();
+        let conn = new_mem_connection();
+        let unfiled = &BookmarkRootGuid::Unfiled.as_guid();
+
+        insert_json_tree(
+            &conn,
+            json!({
+                "guid": unfiled,
+                "children": [
+                    {
+                        "guid": "bookmark1___",
+                        "url": "https://www.example1.com/"
+                    },
+                    {
+                        "guid": "folder1_____",
+                        "title": "A folder",
+                        "children": [
+                            {
+                                "guid": "bookmark2___",
+                                "url": "https://www.example2.com/"
+                            },
+                        ]
+                    },
+                    {
+                        "guid": "bookmark3___",
+                        "url": "https://www.example3.com/"
+                    },
+                    {
+                        "guid": "bookmark4___",
+                        "url": "https://www.example4.com/"
+                    },
+                    {
+                        "guid": "separator1__",
+                        "type": BookmarkType::Separator as u8,
+                    },
+                ]
+            }),
+        );
+        conn.execute("UPDATE moz_bookmarks SET syncChangeCounter = 0", NO_PARAMS)?;
+
+        // Unknown GUIDs, duplicates, and items in other folders are ignored,
+        // and unlisted children keep their relative order after the listed
+        // ones.
+        reorder_children(
+            &conn,
+            unfiled,
+            &[
+                "bookmark4___".into(),
+                "bookmarkXXXX".into(),
+                "bookmark2___".into(),
+                "bookmark3___".into(),
+                "bookmark4___".into(),
+            ],
+        )?;
+        assert_json_tree(
+            &conn,
+            unfiled,
+            json!({
+                "guid": unfiled,
+                "children": [
+                    {"guid": "bookmark4___", "url": "https://www.example4.com/"},
+                    {"guid": "bookmark3___", "url": "https://www.example3.com/"},
+                    {"guid": "bookmark1___", "url": "https://www.example1.com/"},
+                    {
+                        "guid": "folder1_____",
+                        "children": [{"guid": "bookmark2___", "url": "https://www.example2.com/"}],
+                    },
+                    {"guid": "separator1__", "type": BookmarkType::Separator as u8},
+                ]
+            }),
+        );
+
+        // Only the folder is marked as changed.
+        let changed: Vec<String> = conn.query_rows_and_then_named(
+            "SELECT guid FROM moz_bookmarks WHERE syncChangeCounter > 0",
+            &[],
+            |row| row.get(0),
+        )?;
+        assert_eq!(changed, vec![unfiled.to_string()]);
+
+        // Reordering a bookmark, a separator, or a missing folder fails.
+        assert!(reorder_children(&conn, &"bookmark1___".into(), &[]).is_err());
+        assert!(reorder_children(&conn, &"separator1__".into(), &[]).is_err());
+        assert!(reorder_children(&conn, &"folderXXXXXX".into(), &[]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_move_many() -> Result<()> {
+        let _ = env_logger::try_init();
+        let conn = new_mem_connection();
+        let unfiled = &BookmarkRootGuid::Unfiled.as_guid();
+
+        insert_json_tree(
+            &conn,
+            json!({
+                "guid": unfiled,
+                "children": [
+                    {
+                        "guid": "bookmark1___",
+                        "url": "https://www.example1.com/"
+                    },
+                    {
+                        "guid": "folder1_____",
+                        "title": "A folder",
+                        "children": [
+                            {
+                                "guid": "bookmark2___",
+                                "url": "https://www.example2.com/"
+                            },
+                            {
+                                "guid": "bookmark3___",
+                                "url": "https://www.example3.com/"
+                            },
+                            {
+                                "guid": "bookmark4___",
+                                "url": "https://www.example4.com/"
+                            },
+                        ]
+                    },
+                    {
+                        "guid": "bookmark5___",
+                        "url": "https://www.example5.com/"
+                    },
+                ]
+            }),
+        );
+        conn.execute("UPDATE moz_bookmarks SET syncChangeCounter = 0", NO_PARAMS)?;
+
+        // Move items from two folders into the middle of one of them. The
+        // position doesn't count the items being moved.
+        move_many(
+            &conn,
+            &[
+                "bookmark5___".into(),
+                "bookmark2___".into(),
+                "bookmark1___".into(),
+            ],
+            &"folder1_____".into(),
+            BookmarkPosition::Specific(1),
+        )?;
+        assert_json_tree(
+            &conn,
+            unfiled,
+            json!({
+                "guid": unfiled,
+                "children": [
+                    {
+                        "guid": "folder1_____",
+                        "children": [
+                            {"guid": "bookmark3___", "url": "https://www.example3.com/"},
+                            {"guid": "bookmark5___", "url": "https://www.example5.com/"},
+                            {"guid": "bookmark2___", "url": "https://www.example2.com/"},
+                            {"guid": "bookmark1___", "url": "https://www.example1.com/"},
+                            {"guid": "bookmark4___", "url": "https://www.example4.com/"},
+                        ],
+                    },
+                ]
+            }),
+        );
+        let mut changed: Vec<String> = conn.query_rows_and_then_named(
+            "SELECT guid FROM moz_bookmarks WHERE syncChangeCounter > 0",
+            &[],
+            |row| row.get(0),
+        )?;
+        changed.sort();
+        assert_eq!(
+            changed,
+            vec!["folder1_____".to_string(), unfiled.to_string()]
+        );
+
+        // Move them back out, to the end.
+        move_many(
+            &conn,
+            &["bookmark4___".into(), "bookmark3___".into()],
+            unfiled,
+            BookmarkPosition::Append,
+        )?;
+        assert_json_tree(
+            &conn,
+            unfiled,
+            json!({
+                "guid": unfiled,
+                "children": [
+                    {
+                        "guid": "folder1_____",
+                        "children": [
+                            {"guid": "bookmark5___", "url": "https://www.example5.com/"},
+                            {"guid": "bookmark2___", "url": "https://www.example2.com/"},
+                            {"guid": "bookmark1___", "url": "https://www.example1.com/"},
+                        ],
+                    },
+                    {"guid": "bookmark4___", "url": "https://www.example4.com/"},
+                    {"guid": "bookmark3___", "url": "https://www.example3.com/"},
+                ]
+            }),
+        );
+
+        // A folder can't be moved into itself or its descendants, and nothing
+        // is moved if any item is invalid.
+        let menu = &BookmarkRootGuid::Menu.as_guid();
+        for (guids, parent) in &[
+            (vec!["folder1_____".into()], "folder1_____".into()),
+            (vec![unfiled.clone()], menu.clone()),
+            (
+                vec!["bookmark4___".into(), "bookmarkXXXX".into()],
+                menu.clone(),
+            ),
+            (vec!["bookmark4___".into()], "bookmark3___".into()),
+            (
+                vec!["bookmark4___".into()],
+                BookmarkRootGuid::Root.as_guid(),
+            ),
+        ] {
+            let guids: &Vec<SyncGuid> = guids;
+            assert!(move_many(&conn, guids, parent, BookmarkPosition::Append).is_err());
+        }
+        assert_eq!(get_pos(&conn, &"bookmark4___".into()), 1);
+        Ok(())
+    }
+
     #[test]
     fn test_update() -> Result<()> {
         let _ = env_logger::try_init();
@@ -2316,6 +3415,7 @@ mod tests {
                     last_modified: None,
                     title: Some("the bookmark".into()),
                     url: Url::parse("https://www.example.com")?,
+                    tags: vec![],
                 }
                 .into(),
                 FolderNode {
@@ -2327,6 +3427,7 @@ mod tests {
                             last_modified: None,
                             title: Some("bookmark 1 in A folder".into()),
                             url: Url::parse("https://www.example2.com")?,
+                            tags: vec![],
                         }
                         .into(),
                         BookmarkNode {
@@ -2335,6 +3436,7 @@ mod tests {
                             last_modified: None,
                             title: Some("bookmark 2 in A folder".into()),
                             url: Url::parse("https://www.example3.com")?,
+                            tags: vec![],
                         }
                         .into(),
                     ],
@@ -2347,6 +3449,7 @@ mod tests {
                     last_modified: None,
                     title: Some("another bookmark".into()),
                     url: Url::parse("https://www.example4.com")?,
+                    tags: vec![],
                 }
                 .into(),
             ],
@@ -2542,4 +3645,102 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_observer_events() -> Result<()> {
+        use crate::api::places_api::{test::new_mem_api, ConnectionType};
+        use crate::observer::test::RecordingObserver;
+        use std::sync::Arc;
+
+        let api = new_mem_api();
+        let conn = api.open_connection(ConnectionType::ReadWrite)?;
+        let observer = Arc::new(RecordingObserver::default());
+        api.add_observer(observer.clone());
+
+        let url = Url::parse("https://www.example.com")?;
+        let guid = insert_bookmark(
+            &conn,
+            &InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Unfiled.into(),
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                url: url.clone(),
+                title: Some("the title".into()),
+            }
+            .into(),
+        )?;
+        assert_eq!(
+            observer.take(),
+            vec![PlacesEvent::BookmarkAdded {
+                guid: guid.clone(),
+                parent_guid: BookmarkRootGuid::Unfiled.into(),
+                position: 0,
+                node_type: BookmarkType::Bookmark,
+                url: Some(url.clone()),
+            }]
+        );
+
+        let new_url = Url::parse("https://www.example.com/new")?;
+        update_bookmark(
+            &conn,
+            &guid,
+            &UpdatableBookmark {
+                location: UpdateTreeLocation::Parent(
+                    BookmarkRootGuid::Mobile.into(),
+                    BookmarkPosition::Append,
+                ),
+                url: Some(new_url.clone()),
+                title: Some("".into()),
+            }
+            .into(),
+        )?;
+        assert_eq!(
+            observer.take(),
+            vec![
+                PlacesEvent::BookmarkMoved {
+                    guid: guid.clone(),
+                    old_parent_guid: BookmarkRootGuid::Unfiled.into(),
+                    old_position: 0,
+                    new_parent_guid: BookmarkRootGuid::Mobile.into(),
+                    new_position: 0,
+                },
+                PlacesEvent::BookmarkChanged {
+                    guid: guid.clone(),
+                    property: BookmarkProperty::Title(None),
+                },
+                PlacesEvent::BookmarkChanged {
+                    guid: guid.clone(),
+                    property: BookmarkProperty::Url(new_url.clone()),
+                },
+            ]
+        );
+
+        // Failed updates don't report anything.
+        update_bookmark(
+            &conn,
+            &guid,
+            &UpdatableFolder {
+                location: UpdateTreeLocation::None,
+                title: Some("folder".into()),
+            }
+            .into(),
+        )
+        .expect_err("can't update a bookmark as a folder");
+        assert_eq!(observer.take(), vec![]);
+
+        assert!(delete_bookmark(&conn, &guid)?);
+        assert_eq!(
+            observer.take(),
+            vec![PlacesEvent::BookmarkRemoved {
+                guid,
+                parent_guid: BookmarkRootGuid::Mobile.into(),
+                position: 0,
+                node_type: BookmarkType::Bookmark,
+                url: Some(new_url),
+            }]
+        );
+        Ok(())
+    }
 }
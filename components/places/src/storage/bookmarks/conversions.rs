@@ -3,9 +3,9 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use super::{
-    BookmarkPosition, BookmarkRootGuid, BookmarkTreeNode, InsertableBookmark, InsertableFolder,
-    InsertableItem, InsertableSeparator, PublicNode, RawBookmark, UpdatableBookmark,
-    UpdatableFolder, UpdatableItem, UpdatableSeparator, UpdateTreeLocation,
+    BookmarkKeyword, BookmarkPosition, BookmarkRootGuid, BookmarkTreeNode, InsertableBookmark,
+    InsertableFolder, InsertableItem, InsertableSeparator, PublicNode, RawBookmark,
+    UpdatableBookmark, UpdatableFolder, UpdatableItem, UpdatableSeparator, UpdateTreeLocation,
 };
 
 use crate::error::{InvalidPlaceInfo, Result};
@@ -33,6 +33,7 @@ impl From<BookmarkTreeNode> for PublicNode {
             BookmarkTreeNode::Bookmark(b) => {
                 result.title = b.title;
                 result.url = Some(b.url);
+                result.tags = b.tags;
             }
             BookmarkTreeNode::Separator(_) => {
                 // No separator-specific properties.
@@ -72,6 +73,8 @@ impl From<PublicNode> for msg_types::BookmarkNode {
             last_modified: Some(n.last_modified.0 as i64),
             title: n.title,
             url: n.url.map(url::Url::into_string),
+            keyword: n.keyword,
+            tags: n.tags,
             parent_guid: n.parent_guid.map(|g| g.into_string()),
             position: Some(n.position),
             child_guids: n.child_guids.map_or(vec![], |child_guids| {
@@ -103,6 +106,8 @@ impl From<RawBookmark> for PublicNode {
             last_modified: rb.date_modified,
             url: rb.url,
             title: rb.title,
+            keyword: None,
+            tags: Vec::new(),
             child_guids: None,
             child_nodes: None,
         }
@@ -117,6 +122,27 @@ impl From<Vec<PublicNode>> for msg_types::BookmarkNodeList {
     }
 }
 
+impl From<BookmarkKeyword> for msg_types::BookmarkKeyword {
+    fn from(k: BookmarkKeyword) -> Self {
+        Self {
+            keyword: k.keyword,
+            url: k.url.into_string(),
+            post_data: k.post_data,
+        }
+    }
+}
+
+impl From<Vec<BookmarkKeyword>> for msg_types::BookmarkKeywordList {
+    fn from(ks: Vec<BookmarkKeyword>) -> Self {
+        Self {
+            keywords: ks
+                .into_iter()
+                .map(msg_types::BookmarkKeyword::from)
+                .collect(),
+        }
+    }
+}
+
 impl msg_types::BookmarkNode {
     /// Get the BookmarkType, panicking if it's invalid (because it really never
     /// should be unless we have a bug somewhere).
@@ -19,6 +19,10 @@ pub struct PublicNode {
     pub last_modified: Timestamp,
     pub url: Option<Url>,
     pub title: Option<String>,
+    // Only set for bookmarks whose URL has a keyword.
+    pub keyword: Option<String>,
+    // Always empty for folders and separators.
+    pub tags: Vec<String>,
     pub child_guids: Option<Vec<SyncGuid>>,
     pub child_nodes: Option<Vec<PublicNode>>,
 }
@@ -36,6 +40,8 @@ impl Default for PublicNode {
             last_modified: Timestamp(0),
             url: None,
             title: None,
+            keyword: None,
+            tags: Vec::new(),
             child_guids: None,
             child_nodes: None,
         }
@@ -55,6 +61,9 @@ impl PartialEq for PublicNode {
 }
 
 pub fn fetch_bookmarks_by_url(db: &PlacesDb, url: &Url) -> Result<Vec<PublicNode>> {
+    let keyword = bookmarks_get_keyword_for_url(db, url)?.map(|k| k.keyword);
+    let mut tags = crate::storage::tags::get_tags_for_url(db, url)?;
+    tags.sort();
     let nodes = get_raw_bookmarks_for_url(db, url)?
         .into_iter()
         .map(|rb| {
@@ -72,6 +81,8 @@ pub fn fetch_bookmarks_by_url(db: &PlacesDb, url: &Url) -> Result<Vec<PublicNode
                 last_modified: rb.date_modified,
                 url: rb.url,
                 title: rb.title,
+                keyword: keyword.clone(),
+                tags: tags.clone(),
                 child_guids: None,
                 child_nodes: None,
             }
@@ -163,17 +174,62 @@ pub fn fetch_public_tree_with_depth(
         proto.parent_guid = parent_guid;
         proto.position = position;
     }
+    fill_keywords(db, &mut proto)?;
     Ok(Some(proto))
 }
 
+/// Fills in the keywords for all bookmarks in a tree. There are usually very
+/// few keywords, so we fetch all of them up front instead of querying per
+/// bookmark.
+fn fill_keywords(db: &PlacesDb, node: &mut PublicNode) -> Result<()> {
+    let keywords = bookmarks_get_keywords(db)?;
+    if keywords.is_empty() {
+        return Ok(());
+    }
+    let keywords_by_url = keywords
+        .into_iter()
+        .map(|k| (k.url, k.keyword))
+        .collect::<HashMap<_, _>>();
+    fill_keywords_from_map(node, &keywords_by_url);
+    Ok(())
+}
+
+fn fill_keywords_from_map(node: &mut PublicNode, keywords_by_url: &HashMap<Url, String>) {
+    if let Some(url) = &node.url {
+        node.keyword = keywords_by_url.get(url).cloned();
+    }
+    if let Some(child_nodes) = node.child_nodes.as_mut() {
+        for child in child_nodes {
+            fill_keywords_from_map(child, keywords_by_url);
+        }
+    }
+}
+
+/// Fills in the tags for bookmarks returned from a query, along with their
+/// place IDs.
+fn with_tags(db: &PlacesDb, nodes: Vec<(i64, PublicNode)>) -> Result<Vec<PublicNode>> {
+    let place_ids = nodes
+        .iter()
+        .map(|(place_id, _)| *place_id)
+        .collect::<Vec<_>>();
+    let tags_by_place_id = get_tags_by_place_id(db, &place_ids)?;
+    Ok(nodes
+        .into_iter()
+        .map(|(place_id, node)| PublicNode {
+            tags: tags_by_place_id.get(&place_id).cloned().unwrap_or_default(),
+            ..node
+        })
+        .collect())
+}
+
 pub fn search_bookmarks(db: &PlacesDb, search: &str, limit: u32) -> Result<Vec<PublicNode>> {
     let scope = db.begin_interrupt_scope();
-    Ok(db.query_rows_into_cached(
+    let nodes: Vec<(i64, PublicNode)> = db.query_rows_into_cached(
         &SEARCH_QUERY,
         &[(":search", &search), (":limit", &limit)],
         |row| -> Result<_> {
             scope.err_if_interrupted()?;
-            Ok(PublicNode {
+            let node = PublicNode {
                 node_type: BookmarkType::Bookmark,
                 guid: row.get("guid")?,
                 parent_guid: row.get("parentGuid")?,
@@ -185,11 +241,15 @@ pub fn search_bookmarks(db: &PlacesDb, search: &str, limit: u32) -> Result<Vec<P
                     .get::<_, Option<String>>("url")?
                     .map(|href| url::Url::parse(&href))
                     .transpose()?,
+                keyword: row.get("keyword")?,
+                tags: vec![],
                 child_guids: None,
                 child_nodes: None,
-            })
+            };
+            Ok((row.get::<_, i64>("placeId")?, node))
         },
-    )?)
+    )?;
+    with_tags(db, nodes)
 }
 
 pub fn recent_bookmarks(db: &PlacesDb, limit: u32) -> Result<Vec<PublicNode>> {
@@ -202,7 +262,10 @@ pub fn recent_bookmarks(db: &PlacesDb, limit: u32) -> Result<Vec<PublicNode>> {
             b.dateAdded,
             b.lastModified,
             NULLIF(b.title, '') AS title,
-            h.url AS url
+            h.id AS placeId,
+            h.url AS url,
+            (SELECT k.keyword FROM moz_keywords k
+             WHERE k.place_id = h.id) AS keyword
         FROM moz_bookmarks b
         JOIN moz_bookmarks p ON p.id = b.parent
         JOIN moz_places h ON h.id = b.fk
@@ -211,10 +274,10 @@ pub fn recent_bookmarks(db: &PlacesDb, limit: u32) -> Result<Vec<PublicNode>> {
         LIMIT :limit",
         bookmark_type = BookmarkType::Bookmark as u8,
     );
-    Ok(
+    let nodes: Vec<(i64, PublicNode)> =
         db.query_rows_into_cached(&sql, &[(":limit", &limit)], |row| -> Result<_> {
             scope.err_if_interrupted()?;
-            Ok(PublicNode {
+            let node = PublicNode {
                 node_type: BookmarkType::Bookmark,
                 guid: row.get("guid")?,
                 parent_guid: row.get("parentGuid")?,
@@ -226,11 +289,14 @@ pub fn recent_bookmarks(db: &PlacesDb, limit: u32) -> Result<Vec<PublicNode>> {
                     .get::<_, Option<String>>("url")?
                     .map(|href| url::Url::parse(&href))
                     .transpose()?,
+                keyword: row.get("keyword")?,
+                tags: vec![],
                 child_guids: None,
                 child_nodes: None,
-            })
-        })?,
-    )
+            };
+            Ok((row.get::<_, i64>("placeId")?, node))
+        })?;
+    with_tags(db, nodes)
 }
 
 lazy_static::lazy_static! {
@@ -243,7 +309,10 @@ lazy_static::lazy_static! {
             b.lastModified,
             -- Note we return null for titles with an empty string.
             NULLIF(b.title, '') AS title,
-            h.url AS url
+            h.id AS placeId,
+            h.url AS url,
+            (SELECT k.keyword FROM moz_keywords k
+             WHERE k.place_id = h.id) AS keyword
         FROM moz_bookmarks b
         JOIN moz_bookmarks p ON p.id = b.parent
         JOIN moz_places h ON h.id = b.fk
@@ -321,6 +390,8 @@ mod test {
                 url: Some(url.clone()),
                 parent_guid: Some(BookmarkRootGuid::Unfiled.into()),
                 position: 1,
+                keyword: None,
+                tags: Vec::new(),
                 child_guids: None,
                 child_nodes: None,
                 // Ignored by our PartialEq
@@ -337,6 +408,8 @@ mod test {
                 url: Some(url),
                 parent_guid: Some(BookmarkRootGuid::Unfiled.into()),
                 position: 3,
+                keyword: None,
+                tags: Vec::new(),
                 child_guids: None,
                 child_nodes: None,
                 // Ignored by our PartialEq
@@ -611,6 +684,8 @@ mod test {
                 url: Some(Url::parse("https://www.example5.com/").unwrap()),
                 parent_guid: Some(BookmarkRootGuid::Unfiled.into()),
                 position: 5,
+                keyword: None,
+                tags: Vec::new(),
                 child_guids: None,
                 child_nodes: None,
                 // Ignored by our PartialEq
@@ -627,6 +702,8 @@ mod test {
                 url: Some(Url::parse("https://www.example4.com/").unwrap()),
                 parent_guid: Some(BookmarkRootGuid::Unfiled.into()),
                 position: 3,
+                keyword: None,
+                tags: Vec::new(),
                 child_guids: None,
                 child_nodes: None,
                 // Ignored by our PartialEq
@@ -643,6 +720,8 @@ mod test {
                 url: Some(Url::parse("https://www.example3.com/").unwrap()),
                 parent_guid: Some(BookmarkRootGuid::Unfiled.into()),
                 position: 2,
+                keyword: None,
+                tags: Vec::new(),
                 child_guids: None,
                 child_nodes: None,
                 // Ignored by our PartialEq
@@ -0,0 +1,537 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! An undo log for bookmark changes.
+//!
+//! Each connection remembers the inverse of the last few changes made with
+//! `insert_bookmark`, `update_bookmark`, `delete_bookmark`,
+//! `reorder_children` and `move_many`, and `undo_last_change` reverts them,
+//! most recent first. Deleted items are
+//! restored with their original GUIDs, which removes their tombstones, so
+//! Sync sees the items as changed instead of deleted and recreated.
+//!
+//! Other changes, like syncing or inserting a tree, aren't recorded. If they
+//! change an item that's in the log, undoing a change to that item might fail,
+//! in which case the change is dropped from the log.
+
+use super::{
+    add_subtree_infos, delete_bookmark_in_tx, fetch_tree, get_raw_bookmark, insert_bookmark_in_tx,
+    move_many_in_tx, reorder_children_in_tx, update_bookmark_in_tx, BookmarkPosition,
+    BookmarkTreeNode, FetchDepth, InsertableBookmark, InsertableFolder, InsertableItem,
+    InsertableSeparator, RawBookmark, UpdatableBookmark, UpdatableFolder, UpdatableItem,
+    UpdatableSeparator, UpdateTreeLocation,
+};
+use crate::db::PlacesDb;
+use crate::error::*;
+use crate::types::BookmarkType;
+use std::collections::VecDeque;
+use sync_guid::Guid as SyncGuid;
+use url::Url;
+
+/// The number of changes each connection remembers by default.
+pub const DEFAULT_UNDO_LIMIT: usize = 10;
+
+/// The inverse of a bookmark change.
+#[derive(Debug)]
+pub(crate) enum UndoEntry {
+    /// Undoes an insert by deleting the inserted item.
+    Insert { guid: SyncGuid },
+    /// Undoes a deletion by reinserting the deleted subtree.
+    Delete {
+        parent_guid: SyncGuid,
+        position: u32,
+        node: BookmarkTreeNode,
+    },
+    /// Undoes an update by restoring the item's old location, title and URL.
+    Update {
+        guid: SyncGuid,
+        parent_guid: SyncGuid,
+        position: u32,
+        title: Option<String>,
+        url: Option<Url>,
+    },
+    /// Undoes reordering a folder by restoring its children's old order.
+    Reorder {
+        folder_guid: SyncGuid,
+        guids: Vec<SyncGuid>,
+    },
+    /// Undoes moving several items by moving them back to their old parents,
+    /// and restoring the old order of each parent's children.
+    Move {
+        guids: Vec<SyncGuid>,
+        old_children: Vec<(SyncGuid, Vec<SyncGuid>)>,
+    },
+}
+
+impl UndoEntry {
+    /// Returns the entry that undoes deleting an item, or `None` if the item
+    /// doesn't exist, or if the log is disabled. This must be called before
+    /// deleting the item.
+    pub(super) fn for_delete(db: &PlacesDb, guid: &SyncGuid) -> Result<Option<UndoEntry>> {
+        if !db.bookmark_undo_log().borrow().is_enabled() {
+            return Ok(None);
+        }
+        Ok(match fetch_tree(db, guid, &FetchDepth::Deepest)? {
+            Some((node, Some(parent_guid), position)) => Some(UndoEntry::Delete {
+                parent_guid,
+                position,
+                node,
+            }),
+            _ => None,
+        })
+    }
+
+    /// Returns the entry that undoes updating an item, given the item before
+    /// it was updated.
+    pub(super) fn for_update(raw: &RawBookmark) -> Option<UndoEntry> {
+        Some(UndoEntry::Update {
+            guid: raw.guid.clone(),
+            parent_guid: raw.parent_guid.clone()?,
+            position: raw.position,
+            title: raw.title.clone(),
+            url: raw.url.clone(),
+        })
+    }
+
+    fn revert(&self, db: &PlacesDb) -> Result<()> {
+        match self {
+            UndoEntry::Insert { guid } => {
+                if !delete_bookmark_in_tx(db, guid)? {
+                    log::debug!("Can't undo inserting {:?}; it's already gone", guid);
+                }
+            }
+            UndoEntry::Delete {
+                parent_guid,
+                position,
+                node,
+            } => restore_subtree(db, parent_guid, *position, node)?,
+            UndoEntry::Update {
+                guid,
+                parent_guid,
+                position,
+                title,
+                url,
+            } => {
+                let raw = get_raw_bookmark(db, guid)?
+                    .ok_or_else(|| InvalidPlaceInfo::NoSuchGuid(guid.to_string()))?;
+                let location = if raw.parent_guid.as_ref() != Some(parent_guid) {
+                    UpdateTreeLocation::Parent(
+                        parent_guid.clone(),
+                        BookmarkPosition::Specific(*position),
+                    )
+                } else if raw.position != *position {
+                    UpdateTreeLocation::Position(BookmarkPosition::Specific(*position))
+                } else {
+                    UpdateTreeLocation::None
+                };
+                // An empty title means "remove the title".
+                let title = Some(title.clone().unwrap_or_default());
+                let item: UpdatableItem = match raw.bookmark_type {
+                    BookmarkType::Bookmark => UpdatableBookmark {
+                        location,
+                        url: url.clone(),
+                        title,
+                    }
+                    .into(),
+                    BookmarkType::Folder => UpdatableFolder { location, title }.into(),
+                    BookmarkType::Separator => UpdatableSeparator { location }.into(),
+                };
+                update_bookmark_in_tx(db, guid, &item, raw)?;
+            }
+            UndoEntry::Reorder { folder_guid, guids } => {
+                reorder_children_in_tx(db, folder_guid, guids)?;
+            }
+            UndoEntry::Move {
+                guids,
+                old_children,
+            } => {
+                for (parent_guid, children) in old_children {
+                    let moved: Vec<SyncGuid> = guids
+                        .iter()
+                        .filter(|guid| children.contains(guid))
+                        .cloned()
+                        .collect();
+                    move_many_in_tx(db, &moved, parent_guid, BookmarkPosition::Append)?;
+                    reorder_children_in_tx(db, parent_guid, children)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reinserts a deleted subtree at its old position, with its original GUIDs
+/// and dates.
+fn restore_subtree(
+    db: &PlacesDb,
+    parent_guid: &SyncGuid,
+    position: u32,
+    node: &BookmarkTreeNode,
+) -> Result<()> {
+    let position = BookmarkPosition::Specific(position);
+    let item: InsertableItem = match node {
+        BookmarkTreeNode::Bookmark(b) => InsertableBookmark {
+            parent_guid: parent_guid.clone(),
+            position,
+            date_added: b.date_added,
+            last_modified: b.last_modified,
+            guid: b.guid.clone(),
+            url: b.url.clone(),
+            title: b.title.clone(),
+        }
+        .into(),
+        BookmarkTreeNode::Separator(s) => InsertableSeparator {
+            parent_guid: parent_guid.clone(),
+            position,
+            date_added: s.date_added,
+            last_modified: s.last_modified,
+            guid: s.guid.clone(),
+        }
+        .into(),
+        BookmarkTreeNode::Folder(f) => InsertableFolder {
+            parent_guid: parent_guid.clone(),
+            position,
+            date_added: f.date_added,
+            last_modified: f.last_modified,
+            guid: f.guid.clone(),
+            title: f.title.clone(),
+        }
+        .into(),
+    };
+    insert_bookmark_in_tx(db, &item)?;
+    if let BookmarkTreeNode::Folder(f) = node {
+        let mut insert_infos = Vec::new();
+        add_subtree_infos(node.guid(), f, &mut insert_infos);
+        for insertable in insert_infos {
+            insert_bookmark_in_tx(db, &insertable)?;
+        }
+    }
+    Ok(())
+}
+
+/// The undo log for a connection.
+#[derive(Debug)]
+pub(crate) struct UndoLog {
+    limit: usize,
+    entries: VecDeque<UndoEntry>,
+}
+
+impl Default for UndoLog {
+    fn default() -> Self {
+        UndoLog {
+            limit: DEFAULT_UNDO_LIMIT,
+            entries: VecDeque::new(),
+        }
+    }
+}
+
+impl UndoLog {
+    fn is_enabled(&self) -> bool {
+        self.limit > 0
+    }
+
+    pub(super) fn push(&mut self, entry: UndoEntry) {
+        if !self.is_enabled() {
+            return;
+        }
+        while self.entries.len() >= self.limit {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        while self.entries.len() > limit {
+            self.entries.pop_front();
+        }
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// Sets the number of changes that the connection remembers, forgetting the
+/// oldest changes if there are more than `limit`. Setting the limit to 0
+/// disables the undo log.
+pub fn set_undo_limit(db: &PlacesDb, limit: usize) {
+    db.bookmark_undo_log().borrow_mut().set_limit(limit);
+}
+
+/// Returns the number of changes that can be undone.
+pub fn undo_count(db: &PlacesDb) -> usize {
+    db.bookmark_undo_log().borrow().entries.len()
+}
+
+/// Forgets all changes, so that they can't be undone.
+pub fn clear_undo_log(db: &PlacesDb) {
+    db.bookmark_undo_log().borrow_mut().clear();
+}
+
+/// Reverts the most recent bookmark change. Returns `false` if there's
+/// nothing to undo. If the change can't be reverted, it's removed from the
+/// log, and the error is returned.
+pub fn undo_last_change(db: &PlacesDb) -> Result<bool> {
+    let entry = match db.bookmark_undo_log().borrow_mut().entries.pop_back() {
+        Some(entry) => entry,
+        None => return Ok(false),
+    };
+    let tx = db.begin_transaction()?;
+    let result = entry.revert(db);
+    crate::storage::delete_pending_temp_tables(db)?;
+    match result {
+        Ok(_) => tx.commit()?,
+        Err(_) => tx.rollback()?,
+    }
+    result.map(|_| true)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+    use crate::storage::bookmarks::{
+        delete_bookmark, insert_bookmark, move_many, reorder_children, update_bookmark,
+        BookmarkRootGuid,
+    };
+    use crate::tests::{assert_json_tree, insert_json_tree};
+    use rusqlite::NO_PARAMS;
+    use serde_json::json;
+    use sql_support::ConnExt;
+
+    fn unfiled_tree() -> serde_json::Value {
+        json!({
+            "guid": String::from(BookmarkRootGuid::Unfiled.as_str()),
+            "children": [
+                {
+                    "guid": "folderAAAAAA",
+                    "title": "A",
+                    "children": [
+                        {
+                            "guid": "bookmarkBBBB",
+                            "title": "B",
+                            "url": "http://example.com/b",
+                        },
+                        { "guid": "separatorCCC", "type": 3 },
+                    ],
+                },
+                {
+                    "guid": "bookmarkDDDD",
+                    "title": "D",
+                    "url": "http://example.com/d",
+                },
+            ],
+        })
+    }
+
+    #[test]
+    fn test_undo_update() -> Result<()> {
+        let conn = new_mem_connection();
+        insert_json_tree(&conn, unfiled_tree());
+        // Inserting a tree isn't recorded.
+        assert_eq!(undo_count(&conn), 0);
+
+        update_bookmark(
+            &conn,
+            &"bookmarkDDDD".into(),
+            &UpdatableBookmark {
+                location: UpdateTreeLocation::Parent(
+                    "folderAAAAAA".into(),
+                    BookmarkPosition::Specific(0),
+                ),
+                url: Some(Url::parse("http://example.com/e")?),
+                title: Some("E".into()),
+            }
+            .into(),
+        )?;
+        update_bookmark(
+            &conn,
+            &"folderAAAAAA".into(),
+            &UpdatableFolder {
+                location: UpdateTreeLocation::None,
+                title: Some("".into()),
+            }
+            .into(),
+        )?;
+        assert_eq!(undo_count(&conn), 2);
+
+        assert!(undo_last_change(&conn)?);
+        assert!(undo_last_change(&conn)?);
+        assert!(!undo_last_change(&conn)?);
+        assert_json_tree(&conn, &BookmarkRootGuid::Unfiled.into(), unfiled_tree());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo_reorder_and_move() -> Result<()> {
+        let conn = new_mem_connection();
+        insert_json_tree(&conn, unfiled_tree());
+        let unfiled = BookmarkRootGuid::Unfiled.as_guid();
+
+        reorder_children(
+            &conn,
+            &"folderAAAAAA".into(),
+            &["separatorCCC".into(), "bookmarkBBBB".into()],
+        )?;
+        // Reordering a folder into its current order isn't recorded.
+        reorder_children(&conn, &unfiled, &["folderAAAAAA".into()])?;
+        move_many(
+            &conn,
+            &["bookmarkBBBB".into(), "bookmarkDDDD".into()],
+            &BookmarkRootGuid::Menu.as_guid(),
+            BookmarkPosition::Append,
+        )?;
+        move_many(
+            &conn,
+            &["separatorCCC".into(), "folderAAAAAA".into()],
+            &BookmarkRootGuid::Menu.as_guid(),
+            BookmarkPosition::Specific(1),
+        )?;
+        assert_eq!(undo_count(&conn), 3);
+
+        assert!(undo_last_change(&conn)?);
+        assert!(undo_last_change(&conn)?);
+        assert!(undo_last_change(&conn)?);
+        assert_eq!(undo_count(&conn), 0);
+        assert_json_tree(&conn, &unfiled, unfiled_tree());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo_delete() -> Result<()> {
+        let conn = new_mem_connection();
+        insert_json_tree(&conn, unfiled_tree());
+        // Pretend everything has been synced.
+        conn.execute(
+            "UPDATE moz_bookmarks SET syncStatus = 2, syncChangeCounter = 0",
+            NO_PARAMS,
+        )?;
+        let get_date_added = |guid: &str| -> Result<i64> {
+            Ok(conn.query_row_and_then_named(
+                "SELECT dateAdded FROM moz_bookmarks WHERE guid = :guid",
+                &[(":guid", &guid)],
+                |row| row.get(0),
+                false,
+            )?)
+        };
+        let date_added = get_date_added("bookmarkBBBB")?;
+
+        assert!(delete_bookmark(&conn, &"folderAAAAAA".into())?);
+        assert!(!delete_bookmark(&conn, &"folderAAAAAA".into())?);
+        let tombstones: u32 = conn.query_one("SELECT COUNT(*) FROM moz_bookmarks_deleted")?;
+        assert_eq!(tombstones, 3);
+        // Only the successful deletion is recorded.
+        assert_eq!(undo_count(&conn), 1);
+
+        assert!(undo_last_change(&conn)?);
+        assert_json_tree(&conn, &BookmarkRootGuid::Unfiled.into(), unfiled_tree());
+        assert_eq!(get_date_added("bookmarkBBBB")?, date_added);
+
+        // Restoring the items removes their tombstones, and flags them for
+        // upload.
+        let tombstones: u32 = conn.query_one("SELECT COUNT(*) FROM moz_bookmarks_deleted")?;
+        assert_eq!(tombstones, 0);
+        let mut changed: Vec<String> = conn.query_rows_and_then_named(
+            "SELECT guid FROM moz_bookmarks WHERE syncChangeCounter > 0",
+            &[],
+            |row| row.get(0),
+        )?;
+        changed.sort();
+        assert_eq!(
+            changed,
+            vec![
+                "bookmarkBBBB",
+                "folderAAAAAA",
+                "separatorCCC",
+                "unfiled_____"
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo_insert() -> Result<()> {
+        let conn = new_mem_connection();
+        insert_json_tree(&conn, unfiled_tree());
+        let guid = insert_bookmark(
+            &conn,
+            &InsertableBookmark {
+                parent_guid: "folderAAAAAA".into(),
+                position: BookmarkPosition::Specific(1),
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                url: Url::parse("http://example.com/f")?,
+                title: None,
+            }
+            .into(),
+        )?;
+        assert!(get_raw_bookmark(&conn, &guid)?.is_some());
+
+        assert!(undo_last_change(&conn)?);
+        assert!(get_raw_bookmark(&conn, &guid)?.is_none());
+        assert_json_tree(&conn, &BookmarkRootGuid::Unfiled.into(), unfiled_tree());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo_limit() -> Result<()> {
+        let conn = new_mem_connection();
+        insert_json_tree(&conn, unfiled_tree());
+        set_undo_limit(&conn, 2);
+        for title in &["1", "2", "3"] {
+            update_bookmark(
+                &conn,
+                &"bookmarkDDDD".into(),
+                &UpdatableBookmark {
+                    title: Some(title.to_string()),
+                    ..UpdatableBookmark::default()
+                }
+                .into(),
+            )?;
+        }
+        assert_eq!(undo_count(&conn), 2);
+        assert!(undo_last_change(&conn)?);
+        assert!(undo_last_change(&conn)?);
+        assert!(!undo_last_change(&conn)?);
+        let raw = get_raw_bookmark(&conn, &"bookmarkDDDD".into())?.unwrap();
+        assert_eq!(raw.title, Some("1".to_string()));
+
+        set_undo_limit(&conn, 0);
+        delete_bookmark(&conn, &"bookmarkDDDD".into())?;
+        assert_eq!(undo_count(&conn), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo_fails() -> Result<()> {
+        let conn = new_mem_connection();
+        insert_json_tree(&conn, unfiled_tree());
+        update_bookmark(
+            &conn,
+            &"bookmarkBBBB".into(),
+            &UpdatableBookmark {
+                title: Some("Not B".into()),
+                ..UpdatableBookmark::default()
+            }
+            .into(),
+        )?;
+        // Sync or another connection could delete the item without recording
+        // the change.
+        conn.execute(
+            "DELETE FROM moz_bookmarks WHERE guid = 'folderAAAAAA'",
+            NO_PARAMS,
+        )?;
+        undo_last_change(&conn).expect_err("Should fail to undo updating a missing item");
+        assert_eq!(undo_count(&conn), 0);
+
+        Ok(())
+    }
+}
@@ -2,18 +2,21 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use super::{fetch_page_info, new_page_info, PageInfo, RowId};
+use super::{fetch_page_info, new_page_info, search_terms, PageInfo, RowId};
 use crate::db::PlacesDb;
 use crate::error::Result;
 use crate::frecency;
 use crate::hash;
 use crate::history_sync::store::{
-    COLLECTION_SYNCID_META_KEY, GLOBAL_SYNCID_META_KEY, LAST_SYNC_META_KEY,
+    COLLECTION_SYNCID_META_KEY, DOWNLOAD_OFFSET_META_KEY, GLOBAL_SYNCID_META_KEY,
+    LAST_SYNC_META_KEY,
 };
 use crate::msg_types::{HistoryVisitInfo, HistoryVisitInfos, HistoryVisitInfosWithBound};
 use crate::observation::VisitObservation;
+use crate::observer::PlacesEvent;
 use crate::storage::{delete_meta, delete_pending_temp_tables, get_meta, put_meta};
 use crate::types::{SyncStatus, Timestamp, VisitTransition, VisitTransitionSet};
+use interrupt_support::Interruptee;
 use rusqlite::types::ToSql;
 use rusqlite::Result as RusqliteResult;
 use rusqlite::{Row, NO_PARAMS};
@@ -54,9 +57,18 @@ pub fn apply_observation_direct(
     let mut update_change_counter = false;
     let mut update_frec = false;
     let mut updates: Vec<(&str, &str, &dyn ToSql)> = Vec::new();
+    let mut events = Vec::new();
 
     if let Some(ref title) = visit_ob.title {
-        page_info.title = crate::util::slice_up_to(title, super::TITLE_LENGTH_MAX).into();
+        let title = crate::util::slice_up_to(title, super::TITLE_LENGTH_MAX);
+        if title != page_info.title {
+            events.push(PlacesEvent::TitleChanged {
+                guid: page_info.guid.clone(),
+                url: url.clone(),
+                title: title.into(),
+            });
+        }
+        page_info.title = title.into();
         updates.push(("title", ":title", &page_info.title));
         update_change_counter = true;
     }
@@ -67,6 +79,11 @@ pub fn apply_observation_direct(
             // A single non-hidden visit makes the place non-hidden.
             if !visit_ob.get_is_hidden() {
                 updates.push(("hidden", ":hidden", &false));
+            } else if page_info.hidden {
+                // New pages are inserted as visible, so we need to hide
+                // them if their first visit is hidden (for example, if the
+                // page is only the source of a redirect).
+                updates.push(("hidden", ":hidden", &true));
             }
             if visit_type == VisitTransition::Typed {
                 page_info.typed += 1;
@@ -75,9 +92,26 @@ pub fn apply_observation_direct(
 
             let at = visit_ob.at.unwrap_or_else(Timestamp::now);
             let is_remote = visit_ob.is_remote.unwrap_or(false);
-            let row_id = add_visit(db, page_info.row_id, None, at, visit_type, !is_remote)?;
+            let is_error = visit_ob.is_error.unwrap_or(false);
+            let row_id = add_visit(
+                db,
+                page_info.row_id,
+                None,
+                at,
+                visit_type,
+                !is_remote,
+                is_error,
+            )?;
+            search_terms::note_search_term(db, page_info.row_id, &url)?;
+            events.push(PlacesEvent::VisitAdded {
+                guid: page_info.guid.clone(),
+                url: url.clone(),
+                visit_date: at,
+                visit_type,
+                is_local: !is_remote,
+            });
             // a new visit implies new frecency except in error cases.
-            if !visit_ob.is_error.unwrap_or(false) {
+            if !is_error {
                 update_frec = true;
             }
             update_change_counter = true;
@@ -120,13 +154,16 @@ pub fn apply_observation_direct(
         )?;
     }
     delete_pending_temp_tables(db)?;
+    for event in events {
+        db.note_event(event);
+    }
     Ok(visit_row_id)
 }
 
 pub fn update_frecency(db: &PlacesDb, id: RowId, redirect_boost: Option<bool>) -> Result<()> {
     let score = frecency::calculate_frecency(
         db.conn(),
-        &frecency::DEFAULT_FRECENCY_SETTINGS,
+        &db.frecency_settings(),
         id.0, // TODO: calculate_frecency should take a RowId here.
         redirect_boost,
     )?;
@@ -156,6 +193,123 @@ pub fn frecency_stale_at(db: &PlacesDb, url: &Url) -> Result<Option<Timestamp>>
     Ok(result)
 }
 
+/// The maximum number of URLs for which to recalculate frecencies at once.
+/// This is a trade-off between write efficiency and transaction time: higher
+/// maximums mean fewer write statements, but longer transactions, possibly
+/// blocking writes from other connections.
+pub const STALE_FRECENCIES_BATCH_SIZE: u32 = 400;
+
+/// Marks a page's frecency as stale, so that it's recalculated the next time
+/// stale frecencies are processed.
+pub(crate) fn mark_frecency_stale(db: &PlacesDb, page_id: RowId) -> Result<()> {
+    db.execute_named_cached(
+        "REPLACE INTO moz_places_stale_frecencies(place_id, stale_at)
+         VALUES(:page_id, :now)",
+        &[(":page_id", &page_id), (":now", &Timestamp::now())],
+    )?;
+    Ok(())
+}
+
+/// Recalculates up to `limit` stale frecencies, most recently marked first,
+/// in a single transaction. Returns the number of frecencies that are still
+/// stale, so callers can process them in bounded batches, letting other
+/// connections write in between. If `interruptee` is interrupted, none of the
+/// frecencies in the batch are updated.
+pub fn update_stale_frecencies(
+    db: &PlacesDb,
+    limit: u32,
+    interruptee: &impl Interruptee,
+) -> Result<u32> {
+    let tx = db.begin_transaction()?;
+    let settings = db.frecency_settings();
+    let page_ids = db.query_rows_and_then_named_cached(
+        "SELECT place_id FROM moz_places_stale_frecencies
+         ORDER BY stale_at DESC
+         LIMIT :limit",
+        &[(":limit", &limit)],
+        |row| row.get::<_, RowId>(0),
+    )?;
+    for page_id in &page_ids {
+        // Frecency recalculation runs several statements, so check to make
+        // sure we aren't interrupted before each calculation.
+        interruptee.err_if_interrupted()?;
+        let score = frecency::calculate_frecency(db.conn(), &settings, page_id.0, None)?;
+        db.execute_named_cached(
+            "UPDATE moz_places SET frecency = :frecency
+             WHERE id = :page_id",
+            &[(":frecency", &score), (":page_id", page_id)],
+        )?;
+    }
+    sql_support::each_chunk(&page_ids, |chunk, _| -> Result<()> {
+        db.conn().execute(
+            &format!(
+                "DELETE FROM moz_places_stale_frecencies WHERE place_id IN ({})",
+                sql_support::repeat_sql_vars(chunk.len())
+            ),
+            chunk,
+        )?;
+        Ok(())
+    })?;
+    delete_pending_temp_tables(db)?;
+    let remaining = db.query_one::<u32>("SELECT COUNT(*) FROM moz_places_stale_frecencies")?;
+    tx.commit()?;
+    Ok(remaining)
+}
+
+/// How much frecencies decay each day. This matches Desktop.
+const FRECENCY_DECAY_RATE: f64 = 0.975;
+
+/// The `moz_meta` key for when frecencies were last decayed.
+const LAST_FRECENCY_DECAY_META_KEY: &str = "last_frecency_decay";
+
+const ONE_DAY_IN_MILLIS: u64 = 24 * 60 * 60 * 1000;
+
+/// Ages frecencies and adaptive history, like Desktop's idle-daily decay, so
+/// that pages which the user hasn't visited in a while rank lower than pages
+/// with the same visits that were visited recently. This is meant to be
+/// called periodically; frecencies decay once for each day since the last
+/// call, and calling it again on the same day does nothing. Returns the
+/// number of days that frecencies were decayed by.
+pub fn decay_frecencies(db: &PlacesDb) -> Result<u64> {
+    let tx = db.begin_transaction()?;
+    let now = Timestamp::now();
+    let last_decay = match get_meta::<Timestamp>(db, LAST_FRECENCY_DECAY_META_KEY)? {
+        Some(last_decay) => last_decay,
+        None => {
+            // If we've never decayed frecencies, start counting from now.
+            put_meta(db, LAST_FRECENCY_DECAY_META_KEY, &now)?;
+            tx.commit()?;
+            return Ok(0);
+        }
+    };
+    // Our clock might have gone backward, in which case we shouldn't decay
+    // anything until it catches up.
+    let days = now.0.saturating_sub(last_decay.0) / ONE_DAY_IN_MILLIS;
+    if days > 0 {
+        let factor = FRECENCY_DECAY_RATE.powf(days as f64);
+        db.execute_named_cached(
+            "UPDATE moz_places SET frecency = ROUND(frecency * :factor)
+             WHERE frecency > 0",
+            &[(":factor", &factor)],
+        )?;
+        db.execute_named_cached(
+            "UPDATE moz_inputhistory SET use_count = use_count * :factor",
+            &[(":factor", &factor)],
+        )?;
+        db.execute_all(&["DELETE FROM moz_inputhistory WHERE use_count < .01"])?;
+        delete_pending_temp_tables(db)?;
+        // Only count the whole days we decayed by, so that we don't lose
+        // the rest of the current day.
+        put_meta(
+            db,
+            LAST_FRECENCY_DECAY_META_KEY,
+            &Timestamp(last_decay.0 + days * ONE_DAY_IN_MILLIS),
+        )?;
+    }
+    tx.commit()?;
+    Ok(days)
+}
+
 // Add a single visit - you must know the page rowid. Does not update the
 // page info - if you are calling this, you will also need to update the
 // parent page with an updated change counter etc.
@@ -166,10 +320,11 @@ fn add_visit(
     visit_date: Timestamp,
     visit_type: VisitTransition,
     is_local: bool,
+    is_error: bool,
 ) -> Result<RowId> {
     let sql = "INSERT INTO moz_historyvisits
-            (from_visit, place_id, visit_date, visit_type, is_local)
-        VALUES (:from_visit, :page_id, :visit_date, :visit_type, :is_local)";
+            (from_visit, place_id, visit_date, visit_type, is_local, is_error)
+        VALUES (:from_visit, :page_id, :visit_date, :visit_type, :is_local, :is_error)";
     db.execute_named_cached(
         sql,
         &[
@@ -178,6 +333,7 @@ fn add_visit(
             (":visit_date", &visit_date),
             (":visit_type", &visit_type),
             (":is_local", &is_local),
+            (":is_error", &is_error),
         ],
     )?;
     let rid = db.conn().last_insert_rowid();
@@ -216,7 +372,7 @@ fn delete_visits_for_in_tx(db: &PlacesDb, guid: &SyncGuid) -> Result<()> {
     // We only create tombstones for history which exists and with sync_status
     // == SyncStatus::Normal
     let to_clean = db.conn().try_query_row(
-        "SELECT id,
+        "SELECT id, url, guid,
                 (foreign_count != 0) AS has_foreign,
                 1 as has_visits,
                 sync_status
@@ -226,56 +382,84 @@ fn delete_visits_for_in_tx(db: &PlacesDb, guid: &SyncGuid) -> Result<()> {
         PageToClean::from_row,
         true,
     )?;
-    match to_clean {
-        Some(PageToClean {
+    if let Some(page) = &to_clean {
+        delete_all_visits_and_page(db, page)?;
+    }
+    delete_pending_temp_tables(db)?;
+    Ok(())
+}
+
+/// Deletes all visits for a page, and the page itself unless it's bookmarked,
+/// or has a keyword or tags. Creates tombstones if the page is syncing.
+fn delete_all_visits_and_page(db: &PlacesDb, page: &PageToClean) -> Result<()> {
+    // Metadata is deleted along with the page, but we need to remove it
+    // ourselves if the page is staying around.
+    delete_metadata_for_page(db, page.id)?;
+    db.note_event(PlacesEvent::PageRemoved {
+        guid: page.guid.clone(),
+        url: page.url.clone(),
+        is_removed_from_store: !page.has_foreign,
+        is_partial_visit_removal: false,
+    });
+    match page {
+        PageToClean {
             id,
             has_foreign: true,
             sync_status: SyncStatus::Normal,
             ..
-        }) => {
+        } => {
             // If our page is syncing, and has foreign key references (like
             // local or synced bookmarks, keywords, and tags), we can't delete
             // its row from `moz_places` directly; that would cause a constraint
             // violation. Instead, we must insert tombstones for all visits, and
             // then delete just the visits, keeping the page in place (pun most
             // definitely intended).
-            insert_tombstones_for_all_page_visits(db, id)?;
-            delete_all_visits_for_page(db, id)?;
+            insert_tombstones_for_all_page_visits(db, *id)?;
+            delete_all_visits_for_page(db, *id)?;
         }
-        Some(PageToClean {
+        PageToClean {
             id,
+            guid,
             has_foreign: false,
             sync_status: SyncStatus::Normal,
             ..
-        }) => {
+        } => {
             // However, if our page is syncing and _doesn't_ have any foreign
             // key references, we can delete it from `moz_places` outright, and
             // write a tombstone for the page instead of all the visits.
             insert_tombstone_for_page(db, guid)?;
-            delete_page(db, id)?;
+            delete_page(db, *id)?;
         }
-        Some(PageToClean {
+        PageToClean {
             id,
             has_foreign: true,
             ..
-        }) => {
+        } => {
             // If our page has foreign key references but _isn't_ syncing,
             // we still can't delete it; we must delete its visits. But we
             // don't need to write any tombstones for those deleted visits.
-            delete_all_visits_for_page(db, id)?;
+            delete_all_visits_for_page(db, *id)?;
         }
-        Some(PageToClean {
+        PageToClean {
             id,
             has_foreign: false,
             ..
-        }) => {
+        } => {
             // And, finally, the easiest case: not syncing, and no foreign
             // key references, so just delete the page.
-            delete_page(db, id)?;
+            delete_page(db, *id)?;
         }
-        None => {}
     }
-    delete_pending_temp_tables(db)?;
+    Ok(())
+}
+
+/// Removes all history metadata for a page.
+fn delete_metadata_for_page(db: &PlacesDb, page_id: RowId) -> Result<()> {
+    db.execute_named_cached(
+        "DELETE FROM moz_places_metadata
+         WHERE place_id = :page_id",
+        &[(":page_id", &page_id)],
+    )?;
     Ok(())
 }
 
@@ -331,6 +515,62 @@ pub fn delete_visits_for(db: &PlacesDb, guid: &SyncGuid) -> Result<()> {
     result
 }
 
+/// Deletes all pages and visits for a host, creating tombstones if necessary.
+/// If `include_subdomains` is true, pages for all subdomains of the host are
+/// deleted, too, so passing a registrable domain like "example.com" forgets
+/// everything about that site. Like the origins it's matched against, `host`
+/// should include the port if the URLs do, like "example.com:8080". As with
+/// `delete_visits_for`, pages that are bookmarked, or have a keyword or tags,
+/// are kept, but lose all their visits.
+pub fn delete_visits_for_host(db: &PlacesDb, host: &str, include_subdomains: bool) -> Result<()> {
+    let tx = db.begin_transaction()?;
+    delete_visits_for_host_in_tx(db, host, include_subdomains)?;
+    tx.commit()?;
+    Ok(())
+}
+
+fn delete_visits_for_host_in_tx(db: &PlacesDb, host: &str, include_subdomains: bool) -> Result<()> {
+    // Parsing the host as part of a URL Punycodes and lowercases it, the
+    // same way as the hosts of the URLs we store.
+    let url = Url::parse(&format!("https://{}/", host))?;
+    let host = match (url.host_str(), url.port()) {
+        (Some(host), Some(port)) => format!("{}:{}", host, port),
+        (Some(host), None) => host.to_owned(),
+        (None, _) => return Ok(()),
+    };
+    // `rev_host` is the reversed host with a trailing dot, like
+    // "moc.elpmaxe.", so the `rev_host`s of a host's subdomains all start
+    // with its `rev_host`. They sort between it and the same string with the
+    // trailing dot replaced by the next character, "/", which lets us use the
+    // index on `rev_host`.
+    let rev_host_without_dot = host.chars().rev().collect::<String>();
+    let rev_host = format!("{}.", rev_host_without_dot);
+    let subdomains_end = if include_subdomains {
+        format!("{}/", rev_host_without_dot)
+    } else {
+        rev_host.clone()
+    };
+    let pages = db.query_rows_and_then_named(
+        "SELECT h.id, h.url, h.guid,
+                (h.foreign_count != 0) AS has_foreign,
+                1 AS has_visits,
+                h.sync_status
+         FROM moz_places h
+         JOIN moz_origins o ON o.id = h.origin_id
+         WHERE o.rev_host BETWEEN :rev_host AND :subdomains_end",
+        &[
+            (":rev_host", &rev_host),
+            (":subdomains_end", &subdomains_end),
+        ],
+        PageToClean::from_row,
+    )?;
+    for page in &pages {
+        delete_all_visits_and_page(db, page)?;
+    }
+    delete_pending_temp_tables(db)?;
+    Ok(())
+}
+
 /// Delete all visits in a date range.
 pub fn delete_visits_between(db: &PlacesDb, start: Timestamp, end: Timestamp) -> Result<()> {
     let tx = db.begin_transaction()?;
@@ -354,9 +594,246 @@ pub fn delete_place_visit_at_time_by_href(
     Ok(())
 }
 
-pub fn prune_destructively(db: &PlacesDb) -> Result<()> {
-    // For now, just fall back to wipe_local until we decide how this should work.
-    wipe_local(db)
+/// The maximum number of pages or visits to remove at a time when pruning.
+const PRUNE_CHUNK_SIZE: u32 = 500;
+
+/// How much history to keep when pruning.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PruneLimits {
+    /// The maximum number of bytes of the database that should be in use,
+    /// not counting free pages that will be reclaimed by a `VACUUM`.
+    pub max_db_size: u64,
+    /// The maximum number of pages to keep. Bookmarked pages count toward
+    /// this limit, but are never removed.
+    pub max_pages: u32,
+}
+
+impl Default for PruneLimits {
+    fn default() -> Self {
+        Self {
+            max_db_size: 30 * 1024 * 1024,
+            max_pages: 20_000,
+        }
+    }
+}
+
+/// What was removed by `prune_destructively`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PruneMetrics {
+    pub pages_removed: u32,
+    pub visits_removed: u32,
+}
+
+impl From<PruneMetrics> for crate::msg_types::PruneMetrics {
+    fn from(metrics: PruneMetrics) -> Self {
+        Self {
+            pages_removed: metrics.pages_removed,
+            visits_removed: metrics.visits_removed,
+        }
+    }
+}
+
+/// Expires history until the database fits in the default `PruneLimits`.
+/// This is meant to be called when the device is low on storage.
+pub fn prune_destructively(db: &PlacesDb) -> Result<PruneMetrics> {
+    prune_destructively_with_limits(db, &PruneLimits::default())
+}
+
+/// Expires history until the database fits in `limits`, like Desktop's
+/// expiration. Pages that aren't bookmarked, tagged, or used by a keyword are
+/// removed first, least frecent first. If that's not enough to get the
+/// database under `limits.max_db_size`, the oldest visits to the remaining
+/// pages are removed, too.
+///
+/// Expired history isn't deleted from the server, so we don't write
+/// tombstones, and expired pages won't come back unless they're changed on
+/// another device. However, any local changes that haven't been synced yet
+/// are lost.
+pub fn prune_destructively_with_limits(
+    db: &PlacesDb,
+    limits: &PruneLimits,
+) -> Result<PruneMetrics> {
+    let mut metrics = PruneMetrics::default();
+    // We can only estimate how many rows to remove to get under the size
+    // limit, so we check the real size after each round, and prune again if
+    // we're still over.
+    loop {
+        let tx = db.begin_transaction()?;
+        let round = prune_in_tx(db, limits)?;
+        tx.commit()?;
+        if round == PruneMetrics::default() {
+            break;
+        }
+        metrics.pages_removed += round.pages_removed;
+        metrics.visits_removed += round.visits_removed;
+        // Note: SQLite cannot VACUUM within a transaction.
+        db.execute_batch("VACUUM")?;
+    }
+    Ok(metrics)
+}
+
+fn prune_in_tx(db: &PlacesDb, limits: &PruneLimits) -> Result<PruneMetrics> {
+    let mut metrics = PruneMetrics::default();
+    // Deleted rows usually aren't returned to the freelist until the
+    // transaction commits, so we can't measure our progress toward the size
+    // limit as we go. Instead, we estimate how much space each row uses up
+    // front, and count the rows we remove.
+    let page_count = db.query_one::<u32>("SELECT COUNT(*) FROM moz_places")?;
+    let visit_count = db.query_one::<u32>("SELECT COUNT(*) FROM moz_historyvisits")?;
+    let row_count = u64::from(page_count) + u64::from(visit_count);
+    let used_size = used_db_size(db)?;
+    let bytes_per_row = used_size / row_count.max(1);
+    let rows_per_page = (row_count / u64::from(page_count.max(1))).max(1);
+    let mut pages_to_remove = page_count.saturating_sub(limits.max_pages);
+    let excess_bytes = used_size.saturating_sub(limits.max_db_size);
+    // Round up, so that we don't stop just short of the limit.
+    let mut rows_to_remove = match excess_bytes.checked_div(bytes_per_row) {
+        Some(rows) if excess_bytes > 0 => rows + 1,
+        _ => 0,
+    };
+    while pages_to_remove > 0 || rows_to_remove > 0 {
+        let pages_for_size = if rows_to_remove > 0 {
+            rows_to_remove / rows_per_page + 1
+        } else {
+            0
+        };
+        let chunk_size = pages_for_size
+            .max(u64::from(pages_to_remove))
+            .min(u64::from(PRUNE_CHUNK_SIZE)) as u32;
+        let before = metrics;
+        let pages_removed = prune_least_frecent_pages(db, chunk_size, &mut metrics)?;
+        if pages_removed > 0 {
+            pages_to_remove = pages_to_remove.saturating_sub(pages_removed as u32);
+            let rows_removed = (metrics.pages_removed - before.pages_removed)
+                + (metrics.visits_removed - before.visits_removed);
+            rows_to_remove = rows_to_remove.saturating_sub(u64::from(rows_removed));
+            continue;
+        }
+        // Only bookmarked pages are left. Removing their visits won't get us
+        // under the page limit, but can still free some space.
+        if rows_to_remove == 0 {
+            break;
+        }
+        let chunk_size = rows_to_remove.min(u64::from(PRUNE_CHUNK_SIZE)) as u32;
+        let visits_removed = prune_oldest_visits(db, chunk_size, &mut metrics)?;
+        if visits_removed == 0 {
+            break;
+        }
+        rows_to_remove = rows_to_remove.saturating_sub(visits_removed as u64);
+    }
+    delete_pending_temp_tables(db)?;
+    Ok(metrics)
+}
+
+/// Returns the number of bytes of the database that are in use.
+fn used_db_size(db: &PlacesDb) -> Result<u64> {
+    let page_size = db.query_one::<i64>("PRAGMA page_size")?;
+    let page_count = db.query_one::<i64>("PRAGMA page_count")?;
+    let free_count = db.query_one::<i64>("PRAGMA freelist_count")?;
+    Ok(((page_count - free_count) * page_size).max(0) as u64)
+}
+
+/// Removes up to `limit` of the least frecent pages, along with their
+/// visits, that aren't bookmarked, tagged, or used by a keyword. Returns the
+/// number of pages removed.
+fn prune_least_frecent_pages(
+    db: &PlacesDb,
+    limit: u32,
+    metrics: &mut PruneMetrics,
+) -> Result<usize> {
+    let pages = db.query_rows_and_then_named_cached(
+        "SELECT id, url, guid
+         FROM moz_places
+         WHERE foreign_count = 0
+         ORDER BY frecency ASC,
+                  MAX(last_visit_date_local, last_visit_date_remote) ASC,
+                  id ASC
+         LIMIT :limit",
+        &[(":limit", &limit)],
+        |row| -> Result<_> {
+            Ok((
+                row.get::<_, RowId>("id")?,
+                Url::parse(&row.get::<_, String>("url")?)?,
+                row.get::<_, SyncGuid>("guid")?,
+            ))
+        },
+    )?;
+    let page_ids = pages.iter().map(|(id, _, _)| *id).collect::<Vec<_>>();
+    sql_support::each_chunk(&page_ids, |chunk, _| -> Result<()> {
+        let vars = sql_support::repeat_sql_vars(chunk.len());
+        // Visits would be removed along with their pages anyway, but we
+        // delete them explicitly to count them.
+        let visits_removed = db.conn().execute(
+            &format!("DELETE FROM moz_historyvisits WHERE place_id IN ({})", vars),
+            chunk,
+        )?;
+        metrics.visits_removed += visits_removed as u32;
+        let pages_removed = db.conn().execute(
+            &format!("DELETE FROM moz_places WHERE id IN ({})", vars),
+            chunk,
+        )?;
+        metrics.pages_removed += pages_removed as u32;
+        Ok(())
+    })?;
+    for (_, url, guid) in &pages {
+        db.note_event(PlacesEvent::PageRemoved {
+            guid: guid.clone(),
+            url: url.clone(),
+            is_removed_from_store: true,
+            is_partial_visit_removal: false,
+        });
+    }
+    Ok(pages.len())
+}
+
+/// Removes up to `limit` of the oldest visits, and updates the frecencies of
+/// their pages. Returns the number of visits removed.
+fn prune_oldest_visits(db: &PlacesDb, limit: u32, metrics: &mut PruneMetrics) -> Result<usize> {
+    let visits = db.query_rows_and_then_named_cached(
+        "SELECT id, place_id
+         FROM moz_historyvisits
+         ORDER BY visit_date ASC
+         LIMIT :limit",
+        &[(":limit", &limit)],
+        |row| -> rusqlite::Result<_> { Ok((row.get::<_, RowId>(0)?, row.get::<_, RowId>(1)?)) },
+    )?;
+    sql_support::each_chunk_mapped(
+        &visits,
+        |(visit_id, _)| visit_id,
+        |chunk, _| -> Result<()> {
+            let visits_removed = db.conn().execute(
+                &format!(
+                    "DELETE FROM moz_historyvisits WHERE id IN ({})",
+                    sql_support::repeat_sql_vars(chunk.len()),
+                ),
+                chunk,
+            )?;
+            metrics.visits_removed += visits_removed as u32;
+            Ok(())
+        },
+    )?;
+    let mut page_ids = visits
+        .iter()
+        .map(|(_, place_id)| *place_id)
+        .collect::<Vec<_>>();
+    page_ids.sort();
+    page_ids.dedup();
+    sql_support::each_chunk(&page_ids, |chunk, _| -> Result<()> {
+        let query = format!(
+            "SELECT id, url, guid,
+                (foreign_count != 0) AS has_foreign,
+                ((last_visit_date_local + last_visit_date_remote) != 0) as has_visits,
+                sync_status
+            FROM moz_places
+            WHERE id IN ({})",
+            sql_support::repeat_sql_vars(chunk.len()),
+        );
+        let mut stmt = db.conn().prepare(&query)?;
+        let page_results = stmt.query_and_then(chunk, PageToClean::from_row)?;
+        let pages: Vec<PageToClean> = page_results.collect::<Result<_>>()?;
+        cleanup_pages(db, &pages)
+    })?;
+    Ok(visits.len())
 }
 
 pub fn wipe_local(db: &PlacesDb) -> Result<()> {
@@ -369,10 +846,10 @@ pub fn wipe_local(db: &PlacesDb) -> Result<()> {
 }
 
 fn wipe_local_in_tx(db: &PlacesDb) -> Result<()> {
-    use crate::frecency::DEFAULT_FRECENCY_SETTINGS;
     db.execute_all(&[
         "DELETE FROM moz_places WHERE foreign_count == 0",
         "DELETE FROM moz_historyvisits",
+        "DELETE FROM moz_places_metadata",
         "DELETE FROM moz_places_tombstones",
         "DELETE FROM moz_inputhistory AS i WHERE NOT EXISTS(
              SELECT 1 FROM moz_places h
@@ -388,7 +865,7 @@ fn wipe_local_in_tx(db: &PlacesDb) -> Result<()> {
                                  ELSE {unvisited_bookmark_frec}
                             END),
                 sync_change_counter = 0"#,
-            unvisited_bookmark_frec = DEFAULT_FRECENCY_SETTINGS.unvisited_bookmark_bonus
+            unvisited_bookmark_frec = db.frecency_settings().unvisited_bookmark_bonus
         ),
     ])?;
 
@@ -400,6 +877,7 @@ fn wipe_local_in_tx(db: &PlacesDb) -> Result<()> {
         update_frecency(db, row_id, None)?;
     }
     delete_pending_temp_tables(db)?;
+    db.note_event(PlacesEvent::HistoryCleared);
     Ok(())
 }
 
@@ -425,6 +903,7 @@ pub fn delete_everything(db: &PlacesDb) -> Result<()> {
 
     // Remove Sync metadata, too.
     put_meta(db, LAST_SYNC_META_KEY, &0)?;
+    delete_meta(db, DOWNLOAD_OFFSET_META_KEY)?;
     delete_meta(db, GLOBAL_SYNCID_META_KEY)?;
     delete_meta(db, COLLECTION_SYNCID_META_KEY)?;
 
@@ -472,7 +951,7 @@ fn delete_place_visit_at_time_in_tx(db: &PlacesDb, url: &str, visit_date: Timest
 
     let to_clean = db.conn().query_row_and_then_named(
         "SELECT
-            id,
+            id, url, guid,
             (foreign_count != 0) AS has_foreign,
             ((last_visit_date_local + last_visit_date_remote) != 0) as has_visits,
             sync_status
@@ -525,6 +1004,14 @@ pub fn delete_visits_between_in_tx(db: &PlacesDb, start: Timestamp, end: Timesta
         },
     )?;
 
+    // History metadata isn't tied to visits, so we remove any which was
+    // updated in the range.
+    db.execute_named_cached(
+        "DELETE FROM moz_places_metadata
+         WHERE updated_at BETWEEN :start AND :end",
+        &[(":start", &start), (":end", &end)],
+    )?;
+
     // Insert tombstones for the deleted visits.
     if !visits.is_empty() {
         let sql = format!(
@@ -543,7 +1030,7 @@ pub fn delete_visits_between_in_tx(db: &PlacesDb, start: Timestamp, end: Timesta
         |(_, place_id, _)| place_id.0,
         |chunk, _| -> Result<()> {
             let query = format!(
-                "SELECT id, -- url, url_hash, guid
+                "SELECT id, url, guid,
                     (foreign_count != 0) AS has_foreign,
                     ((last_visit_date_local + last_visit_date_remote) != 0) as has_visits,
                     sync_status
@@ -565,6 +1052,8 @@ pub fn delete_visits_between_in_tx(db: &PlacesDb, start: Timestamp, end: Timesta
 #[derive(Debug)]
 struct PageToClean {
     id: RowId,
+    url: Url,
+    guid: SyncGuid,
     has_foreign: bool,
     has_visits: bool,
     sync_status: SyncStatus,
@@ -574,6 +1063,8 @@ impl PageToClean {
     pub fn from_row(row: &Row<'_>) -> Result<Self> {
         Ok(Self {
             id: row.get("id")?,
+            url: Url::parse(&row.get::<_, String>("url")?)?,
+            guid: row.get("guid")?,
             has_foreign: row.get("has_foreign")?,
             has_visits: row.get("has_visits")?,
             sync_status: row.get("sync_status")?,
@@ -587,6 +1078,14 @@ impl PageToClean {
 /// are no more foreign keys such as bookmarks) or updating
 /// their frecency.
 fn cleanup_pages(db: &PlacesDb, pages: &[PageToClean]) -> Result<()> {
+    for page in pages {
+        db.note_event(PlacesEvent::PageRemoved {
+            guid: page.guid.clone(),
+            url: page.url.clone(),
+            is_removed_from_store: !page.has_foreign && !page.has_visits,
+            is_partial_visit_removal: page.has_visits,
+        });
+    }
     // desktop does this frecency work using a function in a single sql
     // statement - we should see if we can do that too.
     let frec_ids = pages
@@ -817,16 +1316,25 @@ pub mod history_sync {
                 }
                 let transition = VisitTransition::from_primitive(visit.transition)
                     .expect("these should already be validated");
-                add_visit(db, page_info.row_id, None, timestamp, transition, false)?;
+                add_visit(
+                    db,
+                    page_info.row_id,
+                    None,
+                    timestamp,
+                    transition,
+                    false,
+                    false,
+                )?;
                 // Make sure that even if a history entry weirdly has the same visit
                 // twice, we don't insert it twice. (This avoids us needing to
                 // recompute visits_to_skip in each step of the iteration)
                 visits_to_skip.insert(timestamp);
             }
         }
-        // XXX - we really need a better story for frecency-boost than
-        // Option<bool> - None vs Some(false) is confusing. We should use an enum.
-        update_frecency(&db, page_info.row_id, None)?;
+        // Recalculating frecencies is slow, so we do it in batches after
+        // applying all incoming records, instead of holding the transaction
+        // open while we recalculate each one.
+        mark_frecency_stale(db, page_info.row_id)?;
 
         // and the place itself if necessary.
         let new_title = title.as_ref().unwrap_or(&page_info.title);
@@ -1091,6 +1599,7 @@ pub mod history_sync {
             NO_PARAMS,
         )?;
         put_meta(db, LAST_SYNC_META_KEY, &0)?;
+        delete_meta(db, DOWNLOAD_OFFSET_META_KEY)?;
         Ok(())
     }
 } // end of sync module.
@@ -1185,10 +1694,13 @@ pub fn get_visit_infos(
 ) -> Result<HistoryVisitInfos> {
     let allowed_types = exclude_types.complement();
     let infos = db.query_rows_and_then_named_cached(
-        "SELECT h.url, h.title, v.visit_date, v.visit_type, h.hidden
+        "SELECT h.url, h.title, v.visit_date, v.visit_type, h.hidden,
+                t.search_term, t.engine AS search_engine
          FROM moz_places h
          JOIN moz_historyvisits v
            ON h.id = v.place_id
+         LEFT JOIN moz_places_search_terms t
+           ON t.place_id = h.id
          WHERE v.visit_date BETWEEN :start AND :end
            AND ((1 << visit_type) & :allowed_types) != 0 AND
            NOT h.hidden
@@ -1230,10 +1742,13 @@ pub fn get_visit_page(
 ) -> Result<HistoryVisitInfos> {
     let allowed_types = exclude_types.complement();
     let infos = db.query_rows_and_then_named_cached(
-        "SELECT h.url, h.title, v.visit_date, v.visit_type, h.hidden
+        "SELECT h.url, h.title, v.visit_date, v.visit_type, h.hidden,
+                t.search_term, t.engine AS search_engine
          FROM moz_places h
          JOIN moz_historyvisits v
            ON h.id = v.place_id
+         LEFT JOIN moz_places_search_terms t
+           ON t.place_id = h.id
          WHERE ((1 << v.visit_type) & :allowed_types) != 0 AND
                NOT h.hidden
          ORDER BY v.visit_date DESC, v.id
@@ -1258,10 +1773,13 @@ pub fn get_visit_page_with_bound(
 ) -> Result<HistoryVisitInfosWithBound> {
     let allowed_types = exclude_types.complement();
     let infos = db.query_rows_and_then_named_cached(
-        "SELECT h.url, h.title, v.visit_date, v.visit_type, h.hidden
+        "SELECT h.url, h.title, v.visit_date, v.visit_type, h.hidden,
+                t.search_term, t.engine AS search_engine
          FROM moz_places h
          JOIN moz_historyvisits v
            ON h.id = v.place_id
+         LEFT JOIN moz_places_search_terms t
+           ON t.place_id = h.id
          WHERE ((1 << v.visit_type) & :allowed_types) != 0 AND
                NOT h.hidden
                AND v.visit_date <= :bound
@@ -1526,6 +2044,58 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_hidden_visits() -> Result<()> {
+        let _ = env_logger::try_init();
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite)?;
+
+        // A page whose first visit is hidden should be hidden...
+        let url = Url::parse("https://www.example.com/redirect").unwrap();
+        apply_observation(
+            &conn,
+            VisitObservation::new(url.clone())
+                .with_visit_type(VisitTransition::Link)
+                .with_is_redirect_source(true),
+        )?;
+        let pi = fetch_page_info(&conn, &url)?.expect("should have the page");
+        assert!(pi.page.hidden);
+
+        // ...and stay hidden after more hidden visits...
+        apply_observation(
+            &conn,
+            VisitObservation::new(url.clone())
+                .with_visit_type(VisitTransition::Link)
+                .with_is_redirect_source(true),
+        )?;
+        let pi = fetch_page_info(&conn, &url)?.expect("should have the page");
+        assert!(pi.page.hidden);
+
+        // ...until it's visited directly.
+        apply_observation(
+            &conn,
+            VisitObservation::new(url.clone()).with_visit_type(VisitTransition::Typed),
+        )?;
+        let pi = fetch_page_info(&conn, &url)?.expect("should have the page");
+        assert!(!pi.page.hidden);
+
+        // A later hidden visit shouldn't hide a visible page.
+        apply_observation(
+            &conn,
+            VisitObservation::new(url.clone()).with_visit_type(VisitTransition::Embed),
+        )?;
+        let pi = fetch_page_info(&conn, &url)?.expect("should have the page");
+        assert!(!pi.page.hidden);
+
+        let embedded = Url::parse("https://www.example.com/embed").unwrap();
+        apply_observation(
+            &conn,
+            VisitObservation::new(embedded.clone()).with_visit_type(VisitTransition::Embed),
+        )?;
+        let pi = fetch_page_info(&conn, &embedded)?.expect("should have the page");
+        assert!(pi.page.hidden);
+        Ok(())
+    }
+
     #[test]
     fn test_get_visited() -> Result<()> {
         let _ = env_logger::try_init();
@@ -2035,6 +2605,101 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_delete_visits_for_host() -> Result<()> {
+        use crate::storage::bookmarks::{
+            self, BookmarkPosition, BookmarkRootGuid, InsertableBookmark,
+        };
+
+        let db = PlacesDb::open_in_memory(ConnectionType::ReadWrite)?;
+        let hrefs = [
+            "https://example.com/a",
+            "http://example.com/b",
+            "https://example.com/bookmarked",
+            "https://www.example.com/c",
+            "https://sub.www.example.com/d",
+            "https://example.com:8080/e",
+            "https://notexample.com/f",
+            "https://example.com.example.org/g",
+        ];
+        for href in &hrefs {
+            let obs = VisitObservation::new(Url::parse(href)?)
+                .with_visit_type(VisitTransition::Link)
+                .with_at(Some(SystemTime::now().into()));
+            apply_observation(&db, obs)?;
+        }
+        db.execute_all(&[&format!(
+            "UPDATE moz_places SET sync_status = {}",
+            (SyncStatus::Normal as u8)
+        )])?;
+        bookmarks::insert_bookmark(
+            &db,
+            &InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Unfiled.into(),
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                url: Url::parse("https://example.com/bookmarked")?,
+                title: None,
+            }
+            .into(),
+        )?;
+
+        let pages_with_visits = |db: &PlacesDb| -> Result<Vec<String>> {
+            db.query_rows_and_then_named(
+                "SELECT url FROM moz_places
+                 WHERE last_visit_date_local != 0
+                 ORDER BY id",
+                &[],
+                |row| -> Result<_> { Ok(row.get::<_, String>(0)?) },
+            )
+        };
+
+        // Hosts should be matched case-insensitively, and without
+        // subdomains, unless we ask for them.
+        delete_visits_for_host(&db, "EXAMPLE.com", false)?;
+        assert_eq!(
+            pages_with_visits(&db)?,
+            &hrefs[3..],
+            "Should only delete pages for the exact host"
+        );
+        // The bookmarked page should be kept, and since it's syncing, we
+        // should write tombstones for its visits instead of the page.
+        let bookmarked = fetch_page_info(&db, &Url::parse(hrefs[2])?)?
+            .expect("Should keep bookmarked page")
+            .page;
+        assert_eq!(get_tombstone_count(&db), 2);
+        assert_eq!(
+            db.query_one::<i64>(&format!(
+                "SELECT COUNT(*) FROM moz_historyvisit_tombstones WHERE place_id = {}",
+                bookmarked.row_id
+            ))?,
+            1
+        );
+        // The "http://example.com" origin should be gone, but we still need
+        // the "https://example.com" origin for the bookmarked page.
+        let origins = db.query_rows_and_then_named(
+            "SELECT prefix || host FROM moz_origins
+             WHERE host = 'example.com'",
+            &[],
+            |row| -> Result<_> { Ok(row.get::<_, String>(0)?) },
+        )?;
+        assert_eq!(origins, vec!["https://example.com"]);
+
+        delete_visits_for_host(&db, "example.com", true)?;
+        assert_eq!(
+            pages_with_visits(&db)?,
+            &hrefs[5..],
+            "Should delete pages for subdomains, but not other hosts"
+        );
+
+        delete_visits_for_host(&db, "example.com:8080", false)?;
+        assert_eq!(pages_with_visits(&db)?, &hrefs[6..]);
+        assert_eq!(get_tombstone_count(&db), 5);
+        Ok(())
+    }
+
     #[test]
     fn test_tombstones() -> Result<()> {
         let _ = env_logger::try_init();
@@ -2431,6 +3096,268 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_update_stale_frecencies() {
+        use interrupt_support::NeverInterrupts;
+        let _ = env_logger::try_init();
+        let mut conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).unwrap();
+        let urls = (0..3)
+            .map(|i| Url::parse(&format!("http://example.com/{}", i)).unwrap())
+            .collect::<Vec<_>>();
+        for url in &urls {
+            get_observed_page(&mut conn, url.as_str()).unwrap();
+        }
+        let frecency = |conn: &PlacesDb, url: &Url| -> i32 {
+            fetch_page_info(conn, url).unwrap().unwrap().page.frecency
+        };
+        let expected = frecency(&conn, &urls[0]);
+        assert!(expected > 0);
+
+        conn.execute_all(&["UPDATE moz_places SET frecency = 0"])
+            .unwrap();
+        for url in &urls {
+            let page_id = fetch_page_info(&conn, url).unwrap().unwrap().page.row_id;
+            mark_frecency_stale(&conn, page_id).unwrap();
+        }
+
+        // An interrupted batch shouldn't update anything.
+        let scope = conn.begin_interrupt_scope();
+        conn.new_interrupt_handle().interrupt();
+        assert!(update_stale_frecencies(&conn, 2, &scope).is_err());
+        for url in &urls {
+            assert_eq!(frecency(&conn, url), 0);
+            assert!(frecency_stale_at(&conn, url).unwrap().is_some());
+        }
+
+        assert_eq!(
+            update_stale_frecencies(&conn, 2, &NeverInterrupts).unwrap(),
+            1
+        );
+        let updated = urls.iter().filter(|url| frecency(&conn, url) > 0).count();
+        assert_eq!(updated, 2);
+
+        assert_eq!(
+            update_stale_frecencies(&conn, 2, &NeverInterrupts).unwrap(),
+            0
+        );
+        for url in &urls {
+            assert_eq!(frecency(&conn, url), expected);
+            assert_eq!(frecency_stale_at(&conn, url).unwrap(), None);
+        }
+        // Nothing's stale, so this shouldn't do anything.
+        assert_eq!(
+            update_stale_frecencies(&conn, 2, &NeverInterrupts).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_decay_frecencies() {
+        let _ = env_logger::try_init();
+        let mut conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).unwrap();
+        let url = Url::parse("http://example.com/").unwrap();
+        let page_id = get_observed_page(&mut conn, url.as_str()).unwrap().row_id;
+        conn.execute_all(&["UPDATE moz_places SET frecency = 1000"])
+            .unwrap();
+        conn.execute_named(
+            "INSERT INTO moz_inputhistory(place_id, input, use_count)
+             VALUES(:page_id, 'ex', 1), (:page_id, 'example', 0.01)",
+            &[(":page_id", &page_id)],
+        )
+        .unwrap();
+
+        // The first call should only start counting.
+        assert_eq!(decay_frecencies(&conn).unwrap(), 0);
+        assert_eq!(
+            fetch_page_info(&conn, &url).unwrap().unwrap().page.frecency,
+            1000
+        );
+
+        // Pretend we last decayed two and a half days ago.
+        let last_decay =
+            Timestamp(Timestamp::now().0 - 2 * ONE_DAY_IN_MILLIS - ONE_DAY_IN_MILLIS / 2);
+        put_meta(&conn, LAST_FRECENCY_DECAY_META_KEY, &last_decay).unwrap();
+        assert_eq!(decay_frecencies(&conn).unwrap(), 2);
+        assert_eq!(
+            fetch_page_info(&conn, &url).unwrap().unwrap().page.frecency,
+            951
+        );
+        let inputs = conn
+            .query_rows_and_then_named(
+                "SELECT input, use_count FROM moz_inputhistory",
+                &[],
+                |row| -> rusqlite::Result<(String, f64)> { Ok((row.get(0)?, row.get(1)?)) },
+            )
+            .unwrap();
+        assert_eq!(inputs, vec![("ex".to_owned(), 0.975 * 0.975)]);
+        // The leftover half day should count toward the next decay.
+        assert_eq!(
+            get_meta::<Timestamp>(&conn, LAST_FRECENCY_DECAY_META_KEY).unwrap(),
+            Some(Timestamp(last_decay.0 + 2 * ONE_DAY_IN_MILLIS))
+        );
+
+        // Decaying again on the same day shouldn't do anything.
+        assert_eq!(decay_frecencies(&conn).unwrap(), 0);
+        assert_eq!(
+            fetch_page_info(&conn, &url).unwrap().unwrap().page.frecency,
+            951
+        );
+    }
+
+    #[test]
+    fn test_prune_destructively() {
+        use crate::storage::bookmarks::{
+            self, BookmarkPosition, BookmarkRootGuid, InsertableBookmark, InsertableItem,
+        };
+        let _ = env_logger::try_init();
+        let mut conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).unwrap();
+        let ts = Timestamp::now().0 - 5_000_000;
+        // Page `i` has `i + 1` visits, so later pages are more frecent.
+        for i in 0..20 {
+            for v in 0..=i {
+                get_custom_observed_page(
+                    &mut conn,
+                    &format!("http://www.example{}.com/", i),
+                    |obs| obs.with_at(Timestamp(ts + i * 10_000 + v * 100)),
+                )
+                .unwrap();
+            }
+        }
+        // Bookmark the least frecent page.
+        let bookmarked = Url::parse("http://www.example0.com/").unwrap();
+        bookmarks::insert_bookmark(
+            &conn,
+            &InsertableItem::Bookmark(InsertableBookmark {
+                parent_guid: BookmarkRootGuid::Unfiled.into(),
+                position: BookmarkPosition::Append,
+                date_added: None,
+                last_modified: None,
+                guid: None,
+                url: bookmarked.clone(),
+                title: None,
+            }),
+        )
+        .unwrap();
+        conn.execute_all(&[&format!(
+            "UPDATE moz_places set sync_status = {}",
+            (SyncStatus::Normal as u8)
+        )])
+        .unwrap();
+
+        // Our history is tiny, so the default limits shouldn't remove anything.
+        assert_eq!(prune_destructively(&conn).unwrap(), PruneMetrics::default());
+
+        // The least frecent pages should be removed first, skipping the
+        // bookmarked page.
+        let metrics = prune_destructively_with_limits(
+            &conn,
+            &PruneLimits {
+                max_db_size: u64::max_value(),
+                max_pages: 15,
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            metrics,
+            PruneMetrics {
+                pages_removed: 5,
+                visits_removed: 2 + 3 + 4 + 5 + 6,
+            }
+        );
+        let urls = conn
+            .query_rows_and_then_named(
+                "SELECT url FROM moz_places ORDER BY id",
+                &[],
+                |row| -> rusqlite::Result<String> { row.get(0) },
+            )
+            .unwrap();
+        let expected = std::iter::once(0)
+            .chain(6..20)
+            .map(|i| format!("http://www.example{}.com/", i))
+            .collect::<Vec<_>>();
+        assert_eq!(urls, expected);
+
+        // Pruning everything should keep the bookmarked page, but remove its
+        // visits.
+        let metrics = prune_destructively_with_limits(
+            &conn,
+            &PruneLimits {
+                max_db_size: 0,
+                max_pages: u32::max_value(),
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            metrics,
+            PruneMetrics {
+                pages_removed: 14,
+                visits_removed: (7..=20).sum::<u32>() + 1,
+            }
+        );
+        let page = fetch_page_info(&conn, &bookmarked)
+            .unwrap()
+            .expect("Bookmarked page should exist")
+            .page;
+        assert_eq!(page.visit_count_local, 0);
+        assert_eq!(page.last_visit_date_local, Timestamp(0));
+
+        let counts_sql = [
+            (1i64, "SELECT COUNT(*) FROM moz_places"),
+            (0, "SELECT COUNT(*) FROM moz_historyvisits"),
+            (1, "SELECT COUNT(*) FROM moz_origins"),
+            // Pruning is local-only, so we shouldn't write any tombstones.
+            (0, "SELECT COUNT(*) FROM moz_historyvisit_tombstones"),
+            (0, "SELECT COUNT(*) FROM moz_places_tombstones"),
+        ];
+        for (want, query) in &counts_sql {
+            assert_eq!(
+                *want,
+                conn.query_one::<i64>(query).unwrap(),
+                "Unexpected value for {}",
+                query
+            );
+        }
+    }
+
+    #[test]
+    fn test_prune_to_size() {
+        let _ = env_logger::try_init();
+        let conn = PlacesDb::open_in_memory(ConnectionType::ReadWrite).unwrap();
+        // Add enough pages that they, and not the schema, take up most of the
+        // database. Odd pages are more frecent, so the pages we prune first
+        // are spread over the whole table, and deleting them doesn't free any
+        // database pages until the transaction commits.
+        conn.execute_all(&[
+            &format!(
+                "WITH RECURSIVE n(i) AS (SELECT 0 UNION ALL SELECT i + 1 FROM n WHERE i < 19999)
+                 INSERT INTO moz_places(guid, url, url_hash, frecency)
+                 SELECT printf('page%08d', i), url, hash(url), 100 + 100 * (i % 2)
+                 FROM (SELECT i, 'http://www.example.com/' || i || '/{}' AS url FROM n)",
+                "x".repeat(200)
+            ),
+            "INSERT INTO moz_historyvisits(place_id, visit_date, visit_type, is_local)
+             SELECT id, 1000 * id, 1, 1 FROM moz_places WHERE url LIKE 'http://www.example.com/%'",
+        ])
+        .unwrap();
+        let max_db_size = used_db_size(&conn).unwrap() * 3 / 4;
+
+        let metrics = prune_destructively_with_limits(
+            &conn,
+            &PruneLimits {
+                max_db_size,
+                max_pages: u32::max_value(),
+            },
+        )
+        .unwrap();
+        assert!(metrics.pages_removed > 0, "Should remove some pages");
+        assert!(
+            metrics.pages_removed <= 10_000,
+            "Removed {} pages to free a quarter of the database",
+            metrics.pages_removed
+        );
+        assert!(used_db_size(&conn).unwrap() <= max_db_size);
+    }
+
     #[test]
     fn test_delete_everything() {
         use crate::storage::bookmarks::{
@@ -2845,4 +3772,133 @@ mod tests {
         assert_eq!(infos_with_bound.bound, now_i64 - 199_000);
         assert_eq!(infos_with_bound.offset, 1);
     }
+
+    #[test]
+    fn test_observer_events() -> Result<()> {
+        use crate::api::places_api::test::new_mem_api;
+        use crate::observer::test::RecordingObserver;
+        use std::sync::Arc;
+
+        let api = new_mem_api();
+        let conn = api.open_connection(ConnectionType::ReadWrite)?;
+        let observer = Arc::new(RecordingObserver::default());
+        let handle = api.add_observer(observer.clone());
+
+        let url = Url::parse("https://www.example.com/")?;
+        let now = Timestamp::now();
+        apply_observation(
+            &conn,
+            VisitObservation::new(url.clone())
+                .with_title("example".to_string())
+                .with_visit_type(VisitTransition::Link)
+                .with_at(now),
+        )?;
+        let guid = url_to_guid(&conn, &url)?.expect("should exist");
+        assert_eq!(
+            observer.take(),
+            vec![
+                PlacesEvent::TitleChanged {
+                    guid: guid.clone(),
+                    url: url.clone(),
+                    title: "example".into(),
+                },
+                PlacesEvent::VisitAdded {
+                    guid: guid.clone(),
+                    url: url.clone(),
+                    visit_date: now,
+                    visit_type: VisitTransition::Link,
+                    is_local: true,
+                },
+            ]
+        );
+
+        // Setting the same title again isn't a change.
+        apply_observation(
+            &conn,
+            VisitObservation::new(url.clone()).with_title("example".to_string()),
+        )?;
+        assert_eq!(observer.take(), vec![]);
+
+        delete_visits_for(&conn, &guid)?;
+        assert_eq!(
+            observer.take(),
+            vec![PlacesEvent::PageRemoved {
+                guid,
+                url,
+                is_removed_from_store: true,
+                is_partial_visit_removal: false,
+            }]
+        );
+
+        delete_everything(&conn)?;
+        assert_eq!(observer.take(), vec![PlacesEvent::HistoryCleared]);
+
+        // Changes which are rolled back aren't reported.
+        let tx = conn.begin_transaction()?;
+        apply_observation_direct(
+            &conn,
+            VisitObservation::new(Url::parse("https://www.example.com/2")?)
+                .with_visit_type(VisitTransition::Link),
+        )?;
+        tx.rollback()?;
+        assert_eq!(observer.take(), vec![]);
+
+        assert!(api.remove_observer(handle));
+        apply_observation(
+            &conn,
+            VisitObservation::new(Url::parse("https://www.example.com/3")?)
+                .with_visit_type(VisitTransition::Link),
+        )?;
+        assert_eq!(observer.take(), vec![]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_observer_partial_removal() -> Result<()> {
+        use crate::api::places_api::test::new_mem_api;
+        use crate::observer::test::RecordingObserver;
+        use std::sync::Arc;
+
+        let api = new_mem_api();
+        let conn = api.open_connection(ConnectionType::ReadWrite)?;
+        let observer = Arc::new(RecordingObserver::default());
+        api.add_observer(observer.clone());
+
+        let url = Url::parse("https://www.example.com/")?;
+        let early = Timestamp(Timestamp::now().0 - 10_000);
+        let late = Timestamp::now();
+        for at in &[early, late] {
+            apply_observation(
+                &conn,
+                VisitObservation::new(url.clone())
+                    .with_visit_type(VisitTransition::Link)
+                    .with_at(*at),
+            )?;
+        }
+        let guid = url_to_guid(&conn, &url)?.expect("should exist");
+        observer.take();
+
+        delete_place_visit_at_time(&conn, &url, early)?;
+        assert_eq!(
+            observer.take(),
+            vec![PlacesEvent::PageRemoved {
+                guid: guid.clone(),
+                url: url.clone(),
+                is_removed_from_store: false,
+                is_partial_visit_removal: true,
+            }]
+        );
+
+        delete_visits_between(&conn, early, late)?;
+        assert_eq!(
+            observer.take(),
+            vec![PlacesEvent::PageRemoved {
+                guid,
+                url,
+                is_removed_from_store: true,
+                is_partial_visit_removal: false,
+            }]
+        );
+        Ok(())
+    }
 }
@@ -0,0 +1,601 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! History metadata records how the user engaged with a page (how long they
+//! looked at it, and whether it played media), and how they got there (the
+//! page they came from, and the search term which led them to it). It's
+//! intended for "journeys"-style history UIs, which group recently viewed
+//! pages by what the user was searching for.
+//!
+//! Metadata is kept separately from visits: an entry is keyed by the page,
+//! its referrer and its search term, and is updated as the user keeps
+//! interacting with the page. Removing history for a page also removes its
+//! metadata.
+
+use super::{fetch_page_info, new_page_info, RowId};
+use crate::db::PlacesDb;
+use crate::error::Result;
+use crate::msg_types;
+use crate::types::{DocumentType, Timestamp};
+use rusqlite::Row;
+use serde_derive::*;
+use sql_support::ConnExt;
+use url::Url;
+
+/// An observation about the user's engagement with a page. Like
+/// `VisitObservation`, every field other than the URL is optional, and only
+/// the fields which are provided are recorded.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HistoryMetadataObservation {
+    /// Ideally this, and `referrer_url`, would be `url::Url`s, but we'd like
+    /// to report invalid URLs over the FFI as URL parse errors, not JSON
+    /// errors. See the same comment on `VisitObservation`.
+    pub url: String,
+
+    /// The page the user came from, if any. Typically this is a search
+    /// engine results page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub referrer_url: Option<String>,
+
+    /// The search term which led the user to this page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub search_term: Option<String>,
+
+    /// Additional time, in milliseconds, which the user spent viewing the
+    /// page. This is added to the existing total.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub view_time: Option<i64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub document_type: Option<DocumentType>,
+}
+
+impl HistoryMetadataObservation {
+    pub fn new(url: Url) -> Self {
+        Self {
+            url: url.into_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_referrer_url(mut self, v: impl Into<Option<Url>>) -> Self {
+        self.referrer_url = v.into().map(Url::into_string);
+        self
+    }
+
+    pub fn with_search_term(mut self, v: impl Into<Option<String>>) -> Self {
+        self.search_term = v.into();
+        self
+    }
+
+    pub fn with_view_time(mut self, v: impl Into<Option<i64>>) -> Self {
+        self.view_time = v.into();
+        self
+    }
+
+    pub fn with_document_type(mut self, v: impl Into<Option<DocumentType>>) -> Self {
+        self.document_type = v.into();
+        self
+    }
+}
+
+/// A history metadata entry, as returned by the query functions below.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryMetadata {
+    pub url: Url,
+    pub title: Option<String>,
+    pub created_at: Timestamp,
+    pub updated_at: Timestamp,
+    /// In milliseconds.
+    pub total_view_time: i64,
+    pub search_term: Option<String>,
+    pub document_type: DocumentType,
+    pub referrer_url: Option<Url>,
+}
+
+impl HistoryMetadata {
+    fn from_row(row: &Row<'_>) -> Result<Self> {
+        let referrer_url = match row.get::<_, Option<String>>("referrer_url")? {
+            Some(url) => Some(Url::parse(&url)?),
+            None => None,
+        };
+        Ok(Self {
+            url: Url::parse(&row.get::<_, String>("url")?)?,
+            title: row.get("title")?,
+            created_at: row.get("created_at")?,
+            updated_at: row.get("updated_at")?,
+            total_view_time: row.get("total_view_time")?,
+            search_term: row.get("search_term")?,
+            document_type: row.get("document_type")?,
+            referrer_url,
+        })
+    }
+}
+
+/// The most recently viewed pages for a search term.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchTermGroup {
+    pub search_term: String,
+    /// Most recently updated first.
+    pub metadata: Vec<HistoryMetadata>,
+}
+
+const COMMON_METADATA_SELECT: &str = "
+    SELECT h.url, NULLIF(h.title, '') AS title,
+           m.created_at, m.updated_at, m.total_view_time, m.document_type,
+           q.term AS search_term, r.url AS referrer_url
+    FROM moz_places_metadata m
+    JOIN moz_places h ON h.id = m.place_id
+    LEFT JOIN moz_places r ON r.id = m.referrer_place_id
+    LEFT JOIN moz_places_metadata_search_queries q ON q.id = m.search_query_id";
+
+/// Search terms are compared case-insensitively, and without surrounding
+/// whitespace, so that "Cats" and "cats " are grouped together.
+fn normalize_search_term(term: &str) -> Option<String> {
+    let term = term.trim();
+    if term.is_empty() {
+        None
+    } else {
+        Some(term.to_lowercase())
+    }
+}
+
+/// Records a history metadata observation, creating a new entry for the
+/// page, referrer and search term if one doesn't already exist.
+pub fn note_observation(db: &PlacesDb, observation: HistoryMetadataObservation) -> Result<()> {
+    let tx = db.begin_transaction()?;
+    let result = note_observation_in_tx(db, observation);
+    super::delete_pending_temp_tables(db)?;
+    match result {
+        Ok(_) => tx.commit()?,
+        Err(_) => tx.rollback()?,
+    }
+    result
+}
+
+fn note_observation_in_tx(db: &PlacesDb, observation: HistoryMetadataObservation) -> Result<()> {
+    let url = Url::parse(&observation.url)?;
+    // Like history, we silently ignore URLs that are too long.
+    if url.as_str().len() > super::URL_LENGTH_MAX {
+        return Ok(());
+    }
+    let place_id = get_or_insert_place(db, &url)?;
+    let referrer_place_id = match observation.referrer_url {
+        Some(referrer) => {
+            let referrer = Url::parse(&referrer)?;
+            if referrer.as_str().len() > super::URL_LENGTH_MAX {
+                None
+            } else {
+                Some(get_or_insert_place(db, &referrer)?)
+            }
+        }
+        None => None,
+    };
+    let search_query_id = match observation
+        .search_term
+        .as_deref()
+        .and_then(normalize_search_term)
+    {
+        Some(term) => {
+            db.execute_named_cached(
+                "INSERT OR IGNORE INTO moz_places_metadata_search_queries(term)
+                 VALUES(:term)",
+                &[(":term", &term)],
+            )?;
+            Some(db.query_row_and_then_named(
+                "SELECT id FROM moz_places_metadata_search_queries WHERE term = :term",
+                &[(":term", &term)],
+                |row| row.get::<_, RowId>(0),
+                true,
+            )?)
+        }
+        None => None,
+    };
+
+    let existing_id = db.try_query_row(
+        "SELECT id FROM moz_places_metadata
+         WHERE place_id = :place_id AND
+               referrer_place_id IS :referrer_place_id AND
+               search_query_id IS :search_query_id",
+        &[
+            (":place_id", &place_id),
+            (":referrer_place_id", &referrer_place_id),
+            (":search_query_id", &search_query_id),
+        ],
+        |row| row.get::<_, RowId>(0),
+        true,
+    )?;
+    let now = Timestamp::now();
+    let view_time = observation.view_time.unwrap_or(0).max(0);
+    match existing_id {
+        Some(id) => {
+            db.execute_named_cached(
+                "UPDATE moz_places_metadata SET
+                     updated_at = :now,
+                     total_view_time = total_view_time + :view_time,
+                     document_type = IFNULL(:document_type, document_type)
+                 WHERE id = :id",
+                &[
+                    (":now", &now),
+                    (":view_time", &view_time),
+                    (":document_type", &observation.document_type),
+                    (":id", &id),
+                ],
+            )?;
+        }
+        None => {
+            db.execute_named_cached(
+                "INSERT INTO moz_places_metadata
+                     (place_id, referrer_place_id, search_query_id, created_at,
+                      updated_at, total_view_time, document_type)
+                 VALUES (:place_id, :referrer_place_id, :search_query_id, :now,
+                         :now, :view_time, :document_type)",
+                &[
+                    (":place_id", &place_id),
+                    (":referrer_place_id", &referrer_place_id),
+                    (":search_query_id", &search_query_id),
+                    (":now", &now),
+                    (":view_time", &view_time),
+                    (
+                        ":document_type",
+                        &observation.document_type.unwrap_or_default(),
+                    ),
+                ],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn get_or_insert_place(db: &PlacesDb, url: &Url) -> Result<RowId> {
+    Ok(match fetch_page_info(db, url)? {
+        Some(info) => info.page.row_id,
+        None => new_page_info(db, url, None)?.row_id,
+    })
+}
+
+/// Returns the most recently updated metadata entry for a page.
+pub fn get_latest_for_url(db: &PlacesDb, url: &Url) -> Result<Option<HistoryMetadata>> {
+    let sql = format!(
+        "{common}
+         WHERE h.url_hash = hash(:url) AND h.url = :url
+         ORDER BY m.updated_at DESC
+         LIMIT 1",
+        common = COMMON_METADATA_SELECT
+    );
+    db.try_query_row(
+        &sql,
+        &[(":url", &url.as_str())],
+        HistoryMetadata::from_row,
+        true,
+    )
+}
+
+/// Returns all metadata entries updated between `start` and `end`,
+/// inclusive, most recently updated first.
+pub fn get_between(
+    db: &PlacesDb,
+    start: Timestamp,
+    end: Timestamp,
+) -> Result<Vec<HistoryMetadata>> {
+    let sql = format!(
+        "{common}
+         WHERE m.updated_at BETWEEN :start AND :end
+         ORDER BY m.updated_at DESC",
+        common = COMMON_METADATA_SELECT
+    );
+    db.query_rows_and_then_named_cached(
+        &sql,
+        &[(":start", &start), (":end", &end)],
+        HistoryMetadata::from_row,
+    )
+}
+
+/// Returns all metadata entries updated since `start`, most recently
+/// updated first.
+pub fn get_since(db: &PlacesDb, start: Timestamp) -> Result<Vec<HistoryMetadata>> {
+    get_between(db, start, Timestamp(i64::max_value() as u64))
+}
+
+/// Returns the pages viewed since `start`, grouped by the search term which
+/// led to them. Pages which weren't reached via a search aren't included.
+/// The groups are ordered by the most recently updated page in each, and at
+/// most `limit` groups are returned.
+pub fn get_grouped_by_search_term(
+    db: &PlacesDb,
+    start: Timestamp,
+    limit: u32,
+) -> Result<Vec<SearchTermGroup>> {
+    let sql = format!(
+        "{common}
+         WHERE m.updated_at >= :start AND
+               m.search_query_id IN (
+                   SELECT search_query_id FROM moz_places_metadata
+                   WHERE updated_at >= :start AND
+                         search_query_id NOT NULL
+                   GROUP BY search_query_id
+                   ORDER BY MAX(updated_at) DESC
+                   LIMIT :limit
+               )
+         ORDER BY m.updated_at DESC",
+        common = COMMON_METADATA_SELECT
+    );
+    let entries = db.query_rows_and_then_named_cached(
+        &sql,
+        &[(":start", &start), (":limit", &limit)],
+        HistoryMetadata::from_row,
+    )?;
+    // Since the entries are ordered by when they were updated, the groups
+    // end up ordered by their most recent entry.
+    let mut groups: Vec<SearchTermGroup> = Vec::new();
+    for entry in entries {
+        let term = entry
+            .search_term
+            .clone()
+            .expect("Should only select entries with search terms");
+        match groups.iter_mut().find(|g| g.search_term == term) {
+            Some(group) => group.metadata.push(entry),
+            None => groups.push(SearchTermGroup {
+                search_term: term,
+                metadata: vec![entry],
+            }),
+        }
+    }
+    Ok(groups)
+}
+
+/// Removes all metadata entries which haven't been updated since
+/// `older_than`.
+pub fn delete_older_than(db: &PlacesDb, older_than: Timestamp) -> Result<()> {
+    db.execute_named_cached(
+        "DELETE FROM moz_places_metadata WHERE updated_at < :older_than",
+        &[(":older_than", &older_than)],
+    )?;
+    Ok(())
+}
+
+impl From<HistoryMetadata> for msg_types::HistoryMetadata {
+    fn from(m: HistoryMetadata) -> Self {
+        Self {
+            url: m.url.into_string(),
+            title: m.title,
+            created_at: m.created_at.as_millis() as i64,
+            updated_at: m.updated_at.as_millis() as i64,
+            total_view_time: m.total_view_time,
+            search_term: m.search_term,
+            document_type: m.document_type as i32,
+            referrer_url: m.referrer_url.map(Url::into_string),
+        }
+    }
+}
+
+impl From<Vec<HistoryMetadata>> for msg_types::HistoryMetadataList {
+    fn from(entries: Vec<HistoryMetadata>) -> Self {
+        Self {
+            metadata: entries.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<Vec<SearchTermGroup>> for msg_types::HistoryMetadataSearchTermGroups {
+    fn from(groups: Vec<SearchTermGroup>) -> Self {
+        Self {
+            groups: groups
+                .into_iter()
+                .map(|g| msg_types::HistoryMetadataSearchTermGroup {
+                    search_term: g.search_term,
+                    metadata: g.metadata.into_iter().map(Into::into).collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+    use crate::storage::history::{delete_everything, delete_visits_for, url_to_guid};
+    use pretty_assertions::assert_eq;
+
+    fn observe(db: &PlacesDb, url: &str, search_term: Option<&str>, view_time: i64) {
+        note_observation(
+            db,
+            HistoryMetadataObservation::new(Url::parse(url).unwrap())
+                .with_referrer_url(
+                    search_term.map(|_| Url::parse("https://www.example.com/search").unwrap()),
+                )
+                .with_search_term(search_term.map(String::from))
+                .with_view_time(view_time),
+        )
+        .expect("should note observation");
+    }
+
+    #[test]
+    fn test_note_observation() -> Result<()> {
+        let conn = new_mem_connection();
+        let url = Url::parse("https://www.mozilla.org/")?;
+        assert_eq!(get_latest_for_url(&conn, &url)?, None);
+
+        note_observation(
+            &conn,
+            HistoryMetadataObservation::new(url.clone())
+                .with_search_term("  Firefox ".to_string())
+                .with_document_type(DocumentType::Media),
+        )?;
+        let meta = get_latest_for_url(&conn, &url)?.expect("should exist");
+        assert_eq!(meta.url, url);
+        assert_eq!(meta.total_view_time, 0);
+        assert_eq!(meta.search_term, Some("firefox".to_string()));
+        assert_eq!(meta.document_type, DocumentType::Media);
+        assert_eq!(meta.referrer_url, None);
+        assert_eq!(meta.created_at, meta.updated_at);
+
+        // Further observations for the same search term update the same
+        // entry, and keep the document type unless it's given.
+        note_observation(
+            &conn,
+            HistoryMetadataObservation::new(url.clone())
+                .with_search_term("firefox".to_string())
+                .with_view_time(2000),
+        )?;
+        note_observation(
+            &conn,
+            HistoryMetadataObservation::new(url.clone())
+                .with_search_term("FIREFOX".to_string())
+                .with_view_time(500),
+        )?;
+        let meta = get_latest_for_url(&conn, &url)?.expect("should exist");
+        assert_eq!(meta.total_view_time, 2500);
+        assert_eq!(meta.document_type, DocumentType::Media);
+        assert_eq!(
+            conn.query_one::<i64>("SELECT COUNT(*) FROM moz_places_metadata")?,
+            1
+        );
+
+        // A different referrer makes a new entry.
+        let referrer = Url::parse("https://www.example.com/")?;
+        note_observation(
+            &conn,
+            HistoryMetadataObservation::new(url.clone())
+                .with_referrer_url(referrer.clone())
+                .with_view_time(100),
+        )?;
+        assert_eq!(
+            conn.query_one::<i64>("SELECT COUNT(*) FROM moz_places_metadata")?,
+            2
+        );
+        let entries = get_since(&conn, Timestamp(0))?;
+        assert_eq!(entries.len(), 2);
+        assert!(entries
+            .iter()
+            .any(|m| m.referrer_url.as_ref() == Some(&referrer) && m.total_view_time == 100));
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_url() {
+        let conn = new_mem_connection();
+        let observation = HistoryMetadataObservation {
+            url: "not a url".into(),
+            ..Default::default()
+        };
+        note_observation(&conn, observation).expect_err("should fail to parse");
+    }
+
+    #[test]
+    fn test_grouped_by_search_term() -> Result<()> {
+        let conn = new_mem_connection();
+        observe(&conn, "https://www.example.com/cats/1", Some("cats"), 10);
+        observe(&conn, "https://www.example.com/dogs/1", Some("dogs"), 10);
+        observe(&conn, "https://www.example.com/cats/2", Some("Cats"), 20);
+        observe(&conn, "https://www.example.com/no-search", None, 10);
+        observe(&conn, "https://www.example.com/birds", Some("birds"), 10);
+
+        // Make the timestamps deterministic, since observations in quick
+        // succession may end up with the same time.
+        for (i, url) in [
+            "https://www.example.com/cats/1",
+            "https://www.example.com/dogs/1",
+            "https://www.example.com/cats/2",
+            "https://www.example.com/no-search",
+            "https://www.example.com/birds",
+        ]
+        .iter()
+        .enumerate()
+        {
+            conn.execute_named(
+                "UPDATE moz_places_metadata SET updated_at = :time
+                 WHERE place_id = (SELECT id FROM moz_places WHERE url = :url)",
+                &[(":time", &(1000 + i as i64)), (":url", url)],
+            )?;
+        }
+
+        let groups = get_grouped_by_search_term(&conn, Timestamp(0), 10)?;
+        let summary: Vec<(String, Vec<String>)> = groups
+            .into_iter()
+            .map(|g| {
+                (
+                    g.search_term,
+                    g.metadata
+                        .into_iter()
+                        .map(|m| m.url.into_string())
+                        .collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    "birds".to_string(),
+                    vec!["https://www.example.com/birds".to_string()]
+                ),
+                (
+                    "cats".to_string(),
+                    vec![
+                        "https://www.example.com/cats/2".to_string(),
+                        "https://www.example.com/cats/1".to_string()
+                    ]
+                ),
+                (
+                    "dogs".to_string(),
+                    vec!["https://www.example.com/dogs/1".to_string()]
+                ),
+            ]
+        );
+
+        let groups = get_grouped_by_search_term(&conn, Timestamp(1001), 1)?;
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].search_term, "birds");
+
+        // Entries older than `start` are excluded, even if their group isn't.
+        let groups = get_grouped_by_search_term(&conn, Timestamp(1001), 10)?;
+        assert_eq!(groups[1].search_term, "cats");
+        assert_eq!(groups[1].metadata.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete() -> Result<()> {
+        let conn = new_mem_connection();
+        observe(&conn, "https://www.example.com/1", Some("one"), 10);
+        observe(&conn, "https://www.example.com/2", Some("two"), 10);
+        observe(&conn, "https://www.example.com/3", Some("three"), 10);
+
+        // Removing history for a page removes its metadata, and search
+        // terms which are no longer used.
+        let url = Url::parse("https://www.example.com/1")?;
+        let guid = url_to_guid(&conn, &url)?.expect("should exist");
+        delete_visits_for(&conn, &guid)?;
+        assert_eq!(get_latest_for_url(&conn, &url)?, None);
+        assert_eq!(
+            conn.query_one::<i64>("SELECT COUNT(*) FROM moz_places_metadata_search_queries")?,
+            2
+        );
+
+        conn.execute_named(
+            "UPDATE moz_places_metadata SET updated_at = 1
+             WHERE place_id = (SELECT id FROM moz_places
+                               WHERE url = 'https://www.example.com/2')",
+            &[],
+        )?;
+        delete_older_than(&conn, Timestamp(2))?;
+        let remaining = get_since(&conn, Timestamp(0))?;
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].search_term, Some("three".to_string()));
+
+        delete_everything(&conn)?;
+        assert_eq!(get_since(&conn, Timestamp(0))?, vec![]);
+        assert_eq!(
+            conn.query_one::<i64>("SELECT COUNT(*) FROM moz_places_metadata_search_queries")?,
+            0
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,523 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A structured view of history, for history screens. Visits are grouped into
+//! days, and each day's visits are collapsed into one entry per site, with the
+//! number of visits and the most recently visited page.
+//!
+//! Sites are returned most recent first, a page at a time. Each page comes
+//! with a cursor for fetching the next one. Unlike an offset, the cursor stays
+//! valid as new visits are added: visits made after the first page was
+//! fetched are left out of later pages, so sites don't move between pages.
+
+use crate::db::PlacesDb;
+use crate::error::Result;
+use crate::match_impl::{MatchBehavior, SearchBehavior};
+use crate::msg_types;
+use crate::types::{Timestamp, VisitTransitionSet};
+use rusqlite::Row;
+use serde_derive::*;
+use sql_support::ConnExt;
+use url::Url;
+
+const MS_PER_DAY: i64 = 24 * 60 * 60 * 1000;
+
+/// The number of days of visits to group in each sections query. A page can
+/// take several queries to fill, but each query only has to group a few
+/// days, instead of all history older than the cursor.
+const DAYS_PER_QUERY: i64 = 7;
+
+/// Options for `get_history_sections`.
+#[derive(Debug, Clone)]
+pub struct HistoryQuery {
+    /// If set, only visits to pages whose title or URL matches every word in
+    /// the search string are included.
+    pub search: Option<String>,
+    pub exclude_types: VisitTransitionSet,
+    /// The local time zone's offset from UTC, in minutes, used to find where
+    /// each day starts. This must be the same for every page.
+    pub utc_offset_minutes: i32,
+    /// The maximum number of sites to return, across all sections.
+    pub limit: u32,
+    /// The cursor returned with the previous page, or `None` to fetch the
+    /// first page.
+    pub cursor: Option<HistoryCursor>,
+}
+
+impl HistoryQuery {
+    pub fn new(limit: u32) -> Self {
+        Self {
+            search: None,
+            exclude_types: VisitTransitionSet::empty(),
+            utc_offset_minutes: 0,
+            limit,
+            cursor: None,
+        }
+    }
+}
+
+/// A site's visits on one day.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistorySite {
+    /// The site's host. This is empty for URLs without a host, like `file:`
+    /// URLs.
+    pub host: String,
+    pub visit_count: u32,
+    /// The URL and title of the most recently visited page on the site.
+    pub url: Url,
+    pub title: Option<String>,
+    pub latest_visit: Timestamp,
+}
+
+/// The sites visited on one day, most recently visited first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistorySection {
+    /// The start of the day, in the query's time zone.
+    pub day: Timestamp,
+    pub sites: Vec<HistorySite>,
+}
+
+/// A page of history sections. The first and last sections may only have
+/// some of that day's sites; the rest are in the neighbouring pages.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistorySections {
+    pub sections: Vec<HistorySection>,
+    /// The cursor for the next page, or `None` if this is the last page.
+    pub cursor: Option<HistoryCursor>,
+}
+
+/// Where to resume listing history sections. This is opaque to callers, but
+/// can be serialized to pass it over the FFI.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryCursor {
+    /// Visits after this time are ignored.
+    bound: Timestamp,
+    /// The day, site and latest visit of the last site on the previous page.
+    day: i64,
+    host: String,
+    latest_visit: Timestamp,
+}
+
+/// One row from the sections query.
+struct SiteRow {
+    day: i64,
+    site: HistorySite,
+}
+
+impl SiteRow {
+    fn from_row(row: &Row<'_>) -> Result<Self> {
+        let url: String = row.get("url")?;
+        Ok(Self {
+            day: row.get("day")?,
+            site: HistorySite {
+                host: row.get("host")?,
+                visit_count: row.get("visit_count")?,
+                url: Url::parse(&url)?,
+                title: row.get("title")?,
+                latest_visit: row.get("latest_visit")?,
+            },
+        })
+    }
+}
+
+/// Returns a page of visited sites, grouped into days.
+pub fn get_history_sections(db: &PlacesDb, query: &HistoryQuery) -> Result<HistorySections> {
+    let bound = match &query.cursor {
+        Some(cursor) => cursor.bound,
+        None => db.query_row_and_then_named(
+            "SELECT IFNULL(MAX(visit_date), 0) FROM moz_historyvisits",
+            &[],
+            |row| row.get(0),
+            true,
+        )?,
+    };
+    let offset = i64::from(query.utc_offset_minutes) * 60 * 1000;
+    // Sites on days after the cursor's day were on earlier pages, so we skip
+    // their visits in the `WHERE` clause, which can use the visit date index.
+    // The cursor's own day is filtered by site in the `HAVING` clause.
+    let mut end = match &query.cursor {
+        Some(cursor) => ((cursor.day + 1) * MS_PER_DAY - offset).min(bound.0 as i64 + 1),
+        None => bound.0 as i64 + 1,
+    };
+    // Fetch an extra row to find out if there's another page.
+    let wanted = query.limit as usize + 1;
+    let mut rows: Vec<SiteRow> = Vec::new();
+    while rows.len() < wanted {
+        // Skip over days without any visits, so that gaps in history don't
+        // take a query for every `DAYS_PER_QUERY` days.
+        let latest: Option<i64> = db.query_row_and_then_named(
+            "SELECT MAX(visit_date) FROM moz_historyvisits WHERE visit_date < :end",
+            &[(":end", &end)],
+            |row| row.get(0),
+            true,
+        )?;
+        let latest = match latest {
+            Some(latest) => latest,
+            None => break,
+        };
+        // Start the window at the beginning of a day, so that each day's
+        // visits are grouped by the same query.
+        let start = ((latest + offset) / MS_PER_DAY - (DAYS_PER_QUERY - 1)) * MS_PER_DAY - offset;
+        rows.extend(get_site_rows(
+            db,
+            query,
+            offset,
+            start,
+            end,
+            wanted - rows.len(),
+        )?);
+        end = start;
+    }
+
+    let cursor = if rows.len() > query.limit as usize {
+        rows.truncate(query.limit as usize);
+        rows.last().map(|last| HistoryCursor {
+            bound,
+            day: last.day,
+            host: last.site.host.clone(),
+            latest_visit: last.site.latest_visit,
+        })
+    } else {
+        None
+    };
+    let mut sections: Vec<HistorySection> = Vec::new();
+    let mut current_day = None;
+    for row in rows {
+        if current_day != Some(row.day) {
+            current_day = Some(row.day);
+            sections.push(HistorySection {
+                day: Timestamp((row.day * MS_PER_DAY - offset).max(0) as u64),
+                sites: Vec::new(),
+            });
+        }
+        if let Some(section) = sections.last_mut() {
+            section.sites.push(row.site);
+        }
+    }
+    Ok(HistorySections { sections, cursor })
+}
+
+/// Groups the visits between `start` and `end` into sites, and returns up to
+/// `limit` of them, most recent first.
+fn get_site_rows(
+    db: &PlacesDb,
+    query: &HistoryQuery,
+    offset: i64,
+    start: i64,
+    end: i64,
+    limit: usize,
+) -> Result<Vec<SiteRow>> {
+    // SQLite fills in `url` and `title` from the row with the `MAX()`
+    // `visit_date` in each group, which is the site's most recent page.
+    db.query_rows_and_then_named_cached(
+        "WITH visits AS (
+           SELECT v.visit_date, h.url, h.title, IFNULL(o.host, '') AS host,
+                  (v.visit_date + :offset) / :ms_per_day AS day
+           FROM moz_historyvisits v
+           JOIN moz_places h ON h.id = v.place_id
+           LEFT JOIN moz_origins o ON o.id = h.origin_id
+           WHERE v.visit_date >= :start AND v.visit_date < :end
+             AND ((1 << v.visit_type) & :allowed_types) != 0
+             AND NOT h.hidden
+             AND (:search IS NULL OR
+                  AUTOCOMPLETE_MATCH(:search, h.url, h.title, NULL, 1, 0, 0, NULL,
+                                     :match_behavior, :search_behavior))
+         )
+         SELECT day, host, COUNT(*) AS visit_count,
+                MAX(visit_date) AS latest_visit, url, title
+         FROM visits
+         GROUP BY day, host
+         HAVING :cursor_day IS NULL OR
+                day < :cursor_day OR
+                (day = :cursor_day AND
+                 (latest_visit < :cursor_latest_visit OR
+                  (latest_visit = :cursor_latest_visit AND host > :cursor_host)))
+         ORDER BY day DESC, latest_visit DESC, host
+         LIMIT :limit",
+        rusqlite::named_params! {
+            ":offset": offset,
+            ":ms_per_day": MS_PER_DAY,
+            ":start": start,
+            ":end": end,
+            ":allowed_types": query.exclude_types.complement(),
+            ":search": query.search,
+            ":match_behavior": MatchBehavior::Anywhere,
+            ":search_behavior": SearchBehavior::HISTORY,
+            ":cursor_day": query.cursor.as_ref().map(|c| c.day),
+            ":cursor_latest_visit": query.cursor.as_ref().map(|c| c.latest_visit),
+            ":cursor_host": query.cursor.as_ref().map(|c| c.host.as_str()),
+            ":limit": limit as i64,
+        },
+        SiteRow::from_row,
+    )
+}
+
+impl From<HistorySite> for msg_types::HistorySite {
+    fn from(site: HistorySite) -> Self {
+        Self {
+            host: site.host,
+            visit_count: site.visit_count,
+            url: site.url.into_string(),
+            title: site.title,
+            latest_visit: site.latest_visit.as_millis() as i64,
+        }
+    }
+}
+
+impl From<HistorySection> for msg_types::HistorySection {
+    fn from(section: HistorySection) -> Self {
+        Self {
+            day: section.day.as_millis() as i64,
+            sites: section.sites.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<HistorySections> for msg_types::HistorySections {
+    fn from(page: HistorySections) -> Self {
+        Self {
+            sections: page.sections.into_iter().map(Into::into).collect(),
+            // Serializing the cursor can't fail, since it only has strings
+            // and numbers.
+            cursor: page
+                .cursor
+                .map(|cursor| serde_json::to_string(&cursor).unwrap()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+    use crate::observation::VisitObservation;
+    use crate::storage::history::apply_observation;
+    use crate::types::VisitTransition;
+    use pretty_assertions::assert_eq;
+
+    const DAY: u64 = MS_PER_DAY as u64;
+    const HOUR: u64 = 60 * 60 * 1000;
+
+    fn visit(conn: &PlacesDb, url: &str, title: &str, at: u64) {
+        apply_observation(
+            conn,
+            VisitObservation::new(Url::parse(url).unwrap())
+                .with_title(Some(title.to_string()))
+                .with_visit_type(VisitTransition::Link)
+                .with_at(Some(Timestamp(at))),
+        )
+        .expect("should apply observation");
+    }
+
+    /// A day, and the host, visit count and URL of each site on that day.
+    type Summary<'a> = (u64, Vec<(&'a str, u32, &'a str)>);
+
+    fn summarize(page: &HistorySections) -> Vec<Summary<'_>> {
+        page.sections
+            .iter()
+            .map(|section| {
+                let sites = section
+                    .sites
+                    .iter()
+                    .map(|site| (site.host.as_str(), site.visit_count, site.url.as_str()))
+                    .collect();
+                (section.day.0, sites)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sections() -> Result<()> {
+        let conn = new_mem_connection();
+        let start = 1000 * DAY;
+        visit(&conn, "https://a.example.com/1", "A 1", start + HOUR);
+        visit(&conn, "https://a.example.com/2", "A 2", start + 2 * HOUR);
+        visit(&conn, "https://b.example.com/", "B", start + 3 * HOUR);
+        visit(&conn, "https://a.example.com/1", "A 1", start + DAY + HOUR);
+        visit(&conn, "https://c.example.com/", "C", start + DAY + 2 * HOUR);
+
+        let page = get_history_sections(&conn, &HistoryQuery::new(10))?;
+        assert_eq!(
+            summarize(&page),
+            vec![
+                (
+                    start + DAY,
+                    vec![
+                        ("c.example.com", 1, "https://c.example.com/"),
+                        ("a.example.com", 1, "https://a.example.com/1"),
+                    ]
+                ),
+                (
+                    start,
+                    vec![
+                        ("b.example.com", 1, "https://b.example.com/"),
+                        ("a.example.com", 2, "https://a.example.com/2"),
+                    ]
+                ),
+            ]
+        );
+        assert_eq!(page.cursor, None);
+        assert_eq!(page.sections[1].sites[1].title.as_deref(), Some("A 2"));
+
+        // Shifting the time zone moves the visit at 1am back a day.
+        let mut query = HistoryQuery::new(10);
+        query.utc_offset_minutes = -90;
+        let page = get_history_sections(&conn, &query)?;
+        assert_eq!(
+            summarize(&page),
+            vec![
+                (
+                    start + DAY + 90 * 60 * 1000,
+                    vec![("c.example.com", 1, "https://c.example.com/")]
+                ),
+                (
+                    start + 90 * 60 * 1000,
+                    vec![
+                        ("a.example.com", 2, "https://a.example.com/1"),
+                        ("b.example.com", 1, "https://b.example.com/"),
+                    ]
+                ),
+                (
+                    start - DAY + 90 * 60 * 1000,
+                    vec![("a.example.com", 1, "https://a.example.com/1")]
+                ),
+            ]
+        );
+
+        // Searching matches titles and URLs.
+        let mut query = HistoryQuery::new(10);
+        query.search = Some("a 2".into());
+        let page = get_history_sections(&conn, &query)?;
+        assert_eq!(
+            summarize(&page),
+            vec![(start, vec![("a.example.com", 1, "https://a.example.com/2")])]
+        );
+        query.search = Some("c.example".into());
+        let page = get_history_sections(&conn, &query)?;
+        assert_eq!(
+            summarize(&page),
+            vec![(
+                start + DAY,
+                vec![("c.example.com", 1, "https://c.example.com/")]
+            )]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sections_paging() -> Result<()> {
+        let conn = new_mem_connection();
+        let start = 1000 * DAY;
+        visit(&conn, "https://a.example.com/", "A", start + HOUR);
+        visit(&conn, "https://b.example.com/", "B", start + HOUR);
+        visit(&conn, "https://c.example.com/", "C", start + 2 * HOUR);
+        visit(&conn, "https://d.example.com/", "D", start + DAY);
+
+        let mut query = HistoryQuery::new(2);
+        let page = get_history_sections(&conn, &query)?;
+        assert_eq!(
+            summarize(&page),
+            vec![
+                (
+                    start + DAY,
+                    vec![("d.example.com", 1, "https://d.example.com/")]
+                ),
+                (start, vec![("c.example.com", 1, "https://c.example.com/")]),
+            ]
+        );
+        // Cursors survive a round trip through JSON, which is how they're
+        // passed over the FFI.
+        let cursor = page.cursor.expect("should have a cursor");
+        let serialized = serde_json::to_string(&cursor)?;
+        query.cursor = Some(serde_json::from_str(&serialized)?);
+
+        // New visits don't change the next page.
+        visit(&conn, "https://a.example.com/", "A", start + DAY + HOUR);
+
+        let page = get_history_sections(&conn, &query)?;
+        assert_eq!(
+            summarize(&page),
+            vec![(
+                start,
+                vec![
+                    ("a.example.com", 1, "https://a.example.com/"),
+                    ("b.example.com", 1, "https://b.example.com/"),
+                ]
+            )]
+        );
+        assert_eq!(page.cursor, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sections_across_queries() -> Result<()> {
+        let conn = new_mem_connection();
+        let start = 1000 * DAY;
+        // These visits are further apart than `DAYS_PER_QUERY`, with a long
+        // gap between the first two.
+        visit(&conn, "https://a.example.com/", "A", start + HOUR);
+        visit(
+            &conn,
+            "https://b.example.com/",
+            "B",
+            start + 400 * DAY + HOUR,
+        );
+        visit(
+            &conn,
+            "https://c.example.com/",
+            "C",
+            start + 410 * DAY + HOUR,
+        );
+        visit(
+            &conn,
+            "https://d.example.com/",
+            "D",
+            start + 410 * DAY + 2 * HOUR,
+        );
+
+        let page = get_history_sections(&conn, &HistoryQuery::new(10))?;
+        assert_eq!(
+            summarize(&page),
+            vec![
+                (
+                    start + 410 * DAY,
+                    vec![
+                        ("d.example.com", 1, "https://d.example.com/"),
+                        ("c.example.com", 1, "https://c.example.com/"),
+                    ]
+                ),
+                (
+                    start + 400 * DAY,
+                    vec![("b.example.com", 1, "https://b.example.com/")]
+                ),
+                (start, vec![("a.example.com", 1, "https://a.example.com/")]),
+            ]
+        );
+        assert_eq!(page.cursor, None);
+
+        // A page that ends partway through a day picks up from there.
+        let mut query = HistoryQuery::new(1);
+        let mut hosts = Vec::new();
+        loop {
+            let page = get_history_sections(&conn, &query)?;
+            for section in &page.sections {
+                hosts.extend(section.sites.iter().map(|site| site.host.clone()));
+            }
+            match page.cursor {
+                Some(cursor) => query.cursor = Some(cursor),
+                None => break,
+            }
+        }
+        assert_eq!(
+            hosts,
+            vec![
+                "d.example.com",
+                "c.example.com",
+                "b.example.com",
+                "a.example.com"
+            ]
+        );
+        Ok(())
+    }
+}
@@ -7,10 +7,16 @@
 
 pub mod bookmarks;
 pub mod history;
+pub mod history_metadata;
+pub mod history_view;
+pub mod reading_list;
+pub mod search_terms;
 pub mod tags;
+pub mod top_sites;
 
 use crate::db::PlacesDb;
 use crate::error::{ErrorKind, InvalidPlaceInfo, Result};
+use crate::frecency::FrecencySettings;
 use crate::msg_types::HistoryVisitInfo;
 use crate::types::{SyncStatus, Timestamp, VisitTransition};
 use rusqlite::types::{FromSql, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
@@ -29,7 +35,9 @@ pub const TAG_LENGTH_MAX: usize = 100;
 // pub const DESCRIPTION_LENGTH_MAX: usize = 256;
 
 // Typesafe way to manage RowIds. Does it make sense? A better way?
-#[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Deserialize, Serialize, Default)]
+#[derive(
+    Debug, Copy, Clone, PartialEq, PartialOrd, Eq, Ord, Hash, Deserialize, Serialize, Default,
+)]
 pub struct RowId(pub i64);
 
 impl From<RowId> for i64 {
@@ -188,11 +196,71 @@ impl HistoryVisitInfo {
             timestamp: visit_date.0 as i64,
             visit_type: visit_type as i32,
             is_hidden: row.get("hidden")?,
+            search_term: row.get("search_term")?,
+            search_engine: row.get("search_engine")?,
         })
     }
 }
 
+/// The `moz_meta` key for the persisted frecency settings, stored as JSON.
+const FRECENCY_SETTINGS_META_KEY: &str = "frecency_settings";
+
+/// Returns the persisted frecency settings, or the defaults if none have been
+/// set.
+fn get_persisted_frecency_settings(db: &PlacesDb) -> Result<FrecencySettings> {
+    Ok(match get_meta::<String>(db, FRECENCY_SETTINGS_META_KEY)? {
+        Some(json) => serde_json::from_str(&json)?,
+        None => FrecencySettings::default(),
+    })
+}
+
+/// Makes the persisted frecency settings the current settings for all
+/// connections. Called when the API is first opened.
+pub(crate) fn load_frecency_settings(db: &PlacesDb) -> Result<()> {
+    db.replace_frecency_settings(get_persisted_frecency_settings(db)?);
+    Ok(())
+}
+
+/// Changes and persists the settings used to calculate frecencies. If the
+/// settings changed, every page's frecency is marked as stale, in the same
+/// transaction. That's a single statement, but it writes a row for every page,
+/// so callers should avoid doing this on the main thread. Existing frecencies
+/// aren't recalculated here; `run_maintenance` does that, in interruptible
+/// batches. New visits use the new settings right away. Returns whether the
+/// settings changed.
+pub fn set_frecency_settings(db: &PlacesDb, settings: &FrecencySettings) -> Result<bool> {
+    let tx = db.begin_transaction()?;
+    let changed = get_persisted_frecency_settings(db)? != *settings;
+    if changed {
+        put_meta(
+            db,
+            FRECENCY_SETTINGS_META_KEY,
+            &serde_json::to_string(settings)?,
+        )?;
+        db.execute_named_cached(
+            "REPLACE INTO moz_places_stale_frecencies(place_id, stale_at)
+             SELECT id, :now FROM moz_places",
+            &[(":now", &Timestamp::now())],
+        )?;
+    }
+    tx.commit()?;
+    db.replace_frecency_settings(settings.clone());
+    Ok(changed)
+}
+
 pub fn run_maintenance(conn: &PlacesDb) -> Result<()> {
+    // Age frecencies, and recalculate any that are stale, before we vacuum.
+    // The recalculation can be interrupted with the connection's interrupt
+    // handle.
+    history::decay_frecencies(conn)?;
+    let scope = conn.begin_interrupt_scope();
+    loop {
+        let remaining =
+            history::update_stale_frecencies(conn, history::STALE_FRECENCIES_BATCH_SIZE, &scope)?;
+        if remaining == 0 {
+            break;
+        }
+    }
     conn.execute_all(&[
         "VACUUM",
         "PRAGMA optimize",
@@ -259,4 +327,93 @@ mod tests {
             .is_none());
         delete_meta(&conn, "foo").expect("delete non-existing should work");
     }
+
+    #[test]
+    fn test_frecency_settings_maintenance() {
+        use crate::observation::VisitObservation;
+        use crate::storage::history::{apply_observation, frecency_stale_at};
+
+        let conn = new_mem_connection();
+        let url = Url::parse("https://example.com/").unwrap();
+        apply_observation(
+            &conn,
+            VisitObservation::new(url.clone())
+                .with_visit_type(VisitTransition::Typed)
+                .with_at(Timestamp::now()),
+        )
+        .expect("Should apply visit");
+        let frecency = || fetch_page_info(&conn, &url).unwrap().unwrap().page.frecency;
+        let old_frecency = frecency();
+
+        let settings = FrecencySettings {
+            typed_visit_bonus: 4000,
+            ..FrecencySettings::default()
+        };
+        assert!(set_frecency_settings(&conn, &settings).unwrap());
+        // The page should be marked as stale, but not re-scored yet...
+        assert!(frecency_stale_at(&conn, &url).unwrap().is_some());
+        assert_eq!(frecency(), old_frecency);
+
+        // ...until maintenance runs.
+        run_maintenance(&conn).expect("Should run maintenance");
+        assert_eq!(frecency_stale_at(&conn, &url).unwrap(), None);
+        assert!(frecency() > old_frecency);
+    }
+
+    #[test]
+    fn test_frecency_settings() {
+        use crate::api::places_api::test::new_mem_connections;
+        use crate::observation::VisitObservation;
+        use crate::storage::history::{apply_observation, frecency_stale_at};
+
+        let conns = new_mem_connections();
+        let url = Url::parse("https://example.com/").unwrap();
+        let visit = || {
+            VisitObservation::new(url.clone())
+                .with_visit_type(VisitTransition::Typed)
+                .with_at(Timestamp::now())
+        };
+        apply_observation(&conns.write, visit()).expect("Should apply visit");
+        assert_eq!(frecency_stale_at(&conns.write, &url).unwrap(), None);
+        let default_frecency = fetch_page_info(&conns.write, &url)
+            .unwrap()
+            .unwrap()
+            .page
+            .frecency;
+
+        // Missing settings should use the defaults.
+        let settings: FrecencySettings =
+            serde_json::from_str(r#"{"typed_visit_bonus": 4000}"#).unwrap();
+        assert_eq!(
+            settings,
+            FrecencySettings {
+                typed_visit_bonus: 4000,
+                ..FrecencySettings::default()
+            }
+        );
+
+        assert!(set_frecency_settings(&conns.write, &settings).unwrap());
+        // Changing the settings should mark existing frecencies as stale,
+        // and affect all connections.
+        assert!(frecency_stale_at(&conns.write, &url).unwrap().is_some());
+        assert_eq!(conns.read.frecency_settings(), settings);
+        // Setting the same settings again shouldn't do anything.
+        assert!(!set_frecency_settings(&conns.write, &settings).unwrap());
+
+        // New visits should use the new settings.
+        apply_observation(&conns.write, visit()).expect("Should apply visit");
+        let new_frecency = fetch_page_info(&conns.write, &url)
+            .unwrap()
+            .unwrap()
+            .page
+            .frecency;
+        assert!(new_frecency > default_frecency);
+
+        // The settings should be persisted.
+        conns
+            .write
+            .replace_frecency_settings(FrecencySettings::default());
+        load_frecency_settings(&conns.write).unwrap();
+        assert_eq!(conns.write.frecency_settings(), settings);
+    }
 }
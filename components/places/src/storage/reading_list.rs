@@ -0,0 +1,333 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The reading list holds pages that the user saved to read later. Items are
+//! keyed by URL, and kept separately from history and bookmarks, so saving a
+//! page doesn't add a visit, and removing a page from history doesn't remove
+//! it from the reading list.
+
+use super::{TITLE_LENGTH_MAX, URL_LENGTH_MAX};
+use crate::db::PlacesDb;
+use crate::error::*;
+use crate::msg_types;
+use crate::types::{SyncStatus, Timestamp};
+use crate::util::slice_up_to;
+use rusqlite::Row;
+use sql_support::ConnExt;
+use sync_guid::Guid as SyncGuid;
+use url::Url;
+
+/// The longest excerpt we store. Excerpts are only meant to be a preview of
+/// the page, so longer ones are truncated.
+pub const EXCERPT_LENGTH_MAX: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReadingListItem {
+    pub guid: SyncGuid,
+    pub url: Url,
+    pub title: Option<String>,
+    pub excerpt: Option<String>,
+    /// The name of the device the page was saved on, if known.
+    pub source_device: Option<String>,
+    pub added_at: Timestamp,
+    /// When the page was marked as read, or `None` if it hasn't been.
+    pub read_at: Option<Timestamp>,
+}
+
+impl ReadingListItem {
+    pub(crate) fn from_row(row: &Row<'_>) -> Result<Self> {
+        Ok(Self {
+            guid: row.get::<_, String>("guid")?.into(),
+            url: Url::parse(&row.get::<_, String>("url")?)?,
+            title: row.get("title")?,
+            excerpt: row.get("excerpt")?,
+            source_device: row.get("source_device")?,
+            added_at: row.get("added_at")?,
+            read_at: row.get("read_at")?,
+        })
+    }
+
+    #[inline]
+    pub fn is_read(&self) -> bool {
+        self.read_at.is_some()
+    }
+}
+
+impl From<ReadingListItem> for msg_types::ReadingListItem {
+    fn from(item: ReadingListItem) -> Self {
+        Self {
+            guid: item.guid.into_string(),
+            url: item.url.into_string(),
+            title: item.title,
+            excerpt: item.excerpt,
+            source_device: item.source_device,
+            added_at: item.added_at.as_millis() as i64,
+            read_at: item.read_at.map(|read_at| read_at.as_millis() as i64),
+        }
+    }
+}
+
+impl From<Vec<ReadingListItem>> for msg_types::ReadingListItems {
+    fn from(items: Vec<ReadingListItem>) -> Self {
+        Self {
+            items: items.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+/// Which items `get_items` should return.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum ReadingListFilter {
+    All = 1,
+    Unread = 2,
+    Read = 3,
+}
+
+impl ReadingListFilter {
+    pub fn from_primitive(p: u8) -> Option<Self> {
+        match p {
+            1 => Some(ReadingListFilter::All),
+            2 => Some(ReadingListFilter::Unread),
+            3 => Some(ReadingListFilter::Read),
+            _ => None,
+        }
+    }
+}
+
+const ITEM_COLUMNS: &str = "guid, url, title, excerpt, source_device, added_at, read_at";
+
+/// Saves a page to the reading list, and returns the new item. If the page is
+/// already in the reading list, this returns the existing item unchanged.
+pub fn add_item(
+    db: &PlacesDb,
+    url: &Url,
+    title: Option<&str>,
+    excerpt: Option<&str>,
+    source_device: Option<&str>,
+) -> Result<ReadingListItem> {
+    if url.as_str().len() > URL_LENGTH_MAX {
+        return Err(InvalidPlaceInfo::UrlTooLong.into());
+    }
+    let tx = db.begin_transaction()?;
+    if let Some(existing) = get_item(db, url)? {
+        return Ok(existing);
+    }
+    db.execute_named_cached(
+        "INSERT INTO moz_reading_list(guid, url, title, excerpt, source_device, added_at)
+         VALUES(:guid, :url, :title, :excerpt, :source_device, :added_at)",
+        &[
+            (":guid", &SyncGuid::random().as_str()),
+            (":url", &url.as_str()),
+            (":title", &title.map(|t| slice_up_to(t, TITLE_LENGTH_MAX))),
+            (
+                ":excerpt",
+                &excerpt.map(|e| slice_up_to(e, EXCERPT_LENGTH_MAX)),
+            ),
+            (":source_device", &source_device),
+            (":added_at", &Timestamp::now()),
+        ],
+    )?;
+    let item = get_item(db, url)?.expect("Should have just added the item");
+    tx.commit()?;
+    Ok(item)
+}
+
+/// Returns the reading list item for `url`, if the page has been saved.
+pub fn get_item(db: &PlacesDb, url: &Url) -> Result<Option<ReadingListItem>> {
+    db.try_query_row(
+        &format!(
+            "SELECT {} FROM moz_reading_list WHERE url = :url",
+            ITEM_COLUMNS
+        ),
+        &[(":url", &url.as_str())],
+        ReadingListItem::from_row,
+        true,
+    )
+}
+
+/// Returns reading list items, most recently saved first.
+pub fn get_items(db: &PlacesDb, filter: ReadingListFilter) -> Result<Vec<ReadingListItem>> {
+    let condition = match filter {
+        ReadingListFilter::All => "1",
+        ReadingListFilter::Unread => "read_at IS NULL",
+        ReadingListFilter::Read => "read_at NOT NULL",
+    };
+    db.query_rows_and_then_named_cached(
+        &format!(
+            "SELECT {} FROM moz_reading_list
+             WHERE {}
+             ORDER BY added_at DESC, id DESC",
+            ITEM_COLUMNS, condition
+        ),
+        &[],
+        ReadingListItem::from_row,
+    )
+}
+
+/// Marks a saved page as read, or unread. Marking a page that's already read
+/// as read again doesn't change when it was read. Returns false if the page
+/// isn't in the reading list.
+pub fn mark_read(db: &PlacesDb, url: &Url, read: bool) -> Result<bool> {
+    let tx = db.begin_transaction()?;
+    if read {
+        db.execute_named_cached(
+            "UPDATE moz_reading_list SET
+               read_at = :now,
+               sync_change_counter = sync_change_counter + 1
+             WHERE url = :url AND read_at IS NULL",
+            &[(":url", &url.as_str()), (":now", &Timestamp::now())],
+        )?;
+    } else {
+        db.execute_named_cached(
+            "UPDATE moz_reading_list SET
+               read_at = NULL,
+               sync_change_counter = sync_change_counter + 1
+             WHERE url = :url AND read_at NOT NULL",
+            &[(":url", &url.as_str())],
+        )?;
+    }
+    let exists = get_item(db, url)?.is_some();
+    tx.commit()?;
+    Ok(exists)
+}
+
+/// Removes a page from the reading list. Returns false if it wasn't there.
+pub fn delete_item(db: &PlacesDb, url: &Url) -> Result<bool> {
+    let tx = db.begin_transaction()?;
+    // Items we've never uploaded don't need a tombstone.
+    db.execute_named_cached(
+        "INSERT OR IGNORE INTO moz_reading_list_tombstones(guid)
+         SELECT guid FROM moz_reading_list
+         WHERE url = :url AND sync_status = :normal",
+        &[
+            (":url", &url.as_str()),
+            (":normal", &(SyncStatus::Normal as u8)),
+        ],
+    )?;
+    let deleted = db.execute_named_cached(
+        "DELETE FROM moz_reading_list WHERE url = :url",
+        &[(":url", &url.as_str())],
+    )?;
+    tx.commit()?;
+    Ok(deleted > 0)
+}
+
+/// Removes all pages from the reading list.
+pub fn delete_all_items(db: &PlacesDb) -> Result<()> {
+    let tx = db.begin_transaction()?;
+    db.execute_all(&[
+        &format!(
+            "INSERT OR IGNORE INTO moz_reading_list_tombstones(guid)
+             SELECT guid FROM moz_reading_list
+             WHERE sync_status = {}",
+            SyncStatus::Normal as u8
+        ),
+        "DELETE FROM moz_reading_list",
+    ])?;
+    tx.commit()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+
+    fn count_tombstones(db: &PlacesDb) -> i64 {
+        db.query_one("SELECT COUNT(*) FROM moz_reading_list_tombstones")
+            .unwrap()
+    }
+
+    #[test]
+    fn test_add_and_get() -> Result<()> {
+        let conn = new_mem_connection();
+        let url = Url::parse("https://example.com/article")?;
+        let item = add_item(
+            &conn,
+            &url,
+            Some("An article"),
+            Some("It was a dark and stormy night"),
+            Some("My phone"),
+        )?;
+        assert_eq!(item.url, url);
+        assert_eq!(item.title, Some("An article".to_string()));
+        assert_eq!(item.source_device, Some("My phone".to_string()));
+        assert!(!item.is_read());
+
+        // Adding it again shouldn't change anything.
+        let again = add_item(&conn, &url, Some("Another title"), None, None)?;
+        assert_eq!(again, item);
+
+        assert_eq!(get_item(&conn, &url)?, Some(item));
+        assert_eq!(
+            get_item(&conn, &Url::parse("https://example.com/other")?)?,
+            None
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_mark_read() -> Result<()> {
+        let conn = new_mem_connection();
+        let first = Url::parse("https://example.com/1")?;
+        let second = Url::parse("https://example.com/2")?;
+        add_item(&conn, &first, None, None, None)?;
+        add_item(&conn, &second, None, None, None)?;
+
+        assert!(mark_read(&conn, &first, true)?);
+        let read_at = get_item(&conn, &first)?.unwrap().read_at;
+        assert!(read_at.is_some());
+        // Marking it as read again shouldn't change the read date.
+        assert!(mark_read(&conn, &first, true)?);
+        assert_eq!(get_item(&conn, &first)?.unwrap().read_at, read_at);
+
+        let urls = |filter| -> Result<Vec<Url>> {
+            Ok(get_items(&conn, filter)?
+                .into_iter()
+                .map(|item| item.url)
+                .collect())
+        };
+        assert_eq!(urls(ReadingListFilter::Read)?, vec![first.clone()]);
+        assert_eq!(urls(ReadingListFilter::Unread)?, vec![second.clone()]);
+        assert_eq!(
+            urls(ReadingListFilter::All)?,
+            vec![second.clone(), first.clone()]
+        );
+
+        assert!(mark_read(&conn, &first, false)?);
+        assert_eq!(urls(ReadingListFilter::Read)?, Vec::<Url>::new());
+        assert!(!mark_read(
+            &conn,
+            &Url::parse("https://example.com/3")?,
+            true
+        )?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete() -> Result<()> {
+        let conn = new_mem_connection();
+        let new = Url::parse("https://example.com/new")?;
+        let synced = Url::parse("https://example.com/synced")?;
+        add_item(&conn, &new, None, None, None)?;
+        add_item(&conn, &synced, None, None, None)?;
+        conn.execute_named(
+            "UPDATE moz_reading_list SET sync_status = :normal WHERE url = :url",
+            &[
+                (":normal", &(SyncStatus::Normal as u8)),
+                (":url", &synced.as_str()),
+            ],
+        )?;
+
+        assert!(delete_item(&conn, &new)?);
+        assert!(!delete_item(&conn, &new)?);
+        assert_eq!(count_tombstones(&conn), 0);
+
+        delete_all_items(&conn)?;
+        assert_eq!(count_tombstones(&conn), 1);
+        assert!(get_items(&conn, ReadingListFilter::All)?.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,265 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Search terms extracted from visits to search result pages. When a page
+//! is visited, we check if it's a result page for one of the engines passed
+//! to `PlacesApi::set_search_engines`, and, if so, store what the user
+//! searched for. History, top sites, and autocomplete results include the
+//! term, so that they can show "Searched for X" instead of the URL.
+
+use super::RowId;
+use crate::db::PlacesDb;
+use crate::error::Result;
+use crate::search_engines::SearchTerm;
+use crate::types::Timestamp;
+use rusqlite::Row;
+use sql_support::ConnExt;
+use url::Url;
+
+/// All visits to result pages for the same search term.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchTermGroup {
+    pub term: String,
+    /// The engine used for the most recent search.
+    pub engine: String,
+    /// The number of visits to result pages for this term, including
+    /// visits to later pages of results.
+    pub visit_count: i64,
+    pub last_visit_date: Timestamp,
+}
+
+/// Stores the search term for a page, if it's a search result page.
+pub(crate) fn note_search_term(db: &PlacesDb, place_id: RowId, url: &Url) -> Result<()> {
+    let search_term = match db.search_engines().extract(url) {
+        Some(search_term) => search_term,
+        None => return Ok(()),
+    };
+    db.execute_named_cached(
+        "INSERT OR REPLACE INTO moz_places_search_terms(place_id, search_term, engine)
+         VALUES(:place_id, :search_term, :engine)",
+        &[
+            (":place_id", &place_id),
+            (":search_term", &search_term.term),
+            (":engine", &search_term.engine),
+        ],
+    )?;
+    Ok(())
+}
+
+/// Reads the search term from the `search_term` and `search_engine` columns
+/// of a row, which are usually joined in from `moz_places_search_terms`.
+pub(crate) fn search_term_from_row(row: &Row<'_>) -> Result<Option<SearchTerm>> {
+    let term: Option<String> = row.get("search_term")?;
+    let engine: Option<String> = row.get("search_engine")?;
+    Ok(match (term, engine) {
+        (Some(term), Some(engine)) => Some(SearchTerm { term, engine }),
+        _ => None,
+    })
+}
+
+/// Returns what the user searched for, if `url` is a search result page that
+/// they visited.
+pub fn get_search_term(db: &PlacesDb, url: &Url) -> Result<Option<SearchTerm>> {
+    let search_term = db.try_query_row(
+        "SELECT t.search_term, t.engine AS search_engine
+         FROM moz_places_search_terms t
+         JOIN moz_places h ON h.id = t.place_id
+         WHERE h.url_hash = hash(:url) AND h.url = :url",
+        &[(":url", &url.as_str())],
+        search_term_from_row,
+        true,
+    )?;
+    Ok(search_term.flatten())
+}
+
+/// Returns up to `limit` search terms that the user searched for between
+/// `start` and `end`, with all visits to result pages for the same term
+/// grouped together. Terms are returned most recently searched first.
+pub fn get_search_term_groups(
+    db: &PlacesDb,
+    start: Timestamp,
+    end: Timestamp,
+    limit: u32,
+) -> Result<Vec<SearchTermGroup>> {
+    db.query_rows_and_then_named_cached(
+        // SQLite takes the bare `engine` column from the row with the latest
+        // visit, since we're also selecting `MAX(v.visit_date)`.
+        "SELECT t.search_term, t.engine, COUNT(*) AS visit_count,
+                MAX(v.visit_date) AS last_visit_date
+         FROM moz_places_search_terms t
+         JOIN moz_places h ON h.id = t.place_id
+         JOIN moz_historyvisits v ON v.place_id = t.place_id
+         WHERE v.visit_date BETWEEN :start AND :end AND
+               NOT h.hidden
+         GROUP BY t.search_term
+         ORDER BY last_visit_date DESC, t.search_term
+         LIMIT :limit",
+        rusqlite::named_params! {
+            ":start": start,
+            ":end": end,
+            ":limit": limit,
+        },
+        |row| -> Result<_> {
+            Ok(SearchTermGroup {
+                term: row.get("search_term")?,
+                engine: row.get("engine")?,
+                visit_count: row.get("visit_count")?,
+                last_visit_date: row.get("last_visit_date")?,
+            })
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::matcher::{search_frecent, SearchMode, SearchParams};
+    use crate::api::places_api::test::new_mem_api;
+    use crate::api::places_api::ConnectionType;
+    use crate::observation::VisitObservation;
+    use crate::search_engines::SearchEngines;
+    use crate::storage::history::{apply_observation, delete_visits_for, get_visit_infos};
+    use crate::storage::top_sites::get_top_frecent_sites;
+    use crate::types::{VisitTransition, VisitTransitionSet};
+
+    fn visit(db: &PlacesDb, url: &str, at: u64) -> Result<()> {
+        apply_observation(
+            db,
+            VisitObservation::new(Url::parse(url)?)
+                .with_visit_type(VisitTransition::Link)
+                .with_at(Timestamp(at)),
+        )?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_terms() -> Result<()> {
+        let api = new_mem_api();
+        let conn = api.open_connection(ConnectionType::ReadWrite)?;
+
+        // Visits before the engines are set aren't recognized.
+        visit(&conn, "https://www.example.com/search?q=early", 1000)?;
+        api.set_search_engines(SearchEngines::from_json(
+            r#"[
+                {"name": "Example", "urlTemplate": "https://www.example.com/search?q={searchTerms}"},
+                {"name": "Other", "urlTemplate": "https://other.example.org/find?text={searchTerms}"}
+            ]"#,
+        )?);
+        visit(&conn, "https://www.example.com/search?q=cute+kittens", 2000)?;
+        visit(
+            &conn,
+            "https://www.example.com/search?q=cute+kittens&start=10",
+            3000,
+        )?;
+        visit(&conn, "https://other.example.org/find?text=puppies", 4000)?;
+        visit(
+            &conn,
+            "https://other.example.org/find?text=cute+kittens",
+            5000,
+        )?;
+        visit(&conn, "https://example.com/not-a-search", 6000)?;
+
+        assert_eq!(
+            get_search_term(
+                &conn,
+                &Url::parse("https://www.example.com/search?q=early")?
+            )?,
+            None
+        );
+        assert_eq!(
+            get_search_term(
+                &conn,
+                &Url::parse("https://other.example.org/find?text=puppies")?
+            )?,
+            Some(SearchTerm {
+                term: "puppies".to_string(),
+                engine: "Other".to_string(),
+            })
+        );
+
+        let infos = get_visit_infos(
+            &conn,
+            Timestamp(0),
+            Timestamp(10000),
+            VisitTransitionSet::empty(),
+        )?
+        .infos;
+        let terms = infos
+            .iter()
+            .map(|info| (info.search_term.as_deref(), info.search_engine.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            terms,
+            vec![
+                (None, None),
+                (Some("cute kittens"), Some("Example")),
+                (Some("cute kittens"), Some("Example")),
+                (Some("puppies"), Some("Other")),
+                (Some("cute kittens"), Some("Other")),
+                (None, None),
+            ]
+        );
+
+        let groups = get_search_term_groups(&conn, Timestamp(0), Timestamp(10000), 10)?;
+        assert_eq!(
+            groups,
+            vec![
+                SearchTermGroup {
+                    term: "cute kittens".to_string(),
+                    engine: "Other".to_string(),
+                    visit_count: 3,
+                    last_visit_date: Timestamp(5000),
+                },
+                SearchTermGroup {
+                    term: "puppies".to_string(),
+                    engine: "Other".to_string(),
+                    visit_count: 1,
+                    last_visit_date: Timestamp(4000),
+                },
+            ]
+        );
+        let top_sites = get_top_frecent_sites(&conn, 10, 0)?;
+        let top_site = top_sites
+            .iter()
+            .find(|site| site.url.as_str() == "https://other.example.org/find?text=puppies")
+            .expect("Should return the result page as a top site");
+        assert_eq!(
+            top_site.search_term.as_ref().map(|t| t.term.as_str()),
+            Some("puppies")
+        );
+        let results = search_frecent(
+            &conn,
+            SearchParams {
+                search_string: "puppies".into(),
+                limit: 10,
+                mode: SearchMode::Match,
+            },
+        )?;
+        let result = results
+            .iter()
+            .find(|r| r.url.as_str() == "https://other.example.org/find?text=puppies")
+            .expect("Should suggest the result page");
+        assert_eq!(
+            result.search_term.as_ref().map(|t| t.term.as_str()),
+            Some("puppies")
+        );
+
+        let groups = get_search_term_groups(&conn, Timestamp(0), Timestamp(3000), 10)?;
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].engine, "Example");
+        assert_eq!(groups[0].visit_count, 2);
+
+        // Removing the page from history should remove its search term.
+        let guid = conn.query_row_and_then_named(
+            "SELECT guid FROM moz_places WHERE url = :url",
+            &[(":url", &"https://other.example.org/find?text=puppies")],
+            |row| row.get::<_, String>(0),
+            false,
+        )?;
+        delete_visits_for(&conn, &guid.into())?;
+        let count: i64 = conn.query_one("SELECT COUNT(*) FROM moz_places_search_terms")?;
+        assert_eq!(count, 3);
+        Ok(())
+    }
+}
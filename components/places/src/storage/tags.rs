@@ -2,9 +2,12 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use super::{fetch_page_info, TAG_LENGTH_MAX};
+use super::{fetch_page_info, RowId, TAG_LENGTH_MAX};
 use crate::db::PlacesDb;
 use crate::error::{InvalidPlaceInfo, Result};
+use crate::msg_types;
+use crate::types::Timestamp;
+use rusqlite::Row;
 use sql_support::ConnExt;
 use url::Url;
 
@@ -71,7 +74,12 @@ pub fn validate_tag(tag: &str) -> ValidatedTag<'_> {
 pub fn tag_url(db: &PlacesDb, url: &Url, tag: &str) -> Result<()> {
     let tag = validate_tag(&tag).ensure_valid()?;
     let tx = db.begin_transaction()?;
+    tag_url_in_tx(db, url, tag)?;
+    tx.commit()?;
+    Ok(())
+}
 
+pub(crate) fn tag_url_in_tx(db: &PlacesDb, url: &Url, tag: &str) -> Result<()> {
     // This function will not create a new place.
     // Fetch the place id, so we (a) avoid creating a new tag when we aren't
     // going to reference it and (b) to avoid a sub-query.
@@ -91,7 +99,6 @@ pub fn tag_url(db: &PlacesDb, url: &Url, tag: &str) -> Result<()> {
          VALUES((SELECT id FROM moz_tags WHERE tag = :tag), :place_id)",
         &[(":tag", &tag), (":place_id", &place_id)],
     )?;
-    tx.commit()?;
     Ok(())
 }
 
@@ -166,6 +173,110 @@ pub fn remove_tag(db: &PlacesDb, tag: &str) -> Result<()> {
     Ok(())
 }
 
+/// Renames a tag. If a tag with the new name already exists, the two tags
+/// are merged, so that all URLs with either tag end up with the new tag.
+///
+/// # Arguments
+///
+/// * `conn` - A database connection on which to operate.
+///
+/// * `old_tag` - The tag to rename.
+///
+/// * `new_tag` - The new name for the tag.
+///
+/// # Returns
+///
+/// There is no success return value - the operation is ignored if the old
+/// tag does not exist.
+pub fn rename_tag(db: &PlacesDb, old_tag: &str, new_tag: &str) -> Result<()> {
+    let old_tag = validate_tag(old_tag).ensure_valid()?;
+    let new_tag = validate_tag(new_tag).ensure_valid()?;
+    if old_tag == new_tag {
+        return Ok(());
+    }
+    let tx = db.begin_transaction()?;
+    let old_tag_id = get_tag_id(db, old_tag)?;
+    if let Some(old_tag_id) = old_tag_id {
+        match get_tag_id(db, new_tag)? {
+            Some(new_tag_id) => {
+                // Merge the tags. Deleting the old tag removes its relations,
+                // which bumps the change counters for all bookmarks with the
+                // old tag.
+                db.execute_named_cached(
+                    "INSERT OR IGNORE INTO moz_tags_relation(tag_id, place_id)
+                     SELECT :new_tag_id, place_id FROM moz_tags_relation
+                     WHERE tag_id = :old_tag_id",
+                    &[(":new_tag_id", &new_tag_id), (":old_tag_id", &old_tag_id)],
+                )?;
+                db.execute_named_cached(
+                    "DELETE FROM moz_tags WHERE id = :old_tag_id",
+                    &[(":old_tag_id", &old_tag_id)],
+                )?;
+            }
+            None => {
+                db.execute_named_cached(
+                    "UPDATE moz_tags SET
+                       tag = :new_tag,
+                       lastModified = now()
+                     WHERE id = :old_tag_id",
+                    &[(":new_tag", &new_tag), (":old_tag_id", &old_tag_id)],
+                )?;
+            }
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+fn get_tag_id(db: &PlacesDb, tag: &str) -> Result<Option<RowId>> {
+    Ok(db.try_query_row(
+        "SELECT id FROM moz_tags WHERE tag = :tag",
+        &[(":tag", &tag)],
+        |row| row.get::<_, RowId>(0),
+        true,
+    )?)
+}
+
+/// A tag, and the number of URLs with that tag.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TagInfo {
+    pub tag: String,
+    pub url_count: u32,
+    pub last_modified: Timestamp,
+}
+
+impl TagInfo {
+    pub(crate) fn from_row(row: &Row<'_>) -> Result<Self> {
+        Ok(Self {
+            tag: row.get("tag")?,
+            url_count: row.get("url_count")?,
+            last_modified: row.get("lastModified")?,
+        })
+    }
+}
+
+/// Retrieves all tags, along with the number of URLs with each tag.
+///
+/// # Arguments
+///
+/// * `conn` - A database connection on which to operate.
+///
+/// # Returns
+///
+/// * A Vec<TagInfo> with all tags that are used by at least one URL, sorted
+///   by tag.
+pub fn get_all_tags(db: &PlacesDb) -> Result<Vec<TagInfo>> {
+    db.query_rows_and_then_named_cached(
+        "SELECT t.tag, t.lastModified, COUNT(*) AS url_count
+         FROM moz_tags t
+         JOIN moz_tags_relation r ON r.tag_id = t.id
+         GROUP BY t.id
+         ORDER BY t.tag",
+        &[],
+        TagInfo::from_row,
+    )
+}
+
 /// Retrieves a list of URLs which have the specified tag.
 ///
 /// # Arguments
@@ -228,11 +339,39 @@ pub fn get_tags_for_url(db: &PlacesDb, url: &Url) -> Result<Vec<String>> {
     Ok(tags)
 }
 
+impl From<TagInfo> for msg_types::TagInfo {
+    fn from(info: TagInfo) -> Self {
+        Self {
+            tag: info.tag,
+            url_count: info.url_count,
+            last_modified: info.last_modified.0 as i64,
+        }
+    }
+}
+
+impl From<Vec<TagInfo>> for msg_types::TagInfoList {
+    fn from(infos: Vec<TagInfo>) -> Self {
+        Self {
+            tags: infos.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<Vec<String>> for msg_types::StringList {
+    fn from(values: Vec<String>) -> Self {
+        Self { values }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::api::places_api::test::new_mem_connection;
+    use crate::storage::bookmarks::BookmarkRootGuid;
     use crate::storage::new_page_info;
+    use crate::tests::insert_json_tree;
+    use rusqlite::NO_PARAMS;
+    use serde_json::json;
 
     fn check_tags_for_url(db: &PlacesDb, url: &Url, mut expected: Vec<String>) {
         let mut tags = get_tags_for_url(&db, &url).expect("should work");
@@ -286,6 +425,97 @@ mod tests {
         assert!(validate_tag(&"f".repeat(101)).ensure_valid().is_err());
     }
 
+    #[test]
+    fn test_rename_tag() -> Result<()> {
+        let conn = new_mem_connection();
+        let url1 = Url::parse("http://example.com/1")?;
+        let url2 = Url::parse("http://example.com/2")?;
+        let url3 = Url::parse("http://example.com/3")?;
+        insert_json_tree(
+            &conn,
+            json!({
+                "guid": String::from(BookmarkRootGuid::Unfiled.as_str()),
+                "children": [
+                    { "guid": "bookmark1___", "url": url1.as_str() },
+                    { "guid": "bookmark2___", "url": url2.as_str() },
+                    { "guid": "bookmark3___", "url": url3.as_str() },
+                ]
+            }),
+        );
+        tag_url(&conn, &url1, "one")?;
+        tag_url(&conn, &url2, "one")?;
+        tag_url(&conn, &url2, "two")?;
+        tag_url(&conn, &url3, "three")?;
+        conn.execute("UPDATE moz_bookmarks SET syncChangeCounter = 0", NO_PARAMS)?;
+
+        let get_change_counter = |guid: &str| -> Result<u32> {
+            Ok(conn.query_row_and_then_named(
+                "SELECT syncChangeCounter FROM moz_bookmarks WHERE guid = :guid",
+                &[(":guid", &guid)],
+                |row| row.get(0),
+                false,
+            )?)
+        };
+
+        // Renaming a tag that doesn't exist does nothing.
+        rename_tag(&conn, "missing", "four")?;
+        check_urls_with_tag(&conn, "four", vec![]);
+
+        // Renaming a tag bumps the change counters for bookmarks with the
+        // tag, and updates the full-text index.
+        rename_tag(&conn, "one", " uno ")?;
+        check_urls_with_tag(&conn, "one", vec![]);
+        check_urls_with_tag(&conn, "uno", vec![url1.clone(), url2.clone()]);
+        assert_eq!(get_change_counter("bookmark1___")?, 1);
+        assert_eq!(get_change_counter("bookmark2___")?, 1);
+        assert_eq!(get_change_counter("bookmark3___")?, 0);
+        let fts_tags: String = conn.query_row_and_then_named(
+            "SELECT tags FROM moz_places_fts WHERE url = :url",
+            &[(":url", &url1.as_str())],
+            |row| row.get(0),
+            false,
+        )?;
+        assert_eq!(fts_tags, "uno");
+
+        // Renaming a tag to an existing tag merges them.
+        rename_tag(&conn, "uno", "two")?;
+        check_urls_with_tag(&conn, "uno", vec![]);
+        check_urls_with_tag(&conn, "two", vec![url1.clone(), url2.clone()]);
+        check_tags_for_url(&conn, &url2, vec!["two".to_string()]);
+        assert_eq!(get_foreign_count(&conn, &url2), 2);
+        assert!(get_change_counter("bookmark1___")? > 1);
+        assert!(get_change_counter("bookmark2___")? > 1);
+        assert_eq!(get_change_counter("bookmark3___")?, 0);
+
+        assert!(rename_tag(&conn, "two", "").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_all_tags() -> Result<()> {
+        let conn = new_mem_connection();
+        let url1 = Url::parse("http://example.com/1")?;
+        let url2 = Url::parse("http://example.com/2")?;
+        new_page_info(&conn, &url1, None)?;
+        new_page_info(&conn, &url2, None)?;
+        assert_eq!(get_all_tags(&conn)?, vec![]);
+
+        tag_url(&conn, &url1, "b")?;
+        tag_url(&conn, &url2, "b")?;
+        tag_url(&conn, &url2, "a")?;
+        tag_url(&conn, &url1, "unused")?;
+        untag_url(&conn, &url1, "unused")?;
+
+        let tags = get_all_tags(&conn)?
+            .into_iter()
+            .map(|info| (info.tag, info.url_count))
+            .collect::<Vec<_>>();
+        assert_eq!(tags, vec![("a".to_string(), 1), ("b".to_string(), 2)]);
+
+        Ok(())
+    }
+
     #[test]
     fn test_tags() {
         let conn = new_mem_connection();
@@ -0,0 +1,274 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Support for the "top sites" shown on new tabs. Top sites are the most
+//! frecent pages the user has visited, excluding anything that wouldn't make
+//! sense to show there, like downloads and redirects, and any pages that the
+//! user has removed from their top sites.
+
+use super::search_terms::search_term_from_row;
+use crate::db::PlacesDb;
+use crate::error::Result;
+use crate::msg_types;
+use crate::search_engines::SearchTerm;
+use crate::types::{Timestamp, VisitTransition, VisitTransitionSet};
+use sql_support::ConnExt;
+use url::Url;
+
+/// Visits which don't count toward a page being a top site. Pages which are
+/// only the sources of redirects, and embedded or framed pages, are hidden,
+/// so we don't need to check for them here. Visits to pages that failed to
+/// load don't count, either.
+const EXCLUDED_VISIT_TYPES: VisitTransitionSet =
+    VisitTransitionSet::single(VisitTransition::Download);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopFrecentSiteInfo {
+    pub url: Url,
+    pub title: Option<String>,
+    pub frecency: i64,
+    /// Set if the page is a search result page.
+    pub search_term: Option<SearchTerm>,
+}
+
+impl TopFrecentSiteInfo {
+    pub(crate) fn from_row(row: &rusqlite::Row<'_>) -> Result<Self> {
+        Ok(Self {
+            url: Url::parse(&row.get::<_, String>("url")?)?,
+            title: row.get("title")?,
+            frecency: row.get("frecency")?,
+            search_term: search_term_from_row(row)?,
+        })
+    }
+}
+
+/// Returns up to `limit` of the most frecent web pages with a frecency of at
+/// least `frecency_threshold`, most frecent first. Pages that the user has
+/// blocked with `block_site` are never returned.
+pub fn get_top_frecent_sites(
+    db: &PlacesDb,
+    limit: u32,
+    frecency_threshold: i64,
+) -> Result<Vec<TopFrecentSiteInfo>> {
+    let allowed_types = EXCLUDED_VISIT_TYPES.complement();
+    let infos = db.query_rows_and_then_named_cached(
+        "SELECT h.url, h.title, h.frecency,
+                t.search_term, t.engine AS search_engine
+         FROM moz_places h
+         LEFT JOIN moz_places_search_terms t ON t.place_id = h.id
+         WHERE h.frecency >= MAX(:frecency_threshold, 1) AND
+               NOT h.hidden AND
+               (SUBSTR(h.url, 1, 6) = 'https:' OR SUBSTR(h.url, 1, 5) = 'http:') AND
+               EXISTS(SELECT 1 FROM moz_historyvisits v
+                      WHERE v.place_id = h.id AND
+                            NOT v.is_error AND
+                            ((1 << v.visit_type) & :allowed_types) != 0) AND
+               NOT EXISTS(SELECT 1 FROM moz_top_sites_blocklist b
+                          WHERE b.url_hash = h.url_hash AND
+                                b.url = h.url)
+         ORDER BY h.frecency DESC, h.id DESC
+         LIMIT :limit",
+        rusqlite::named_params! {
+            ":frecency_threshold": frecency_threshold,
+            ":allowed_types": allowed_types,
+            ":limit": limit,
+        },
+        TopFrecentSiteInfo::from_row,
+    )?;
+    Ok(infos)
+}
+
+/// Prevents a page from being returned as a top site. This doesn't affect
+/// the page's history, and it stays blocked even if the page is removed from
+/// history, until it's unblocked with `unblock_site` or `clear_blocklist`.
+pub fn block_site(db: &PlacesDb, url: &Url) -> Result<()> {
+    db.execute_named_cached(
+        "INSERT OR IGNORE INTO moz_top_sites_blocklist(url, url_hash, blocked_at)
+         VALUES(:url, hash(:url), :now)",
+        &[(":url", &url.as_str()), (":now", &Timestamp::now())],
+    )?;
+    Ok(())
+}
+
+/// Allows a page blocked with `block_site` to be returned as a top site
+/// again.
+pub fn unblock_site(db: &PlacesDb, url: &Url) -> Result<()> {
+    db.execute_named_cached(
+        "DELETE FROM moz_top_sites_blocklist
+         WHERE url_hash = hash(:url) AND
+               url = :url",
+        &[(":url", &url.as_str())],
+    )?;
+    Ok(())
+}
+
+/// Returns all blocked pages, most recently blocked first.
+pub fn get_blocked_sites(db: &PlacesDb) -> Result<Vec<Url>> {
+    db.query_rows_and_then_named_cached(
+        "SELECT url FROM moz_top_sites_blocklist
+         ORDER BY blocked_at DESC, url",
+        &[],
+        |row| -> Result<_> { Ok(Url::parse(&row.get::<_, String>(0)?)?) },
+    )
+}
+
+/// Unblocks all blocked pages.
+pub fn clear_blocklist(db: &PlacesDb) -> Result<()> {
+    db.execute_all(&["DELETE FROM moz_top_sites_blocklist"])?;
+    Ok(())
+}
+
+impl From<TopFrecentSiteInfo> for msg_types::TopFrecentSiteInfo {
+    fn from(info: TopFrecentSiteInfo) -> Self {
+        let (search_term, search_engine) = match info.search_term {
+            Some(SearchTerm { term, engine }) => (Some(term), Some(engine)),
+            None => (None, None),
+        };
+        Self {
+            url: info.url.into_string(),
+            title: info.title,
+            frecency: info.frecency,
+            search_term,
+            search_engine,
+        }
+    }
+}
+
+impl From<Vec<TopFrecentSiteInfo>> for msg_types::TopFrecentSiteInfos {
+    fn from(infos: Vec<TopFrecentSiteInfo>) -> Self {
+        Self {
+            infos: infos.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::places_api::test::new_mem_connection;
+    use crate::observation::VisitObservation;
+    use crate::storage::history::{apply_observation, delete_everything};
+
+    fn visit(db: &PlacesDb, url: &str, visit_type: VisitTransition) -> Url {
+        let url = Url::parse(url).unwrap();
+        apply_observation(
+            db,
+            VisitObservation::new(url.clone())
+                .with_title(format!("Title for {}", url))
+                .with_visit_type(visit_type)
+                .with_at(Timestamp::now()),
+        )
+        .expect("Should apply visit");
+        url
+    }
+
+    fn top_site_urls(db: &PlacesDb, limit: u32, frecency_threshold: i64) -> Vec<String> {
+        get_top_frecent_sites(db, limit, frecency_threshold)
+            .expect("Should fetch top sites")
+            .into_iter()
+            .map(|info| info.url.into_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_top_frecent_sites() {
+        let conn = new_mem_connection();
+
+        let typed = visit(&conn, "https://example.com/typed", VisitTransition::Typed);
+        for _ in 0..2 {
+            visit(&conn, "https://example.com/typed", VisitTransition::Typed);
+        }
+        let link = visit(&conn, "https://example.com/link", VisitTransition::Link);
+        visit(&conn, "https://example.com/file", VisitTransition::Download);
+        visit(&conn, "ftp://example.com/ftp", VisitTransition::Typed);
+        visit(&conn, "https://example.com/embed", VisitTransition::Embed);
+        apply_observation(
+            &conn,
+            VisitObservation::new(Url::parse("https://example.com/redirect").unwrap())
+                .with_visit_type(VisitTransition::Link)
+                .with_is_redirect_source(true)
+                .with_at(Timestamp::now()),
+        )
+        .expect("Should apply redirect visit");
+
+        assert_eq!(
+            top_site_urls(&conn, 10, 0),
+            vec![typed.as_str(), link.as_str()]
+        );
+        assert_eq!(top_site_urls(&conn, 1, 0), vec![typed.as_str()]);
+
+        let infos = get_top_frecent_sites(&conn, 10, 0).expect("Should fetch top sites");
+        assert_eq!(
+            infos[0].title.as_deref(),
+            Some("Title for https://example.com/typed")
+        );
+        assert!(infos[0].frecency > infos[1].frecency);
+        assert_eq!(
+            top_site_urls(&conn, 10, infos[1].frecency + 1),
+            vec![typed.as_str()]
+        );
+    }
+
+    #[test]
+    fn test_error_visits() {
+        let conn = new_mem_connection();
+
+        let url = Url::parse("https://example.com/error").unwrap();
+        apply_observation(
+            &conn,
+            VisitObservation::new(url.clone())
+                .with_visit_type(VisitTransition::Typed)
+                .with_is_error(true)
+                .with_at(Timestamp::now()),
+        )
+        .expect("Should apply error visit");
+        // Error visits don't update frecency, but it's recalculated from all
+        // visits later, like after a sync, so give the page one anyway.
+        conn.execute_named(
+            "UPDATE moz_places SET frecency = 100 WHERE url_hash = hash(:url) AND url = :url",
+            &[(":url", &url.as_str())],
+        )
+        .expect("Should set frecency");
+        assert!(top_site_urls(&conn, 10, 0).is_empty());
+
+        // A visit that loads makes the page a top site.
+        visit(&conn, url.as_str(), VisitTransition::Typed);
+        assert_eq!(top_site_urls(&conn, 10, 0), vec![url.as_str()]);
+    }
+
+    #[test]
+    fn test_blocklist() {
+        let conn = new_mem_connection();
+
+        let first = visit(&conn, "https://example.com/1", VisitTransition::Typed);
+        let second = visit(&conn, "https://example.com/2", VisitTransition::Link);
+        assert_eq!(
+            top_site_urls(&conn, 10, 0),
+            vec![first.as_str(), second.as_str()]
+        );
+
+        block_site(&conn, &first).expect("Should block site");
+        // Blocking a site twice shouldn't fail.
+        block_site(&conn, &first).expect("Should block site again");
+        assert_eq!(top_site_urls(&conn, 10, 0), vec![second.as_str()]);
+        assert_eq!(
+            get_blocked_sites(&conn).expect("Should fetch blocklist"),
+            vec![first.clone()]
+        );
+
+        // Blocked sites should stay blocked after clearing history.
+        delete_everything(&conn).expect("Should delete everything");
+        visit(&conn, "https://example.com/1", VisitTransition::Typed);
+        assert!(top_site_urls(&conn, 10, 0).is_empty());
+
+        unblock_site(&conn, &first).expect("Should unblock site");
+        assert_eq!(top_site_urls(&conn, 10, 0), vec![first.as_str()]);
+
+        block_site(&conn, &first).expect("Should block site");
+        block_site(&conn, &second).expect("Should block unvisited site");
+        clear_blocklist(&conn).expect("Should clear blocklist");
+        assert_eq!(get_blocked_sites(&conn).unwrap(), Vec::<Url>::new());
+        assert_eq!(top_site_urls(&conn, 10, 0), vec![first.as_str()]);
+    }
+}
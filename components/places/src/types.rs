@@ -303,6 +303,68 @@ impl ToSql for SyncStatus {
     }
 }
 
+/// The kind of document a page is, as recorded in history metadata. We only
+/// distinguish pages which play media from everything else for now.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+#[repr(u8)]
+pub enum DocumentType {
+    Regular = 0,
+    Media = 1,
+}
+
+impl DocumentType {
+    #[inline]
+    pub fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(DocumentType::Regular),
+            1 => Some(DocumentType::Media),
+            _ => None,
+        }
+    }
+}
+
+impl Default for DocumentType {
+    fn default() -> Self {
+        DocumentType::Regular
+    }
+}
+
+impl FromSql for DocumentType {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let v = value.as_i64()?;
+        if v < 0 || v > i64::from(u8::max_value()) {
+            return Err(FromSqlError::OutOfRange(v));
+        }
+        // Treat types we don't know about, perhaps written by a newer
+        // version, as regular pages.
+        Ok(DocumentType::from_u8(v as u8).unwrap_or_default())
+    }
+}
+
+impl ToSql for DocumentType {
+    fn to_sql(&self) -> RusqliteResult<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(*self as u8))
+    }
+}
+
+impl Serialize for DocumentType {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_u8(*self as u8)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for DocumentType {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+        let v = <u8 as serde::Deserialize<'de>>::deserialize(deserializer)?;
+        DocumentType::from_u8(v)
+            .ok_or_else(|| D::Error::custom(format!("unknown DocumentType value: {}", v)))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,200 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use places::history_sync::HistorySyncConfig;
+use places::storage::history::{delete_visits_for, get_visit_infos, url_to_guid};
+use places::{
+    api::places_api::PlacesApi, apply_observation, ConnectionType, PlacesDb, Result, Timestamp,
+    VisitObservation, VisitTransition, VisitTransitionSet,
+};
+use std::sync::Arc;
+use sync15::{KeyBundle, Sync15StorageClientInit};
+use sync15_mock_server::{MockSyncServer, ServerConfiguration};
+use tempfile::{tempdir, TempDir};
+use url::Url;
+use viaduct::Method;
+
+/// A device that syncs history with other devices through a mock server.
+struct Device {
+    api: Arc<PlacesApi>,
+    conn: PlacesDb,
+    client_init: Sync15StorageClientInit,
+    key_bundle: KeyBundle,
+    config: HistorySyncConfig,
+    _dir: TempDir,
+}
+
+impl Device {
+    fn new(server: &MockSyncServer, key_bundle: &KeyBundle) -> Result<Self> {
+        let dir = tempdir()?;
+        let api = PlacesApi::new(dir.path().join("places.sqlite"))?;
+        let conn = api.open_connection(ConnectionType::ReadWrite)?;
+        Ok(Self {
+            api,
+            conn,
+            client_init: Sync15StorageClientInit {
+                key_id: "key-id".into(),
+                access_token: "access-token".into(),
+                tokenserver_url: server.tokenserver_url(),
+            },
+            key_bundle: key_bundle.clone(),
+            config: HistorySyncConfig::default(),
+            _dir: dir,
+        })
+    }
+
+    fn visit(&mut self, url: &str) -> Result<()> {
+        apply_observation(
+            &mut self.conn,
+            VisitObservation::new(Url::parse(url)?).with_visit_type(VisitTransition::Link),
+        )
+    }
+
+    fn forget(&self, url: &str) -> Result<()> {
+        let guid = url_to_guid(&self.conn, &Url::parse(url)?)?.expect("Should know about the page");
+        delete_visits_for(&self.conn, &guid)
+    }
+
+    fn visited_urls(&self) -> Result<Vec<String>> {
+        let mut urls = get_visit_infos(
+            &self.conn,
+            Timestamp(0),
+            Timestamp::now(),
+            VisitTransitionSet::empty(),
+        )?
+        .infos
+        .into_iter()
+        .map(|info| info.url)
+        .collect::<Vec<_>>();
+        urls.sort();
+        urls.dedup();
+        Ok(urls)
+    }
+
+    fn sync(&self) -> Result<()> {
+        self.api
+            .sync_history(&self.client_init, &self.key_bundle, &self.config)?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_sync_between_devices() -> Result<()> {
+    let server = MockSyncServer::new();
+    // Small uploads, so that we need more than one POST per batch.
+    server.set_configuration(ServerConfiguration {
+        max_post_records: 2,
+        ..ServerConfiguration::default()
+    });
+    let key_bundle = KeyBundle::new_random()?;
+    let mut laptop = Device::new(&server, &key_bundle)?;
+    let mut phone = Device::new(&server, &key_bundle)?;
+
+    laptop.visit("https://example.com/1")?;
+    laptop.visit("https://example.com/2")?;
+    laptop.visit("https://example.com/3")?;
+    laptop.sync()?;
+    assert_eq!(server.records("history").len(), 3);
+    let history_posts = server
+        .requests()
+        .into_iter()
+        .filter(|r| r.method == Method::Post && r.url.path().ends_with("/storage/history"))
+        .count();
+    assert_eq!(history_posts, 2);
+
+    phone.visit("https://example.org/")?;
+    phone.sync()?;
+    laptop.sync()?;
+    let expected = vec![
+        "https://example.com/1".to_string(),
+        "https://example.com/2".to_string(),
+        "https://example.com/3".to_string(),
+        "https://example.org/".to_string(),
+    ];
+    assert_eq!(laptop.visited_urls()?, expected);
+    assert_eq!(phone.visited_urls()?, expected);
+
+    // Removing a page on one device should remove it on the other.
+    phone.forget("https://example.com/2")?;
+    phone.sync()?;
+    laptop.sync()?;
+    assert_eq!(
+        laptop.visited_urls()?,
+        vec![
+            "https://example.com/1".to_string(),
+            "https://example.com/3".to_string(),
+            "https://example.org/".to_string(),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_node_reassignment() -> Result<()> {
+    let server = MockSyncServer::new();
+    let key_bundle = KeyBundle::new_random()?;
+    let mut laptop = Device::new(&server, &key_bundle)?;
+    laptop.visit("https://example.com/")?;
+    laptop.sync()?;
+    assert_eq!(server.records("history").len(), 1);
+
+    // The new node is empty, so syncing should start over, and upload
+    // everything again.
+    server.reassign_node();
+    assert!(server.records("history").is_empty());
+    if laptop.sync().is_err() {
+        laptop.sync()?;
+    }
+    assert_eq!(server.records("history").len(), 1);
+
+    let phone = Device::new(&server, &key_bundle)?;
+    phone.sync()?;
+    assert_eq!(
+        phone.visited_urls()?,
+        vec!["https://example.com/".to_string()]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_resume_interrupted_download() -> Result<()> {
+    let server = MockSyncServer::new();
+    let key_bundle = KeyBundle::new_random()?;
+    let mut laptop = Device::new(&server, &key_bundle)?;
+    for i in 0..5 {
+        laptop.visit(&format!("https://example.com/{}", i))?;
+    }
+    laptop.sync()?;
+
+    let mut phone = Device::new(&server, &key_bundle)?;
+    phone.config.incoming_page_size = 2;
+    // The first two pages should be applied, even though the sync fails.
+    server.fail_download_after("history", 2);
+    assert!(phone.sync().is_err());
+    assert_eq!(phone.visited_urls()?.len(), 4);
+
+    // The next sync should only download the last page.
+    server.clear_requests();
+    phone.sync()?;
+    assert_eq!(phone.visited_urls()?.len(), 5);
+    let downloads = server
+        .requests()
+        .into_iter()
+        .filter(|r| r.method == Method::Get && r.url.path().ends_with("/storage/history"))
+        .collect::<Vec<_>>();
+    assert_eq!(downloads.len(), 1);
+    assert!(downloads[0]
+        .url
+        .query_pairs()
+        .any(|(name, value)| name == "offset" && value == "4"));
+
+    // Once the download finishes, syncing again shouldn't download anything.
+    server.clear_requests();
+    phone.sync()?;
+    assert!(!server
+        .requests()
+        .iter()
+        .any(|r| r.method == Method::Get && r.url.path().ends_with("/storage/history")));
+    Ok(())
+}
@@ -4,7 +4,7 @@
 use std::{ops::Deref, path::Path};
 
 use rusqlite::Connection;
-use sql_support::ConnExt;
+use sql_support::{ConnExt, RecoveryReport};
 
 use crate::error::{ErrorKind, Result};
 
@@ -58,6 +58,72 @@ impl PushDb {
         )?)?)
     }
 
+    /// Like `open`, but if the database is corrupt, moves it aside, creates
+    /// a fresh database in its place, and copies in the subscriptions and
+    /// metadata that can still be read. The returned report describes what
+    /// was recovered, and is `None` if the database opened normally.
+    pub fn open_with_recovery(path: impl AsRef<Path>) -> Result<(Self, Option<RecoveryReport>)> {
+        let path = path.as_ref();
+        let err = match Self::open(path) {
+            Ok(db) => return Ok((db, None)),
+            Err(err) => err,
+        };
+        let recoverable = match err.kind() {
+            ErrorKind::StorageSqlError(e) => sql_support::is_corruption_error(e),
+            _ => false,
+        };
+        if !recoverable || !path.exists() {
+            return Err(err);
+        }
+        log::error!("Failed to open push database, recovering: {}", err);
+        let corrupt_path = sql_support::move_aside(path).map_err(|e| {
+            ErrorKind::StorageError(format!("Could not move corrupt database aside: {}", e))
+        })?;
+        let db = Self::open(path)?;
+        let report = db.salvage(&corrupt_path).unwrap_or_else(|e| {
+            log::error!("Failed to salvage the corrupt database: {}", e);
+            RecoveryReport::new(corrupt_path)
+        });
+        log::info!(
+            "Recovered {} rows from the corrupt database ({} failed)",
+            report.recovered(),
+            report.failed()
+        );
+        Ok((db, Some(report)))
+    }
+
+    fn salvage(&self, corrupt_path: &Path) -> Result<RecoveryReport> {
+        let mut report = RecoveryReport::new(corrupt_path);
+        let src = match Connection::open(corrupt_path) {
+            Ok(src) => src,
+            Err(e) => {
+                log::warn!("Can't open the corrupt database: {}", e);
+                return Ok(report);
+            }
+        };
+        report.integrity_ok = sql_support::check_integrity(&src).unwrap_or(false);
+
+        let tx = self.db.unchecked_transaction()?;
+        let record_cols = schema::COMMON_COLS
+            .split(',')
+            .map(str::trim)
+            .collect::<Vec<_>>();
+        report.tables.push(sql_support::salvage_table(
+            &src,
+            &tx,
+            "push_record",
+            &record_cols,
+        ));
+        report.tables.push(sql_support::salvage_table(
+            &src,
+            &tx,
+            "meta_data",
+            &["key", "value"],
+        ));
+        tx.commit()?;
+        Ok(report)
+    }
+
     pub fn open_in_memory() -> Result<Self> {
         let conn = Connection::open_in_memory()?;
         Ok(Self::with_connection(conn)?)
@@ -268,6 +334,44 @@ mod test {
         )
     }
 
+    #[test]
+    fn test_open_with_recovery() -> Result<()> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("push.sqlite");
+        let chid = &get_uuid()?;
+        {
+            let db = PushDb::open(&path)?;
+            db.put_record(&prec(chid))?;
+            db.set_meta("uaid", DUMMY_UAID)?;
+            let (_, report) = PushDb::open_with_recovery(&path)?;
+            assert!(report.is_none());
+        }
+
+        // Salvage from a healthy copy of the database.
+        let corrupt_path = dir.path().join("push.sqlite.corrupt");
+        std::fs::rename(&path, &corrupt_path).unwrap();
+        let db = PushDb::open(&path)?;
+        let report = db.salvage(&corrupt_path)?;
+        assert!(report.integrity_ok);
+        assert!(report.is_complete());
+        assert_eq!(report.recovered(), 2);
+        assert!(db.get_record(DUMMY_UAID, chid)?.is_some());
+        assert_eq!(db.get_meta("uaid")?, Some(DUMMY_UAID.to_owned()));
+        drop(db);
+
+        // And from a database that isn't one at all.
+        std::fs::write(&path, vec![b'x'; 4096]).unwrap();
+        let (db, report) = PushDb::open_with_recovery(&path)?;
+        let report = report.expect("should recover");
+        assert_eq!(
+            report.corrupt_path,
+            dir.path().join("push.sqlite.corrupt-1")
+        );
+        assert_eq!(report.recovered(), 0);
+        assert!(db.get_meta("uaid")?.is_none());
+        Ok(())
+    }
+
     #[test]
     fn basic() -> Result<()> {
         let db = get_db()?;
@@ -22,7 +22,10 @@ pub struct PushManager {
 impl PushManager {
     pub fn new(config: PushConfiguration) -> Result<Self> {
         let store = if let Some(ref path) = config.database_path {
-            Store::open(path)?
+            // What was recovered from a corrupt database is logged when
+            // opening it.
+            let (store, _) = Store::open_with_recovery(path)?;
+            store
         } else {
             Store::open_in_memory()?
         };
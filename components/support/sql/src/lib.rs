@@ -10,6 +10,7 @@ mod each_chunk;
 mod interrupt;
 mod maybe_cached;
 mod query_plan;
+mod recovery;
 mod repeat;
 
 pub use crate::conn_ext::*;
@@ -17,6 +18,7 @@ pub use crate::each_chunk::*;
 pub use crate::interrupt::*;
 pub use crate::maybe_cached::*;
 pub use crate::query_plan::*;
+pub use crate::recovery::*;
 pub use crate::repeat::*;
 
 /// In PRAGMA foo='bar', `'bar'` must be a constant string (it cannot be a
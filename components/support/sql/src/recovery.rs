@@ -0,0 +1,325 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Helpers for recovering what we can from a corrupt database, instead of
+//! deleting it and starting over.
+//!
+//! The general approach is:
+//!
+//! 1. Move the broken database (and its WAL and shared memory files) aside
+//!    with [`move_aside`], so that we never destroy the user's data.
+//! 2. Create a fresh, empty database at the original path.
+//! 3. Open the moved-aside copy, run [`check_integrity`] to note how badly
+//!    it's damaged, and copy the rows we can still read into the fresh
+//!    database with [`salvage_rows`].
+//! 4. Hand the resulting [`RecoveryReport`] to the caller, so that they can
+//!    log or report what was recovered.
+
+use rusqlite::types::Value;
+use rusqlite::{Connection, ErrorCode, Row, NO_PARAMS};
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// What we managed to copy out of one table of a corrupt database.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TableRecovery {
+    /// The name of the table.
+    pub table: String,
+    /// The number of rows copied into the fresh database.
+    pub recovered: usize,
+    /// The number of rows we could read, but not copy. These are usually
+    /// rows that violate a constraint in the fresh database, like a visit
+    /// for a page that we couldn't recover.
+    pub failed: usize,
+    /// `false` if we stopped early because the table couldn't be read past
+    /// a certain row, or couldn't be read at all.
+    pub complete: bool,
+}
+
+/// Describes the outcome of recovering a corrupt database.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RecoveryReport {
+    /// Where the corrupt database was moved. It's left on disk, in case it's
+    /// useful for debugging, or for a more thorough recovery later.
+    pub corrupt_path: PathBuf,
+    /// The result of `PRAGMA integrity_check` on the corrupt database, or
+    /// `false` if the check couldn't be run at all.
+    pub integrity_ok: bool,
+    /// Per-table results, in the order the tables were salvaged. Empty if
+    /// the corrupt database couldn't be opened.
+    pub tables: Vec<TableRecovery>,
+}
+
+impl RecoveryReport {
+    /// Creates a report for a database that was moved aside, but hasn't been
+    /// salvaged yet.
+    pub fn new(corrupt_path: impl Into<PathBuf>) -> Self {
+        Self {
+            corrupt_path: corrupt_path.into(),
+            ..Self::default()
+        }
+    }
+
+    /// The total number of rows copied into the fresh database.
+    pub fn recovered(&self) -> usize {
+        self.tables.iter().map(|t| t.recovered).sum()
+    }
+
+    /// The total number of rows we read, but couldn't copy.
+    pub fn failed(&self) -> usize {
+        self.tables.iter().map(|t| t.failed).sum()
+    }
+
+    /// Returns true if every table was read all the way through, and every
+    /// row was copied.
+    pub fn is_complete(&self) -> bool {
+        !self.tables.is_empty() && self.tables.iter().all(|t| t.complete && t.failed == 0)
+    }
+}
+
+/// Returns true if `err` indicates that the database file is damaged, or
+/// isn't an SQLite database at all.
+pub fn is_corruption_error(err: &rusqlite::Error) -> bool {
+    match err {
+        rusqlite::Error::SqliteFailure(e, _) => {
+            matches!(e.code, ErrorCode::DatabaseCorrupt | ErrorCode::NotADatabase)
+        }
+        _ => false,
+    }
+}
+
+/// Renames the database at `path`, along with its `-wal` and `-shm` files if
+/// they exist, to `<path>.corrupt`. If that name is already taken by an
+/// earlier recovery, we use `<path>.corrupt-1`, `<path>.corrupt-2`, and so
+/// on. Returns the new path of the database.
+pub fn move_aside(path: impl AsRef<Path>) -> io::Result<PathBuf> {
+    let path = path.as_ref();
+    let mut dest = with_suffix(path, ".corrupt");
+    let mut attempt = 0;
+    while dest.exists() {
+        attempt += 1;
+        dest = with_suffix(path, &format!(".corrupt-{}", attempt));
+    }
+    fs::rename(path, &dest)?;
+    // The WAL may have changes that haven't been checkpointed into the main
+    // file yet, so it needs to move along with it. SQLite finds it by name.
+    for suffix in &["-wal", "-shm"] {
+        let from = with_suffix(path, suffix);
+        if from.exists() {
+            fs::rename(&from, with_suffix(&dest, suffix))?;
+        }
+    }
+    log::warn!("Moved corrupt database aside to {:?}", dest);
+    Ok(dest)
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Runs `PRAGMA integrity_check` on `conn`, and returns true if SQLite didn't
+/// find any problems. Any problems it did find are logged.
+pub fn check_integrity(conn: &Connection) -> rusqlite::Result<bool> {
+    let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+    let problems = stmt
+        .query_and_then(NO_PARAMS, |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    if problems.len() == 1 && problems[0] == "ok" {
+        return Ok(true);
+    }
+    for problem in &problems {
+        log::warn!("Integrity check: {}", problem);
+    }
+    Ok(false)
+}
+
+/// Reads `columns` from every row in `table` of the corrupt database `src`,
+/// and calls `copy_row` to copy each row into the fresh database. Rows are
+/// read with a full table scan, so that a damaged index doesn't hide rows;
+/// for rowid tables, this means they're read in rowid order.
+///
+/// This never fails: rows that `copy_row` rejects are counted as failed and
+/// skipped, and a row that can't be read at all stops the salvage for the
+/// table, since SQLite can't step past a damaged page.
+pub fn salvage_rows<F>(
+    src: &Connection,
+    table: &str,
+    columns: &[&str],
+    mut copy_row: F,
+) -> TableRecovery
+where
+    F: FnMut(&Row<'_>) -> rusqlite::Result<()>,
+{
+    let mut result = TableRecovery {
+        table: table.to_string(),
+        ..TableRecovery::default()
+    };
+    let sql = format!("SELECT {} FROM {} NOT INDEXED", columns.join(", "), table);
+    let mut stmt = match src.prepare(&sql) {
+        Ok(stmt) => stmt,
+        Err(e) => {
+            log::warn!("Can't read {} from the corrupt database: {}", table, e);
+            return result;
+        }
+    };
+    let mut rows = match stmt.query(NO_PARAMS) {
+        Ok(rows) => rows,
+        Err(e) => {
+            log::warn!("Can't read {} from the corrupt database: {}", table, e);
+            return result;
+        }
+    };
+    loop {
+        match rows.next() {
+            Ok(Some(row)) => match copy_row(row) {
+                Ok(()) => result.recovered += 1,
+                Err(e) => {
+                    log::debug!("Failed to copy a row from {}: {}", table, e);
+                    result.failed += 1;
+                }
+            },
+            Ok(None) => {
+                result.complete = true;
+                break;
+            }
+            Err(e) => {
+                log::warn!(
+                    "Stopped reading {} after {} rows: {}",
+                    table,
+                    result.recovered + result.failed,
+                    e
+                );
+                break;
+            }
+        }
+    }
+    log::info!(
+        "Recovered {} rows from {} ({} failed)",
+        result.recovered,
+        table,
+        result.failed
+    );
+    result
+}
+
+/// Copies `columns` from every row in `table` of the corrupt database `src`
+/// into the same table in `dest`, as is. This is a convenience wrapper around
+/// [`salvage_rows`] for tables that don't need any fixing up.
+pub fn salvage_table(
+    src: &Connection,
+    dest: &Connection,
+    table: &str,
+    columns: &[&str],
+) -> TableRecovery {
+    let sql = format!(
+        "INSERT INTO {}({}) VALUES({})",
+        table,
+        columns.join(", "),
+        crate::repeat_sql_vars(columns.len())
+    );
+    salvage_rows(src, table, columns, |row| {
+        let values = (0..columns.len())
+            .map(|i| row.get::<_, Value>(i))
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        dest.prepare_cached(&sql)?.execute(&values)?;
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn setup(conn: &Connection) {
+        conn.execute_batch(
+            "CREATE TABLE items(id INTEGER PRIMARY KEY, value TEXT NOT NULL);
+             INSERT INTO items(id, value) VALUES(1, 'a'), (2, 'b'), (3, 'c');",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_salvage_rows() {
+        let src = Connection::open_in_memory().unwrap();
+        setup(&src);
+        assert!(check_integrity(&src).unwrap());
+
+        let dest = Connection::open_in_memory().unwrap();
+        dest.execute_batch("CREATE TABLE items(id INTEGER PRIMARY KEY, value TEXT UNIQUE)")
+            .unwrap();
+        dest.execute("INSERT INTO items(value) VALUES('b')", NO_PARAMS)
+            .unwrap();
+
+        let result = salvage_rows(&src, "items", &["value"], |row| {
+            let value: String = row.get(0)?;
+            dest.execute("INSERT INTO items(value) VALUES(?)", &[&value])?;
+            Ok(())
+        });
+        assert_eq!(
+            result,
+            TableRecovery {
+                table: "items".to_string(),
+                recovered: 2,
+                failed: 1,
+                complete: true,
+            }
+        );
+
+        // `dest` now has rows 1-3, so only the new ones are copied.
+        src.execute("INSERT INTO items(id, value) VALUES(4, 'd')", NO_PARAMS)
+            .unwrap();
+        let copied = salvage_table(&src, &dest, "items", &["id", "value"]);
+        assert_eq!(copied.recovered, 1);
+        assert_eq!(copied.failed, 3);
+        assert!(copied.complete);
+
+        let missing = salvage_rows(&src, "nope", &["value"], |_| Ok(()));
+        assert_eq!(missing.recovered, 0);
+        assert!(!missing.complete);
+
+        let report = RecoveryReport {
+            corrupt_path: PathBuf::from("x.corrupt"),
+            integrity_ok: true,
+            tables: vec![result, missing],
+        };
+        assert_eq!(report.recovered(), 2);
+        assert_eq!(report.failed(), 1);
+        assert!(!report.is_complete());
+    }
+
+    #[test]
+    fn test_move_aside() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        {
+            let conn = Connection::open(&path).unwrap();
+            setup(&conn);
+        }
+        fs::write(with_suffix(&path, "-wal"), b"").unwrap();
+
+        let first = move_aside(&path).unwrap();
+        assert_eq!(first, dir.path().join("test.db.corrupt"));
+        assert!(!path.exists());
+        assert!(!with_suffix(&path, "-wal").exists());
+        assert!(with_suffix(&first, "-wal").exists());
+
+        let conn = Connection::open(&first).unwrap();
+        assert!(check_integrity(&conn).unwrap());
+        let count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM items", NO_PARAMS, |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 3);
+
+        fs::write(&path, b"this is not a database").unwrap();
+        let second = move_aside(&path).unwrap();
+        assert_eq!(second, dir.path().join("test.db.corrupt-1"));
+        let garbage = Connection::open(&second).unwrap();
+        let err = check_integrity(&garbage).unwrap_err();
+        assert!(is_corruption_error(&err));
+    }
+}
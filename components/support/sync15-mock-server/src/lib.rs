@@ -0,0 +1,320 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+#![allow(unknown_lints)]
+#![warn(rust_2018_idioms)]
+
+//! An in-process mock of the Sync 1.5 tokenserver and storage server, for
+//! testing sync engines without a network connection. See the README for
+//! what it supports.
+//!
+//! ```no_run
+//! # use sync15_mock_server::MockSyncServer;
+//! let server = MockSyncServer::new();
+//! // Pass `server.tokenserver_url()` as the tokenserver URL when syncing.
+//! // All devices that use the same URL sync against the same account.
+//! let url = server.tokenserver_url();
+//! # drop(url);
+//! ```
+
+mod server;
+mod storage;
+
+pub use server::LoggedRequest;
+pub use storage::{ServerConfiguration, ServerRecord};
+pub use sync15_traits::ServerTimestamp;
+
+use server::ServerState;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::{Arc, Mutex, Once, Weak};
+use url::Url;
+use viaduct::{Backend, Request, Response};
+
+// Each server gets its own (fake) port on localhost, which is how the backend
+// knows which server a request is for. We never actually listen on these.
+static NEXT_PORT: AtomicU16 = AtomicU16::new(30_000);
+
+lazy_static::lazy_static! {
+    static ref SERVERS: Mutex<HashMap<u16, Weak<Mutex<ServerState>>>> = Mutex::new(HashMap::new());
+}
+
+struct MockBackend;
+
+impl Backend for MockBackend {
+    fn send(&self, request: Request) -> Result<Response, viaduct::Error> {
+        viaduct::note_backend("sync15-mock-server");
+        let server = request
+            .url
+            .port()
+            .filter(|_| request.url.host_str() == Some("localhost"))
+            .and_then(|port| SERVERS.lock().unwrap().get(&port).and_then(Weak::upgrade))
+            .ok_or_else(|| {
+                viaduct::Error::NetworkError(format!("No mock server for {}", request.url))
+            })?;
+        let response = server.lock().unwrap().handle(request);
+        Ok(response)
+    }
+}
+
+/// Installs the mock server as the `viaduct` backend. This happens
+/// automatically when the first server is created.
+///
+/// # Panics
+///
+/// Panics if a different backend is already installed. Since the backend is
+/// global, tests that use the mock server can't share a process with code
+/// that makes real network requests.
+pub fn use_mock_backend() {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        viaduct::set_backend(&MockBackend).expect("Another viaduct backend is already installed");
+    });
+}
+
+/// A mock tokenserver and storage server, for one account. The server stops
+/// handling requests when it's dropped.
+pub struct MockSyncServer {
+    port: u16,
+    state: Arc<Mutex<ServerState>>,
+}
+
+impl MockSyncServer {
+    pub fn new() -> Self {
+        use_mock_backend();
+        let port = NEXT_PORT.fetch_add(1, Ordering::SeqCst);
+        let base_url = Url::parse(&format!("http://localhost:{}/", port)).unwrap();
+        let state = Arc::new(Mutex::new(ServerState::new(base_url)));
+        SERVERS.lock().unwrap().insert(port, Arc::downgrade(&state));
+        Self { port, state }
+    }
+
+    /// The URL to use as the tokenserver URL. The tokenserver accepts any
+    /// OAuth access token and key ID.
+    pub fn tokenserver_url(&self) -> Url {
+        self.state.lock().unwrap().tokenserver_url()
+    }
+
+    /// Changes the limits in `info/configuration`. Clients usually only
+    /// fetch these once per sync.
+    pub fn set_configuration(&self, config: ServerConfiguration) {
+        self.state.lock().unwrap().config = config;
+    }
+
+    /// Sets how long tokens are valid for, in seconds. This only affects
+    /// tokens issued after the call.
+    pub fn set_token_duration(&self, seconds: u64) {
+        self.state.lock().unwrap().token_duration = seconds;
+    }
+
+    /// Sends an `X-Weave-Backoff` header with all storage responses, or
+    /// stops sending it if `seconds` is `None`.
+    pub fn set_backoff(&self, seconds: Option<u32>) {
+        self.state.lock().unwrap().backoff = seconds;
+    }
+
+    /// Makes all requests, including to the tokenserver, fail with a 503
+    /// and a `Retry-After` header, until this is called again with `None`.
+    pub fn set_unavailable(&self, retry_after: Option<u32>) {
+        self.state.lock().unwrap().retry_after = retry_after;
+    }
+
+    /// Makes a request for records in `collection` fail with a 500, after
+    /// `successful` more requests for that collection succeed. This is useful
+    /// for testing downloads that stop partway through. Only one request
+    /// fails.
+    pub fn fail_download_after(&self, collection: &str, successful: usize) {
+        self.state.lock().unwrap().fail_download = Some((collection.to_string(), successful));
+    }
+
+    /// Moves the account to a new, empty storage node. Clients get a 401 if
+    /// they keep using the old node, and need to fetch a new token.
+    pub fn reassign_node(&self) {
+        self.state.lock().unwrap().reassign_node();
+    }
+
+    /// Returns all records in a collection, oldest first.
+    pub fn records(&self, collection: &str) -> Vec<ServerRecord> {
+        let mut state = self.state.lock().unwrap();
+        let now = state.now();
+        state.storage.all_records(collection, now)
+    }
+
+    /// Returns when a collection was last modified, or `None` if it doesn't
+    /// exist.
+    pub fn collection_modified(&self, collection: &str) -> Option<ServerTimestamp> {
+        let state = self.state.lock().unwrap();
+        state.storage.info_collections().get(collection).copied()
+    }
+
+    /// Returns all requests that the server handled, oldest first.
+    pub fn requests(&self) -> Vec<LoggedRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// Forgets all requests that the server handled so far.
+    pub fn clear_requests(&self) {
+        self.state.lock().unwrap().requests.clear();
+    }
+}
+
+impl Default for MockSyncServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for MockSyncServer {
+    fn drop(&mut self) {
+        if let Ok(mut servers) = SERVERS.lock() {
+            servers.remove(&self.port);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use viaduct::{header_names, Method};
+
+    struct Client {
+        api_endpoint: Url,
+        token: String,
+    }
+
+    impl Client {
+        fn new(server: &MockSyncServer) -> Self {
+            let url = server.tokenserver_url().join("1.0/sync/1.5").unwrap();
+            let resp = Request::get(url)
+                .header(header_names::AUTHORIZATION, "Bearer access-token")
+                .unwrap()
+                .header(header_names::X_KEYID, "key-id")
+                .unwrap()
+                .send()
+                .unwrap();
+            assert_eq!(resp.status, 200);
+            assert!(resp.headers.get(header_names::X_TIMESTAMP).is_some());
+            let token: Value = resp.json().unwrap();
+            Self {
+                api_endpoint: Url::parse(&format!("{}/", token["api_endpoint"].as_str().unwrap()))
+                    .unwrap(),
+                token: token["id"].as_str().unwrap().to_string(),
+            }
+        }
+
+        fn request(&self, method: Method, path: &str) -> Request {
+            Request::new(method, self.api_endpoint.join(path).unwrap())
+                .header(
+                    header_names::AUTHORIZATION,
+                    format!("Hawk id=\"{}\", ts=\"1\", mac=\"mac\"", self.token),
+                )
+                .unwrap()
+        }
+
+        fn post(&self, path: &str, xius: Option<&str>, body: Value) -> Response {
+            let mut request = self.request(Method::Post, path).json(&body);
+            if let Some(xius) = xius {
+                request = request
+                    .header(header_names::X_IF_UNMODIFIED_SINCE, xius)
+                    .unwrap();
+            }
+            request.send().unwrap()
+        }
+    }
+
+    #[test]
+    fn test_uploads_and_conflicts() {
+        let server = MockSyncServer::new();
+        let client = Client::new(&server);
+
+        let resp = client.post(
+            "storage/history?batch=true",
+            Some("0"),
+            json!([{"id": "aaaa", "payload": "{}"}]),
+        );
+        assert_eq!(resp.status, 202);
+        let last_modified = resp.headers.get(header_names::X_LAST_MODIFIED).unwrap();
+        assert_eq!(last_modified, "0");
+        let batch = resp.json::<Value>().unwrap()["batch"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let resp = client.post(
+            &format!("storage/history?batch={}&commit=true", batch),
+            Some(last_modified),
+            json!([{"id": "bbbb", "payload": "{}", "sortindex": 5}]),
+        );
+        assert_eq!(resp.status, 200);
+        let modified = resp
+            .headers
+            .get_as::<ServerTimestamp, _>(header_names::X_LAST_MODIFIED)
+            .unwrap()
+            .unwrap();
+        assert_eq!(server.collection_modified("history"), Some(modified));
+        assert_eq!(server.records("history").len(), 2);
+
+        // Another client writing with an older timestamp should fail.
+        let resp = client.post(
+            "storage/history",
+            Some("0"),
+            json!([{"id": "cccc", "payload": "{}"}]),
+        );
+        assert_eq!(resp.status, 412);
+
+        let resp = client
+            .request(Method::Get, "info/collections")
+            .send()
+            .unwrap();
+        assert_eq!(resp.status, 200);
+        let collections: HashMap<String, ServerTimestamp> = resp.json().unwrap();
+        assert_eq!(collections.get("history"), Some(&modified));
+
+        let resp = client
+            .request(Method::Get, "storage/history?full=1&sort=index&limit=1")
+            .send()
+            .unwrap();
+        assert_eq!(
+            resp.headers.get(header_names::X_WEAVE_NEXT_OFFSET),
+            Some("1")
+        );
+        assert_eq!(resp.headers.get(header_names::X_WEAVE_RECORDS), Some("1"));
+        let records: Value = resp.json().unwrap();
+        assert_eq!(records[0]["id"], "bbbb");
+        assert_eq!(records[0]["sortindex"], 5);
+    }
+
+    #[test]
+    fn test_reassign_node_and_backoff() {
+        let server = MockSyncServer::new();
+        let client = Client::new(&server);
+        let resp = client.post(
+            "storage/tabs",
+            None,
+            json!([{"id": "aaaa", "payload": "{}"}]),
+        );
+        assert_eq!(resp.status, 200);
+
+        server.set_backoff(Some(60));
+        let resp = client.request(Method::Get, "storage/tabs").send().unwrap();
+        assert_eq!(resp.headers.get(header_names::X_WEAVE_BACKOFF), Some("60"));
+        server.set_backoff(None);
+
+        server.reassign_node();
+        let resp = client.request(Method::Get, "storage/tabs").send().unwrap();
+        assert_eq!(resp.status, 401);
+        let client = Client::new(&server);
+        let resp = client.request(Method::Get, "storage/tabs").send().unwrap();
+        assert_eq!(resp.status, 200);
+        assert_eq!(resp.json::<Value>().unwrap(), json!([]));
+
+        server.set_unavailable(Some(120));
+        let resp = client
+            .request(Method::Get, "info/collections")
+            .send()
+            .unwrap();
+        assert_eq!(resp.status, 503);
+        assert_eq!(resp.headers.get(header_names::RETRY_AFTER), Some("120"));
+    }
+}
@@ -0,0 +1,443 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Turns requests into calls on `Storage`, and handles the parts of the
+//! protocol that aren't about storage: the tokenserver, authentication,
+//! backoff, and node reassignment.
+
+use crate::storage::{
+    BatchParam, IncomingRecord, RecordQuery, ServerConfiguration, Sort, Storage, StorageError,
+};
+use percent_encoding::percent_decode_str;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::HashSet;
+use std::time::{SystemTime, UNIX_EPOCH};
+use sync15_traits::ServerTimestamp;
+use url::Url;
+use viaduct::{header_names, Header, HeaderName, Headers, Method, Request, Response};
+
+/// The user ID for the account. Each mock server has exactly one account.
+const UID: u64 = 1;
+
+/// A request that the server handled, and its response status.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoggedRequest {
+    pub method: Method,
+    pub url: Url,
+    pub status: u16,
+}
+
+/// A response, before we know which request it's for.
+struct Reply {
+    status: u16,
+    headers: Headers,
+    body: Vec<u8>,
+}
+
+impl Reply {
+    fn json<T: Serialize + ?Sized>(status: u16, body: &T) -> Self {
+        let mut headers = Headers::new();
+        headers.insert_header(Header::new_unchecked(
+            header_names::CONTENT_TYPE,
+            "application/json",
+        ));
+        Self {
+            status,
+            headers,
+            body: serde_json::to_vec(body).unwrap(),
+        }
+    }
+
+    fn error(status: u16, reason: &str) -> Self {
+        Self::json(status, &json!({ "status": reason }))
+    }
+
+    fn header(mut self, name: HeaderName, value: impl ToString) -> Self {
+        self.headers
+            .insert_header(Header::new_unchecked(name, value.to_string()));
+        self
+    }
+
+    fn last_modified(self, modified: ServerTimestamp) -> Self {
+        self.header(header_names::X_LAST_MODIFIED, modified)
+    }
+}
+
+impl From<StorageError> for Reply {
+    fn from(err: StorageError) -> Self {
+        match err {
+            StorageError::NotFound => Reply::error(404, "not-found"),
+            StorageError::PreconditionFailed => Reply::error(412, "precondition-failed"),
+            StorageError::BadRequest(reason) => Reply::error(400, reason),
+            StorageError::TooLarge => Reply::error(413, "size-limit-exceeded"),
+        }
+    }
+}
+
+type ReplyResult = std::result::Result<Reply, Reply>;
+
+pub(crate) struct ServerState {
+    base_url: Url,
+    pub config: ServerConfiguration,
+    /// Storage for the node that the account is currently assigned to.
+    /// Reassigning the account to a new node starts over with empty storage.
+    pub storage: Storage,
+    node: u32,
+    /// The IDs of the tokens that the tokenserver issued for the current
+    /// node.
+    tokens: HashSet<String>,
+    next_token: u64,
+    pub token_duration: u64,
+    pub backoff: Option<u32>,
+    /// If set, all requests fail with a 503 and this `Retry-After` value.
+    pub retry_after: Option<u32>,
+    /// If set, fetching records from this collection fails with a 500 after
+    /// this many more successful fetches. Only one fetch fails.
+    pub fail_download: Option<(String, usize)>,
+    pub requests: Vec<LoggedRequest>,
+    last_timestamp: ServerTimestamp,
+}
+
+impl ServerState {
+    pub fn new(base_url: Url) -> Self {
+        Self {
+            base_url,
+            config: ServerConfiguration::default(),
+            storage: Storage::default(),
+            node: 1,
+            tokens: HashSet::new(),
+            next_token: 0,
+            token_duration: 300,
+            backoff: None,
+            retry_after: None,
+            fail_download: None,
+            requests: Vec::new(),
+            last_timestamp: ServerTimestamp::EPOCH,
+        }
+    }
+
+    pub fn tokenserver_url(&self) -> Url {
+        self.base_url.join("token/").unwrap()
+    }
+
+    /// Returns the current server time. Like the real server, timestamps
+    /// have a resolution of 10ms, and we make sure they always increase, so
+    /// that two requests never get the same timestamp.
+    pub fn now(&mut self) -> ServerTimestamp {
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as i64;
+        let now = (since_epoch / 10 * 10).max(self.last_timestamp.as_millis() + 10);
+        self.last_timestamp = ServerTimestamp(now);
+        self.last_timestamp
+    }
+
+    /// Moves the account to a new, empty storage node. Clients will need to
+    /// fetch a new token, and their existing tokens stop working.
+    pub fn reassign_node(&mut self) {
+        self.node += 1;
+        self.storage = Storage::default();
+        self.tokens.clear();
+    }
+
+    pub fn handle(&mut self, request: Request) -> Response {
+        let now = self.now();
+        let reply = self.route(&request, now).unwrap_or_else(|reply| reply);
+        log::debug!(
+            "{} {} => {}",
+            request.method,
+            request.url.path(),
+            reply.status
+        );
+        self.requests.push(LoggedRequest {
+            method: request.method,
+            url: request.url.clone(),
+            status: reply.status,
+        });
+        Response {
+            request_method: request.method,
+            url: request.url,
+            status: reply.status,
+            headers: reply.headers,
+            body: reply.body,
+        }
+    }
+
+    fn route(&mut self, request: &Request, now: ServerTimestamp) -> ReplyResult {
+        if let Some(retry_after) = self.retry_after {
+            return Err(
+                Reply::error(503, "unavailable").header(header_names::RETRY_AFTER, retry_after)
+            );
+        }
+        let segments = request
+            .url
+            .path_segments()
+            .map(|segments| {
+                segments
+                    .filter(|s| !s.is_empty())
+                    .map(|s| percent_decode_str(s).decode_utf8_lossy().into_owned())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let segments = segments.iter().map(String::as_str).collect::<Vec<_>>();
+        match segments.as_slice() {
+            ["token", "1.0", "sync", "1.5"] => self.handle_token(request, now),
+            [node, "1.5", uid, path @ ..] => {
+                let result = self
+                    .check_storage_auth(request, node, uid)
+                    .and_then(|()| self.handle_storage(request, path, now));
+                let backoff = self.backoff;
+                let with_headers = |reply: Reply| {
+                    let reply = reply.header(header_names::X_WEAVE_TIMESTAMP, now);
+                    match backoff {
+                        Some(backoff) => reply.header(header_names::X_WEAVE_BACKOFF, backoff),
+                        None => reply,
+                    }
+                };
+                result.map(with_headers).map_err(with_headers)
+            }
+            _ => Err(Reply::error(404, "not-found")),
+        }
+    }
+
+    fn handle_token(&mut self, request: &Request, now: ServerTimestamp) -> ReplyResult {
+        if request.method != Method::Get {
+            return Err(Reply::error(405, "method-not-allowed"));
+        }
+        let has_access_token = request
+            .headers
+            .get(header_names::AUTHORIZATION)
+            .and_then(|auth| auth.strip_prefix("Bearer "))
+            .filter(|token| !token.is_empty())
+            .is_some();
+        if !has_access_token || request.headers.get(header_names::X_KEYID).is_none() {
+            return Err(Reply::error(401, "invalid-credentials"));
+        }
+        self.next_token += 1;
+        let id = format!("mock-token-{}", self.next_token);
+        self.tokens.insert(id.clone());
+        let api_endpoint = self
+            .base_url
+            .join(&format!("node-{}/1.5/{}", self.node, UID))
+            .unwrap();
+        Ok(Reply::json(
+            200,
+            &json!({
+                "id": id,
+                "key": format!("mock-key-{}", self.next_token),
+                "api_endpoint": api_endpoint.as_str(),
+                "uid": UID,
+                "duration": self.token_duration,
+                "hashed_fxa_uid": "mock-hashed-fxa-uid",
+            }),
+        )
+        .header(header_names::X_TIMESTAMP, now))
+    }
+
+    /// Checks that a storage request is for the current node, and uses a
+    /// token that we issued for it. We don't check the Hawk signature.
+    fn check_storage_auth(&self, request: &Request, node: &str, uid: &str) -> Result<(), Reply> {
+        let token_id = request
+            .headers
+            .get(header_names::AUTHORIZATION)
+            .and_then(|auth| auth.strip_prefix("Hawk "))
+            .and_then(|params| {
+                params.split(',').find_map(|param| {
+                    param
+                        .trim()
+                        .strip_prefix("id=\"")
+                        .and_then(|id| id.strip_suffix('"'))
+                })
+            });
+        let authorized = node == format!("node-{}", self.node)
+            && uid == UID.to_string()
+            && token_id.filter(|id| self.tokens.contains(*id)).is_some();
+        if authorized {
+            Ok(())
+        } else {
+            Err(Reply::error(401, "invalid-credentials"))
+        }
+    }
+
+    fn handle_storage(
+        &mut self,
+        request: &Request,
+        path: &[&str],
+        now: ServerTimestamp,
+    ) -> ReplyResult {
+        let unmodified_since = match request.headers.get(header_names::X_IF_UNMODIFIED_SINCE) {
+            Some(value) => Some(
+                value
+                    .parse::<ServerTimestamp>()
+                    .map_err(|_| Reply::error(400, "invalid x-if-unmodified-since"))?,
+            ),
+            None => None,
+        };
+        match (request.method, path) {
+            (Method::Delete, []) | (Method::Delete, ["storage"]) => {
+                self.storage.check_unmodified(unmodified_since)?;
+                let modified = self.storage.delete_all(now);
+                Ok(Reply::json(200, &json!({})).last_modified(modified))
+            }
+            (Method::Get, ["info", "collections"]) => {
+                Ok(Reply::json(200, &self.storage.info_collections())
+                    .last_modified(self.storage.last_modified()))
+            }
+            (Method::Get, ["info", "configuration"]) => {
+                Ok(Reply::json(200, &self.config).last_modified(self.storage.last_modified()))
+            }
+            (Method::Get, ["storage", collection]) => {
+                self.storage
+                    .check_collection_unmodified(collection, unmodified_since)?;
+                match self.fail_download.take() {
+                    Some((failing, 0)) if failing == *collection => {
+                        return Err(Reply::error(500, "download failed"));
+                    }
+                    Some((failing, remaining)) if failing == *collection => {
+                        self.fail_download = Some((failing, remaining - 1));
+                    }
+                    other => self.fail_download = other,
+                }
+                self.get_records(request, collection, now)
+            }
+            (Method::Post, ["storage", collection]) => {
+                self.storage
+                    .check_collection_unmodified(collection, unmodified_since)?;
+                self.post_records(request, collection, now)
+            }
+            (Method::Delete, ["storage", collection]) => {
+                self.storage
+                    .check_collection_unmodified(collection, unmodified_since)?;
+                let ids = query_param(&request.url, "ids")
+                    .map(|ids| ids.split(',').map(str::to_string).collect::<Vec<_>>());
+                let modified = self
+                    .storage
+                    .delete_collection(collection, ids.as_deref(), now)?;
+                Ok(Reply::json(200, &json!({ "modified": modified })).last_modified(modified))
+            }
+            (Method::Get, ["storage", collection, id]) => {
+                let record = self.storage.get_record(collection, id, now)?;
+                Ok(Reply::json(200, &record).last_modified(record.modified))
+            }
+            (Method::Put, ["storage", collection, id]) => {
+                self.storage
+                    .check_collection_unmodified(collection, unmodified_since)?;
+                let mut body = parse_body::<serde_json::Map<String, serde_json::Value>>(
+                    request,
+                    &self.config,
+                )?;
+                body.insert("id".into(), (*id).into());
+                let record = serde_json::from_value::<IncomingRecord>(body.into())
+                    .map_err(|_| Reply::error(400, "invalid record"))?;
+                let modified = self
+                    .storage
+                    .put_record(collection, record, &self.config, now)?;
+                Ok(Reply::json(200, &modified).last_modified(modified))
+            }
+            (Method::Delete, ["storage", collection, id]) => {
+                self.storage
+                    .check_collection_unmodified(collection, unmodified_since)?;
+                let modified = self.storage.delete_record(collection, id, now)?;
+                Ok(Reply::json(200, &json!({ "modified": modified })).last_modified(modified))
+            }
+            (_, ["info", _]) | (_, ["storage", ..]) | (_, []) => {
+                Err(Reply::error(405, "method-not-allowed"))
+            }
+            _ => Err(Reply::error(404, "not-found")),
+        }
+    }
+
+    fn get_records(
+        &self,
+        request: &Request,
+        collection: &str,
+        now: ServerTimestamp,
+    ) -> ReplyResult {
+        let url = &request.url;
+        let timestamp_param = |name| -> Result<Option<ServerTimestamp>, Reply> {
+            query_param(url, name)
+                .map(|value| {
+                    value
+                        .parse::<ServerTimestamp>()
+                        .map_err(|_| Reply::error(400, "invalid timestamp"))
+                })
+                .transpose()
+        };
+        let number_param = |name| -> Result<Option<usize>, Reply> {
+            query_param(url, name)
+                .map(|value| {
+                    value
+                        .parse::<usize>()
+                        .map_err(|_| Reply::error(400, "invalid number"))
+                })
+                .transpose()
+        };
+        let query = RecordQuery {
+            ids: query_param(url, "ids").map(|ids| ids.split(',').map(str::to_string).collect()),
+            newer: timestamp_param("newer")?,
+            older: timestamp_param("older")?,
+            sort: match query_param(url, "sort").as_deref() {
+                None | Some("newest") => Sort::Newest,
+                Some("oldest") => Sort::Oldest,
+                Some("index") => Sort::Index,
+                Some(_) => return Err(Reply::error(400, "invalid sort")),
+            },
+            // A limit of 0 means "no limit".
+            limit: number_param("limit")?.filter(|&limit| limit > 0),
+            offset: number_param("offset")?.unwrap_or_default(),
+        };
+        let page = self.storage.get_records(collection, &query, now);
+        let count = page.records.len();
+        let reply = if query_param(url, "full").is_some() {
+            Reply::json(200, &page.records)
+        } else {
+            let ids = page.records.iter().map(|r| &r.id).collect::<Vec<_>>();
+            Reply::json(200, &ids)
+        }
+        .last_modified(page.last_modified)
+        .header(header_names::X_WEAVE_RECORDS, count);
+        Ok(match page.next_offset {
+            Some(offset) => reply.header(header_names::X_WEAVE_NEXT_OFFSET, offset),
+            None => reply,
+        })
+    }
+
+    fn post_records(
+        &mut self,
+        request: &Request,
+        collection: &str,
+        now: ServerTimestamp,
+    ) -> ReplyResult {
+        let records = parse_body::<Vec<IncomingRecord>>(request, &self.config)?;
+        let batch = match query_param(&request.url, "batch").as_deref() {
+            None => BatchParam::None,
+            Some("true") => BatchParam::Start,
+            Some(id) => BatchParam::Existing(id.to_string()),
+        };
+        let commit = query_param(&request.url, "commit").as_deref() == Some("true");
+        let result =
+            self.storage
+                .post_records(collection, records, batch, commit, &self.config, now)?;
+        Ok(Reply::json(result.status, &result).last_modified(result.last_modified))
+    }
+}
+
+fn query_param(url: &Url, name: &str) -> Option<String> {
+    url.query_pairs()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+}
+
+fn parse_body<T>(request: &Request, config: &ServerConfiguration) -> Result<T, Reply>
+where
+    for<'a> T: serde::Deserialize<'a>,
+{
+    let body = request.body.as_deref().unwrap_or_default();
+    if body.len() > config.max_request_bytes {
+        return Err(StorageError::TooLarge.into());
+    }
+    serde_json::from_slice(body).map_err(|_| Reply::error(400, "invalid json"))
+}
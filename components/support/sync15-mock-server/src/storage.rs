@@ -0,0 +1,778 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The storage half of the mock server. This knows about collections,
+//! records, and batches, but nothing about HTTP; `server.rs` handles turning
+//! requests into calls on `Storage`, and results into responses.
+
+use serde_derive::*;
+use std::collections::{BTreeMap, HashMap};
+use sync15_traits::ServerTimestamp;
+
+/// The limits that the server advertises in `info/configuration`, and
+/// enforces for uploads. The defaults match the production servers.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ServerConfiguration {
+    pub max_request_bytes: usize,
+    pub max_post_records: usize,
+    pub max_post_bytes: usize,
+    pub max_total_records: usize,
+    pub max_total_bytes: usize,
+    pub max_record_payload_bytes: usize,
+}
+
+impl Default for ServerConfiguration {
+    fn default() -> Self {
+        Self {
+            max_request_bytes: 2_101_248,
+            max_post_records: 100,
+            max_post_bytes: 2_097_152,
+            max_total_records: 10_000,
+            max_total_bytes: 100_000_000,
+            max_record_payload_bytes: 256 * 1024,
+        }
+    }
+}
+
+/// A record stored on the server. This serializes to the same JSON that the
+/// server returns for full records.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ServerRecord {
+    pub id: String,
+    pub modified: ServerTimestamp,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sortindex: Option<i32>,
+    pub payload: String,
+    /// When the record expires, if it was uploaded with a TTL. Expired
+    /// records are never returned.
+    #[serde(skip)]
+    pub expires: Option<ServerTimestamp>,
+}
+
+impl ServerRecord {
+    fn is_expired(&self, now: ServerTimestamp) -> bool {
+        matches!(self.expires, Some(expires) if expires <= now)
+    }
+}
+
+/// A record in the body of a `POST` or `PUT`. All fields except the ID are
+/// optional, since uploads can update some fields of an existing record
+/// without changing the others.
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct IncomingRecord {
+    pub id: String,
+    #[serde(default)]
+    pub sortindex: Option<i32>,
+    #[serde(default)]
+    pub ttl: Option<u32>,
+    #[serde(default)]
+    pub payload: Option<String>,
+}
+
+const MAX_ID_LENGTH: usize = 64;
+const MAX_SORTINDEX: i32 = 999_999_999;
+
+impl IncomingRecord {
+    /// Returns the reason the server would reject this record, if any.
+    fn validate(&self, config: &ServerConfiguration) -> Option<&'static str> {
+        if self.id.is_empty()
+            || self.id.len() > MAX_ID_LENGTH
+            || !self.id.chars().all(|c| c.is_ascii_graphic() && c != '/')
+        {
+            return Some("invalid id");
+        }
+        if let Some(sortindex) = self.sortindex {
+            if sortindex.abs() > MAX_SORTINDEX {
+                return Some("invalid sortindex");
+            }
+        }
+        if let Some(payload) = &self.payload {
+            if payload.len() > config.max_record_payload_bytes {
+                return Some("retry bytes");
+            }
+        }
+        None
+    }
+
+    fn payload_len(&self) -> usize {
+        self.payload.as_ref().map_or(0, String::len)
+    }
+}
+
+/// Why the server refused a request.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum StorageError {
+    NotFound,
+    /// The target was modified after the `X-If-Unmodified-Since` timestamp.
+    PreconditionFailed,
+    BadRequest(&'static str),
+    /// The request exceeded one of the limits in `ServerConfiguration`.
+    TooLarge,
+}
+
+pub(crate) type Result<T> = std::result::Result<T, StorageError>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Sort {
+    Oldest,
+    Newest,
+    Index,
+}
+
+/// The filters for fetching records from a collection.
+#[derive(Debug, Clone)]
+pub(crate) struct RecordQuery {
+    pub ids: Option<Vec<String>>,
+    pub newer: Option<ServerTimestamp>,
+    pub older: Option<ServerTimestamp>,
+    pub sort: Sort,
+    pub limit: Option<usize>,
+    /// Offsets are opaque to clients, but they're just the index of the
+    /// first record to return.
+    pub offset: usize,
+}
+
+impl Default for RecordQuery {
+    fn default() -> Self {
+        Self {
+            ids: None,
+            newer: None,
+            older: None,
+            sort: Sort::Newest,
+            limit: None,
+            offset: 0,
+        }
+    }
+}
+
+impl RecordQuery {
+    fn matches(&self, record: &ServerRecord) -> bool {
+        if let Some(newer) = self.newer {
+            if record.modified <= newer {
+                return false;
+            }
+        }
+        if let Some(older) = self.older {
+            if record.modified >= older {
+                return false;
+            }
+        }
+        match &self.ids {
+            Some(ids) => ids.contains(&record.id),
+            None => true,
+        }
+    }
+}
+
+pub(crate) struct RecordsPage {
+    pub records: Vec<ServerRecord>,
+    pub next_offset: Option<usize>,
+    pub last_modified: ServerTimestamp,
+}
+
+/// Which batch a `POST` belongs to, from its `batch` query parameter.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum BatchParam {
+    None,
+    Start,
+    Existing(String),
+}
+
+/// The result of a `POST`. This is the same shape as the response body,
+/// except for `last_modified` and `status`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct PostResult {
+    #[serde(skip)]
+    pub status: u16,
+    #[serde(skip)]
+    pub last_modified: ServerTimestamp,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modified: Option<ServerTimestamp>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub batch: Option<String>,
+    pub success: Vec<String>,
+    pub failed: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Default)]
+struct Collection {
+    modified: ServerTimestamp,
+    records: HashMap<String, ServerRecord>,
+}
+
+#[derive(Debug)]
+struct Batch {
+    collection: String,
+    records: Vec<IncomingRecord>,
+    bytes: usize,
+}
+
+/// All the data for one user on one storage node.
+#[derive(Debug, Default)]
+pub(crate) struct Storage {
+    collections: HashMap<String, Collection>,
+    batches: HashMap<String, Batch>,
+    next_batch_id: u64,
+    modified: ServerTimestamp,
+}
+
+impl Storage {
+    /// The time of the last write to any collection.
+    pub fn last_modified(&self) -> ServerTimestamp {
+        self.modified
+    }
+
+    pub fn collection_modified(&self, collection: &str) -> ServerTimestamp {
+        self.collections
+            .get(collection)
+            .map_or(ServerTimestamp::EPOCH, |c| c.modified)
+    }
+
+    /// Returns the timestamps for `info/collections`.
+    pub fn info_collections(&self) -> BTreeMap<String, ServerTimestamp> {
+        self.collections
+            .iter()
+            .map(|(name, c)| (name.clone(), c.modified))
+            .collect()
+    }
+
+    pub fn get_record(
+        &self,
+        collection: &str,
+        id: &str,
+        now: ServerTimestamp,
+    ) -> Result<ServerRecord> {
+        self.collections
+            .get(collection)
+            .and_then(|c| c.records.get(id))
+            .filter(|record| !record.is_expired(now))
+            .cloned()
+            .ok_or(StorageError::NotFound)
+    }
+
+    /// Returns all unexpired records in a collection, oldest first.
+    pub fn all_records(&self, collection: &str, now: ServerTimestamp) -> Vec<ServerRecord> {
+        self.get_records(
+            collection,
+            &RecordQuery {
+                sort: Sort::Oldest,
+                ..RecordQuery::default()
+            },
+            now,
+        )
+        .records
+    }
+
+    pub fn get_records(
+        &self,
+        collection: &str,
+        query: &RecordQuery,
+        now: ServerTimestamp,
+    ) -> RecordsPage {
+        let coll = match self.collections.get(collection) {
+            Some(coll) => coll,
+            None => {
+                return RecordsPage {
+                    records: Vec::new(),
+                    next_offset: None,
+                    last_modified: ServerTimestamp::EPOCH,
+                }
+            }
+        };
+        let mut records = coll
+            .records
+            .values()
+            .filter(|record| !record.is_expired(now) && query.matches(record))
+            .cloned()
+            .collect::<Vec<_>>();
+        // Break ties by ID, so that paging through the results with `offset`
+        // is stable.
+        match query.sort {
+            Sort::Oldest => records.sort_by(|a, b| {
+                a.modified
+                    .partial_cmp(&b.modified)
+                    .unwrap()
+                    .then_with(|| a.id.cmp(&b.id))
+            }),
+            Sort::Newest => records.sort_by(|a, b| {
+                b.modified
+                    .partial_cmp(&a.modified)
+                    .unwrap()
+                    .then_with(|| a.id.cmp(&b.id))
+            }),
+            Sort::Index => records.sort_by(|a, b| {
+                b.sortindex
+                    .unwrap_or_default()
+                    .cmp(&a.sortindex.unwrap_or_default())
+                    .then_with(|| a.id.cmp(&b.id))
+            }),
+        }
+        let total = records.len();
+        let mut records = records.split_off(query.offset.min(total));
+        let mut next_offset = None;
+        if let Some(limit) = query.limit {
+            if records.len() > limit {
+                records.truncate(limit);
+                next_offset = Some(query.offset + limit);
+            }
+        }
+        RecordsPage {
+            records,
+            next_offset,
+            last_modified: coll.modified,
+        }
+    }
+
+    /// Fails with `PreconditionFailed` if the collection was modified after
+    /// `unmodified_since`.
+    pub fn check_collection_unmodified(
+        &self,
+        collection: &str,
+        unmodified_since: Option<ServerTimestamp>,
+    ) -> Result<()> {
+        match unmodified_since {
+            Some(since) if self.collection_modified(collection) > since => {
+                Err(StorageError::PreconditionFailed)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Like `check_collection_unmodified`, but for the whole storage.
+    pub fn check_unmodified(&self, unmodified_since: Option<ServerTimestamp>) -> Result<()> {
+        match unmodified_since {
+            Some(since) if self.modified > since => Err(StorageError::PreconditionFailed),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn put_record(
+        &mut self,
+        collection: &str,
+        record: IncomingRecord,
+        config: &ServerConfiguration,
+        now: ServerTimestamp,
+    ) -> Result<ServerTimestamp> {
+        if let Some(reason) = record.validate(config) {
+            return Err(StorageError::BadRequest(reason));
+        }
+        let exists = self.get_record(collection, &record.id, now).is_ok();
+        if !exists && record.payload.is_none() {
+            return Err(StorageError::BadRequest("missing payload"));
+        }
+        self.apply(collection, vec![record], now);
+        Ok(now)
+    }
+
+    pub fn post_records(
+        &mut self,
+        collection: &str,
+        records: Vec<IncomingRecord>,
+        batch: BatchParam,
+        commit: bool,
+        config: &ServerConfiguration,
+        now: ServerTimestamp,
+    ) -> Result<PostResult> {
+        if records.len() > config.max_post_records
+            || records
+                .iter()
+                .map(IncomingRecord::payload_len)
+                .sum::<usize>()
+                > config.max_post_bytes
+        {
+            return Err(StorageError::TooLarge);
+        }
+        if let BatchParam::Existing(id) = &batch {
+            match self.batches.get(id) {
+                Some(existing) if existing.collection == collection => {}
+                _ => return Err(StorageError::BadRequest("invalid batch")),
+            }
+        } else if commit && batch == BatchParam::None {
+            return Err(StorageError::BadRequest("commit without batch"));
+        }
+
+        let mut success = Vec::with_capacity(records.len());
+        let mut failed = BTreeMap::new();
+        let mut valid = Vec::with_capacity(records.len());
+        for record in records {
+            let reason = record.validate(config).or_else(|| {
+                // New records need a payload. We don't know if a record in a
+                // batch is new until the batch is committed, so we only check
+                // records that we're applying right away.
+                if batch == BatchParam::None
+                    && record.payload.is_none()
+                    && self.get_record(collection, &record.id, now).is_err()
+                {
+                    Some("missing payload")
+                } else {
+                    None
+                }
+            });
+            match reason {
+                Some(reason) => {
+                    failed.insert(record.id, reason.to_string());
+                }
+                None => {
+                    success.push(record.id.clone());
+                    valid.push(record);
+                }
+            }
+        }
+
+        let batch_id = match batch {
+            BatchParam::None => {
+                let modified = self.apply(collection, valid, now);
+                return Ok(PostResult {
+                    status: 200,
+                    last_modified: modified,
+                    modified: Some(modified),
+                    batch: None,
+                    success,
+                    failed,
+                });
+            }
+            BatchParam::Start => {
+                self.next_batch_id += 1;
+                let id = self.next_batch_id.to_string();
+                self.batches.insert(
+                    id.clone(),
+                    Batch {
+                        collection: collection.to_string(),
+                        records: Vec::new(),
+                        bytes: 0,
+                    },
+                );
+                id
+            }
+            BatchParam::Existing(id) => id,
+        };
+        let pending = self.batches.get_mut(&batch_id).unwrap();
+        pending.bytes += valid.iter().map(IncomingRecord::payload_len).sum::<usize>();
+        pending.records.extend(valid);
+        if pending.records.len() > config.max_total_records
+            || pending.bytes > config.max_total_bytes
+        {
+            self.batches.remove(&batch_id);
+            return Err(StorageError::TooLarge);
+        }
+        if !commit {
+            return Ok(PostResult {
+                status: 202,
+                last_modified: self.collection_modified(collection),
+                modified: None,
+                batch: Some(batch_id),
+                success,
+                failed,
+            });
+        }
+        let pending = self.batches.remove(&batch_id).unwrap();
+        // Records without payloads that don't exist yet fail the whole
+        // commit, like on the real server.
+        if pending.records.iter().any(|record| {
+            record.payload.is_none() && self.get_record(collection, &record.id, now).is_err()
+        }) {
+            return Err(StorageError::BadRequest("missing payload"));
+        }
+        let modified = self.apply(collection, pending.records, now);
+        Ok(PostResult {
+            status: 200,
+            last_modified: modified,
+            modified: Some(modified),
+            batch: None,
+            success,
+            failed,
+        })
+    }
+
+    pub fn delete_record(
+        &mut self,
+        collection: &str,
+        id: &str,
+        now: ServerTimestamp,
+    ) -> Result<ServerTimestamp> {
+        let coll = self
+            .collections
+            .get_mut(collection)
+            .ok_or(StorageError::NotFound)?;
+        coll.records.remove(id).ok_or(StorageError::NotFound)?;
+        coll.modified = now;
+        self.modified = now;
+        Ok(now)
+    }
+
+    /// Deletes some or all records in a collection. Deleting all records
+    /// removes the collection from `info/collections`.
+    pub fn delete_collection(
+        &mut self,
+        collection: &str,
+        ids: Option<&[String]>,
+        now: ServerTimestamp,
+    ) -> Result<ServerTimestamp> {
+        match ids {
+            Some(ids) => {
+                let coll = self
+                    .collections
+                    .get_mut(collection)
+                    .ok_or(StorageError::NotFound)?;
+                for id in ids {
+                    coll.records.remove(id);
+                }
+                coll.modified = now;
+            }
+            None => {
+                self.collections
+                    .remove(collection)
+                    .ok_or(StorageError::NotFound)?;
+                self.batches
+                    .retain(|_, batch| batch.collection != collection);
+            }
+        }
+        self.modified = now;
+        Ok(now)
+    }
+
+    pub fn delete_all(&mut self, now: ServerTimestamp) -> ServerTimestamp {
+        self.collections.clear();
+        self.batches.clear();
+        self.modified = now;
+        now
+    }
+
+    /// Writes records to a collection, and returns the collection's new
+    /// timestamp. All records written together get the same timestamp.
+    fn apply(
+        &mut self,
+        collection: &str,
+        records: Vec<IncomingRecord>,
+        now: ServerTimestamp,
+    ) -> ServerTimestamp {
+        let coll = self.collections.entry(collection.to_string()).or_default();
+        for incoming in records {
+            let expires = incoming
+                .ttl
+                .map(|ttl| ServerTimestamp(now.as_millis() + i64::from(ttl) * 1000));
+            match coll.records.get_mut(&incoming.id) {
+                Some(existing) if !existing.is_expired(now) => {
+                    existing.modified = now;
+                    if let Some(payload) = incoming.payload {
+                        existing.payload = payload;
+                    }
+                    if incoming.sortindex.is_some() {
+                        existing.sortindex = incoming.sortindex;
+                    }
+                    if incoming.ttl.is_some() {
+                        existing.expires = expires;
+                    }
+                }
+                _ => {
+                    let record = ServerRecord {
+                        id: incoming.id.clone(),
+                        modified: now,
+                        sortindex: incoming.sortindex,
+                        payload: incoming.payload.unwrap_or_default(),
+                        expires,
+                    };
+                    coll.records.insert(incoming.id, record);
+                }
+            }
+        }
+        coll.modified = now;
+        self.modified = now;
+        now
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: &str, payload: &str) -> IncomingRecord {
+        IncomingRecord {
+            id: id.to_string(),
+            sortindex: None,
+            ttl: None,
+            payload: Some(payload.to_string()),
+        }
+    }
+
+    fn ids(records: &[ServerRecord]) -> Vec<&str> {
+        records.iter().map(|r| r.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_batches() {
+        let config = ServerConfiguration::default();
+        let mut storage = Storage::default();
+        let started = storage
+            .post_records(
+                "history",
+                vec![record("aaaa", "{}"), record("", "{}")],
+                BatchParam::Start,
+                false,
+                &config,
+                ServerTimestamp(1000),
+            )
+            .unwrap();
+        assert_eq!(started.status, 202);
+        assert_eq!(started.success, vec!["aaaa".to_string()]);
+        assert_eq!(started.failed.get(""), Some(&"invalid id".to_string()));
+        let batch_id = started.batch.unwrap();
+
+        // Records in a batch aren't visible until it's committed.
+        assert!(storage
+            .all_records("history", ServerTimestamp(2000))
+            .is_empty());
+        assert_eq!(
+            storage.post_records(
+                "bookmarks",
+                vec![record("bbbb", "{}")],
+                BatchParam::Existing(batch_id.clone()),
+                true,
+                &config,
+                ServerTimestamp(2000),
+            ),
+            Err(StorageError::BadRequest("invalid batch"))
+        );
+        let committed = storage
+            .post_records(
+                "history",
+                vec![record("bbbb", "{}")],
+                BatchParam::Existing(batch_id),
+                true,
+                &config,
+                ServerTimestamp(3000),
+            )
+            .unwrap();
+        assert_eq!(committed.status, 200);
+        assert_eq!(committed.modified, Some(ServerTimestamp(3000)));
+        assert_eq!(
+            ids(&storage.all_records("history", ServerTimestamp(4000))),
+            vec!["aaaa", "bbbb"]
+        );
+        assert_eq!(
+            storage.collection_modified("history"),
+            ServerTimestamp(3000)
+        );
+        assert_eq!(
+            storage.check_collection_unmodified("history", Some(ServerTimestamp(2000))),
+            Err(StorageError::PreconditionFailed)
+        );
+        assert_eq!(
+            storage.check_collection_unmodified("history", Some(ServerTimestamp(3000))),
+            Ok(())
+        );
+
+        let too_many = ServerConfiguration {
+            max_total_records: 2,
+            ..ServerConfiguration::default()
+        };
+        let started = storage
+            .post_records(
+                "history",
+                vec![record("cccc", "{}"), record("dddd", "{}")],
+                BatchParam::Start,
+                false,
+                &too_many,
+                ServerTimestamp(5000),
+            )
+            .unwrap();
+        assert_eq!(
+            storage.post_records(
+                "history",
+                vec![record("eeee", "{}")],
+                BatchParam::Existing(started.batch.unwrap()),
+                true,
+                &too_many,
+                ServerTimestamp(6000),
+            ),
+            Err(StorageError::TooLarge)
+        );
+        assert_eq!(
+            storage.collection_modified("history"),
+            ServerTimestamp(3000)
+        );
+    }
+
+    #[test]
+    fn test_get_records() {
+        let config = ServerConfiguration::default();
+        let mut storage = Storage::default();
+        for (i, id) in ["aaaa", "bbbb", "cccc", "dddd"].iter().enumerate() {
+            storage
+                .put_record(
+                    "tabs",
+                    IncomingRecord {
+                        id: id.to_string(),
+                        sortindex: Some(i as i32),
+                        ttl: if *id == "dddd" { Some(1) } else { None },
+                        payload: Some("{}".to_string()),
+                    },
+                    &config,
+                    ServerTimestamp(1000 * (i as i64 + 1)),
+                )
+                .unwrap();
+        }
+        let now = ServerTimestamp(4500);
+        let page = storage.get_records(
+            "tabs",
+            &RecordQuery {
+                newer: Some(ServerTimestamp(1000)),
+                sort: Sort::Oldest,
+                limit: Some(2),
+                ..RecordQuery::default()
+            },
+            now,
+        );
+        assert_eq!(ids(&page.records), vec!["bbbb", "cccc"]);
+        assert_eq!(page.next_offset, Some(2));
+        assert_eq!(page.last_modified, ServerTimestamp(4000));
+        let page = storage.get_records(
+            "tabs",
+            &RecordQuery {
+                newer: Some(ServerTimestamp(1000)),
+                sort: Sort::Oldest,
+                limit: Some(2),
+                offset: 2,
+                ..RecordQuery::default()
+            },
+            now,
+        );
+        assert_eq!(ids(&page.records), vec!["dddd"]);
+        assert_eq!(page.next_offset, None);
+
+        // `dddd` expires one second after it was written.
+        let later = ServerTimestamp(6000);
+        let page = storage.get_records(
+            "tabs",
+            &RecordQuery {
+                sort: Sort::Index,
+                ..RecordQuery::default()
+            },
+            later,
+        );
+        assert_eq!(ids(&page.records), vec!["cccc", "bbbb", "aaaa"]);
+        let page = storage.get_records(
+            "tabs",
+            &RecordQuery {
+                ids: Some(vec!["aaaa".to_string(), "cccc".to_string()]),
+                older: Some(ServerTimestamp(3000)),
+                ..RecordQuery::default()
+            },
+            later,
+        );
+        assert_eq!(ids(&page.records), vec!["aaaa"]);
+
+        assert_eq!(
+            storage.delete_collection("tabs", Some(&["aaaa".to_string()]), later),
+            Ok(later)
+        );
+        assert_eq!(
+            storage.get_record("tabs", "aaaa", later),
+            Err(StorageError::NotFound)
+        );
+        assert_eq!(storage.delete_collection("tabs", None, later), Ok(later));
+        assert!(storage.info_collections().is_empty());
+        assert_eq!(storage.last_modified(), later);
+    }
+}
@@ -15,7 +15,7 @@ pub mod telemetry;
 pub use bridged_engine::{ApplyResults, BridgedEngine};
 pub use changeset::{IncomingChangeset, OutgoingChangeset, RecordChangeset};
 pub use payload::Payload;
-pub use request::{CollectionRequest, RequestOrder};
+pub use request::{CollectionRequest, DownloadOffset, RequestOrder};
 pub use server_timestamp::ServerTimestamp;
 pub use store::{CollSyncIds, Store, StoreSyncAssociation};
 pub use sync_guid::Guid;
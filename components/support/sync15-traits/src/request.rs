@@ -2,6 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 use crate::{Guid, ServerTimestamp};
+use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use url::{form_urlencoded as form, Url, UrlQuery};
 #[derive(Debug, Clone, PartialEq)]
@@ -10,6 +11,12 @@ pub struct CollectionRequest {
     pub full: bool,
     pub ids: Option<Vec<Guid>>,
     pub limit: usize,
+    /// If non-zero, records are downloaded in pages of this many records,
+    /// instead of all at once. `limit` still caps the total number of
+    /// records downloaded.
+    pub page_size: usize,
+    /// Continues a paged download from where it stopped.
+    pub resume: Option<DownloadOffset>,
     pub older: Option<ServerTimestamp>,
     pub newer: Option<ServerTimestamp>,
     pub order: Option<RequestOrder>,
@@ -28,6 +35,8 @@ impl CollectionRequest {
             full: false,
             ids: None,
             limit: 0,
+            page_size: 0,
+            resume: None,
             older: None,
             newer: None,
             order: None,
@@ -76,6 +85,18 @@ impl CollectionRequest {
         self
     }
 
+    #[inline]
+    pub fn page_size(mut self, num: usize) -> CollectionRequest {
+        self.page_size = num;
+        self
+    }
+
+    #[inline]
+    pub fn resume_from(mut self, resume: Option<DownloadOffset>) -> CollectionRequest {
+        self.resume = resume;
+        self
+    }
+
     #[inline]
     pub fn batch(mut self, batch: Option<String>) -> CollectionRequest {
         self.batch = batch;
@@ -121,6 +142,9 @@ impl CollectionRequest {
         if let Some(o) = self.order {
             pairs.append_pair("sort", o.as_str());
         }
+        if let Some(resume) = &self.resume {
+            pairs.append_pair("offset", &resume.offset);
+        }
         pairs.finish();
     }
 
@@ -140,6 +164,25 @@ impl CollectionRequest {
         Ok(base_url)
     }
 }
+/// Where a paged download stopped. Stores that want to resume interrupted
+/// downloads persist this, and pass it to `CollectionRequest::resume_from`
+/// in the next sync.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DownloadOffset {
+    /// The server's `X-Weave-Next-Offset` for the next page.
+    pub offset: String,
+    /// The collection's last modified time when the download started. This
+    /// becomes the new sync timestamp once the download finishes, so records
+    /// that change during the download are fetched in the next sync.
+    pub last_modified: ServerTimestamp,
+    /// The number of records downloaded so far. These count towards the
+    /// request's `limit`.
+    pub fetched: usize,
+    /// The `newer` parameter of the original request. Offsets are only valid
+    /// for the same query, so this overrides the resumed request's `newer`.
+    pub newer: Option<ServerTimestamp>,
+}
+
 #[derive(Debug)]
 pub struct UnacceptableBaseUrl(());
 
@@ -3,8 +3,8 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use crate::{
-    client::ClientData, telemetry, CollectionRequest, Guid, IncomingChangeset, OutgoingChangeset,
-    ServerTimestamp,
+    client::ClientData, telemetry, CollectionRequest, DownloadOffset, Guid, IncomingChangeset,
+    OutgoingChangeset, ServerTimestamp,
 };
 use failure::Error;
 
@@ -60,12 +60,44 @@ pub trait Store {
         telem: &mut telemetry::Engine,
     ) -> Result<OutgoingChangeset, Error>;
 
+    /// Called with each page of incoming records, for collection requests
+    /// that set a `page_size`. `next` is where the download will continue
+    /// from, or `None` if this is the last page.
+    ///
+    /// Stores that return `false` (the default) get all pages together in
+    /// `apply_incoming`, so the whole download is kept in memory. Stores that
+    /// sync large collections can apply each page here, or write it to a
+    /// staging table, and return `true`; the records are then left out of the
+    /// changeset passed to `apply_incoming`. Persisting `next` along with the
+    /// page lets a later sync resume an interrupted download, via
+    /// `CollectionRequest::resume_from`.
+    fn apply_incoming_page(
+        &self,
+        _page: &IncomingChangeset,
+        _next: Option<&DownloadOffset>,
+        _telem: &mut telemetry::Engine,
+    ) -> Result<bool, Error> {
+        Ok(false)
+    }
+
     fn sync_finished(
         &self,
         new_timestamp: ServerTimestamp,
         records_synced: Vec<Guid>,
     ) -> Result<(), Error>;
 
+    /// Returns the server timestamp passed to the last successful
+    /// `sync_finished`, or `None` if the store has never synced (or has been
+    /// reset since). Stores persist this themselves, and usually build their
+    /// collection requests from it.
+    ///
+    /// `sync15::StoreBridge` uses this to tell Desktop when the store last
+    /// synced, so stores that run as bridged engines should override it. The
+    /// default returns `None`, which makes every bridged sync a full sync.
+    fn get_last_sync(&self) -> Result<Option<ServerTimestamp>, Error> {
+        Ok(None)
+    }
+
     /// The store is responsible for building the collection request. Engines
     /// typically will store a lastModified timestamp and use that to build a
     /// request saying "give me full records since that date" - however, other
@@ -421,9 +421,25 @@ impl Engine {
         }
     }
 
+    /// Records the counts for incoming records. Engines that apply incoming
+    /// records in more than one step, like when downloading in pages, can
+    /// call this once per step, and the counts are added together.
     pub fn incoming(&mut self, inc: EngineIncoming) {
-        assert!(self.incoming.is_none());
-        self.incoming = Some(inc);
+        match &mut self.incoming {
+            Some(existing) => {
+                existing.applied += inc.applied;
+                existing.failed += inc.failed;
+                existing.new_failed += inc.new_failed;
+                existing.reconciled += inc.reconciled;
+            }
+            None => self.incoming = Some(inc),
+        }
+    }
+
+    /// Get the counts for incoming records, if any were recorded.
+    #[inline]
+    pub fn get_incoming(&self) -> Option<&EngineIncoming> {
+        self.incoming.as_ref()
     }
 
     pub fn outgoing(&mut self, out: EngineOutgoing) {
@@ -514,10 +530,15 @@ mod engine_tests {
         i.failed(2);
         let mut e = Engine::new("TestEngine");
         e.incoming(i);
+        // Counts from later steps are added to the earlier ones.
+        let mut i = EngineIncoming::new();
+        i.applied(2);
+        i.reconciled(3);
+        e.incoming(i);
         e.finished();
         assert_json(
             &e,
-            serde_json::json!({"name": "TestEngine", "when": 0.0, "incoming": {"applied": 1, "failed": 2}}),
+            serde_json::json!({"name": "TestEngine", "when": 0.0, "incoming": {"applied": 3, "failed": 2, "reconciled": 3}}),
         );
     }
 
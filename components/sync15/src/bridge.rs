@@ -0,0 +1,389 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::telemetry;
+use crate::{
+    CollSyncIds, IncomingChangeset, Payload, ServerTimestamp, Store, StoreSyncAssociation,
+};
+use interrupt_support::Interruptee;
+use std::mem;
+use std::sync::Mutex;
+use sync15_traits::{ApplyResults, BridgedEngine};
+use sync_guid::Guid;
+
+/// Runs a `Store` as a `BridgedEngine`, so that Desktop can sync it without
+/// any glue code that's specific to the store.
+///
+/// Desktop downloads and uploads records itself, so the bridge only stages
+/// incoming records until `apply`, and remembers the IDs of uploaded records
+/// until `sync_finished`. Stores persist their own last sync time, which the
+/// bridge reads with `Store::get_last_sync`, so it doesn't need any storage of
+/// its own.
+///
+/// Desktop only tracks one sync ID per collection, but stores also keep the
+/// global sync ID. The bridge keeps the store's existing global sync ID when
+/// the collection's sync ID changes, or makes up a new one if the store isn't
+/// connected yet.
+pub struct StoreBridge<S> {
+    store: S,
+    state: Mutex<BridgeState>,
+}
+
+#[derive(Default)]
+struct BridgeState {
+    /// The last sync time set by Desktop during this sync.
+    last_sync: Option<ServerTimestamp>,
+    incoming: Vec<Payload>,
+    uploaded: Vec<Guid>,
+    /// When the server last accepted an upload during this sync.
+    uploaded_at: Option<ServerTimestamp>,
+}
+
+impl<S: Store> StoreBridge<S> {
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            state: Mutex::default(),
+        }
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+
+    fn last_sync_timestamp(&self) -> Result<ServerTimestamp, failure::Error> {
+        if let Some(last_sync) = self.state.lock().unwrap().last_sync {
+            return Ok(last_sync);
+        }
+        Ok(self.store.get_last_sync()?.unwrap_or_default())
+    }
+
+    fn sync_ids_for(&self, coll: Guid) -> Result<CollSyncIds, failure::Error> {
+        let global = match self.store.get_sync_assoc()? {
+            StoreSyncAssociation::Connected(ids) => ids.global,
+            StoreSyncAssociation::Disconnected => Guid::random(),
+        };
+        Ok(CollSyncIds { global, coll })
+    }
+
+    fn reset_with_sync_ids(&self, ids: CollSyncIds) -> Result<(), failure::Error> {
+        self.store.reset(&StoreSyncAssociation::Connected(ids))?;
+        *self.state.lock().unwrap() = BridgeState::default();
+        Ok(())
+    }
+}
+
+impl<S: Store> BridgedEngine for StoreBridge<S> {
+    type Error = failure::Error;
+
+    fn last_sync(&self) -> Result<i64, Self::Error> {
+        Ok(self.last_sync_timestamp()?.as_millis())
+    }
+
+    fn set_last_sync(&self, last_sync_millis: i64) -> Result<(), Self::Error> {
+        self.state.lock().unwrap().last_sync = Some(ServerTimestamp::from_millis(last_sync_millis));
+        Ok(())
+    }
+
+    fn sync_id(&self) -> Result<Option<String>, Self::Error> {
+        Ok(match self.store.get_sync_assoc()? {
+            StoreSyncAssociation::Connected(ids) => Some(ids.coll.into()),
+            StoreSyncAssociation::Disconnected => None,
+        })
+    }
+
+    fn reset_sync_id(&self) -> Result<String, Self::Error> {
+        let ids = self.sync_ids_for(Guid::random())?;
+        let new_sync_id = ids.coll.to_string();
+        self.reset_with_sync_ids(ids)?;
+        Ok(new_sync_id)
+    }
+
+    fn ensure_current_sync_id(&self, new_sync_id: &str) -> Result<String, Self::Error> {
+        if let StoreSyncAssociation::Connected(ids) = self.store.get_sync_assoc()? {
+            if ids.coll == new_sync_id {
+                return Ok(new_sync_id.to_string());
+            }
+        }
+        let ids = self.sync_ids_for(new_sync_id.into())?;
+        self.reset_with_sync_ids(ids)?;
+        Ok(new_sync_id.to_string())
+    }
+
+    fn store_incoming(
+        &self,
+        incoming_cleartexts: &[String],
+        signal: &dyn Interruptee,
+    ) -> Result<(), Self::Error> {
+        let mut incoming = Vec::with_capacity(incoming_cleartexts.len());
+        for cleartext in incoming_cleartexts {
+            signal.err_if_interrupted()?;
+            incoming.push(serde_json::from_str::<Payload>(cleartext)?);
+        }
+        self.state.lock().unwrap().incoming.append(&mut incoming);
+        Ok(())
+    }
+
+    fn apply(&self, signal: &dyn Interruptee) -> Result<ApplyResults, Self::Error> {
+        signal.err_if_interrupted()?;
+        let timestamp = self.last_sync_timestamp()?;
+        let incoming = mem::take(&mut self.state.lock().unwrap().incoming);
+        let mut inbound = IncomingChangeset::new(self.store.collection_name(), timestamp);
+        // Desktop doesn't tell us when each record was modified on the server,
+        // so the best we can do is when the collection was.
+        inbound.changes = incoming
+            .into_iter()
+            .map(|payload| (payload, timestamp))
+            .collect();
+        let mut telem = telemetry::Engine::new(self.store.collection_name());
+        let outgoing = self.store.apply_incoming(vec![inbound], &mut telem)?;
+        let records = outgoing
+            .changes
+            .into_iter()
+            .map(Payload::into_json_string)
+            .collect();
+        let num_reconciled = telem
+            .get_incoming()
+            .map(|incoming| incoming.get_reconciled() as usize);
+        Ok(ApplyResults::new(records, num_reconciled))
+    }
+
+    fn set_uploaded(
+        &self,
+        server_modified_millis: i64,
+        ids: &[String],
+        signal: &dyn Interruptee,
+    ) -> Result<(), Self::Error> {
+        signal.err_if_interrupted()?;
+        let server_modified = ServerTimestamp::from_millis(server_modified_millis);
+        let mut state = self.state.lock().unwrap();
+        state
+            .uploaded
+            .extend(ids.iter().map(|id| Guid::from(id.as_str())));
+        state.uploaded_at = match state.uploaded_at {
+            Some(uploaded_at) if uploaded_at > server_modified => Some(uploaded_at),
+            _ => Some(server_modified),
+        };
+        Ok(())
+    }
+
+    fn sync_finished(&self, signal: &dyn Interruptee) -> Result<(), Self::Error> {
+        signal.err_if_interrupted()?;
+        let last_sync = self.last_sync_timestamp()?;
+        let (uploaded, uploaded_at) = {
+            let mut state = self.state.lock().unwrap();
+            (mem::take(&mut state.uploaded), state.uploaded_at.take())
+        };
+        // Like `sync::synchronize`, the store's new last sync time is when
+        // the server accepted our upload, unless Desktop has already moved
+        // past it.
+        let timestamp = match uploaded_at {
+            Some(uploaded_at) if uploaded_at > last_sync => uploaded_at,
+            _ => last_sync,
+        };
+        self.store.sync_finished(timestamp, uploaded)?;
+        Ok(())
+    }
+
+    fn reset(&self) -> Result<(), Self::Error> {
+        self.store.reset(&StoreSyncAssociation::Disconnected)?;
+        *self.state.lock().unwrap() = BridgeState::default();
+        Ok(())
+    }
+
+    fn wipe(&self) -> Result<(), Self::Error> {
+        self.store.wipe()?;
+        *self.state.lock().unwrap() = BridgeState::default();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CollectionRequest, OutgoingChangeset};
+    use interrupt_support::NeverInterrupts;
+    use std::cell::RefCell;
+
+    /// A store that keeps its records and sync metadata in memory, and
+    /// uploads everything it has.
+    #[derive(Default)]
+    struct TestStore {
+        records: RefCell<Vec<Payload>>,
+        last_sync: RefCell<ServerTimestamp>,
+        assoc: RefCell<Option<CollSyncIds>>,
+        synced: RefCell<Vec<Guid>>,
+    }
+
+    impl Store for TestStore {
+        fn collection_name(&self) -> std::borrow::Cow<'static, str> {
+            "test".into()
+        }
+
+        fn apply_incoming(
+            &self,
+            inbound: Vec<IncomingChangeset>,
+            telem: &mut telemetry::Engine,
+        ) -> Result<OutgoingChangeset, failure::Error> {
+            assert_eq!(inbound.len(), 1);
+            let inbound = inbound.into_iter().next().unwrap();
+            let mut incoming_telemetry = telemetry::EngineIncoming::new();
+            let mut records = self.records.borrow_mut();
+            for (payload, _) in inbound.changes {
+                if records.iter().any(|record| record.id == payload.id) {
+                    incoming_telemetry.reconciled(1);
+                } else {
+                    incoming_telemetry.applied(1);
+                    records.push(payload);
+                }
+            }
+            telem.incoming(incoming_telemetry);
+            *self.last_sync.borrow_mut() = inbound.timestamp;
+            let mut outgoing = OutgoingChangeset::new("test", inbound.timestamp);
+            outgoing.changes = records.clone();
+            Ok(outgoing)
+        }
+
+        fn sync_finished(
+            &self,
+            new_timestamp: ServerTimestamp,
+            records_synced: Vec<Guid>,
+        ) -> Result<(), failure::Error> {
+            *self.last_sync.borrow_mut() = new_timestamp;
+            *self.synced.borrow_mut() = records_synced;
+            Ok(())
+        }
+
+        fn get_last_sync(&self) -> Result<Option<ServerTimestamp>, failure::Error> {
+            let last_sync = *self.last_sync.borrow();
+            Ok(if last_sync == ServerTimestamp::EPOCH {
+                None
+            } else {
+                Some(last_sync)
+            })
+        }
+
+        fn get_collection_requests(
+            &self,
+            server_timestamp: ServerTimestamp,
+        ) -> Result<Vec<CollectionRequest>, failure::Error> {
+            let since = *self.last_sync.borrow();
+            Ok(if since == server_timestamp {
+                vec![]
+            } else {
+                vec![CollectionRequest::new("test").full().newer_than(since)]
+            })
+        }
+
+        fn get_sync_assoc(&self) -> Result<StoreSyncAssociation, failure::Error> {
+            Ok(match &*self.assoc.borrow() {
+                Some(ids) => StoreSyncAssociation::Connected(ids.clone()),
+                None => StoreSyncAssociation::Disconnected,
+            })
+        }
+
+        fn reset(&self, assoc: &StoreSyncAssociation) -> Result<(), failure::Error> {
+            *self.last_sync.borrow_mut() = ServerTimestamp::EPOCH;
+            *self.assoc.borrow_mut() = match assoc {
+                StoreSyncAssociation::Connected(ids) => Some(ids.clone()),
+                StoreSyncAssociation::Disconnected => None,
+            };
+            Ok(())
+        }
+
+        fn wipe(&self) -> Result<(), failure::Error> {
+            self.records.borrow_mut().clear();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_sync_ids() -> Result<(), failure::Error> {
+        let bridge = StoreBridge::new(TestStore::default());
+        assert_eq!(bridge.sync_id()?, None);
+
+        let sync_id = bridge.reset_sync_id()?;
+        assert_eq!(bridge.sync_id()?, Some(sync_id.clone()));
+        let global = match bridge.store().get_sync_assoc()? {
+            StoreSyncAssociation::Connected(ids) => ids.global,
+            StoreSyncAssociation::Disconnected => panic!("Should be connected"),
+        };
+
+        // A matching sync ID shouldn't reset the store.
+        *bridge.store().last_sync.borrow_mut() = ServerTimestamp(1_000);
+        assert_eq!(bridge.ensure_current_sync_id(&sync_id)?, sync_id);
+        assert_eq!(bridge.last_sync()?, 1_000);
+
+        // A different one should, but keep the global sync ID.
+        assert_eq!(
+            bridge.ensure_current_sync_id("abcdefghijkl")?,
+            "abcdefghijkl"
+        );
+        assert_eq!(bridge.last_sync()?, 0);
+        assert_eq!(
+            bridge.store().get_sync_assoc()?,
+            StoreSyncAssociation::Connected(CollSyncIds {
+                global,
+                coll: "abcdefghijkl".into(),
+            })
+        );
+
+        bridge.reset()?;
+        assert_eq!(bridge.sync_id()?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sync() -> Result<(), failure::Error> {
+        let store = TestStore::default();
+        store.records.borrow_mut().push(Payload::from_json(
+            serde_json::json!({ "id": "aaaaaaaaaaaa" }),
+        )?);
+        let bridge = StoreBridge::new(store);
+        bridge.ensure_current_sync_id("abcdefghijkl")?;
+        assert_eq!(bridge.last_sync()?, 0);
+
+        bridge.store_incoming(
+            &[
+                r#"{"id": "aaaaaaaaaaaa"}"#.to_string(),
+                r#"{"id": "bbbbbbbbbbbb", "title": "B"}"#.to_string(),
+            ],
+            &NeverInterrupts,
+        )?;
+        bridge.set_last_sync(1_000)?;
+        let results = bridge.apply(&NeverInterrupts)?;
+        assert_eq!(results.records.len(), 2);
+        assert_eq!(results.num_reconciled, Some(1));
+        assert_eq!(*bridge.store().last_sync.borrow(), ServerTimestamp(1_000));
+
+        bridge.set_uploaded(
+            2_000,
+            &["aaaaaaaaaaaa".to_string(), "bbbbbbbbbbbb".to_string()],
+            &NeverInterrupts,
+        )?;
+        bridge.set_last_sync(2_000)?;
+        bridge.sync_finished(&NeverInterrupts)?;
+        assert_eq!(*bridge.store().last_sync.borrow(), ServerTimestamp(2_000));
+        assert_eq!(
+            *bridge.store().synced.borrow(),
+            vec![Guid::from("aaaaaaaaaaaa"), Guid::from("bbbbbbbbbbbb")]
+        );
+
+        // A new bridge for the same store should pick up its last sync time.
+        let bridge = StoreBridge::new(bridge.into_inner());
+        assert_eq!(bridge.last_sync()?, 2_000);
+
+        // If the upload finishes after the last sync time Desktop set, the
+        // store should remember when the server accepted it.
+        bridge.set_last_sync(2_500)?;
+        bridge.set_uploaded(3_000, &["aaaaaaaaaaaa".to_string()], &NeverInterrupts)?;
+        bridge.sync_finished(&NeverInterrupts)?;
+        assert_eq!(*bridge.store().last_sync.borrow(), ServerTimestamp(3_000));
+        Ok(())
+    }
+}
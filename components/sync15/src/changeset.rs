@@ -6,9 +6,13 @@ use crate::bso_record::{CleartextBso, EncryptedBso};
 use crate::client::{Sync15ClientResponse, Sync15StorageClient};
 use crate::error::{self, ErrorKind, ErrorResponse, Result};
 use crate::key_bundle::KeyBundle;
-use crate::request::{CollectionRequest, NormalResponseHandler, UploadInfo};
+use crate::request::{
+    CollectionRequest, DownloadOffset, NormalResponseHandler, RequestOrder, UploadInfo,
+};
+use crate::telemetry;
 use crate::util::ServerTimestamp;
-use crate::CollState;
+use crate::{CollState, Store};
+use interrupt_support::Interruptee;
 use std::borrow::Cow;
 
 pub use sync15_traits::{IncomingChangeset, OutgoingChangeset, RecordChangeset};
@@ -55,6 +59,94 @@ pub fn fetch_incoming(
     Ok(result)
 }
 
+/// Like `fetch_incoming`, but downloads records in pages of
+/// `collection_request.page_size`, and hands each page to the store as it
+/// arrives. Records from pages that the store doesn't apply itself are
+/// returned in the changeset.
+pub fn fetch_incoming_pages(
+    client: &Sync15StorageClient,
+    state: &mut CollState,
+    collection_request: &CollectionRequest,
+    store: &dyn Store,
+    telem: &mut telemetry::Engine,
+    interruptee: &dyn Interruptee,
+) -> Result<IncomingChangeset> {
+    // Paging with offsets is only safe if records written while we're
+    // downloading can't shift records we haven't seen yet past the offset.
+    // Newest-first order puts new writes at the front, so the worst case is
+    // that we see some records twice.
+    let mut request = collection_request
+        .clone()
+        .sort_by(RequestOrder::Newest)
+        .resume_from(None);
+    let limit = collection_request.limit;
+    let mut resume = collection_request.resume.clone();
+    if let Some(resume) = &resume {
+        request.newer = resume.newer;
+    }
+    // The timestamp of the collection when the first page was downloaded.
+    // Anything newer than this will be downloaded again by the next sync.
+    let mut snapshot = resume.as_ref().map(|r| r.last_modified);
+    let mut fetched = resume.as_ref().map_or(0, |r| r.fetched);
+    let mut changes = Vec::new();
+    while limit == 0 || fetched < limit {
+        interruptee.err_if_interrupted()?;
+        let mut page_size = collection_request.page_size;
+        if limit > 0 {
+            page_size = page_size.min(limit - fetched);
+        }
+        let page_request = request.clone().limit(page_size).resume_from(resume.clone());
+        let (records, last_modified, next_offset) =
+            match client.get_encrypted_records_page(&page_request)? {
+                (
+                    Sync15ClientResponse::Success {
+                        record,
+                        last_modified,
+                        ..
+                    },
+                    next_offset,
+                ) => (record, last_modified, next_offset),
+                (other, _) => return Err(other.create_storage_error().into()),
+            };
+        let timestamp = *snapshot.get_or_insert(last_modified);
+        let mut page = IncomingChangeset::new(request.collection.clone(), timestamp);
+        page.changes.reserve(records.len());
+        for record in records {
+            // See `fetch_incoming` for why we don't handle HMAC errors here.
+            let decrypted = record.decrypt(&state.key)?;
+            page.changes.push(decrypted.into_timestamped_payload());
+        }
+        fetched += page.changes.len();
+        log::info!(
+            "Downloaded page of {} records ({} so far)",
+            page.changes.len(),
+            fetched
+        );
+        // Once we've reached the limit, we're done, even if the server has
+        // more records.
+        let next = next_offset
+            .filter(|_| limit == 0 || fetched < limit)
+            .map(|offset| DownloadOffset {
+                offset,
+                last_modified: timestamp,
+                fetched,
+                newer: request.newer,
+            });
+        if !store.apply_incoming_page(&page, next.as_ref(), telem)? {
+            changes.append(&mut page.changes);
+        }
+        resume = next;
+        if resume.is_none() {
+            break;
+        }
+    }
+    let timestamp = snapshot.unwrap_or(state.last_modified);
+    state.last_modified = timestamp;
+    let mut result = IncomingChangeset::new(request.collection, timestamp);
+    result.changes = changes;
+    Ok(result)
+}
+
 #[derive(Debug, Clone)]
 pub struct CollectionUpdate<'a> {
     client: &'a Sync15StorageClient,
@@ -114,7 +206,10 @@ impl<'a> CollectionUpdate<'a> {
 
     /// Returns a list of the IDs that failed if allowed_dropped_records is true, otherwise
     /// returns an empty vec.
-    pub fn upload(self) -> error::Result<UploadInfo> {
+    ///
+    /// If the upload is interrupted, any batch that wasn't committed yet is
+    /// discarded by the server.
+    pub fn upload(self, interruptee: &dyn Interruptee) -> error::Result<UploadInfo> {
         let mut failed = vec![];
         let mut q = self.client.new_post_queue(
             &self.collection,
@@ -124,6 +219,9 @@ impl<'a> CollectionUpdate<'a> {
         )?;
 
         for record in self.to_update.into_iter() {
+            // Enqueueing a record posts the ones before it if the queue is
+            // full, so checking here means we stop between requests.
+            interruptee.err_if_interrupted()?;
             let enqueued = q.enqueue(&record)?;
             if !enqueued && self.fully_atomic {
                 return Err(ErrorKind::RecordTooLargeError.into());
@@ -143,3 +241,64 @@ impl<'a> CollectionUpdate<'a> {
         Ok(info)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::InfoConfiguration;
+    use crate::Sync15StorageClientInit;
+    use serde_json::json;
+    use sync15_mock_server::MockSyncServer;
+    use sync15_traits::Payload;
+    use viaduct::Method;
+
+    /// Interrupts the upload once the server has handled a POST.
+    struct InterruptAfterPost<'a>(&'a MockSyncServer);
+
+    impl<'a> Interruptee for InterruptAfterPost<'a> {
+        fn was_interrupted(&self) -> bool {
+            self.0.requests().iter().any(|r| r.method == Method::Post)
+        }
+    }
+
+    #[test]
+    fn test_upload_interrupted() -> Result<()> {
+        let server = MockSyncServer::new();
+        let client = Sync15StorageClient::new(Sync15StorageClientInit {
+            key_id: "key-id".into(),
+            access_token: "access-token".into(),
+            tokenserver_url: server.tokenserver_url(),
+        })?;
+        // Two records per POST, so uploading five records takes three.
+        let state = CollState {
+            config: InfoConfiguration {
+                max_post_records: 2,
+                ..InfoConfiguration::default()
+            },
+            last_modified: ServerTimestamp(0),
+            key: KeyBundle::new_random()?,
+        };
+        let mut changeset = OutgoingChangeset::new("history", ServerTimestamp(0));
+        for i in 0..5 {
+            changeset
+                .changes
+                .push(Payload::from_json(json!({ "id": format!("record{:06}", i) })).unwrap());
+        }
+
+        match CollectionUpdate::new_from_changeset(&client, &state, changeset, false)?
+            .upload(&InterruptAfterPost(&server))
+        {
+            Err(e) => assert!(matches!(e.kind(), ErrorKind::Interrupted(_))),
+            Ok(_) => panic!("Upload should be interrupted"),
+        }
+        let posts = server
+            .requests()
+            .into_iter()
+            .filter(|r| r.method == Method::Post)
+            .count();
+        assert_eq!(posts, 1);
+        // The batch wasn't committed, so the server shouldn't have any records.
+        assert!(server.records("history").is_empty());
+        Ok(())
+    }
+}
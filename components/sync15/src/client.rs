@@ -285,6 +285,25 @@ impl Sync15StorageClient {
         self.collection_request(Method::Get, collection_request)
     }
 
+    /// Like `get_encrypted_records`, but also returns the `X-Weave-Next-Offset`
+    /// header, which is set if the request had a `limit` and there are more
+    /// records to fetch.
+    pub fn get_encrypted_records_page(
+        &self,
+        collection_request: &CollectionRequest,
+    ) -> error::Result<(Sync15ClientResponse<Vec<EncryptedBso>>, Option<String>)> {
+        let url = collection_request.build_url(Url::parse(&self.tsc.api_endpoint()?)?)?;
+        let req = self.build_request(Method::Get, url)?;
+        log::trace!("request: GET {} ({:?})", req.url.path(), req.url.query());
+        let resp = req.send()?;
+        let next_offset = resp
+            .headers
+            .get(header_names::X_WEAVE_NEXT_OFFSET)
+            .map(ToString::to_string);
+        let result = Sync15ClientResponse::from_response(resp, &self.backoff)?;
+        Ok((result, next_offset))
+    }
+
     #[inline]
     fn authorized(&self, req: Request) -> error::Result<Request> {
         let hawk_header_value = self.tsc.authorization(&req)?;
@@ -5,6 +5,7 @@
 #![allow(unknown_lints, clippy::implicit_hasher)]
 #![warn(rust_2018_idioms)]
 
+mod bridge;
 mod bso_record;
 pub mod changeset;
 mod client;
@@ -25,6 +26,7 @@ mod token;
 mod util;
 
 // Re-export some of the types callers are likely to want for convenience.
+pub use crate::bridge::StoreBridge;
 pub use crate::bso_record::{BsoRecord, CleartextBso, EncryptedBso, EncryptedPayload, Payload};
 pub use crate::changeset::{IncomingChangeset, OutgoingChangeset, RecordChangeset};
 pub use crate::client::{
@@ -35,7 +37,7 @@ pub use crate::collection_keys::CollectionKeys;
 pub use crate::error::{Error, ErrorKind, Result};
 pub use crate::key_bundle::KeyBundle;
 pub use crate::migrate_state::extract_v1_state;
-pub use crate::request::CollectionRequest;
+pub use crate::request::{CollectionRequest, DownloadOffset};
 pub use crate::state::{GlobalState, SetupStateMachine};
 pub use crate::status::{ServiceStatus, SyncResult};
 pub use crate::sync::{synchronize, Store};
@@ -10,7 +10,7 @@ use serde_derive::*;
 use std::collections::HashMap;
 use std::default::Default;
 use std::ops::Deref;
-pub use sync15_traits::{CollectionRequest, RequestOrder};
+pub use sync15_traits::{CollectionRequest, DownloadOffset, RequestOrder};
 use sync_guid::Guid;
 use viaduct::status_codes;
 
@@ -542,10 +542,27 @@ mod test {
             .sort_by(RequestOrder::Oldest)
             .older_than(ServerTimestamp(9_876_540))
             .newer_than(ServerTimestamp(1_234_560))
-            .build_url(base)
+            .build_url(base.clone())
             .unwrap();
         assert_eq!(complex.as_str(),
             "https://example.com/sync/storage/specific?full=1&limit=10&older=9876.54&newer=1234.56&sort=oldest");
+
+        let resumed = CollectionRequest::new("paged")
+            .full()
+            .limit(100)
+            .sort_by(RequestOrder::Newest)
+            .resume_from(Some(DownloadOffset {
+                offset: "200".into(),
+                last_modified: ServerTimestamp(1_234_560),
+                fetched: 200,
+                newer: None,
+            }))
+            .build_url(base)
+            .unwrap();
+        assert_eq!(
+            resumed.as_str(),
+            "https://example.com/sync/storage/paged?full=1&limit=100&sort=newest&offset=200"
+        );
     }
 
     #[derive(Debug, Clone)]
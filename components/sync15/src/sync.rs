@@ -80,8 +80,18 @@ pub fn synchronize_with_clients_engine(
             .enumerate()
             .map(|(idx, collection_request)| {
                 interruptee.err_if_interrupted()?;
-                let incoming_changes =
-                    crate::changeset::fetch_incoming(client, &mut coll_state, &collection_request)?;
+                let incoming_changes = if collection_request.page_size > 0 {
+                    crate::changeset::fetch_incoming_pages(
+                        client,
+                        &mut coll_state,
+                        &collection_request,
+                        store,
+                        telem_engine,
+                        interruptee,
+                    )?
+                } else {
+                    crate::changeset::fetch_incoming(client, &mut coll_state, &collection_request)?
+                };
 
                 log::info!(
                     "Downloaded {} remote changes (request {} of {})",
@@ -106,7 +116,7 @@ pub fn synchronize_with_clients_engine(
     log::info!("Uploading {} outgoing changes", outgoing.changes.len());
     let upload_info =
         CollectionUpdate::new_from_changeset(client, &coll_state, outgoing, fully_atomic)?
-            .upload()?;
+            .upload(interruptee)?;
 
     log::info!(
         "Upload success ({} records success, {} records failed)",
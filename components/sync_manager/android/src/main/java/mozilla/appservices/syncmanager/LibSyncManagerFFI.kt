@@ -6,6 +6,7 @@ package mozilla.appservices.syncmanager
 
 import com.sun.jna.Library
 import com.sun.jna.Pointer
+import com.sun.jna.PointerType
 import mozilla.appservices.support.native.RustBuffer
 import mozilla.appservices.support.native.loadIndirect
 import org.mozilla.appservices.syncmanager.BuildConfig
@@ -23,10 +24,16 @@ internal interface LibSyncManagerFFI : Library {
 
     fun sync_manager_sync(data: Pointer, len: Int, error: RustError.ByReference): RustBuffer.ByValue
 
+    fun sync_manager_new_interrupt_handle(error: RustError.ByReference): RawSyncInterruptHandle?
+    fun sync_manager_interrupt(handle: RawSyncInterruptHandle, error: RustError.ByReference)
+
     fun sync_manager_destroy_string(s: Pointer)
     fun sync_manager_destroy_bytebuffer(bb: RustBuffer.ByValue)
+    fun sync_manager_interrupt_handle_destroy(handle: RawSyncInterruptHandle)
 }
 
 internal typealias PlacesApiHandle = Long
 internal typealias LoginsDbHandle = Long
 internal typealias TabsApiHandle = Long
+
+class RawSyncInterruptHandle : PointerType()
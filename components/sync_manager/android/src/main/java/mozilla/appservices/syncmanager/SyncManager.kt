@@ -8,6 +8,13 @@ import com.sun.jna.Native
 import mozilla.appservices.support.native.toNioDirectBuffer
 
 object SyncManager {
+    // The sync manager only has one interrupt handle, which lives as long as
+    // the process, so we never destroy it.
+    private val interruptHandle: RawSyncInterruptHandle by lazy {
+        rustCall { err ->
+            LibSyncManagerFFI.INSTANCE.sync_manager_new_interrupt_handle(err)
+        }!!
+    }
 
     /**
      * Point the manager at the implementation of `PlacesApi` to use.
@@ -54,6 +61,23 @@ object SyncManager {
             LibSyncManagerFFI.INSTANCE.sync_manager_disconnect(err)
         }
     }
+    /**
+     * Interrupt the sync that's in progress, if there is one, like when the
+     * app goes into the background. The interrupted sync returns a [SyncResult]
+     * with the [SyncServiceStatus.INTERRUPTED] status. This can be called from
+     * any thread.
+     *
+     * Only a sync that has already started is interrupted. A [sync] call
+     * that's still waiting for another sync to finish isn't, and runs as
+     * usual once that sync is over, so callers that want to stop it
+     * shouldn't start it, or should interrupt again after it starts.
+     */
+    fun interrupt() {
+        rustCall { err ->
+            LibSyncManagerFFI.INSTANCE.sync_manager_interrupt(interruptHandle, err)
+        }
+    }
+
     /**
      * Perform a sync.
      */
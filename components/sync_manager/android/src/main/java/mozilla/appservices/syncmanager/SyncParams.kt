@@ -63,6 +63,38 @@ data class DeviceSettings(
     val type: DeviceType
 )
 
+/**
+ * Limits for syncing history. Any limit left `null` uses the default.
+ *
+ * @throws IllegalArgumentException if any limit is negative.
+ */
+data class HistorySyncSettings(
+    /**
+     * The maximum number of pages to download in each sync.
+     */
+    val maxIncomingPlaces: Int? = null,
+    /**
+     * The maximum number of changed pages to upload in each sync.
+     */
+    val maxOutgoingPlaces: Int? = null,
+    /**
+     * The maximum number of visits to sync for each page.
+     */
+    val maxVisits: Int? = null,
+    /**
+     * If set, only visits from this many days before now are synced.
+     */
+    val maxAgeDays: Int? = null
+) {
+    init {
+        // These are sent as unsigned integers, so a negative limit would
+        // wrap around to a very large one.
+        listOf(maxIncomingPlaces, maxOutgoingPlaces, maxVisits, maxAgeDays).forEach { limit ->
+            require(limit == null || limit >= 0) { "History sync limits can't be negative" }
+        }
+    }
+}
+
 /**
  * Parameters to use for syncing.
  */
@@ -112,7 +144,12 @@ data class SyncParams(
     /**
      * The information used to populate a client record for this device.
      */
-    val deviceSettings: DeviceSettings
+    val deviceSettings: DeviceSettings,
+
+    /**
+     * Limits for syncing history, if the defaults aren't suitable.
+     */
+    val historySettings: HistorySyncSettings? = null
 ) {
     @Suppress("ComplexMethod")
     internal fun toProtobuf(): MsgTypes.SyncParams {
@@ -152,6 +189,13 @@ data class SyncParams(
             DeviceType.TV -> MsgTypes.DeviceType.TV
         }
 
+        this.historySettings?.let { settings ->
+            settings.maxIncomingPlaces?.let { builder.historyMaxIncomingPlaces = it }
+            settings.maxOutgoingPlaces?.let { builder.historyMaxOutgoingPlaces = it }
+            settings.maxVisits?.let { builder.historyMaxVisits = it }
+            settings.maxAgeDays?.let { builder.historyMaxAgeDays = it }
+        }
+
         return builder.build()
     }
 }
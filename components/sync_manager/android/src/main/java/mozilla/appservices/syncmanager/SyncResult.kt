@@ -41,6 +41,11 @@ enum class SyncServiceStatus {
      * Some other error occurred.
      */
     OTHER_ERROR,
+
+    /**
+     * The sync was interrupted by [SyncManager.interrupt].
+     */
+    INTERRUPTED,
 }
 
 /**
@@ -135,6 +140,7 @@ data class SyncResult(
                 MsgTypes.ServiceStatus.AUTH_ERROR -> SyncServiceStatus.AUTH_ERROR
                 MsgTypes.ServiceStatus.BACKED_OFF -> SyncServiceStatus.BACKED_OFF
                 MsgTypes.ServiceStatus.OTHER_ERROR -> SyncServiceStatus.OTHER_ERROR
+                MsgTypes.ServiceStatus.INTERRUPTED -> SyncServiceStatus.INTERRUPTED
                 else -> SyncServiceStatus.OTHER_ERROR // impossible *sigh*
             }
 
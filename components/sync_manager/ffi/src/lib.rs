@@ -8,8 +8,8 @@
 // the closure is small.
 #![allow(clippy::redundant_closure)]
 
-use ffi_support::{ExternError, HandleError};
-use sync_manager::Result as MgrResult;
+use ffi_support::{define_box_destructor, ExternError, HandleError};
+use sync_manager::{Result as MgrResult, SyncInterruptHandle};
 
 #[no_mangle]
 pub extern "C" fn sync_manager_set_places(_places_api_handle: u64, error: &mut ExternError) {
@@ -56,6 +56,26 @@ pub extern "C" fn sync_manager_disconnect(error: &mut ExternError) {
     });
 }
 
+/// Returns a handle that interrupts the sync that's in progress. Must be
+/// destroyed with `sync_manager_interrupt_handle_destroy`.
+#[no_mangle]
+pub extern "C" fn sync_manager_new_interrupt_handle(
+    error: &mut ExternError,
+) -> *mut SyncInterruptHandle {
+    ffi_support::call_with_output(error, || {
+        log::debug!("sync_manager_new_interrupt_handle");
+        sync_manager::interrupt_handle()
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn sync_manager_interrupt(handle: &SyncInterruptHandle, error: &mut ExternError) {
+    ffi_support::call_with_output(error, || {
+        log::debug!("sync_manager_interrupt");
+        handle.interrupt()
+    })
+}
+
 unsafe fn get_buffer<'a>(data: *const u8, len: i32) -> &'a [u8] {
     assert!(len >= 0, "Bad buffer len: {}", len);
     if len == 0 {
@@ -82,5 +102,6 @@ pub unsafe extern "C" fn sync_manager_sync(
     })
 }
 
+define_box_destructor!(SyncInterruptHandle, sync_manager_interrupt_handle_destroy);
 ffi_support::define_string_destructor!(sync_manager_destroy_string);
 ffi_support::define_bytebuffer_destructor!(sync_manager_destroy_bytebuffer);
@@ -0,0 +1,284 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::error::*;
+use crate::interrupt::SyncInterruptHandle;
+use crate::msg_types::SyncParams;
+use logins::PasswordEngine;
+use places::{
+    bookmark_sync::store::BookmarksStore,
+    history_sync::{store::HistoryStore, HistorySyncConfig},
+    reading_list_sync::store::ReadingListStore,
+    PlacesApi,
+};
+use sql_support::{SqlInterruptHandle, SqlInterruptScope};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use sync15::Store;
+use tabs::TabsEngine;
+
+const LOGINS_ENGINE: &str = "passwords";
+const HISTORY_ENGINE: &str = "history";
+const BOOKMARKS_ENGINE: &str = "bookmarks";
+const READING_LIST_ENGINE: &str = "readinglist";
+const TABS_ENGINE: &str = "tabs";
+
+/// A component that provides one or more engines for the sync manager to
+/// sync, wipe, and reset. Components register a provider with
+/// `register_engine_provider`.
+///
+/// A provider usually holds a weak reference to its component, so that
+/// registering it doesn't keep the component alive.
+pub trait EngineProvider: Send {
+    /// The names of the engines this provider syncs, like `"history"`. These
+    /// are also the names of the collections on the server.
+    fn engine_names(&self) -> Vec<String>;
+
+    /// Returns false if the component has been closed, in which case its
+    /// engines are skipped.
+    fn is_open(&self) -> bool;
+
+    /// Builds stores for `engines`, which are some of the names returned from
+    /// `engine_names`, and calls `sync` with them. Stores usually borrow a
+    /// connection that's only open for the sync, which is why they're passed
+    /// to a callback instead of returned.
+    fn with_stores(
+        &self,
+        engines: &[&str],
+        context: &SyncContext<'_>,
+        sync: &mut dyn FnMut(&[&dyn Store]) -> Result<()>,
+    ) -> Result<()>;
+
+    /// Deletes all local and synced data for `engine`.
+    fn wipe(&self, engine: &str) -> Result<()>;
+
+    /// Resets the sync metadata for `engine`, so that the next sync is a
+    /// first sync.
+    fn reset(&self, engine: &str) -> Result<()>;
+}
+
+/// What a provider needs to build its stores for a sync.
+pub struct SyncContext<'a> {
+    /// The parameters for this sync. Providers can read per-engine settings,
+    /// like the history limits, from these.
+    pub params: &'a SyncParams,
+    /// The interruptee that stores should check.
+    pub interruptee: &'a SqlInterruptScope,
+    interrupt_handle: &'a SyncInterruptHandle,
+}
+
+impl<'a> SyncContext<'a> {
+    pub(crate) fn new(
+        params: &'a SyncParams,
+        interruptee: &'a SqlInterruptScope,
+        interrupt_handle: &'a SyncInterruptHandle,
+    ) -> Self {
+        Self {
+            params,
+            interruptee,
+            interrupt_handle,
+        }
+    }
+
+    /// Registers the interrupt handle for a connection that the stores use,
+    /// so that interrupting the sync also interrupts any statements running
+    /// on the connection.
+    pub fn add_sql_interrupt_handle(&self, handle: SqlInterruptHandle) {
+        self.interrupt_handle.add_sql_handle(handle);
+    }
+}
+
+/// Provides the history, bookmarks, and reading list engines, which share a
+/// sync connection.
+pub(crate) struct PlacesProvider(pub Weak<PlacesApi>);
+
+impl EngineProvider for PlacesProvider {
+    fn engine_names(&self) -> Vec<String> {
+        vec![
+            HISTORY_ENGINE.into(),
+            BOOKMARKS_ENGINE.into(),
+            READING_LIST_ENGINE.into(),
+        ]
+    }
+
+    fn is_open(&self) -> bool {
+        self.0.upgrade().is_some()
+    }
+
+    fn with_stores(
+        &self,
+        engines: &[&str],
+        context: &SyncContext<'_>,
+        sync: &mut dyn FnMut(&[&dyn Store]) -> Result<()>,
+    ) -> Result<()> {
+        let places = self
+            .0
+            .upgrade()
+            .ok_or_else(|| ErrorKind::ConnectionClosed("places".into()))?;
+        let conn = places.open_sync_connection()?;
+        context.add_sql_interrupt_handle(conn.new_interrupt_handle());
+        let interruptee = context.interruptee;
+        let mut stores: Vec<Box<dyn Store + '_>> = vec![];
+        if engines.contains(&HISTORY_ENGINE) {
+            stores.push(Box::new(HistoryStore::new(
+                &conn,
+                interruptee,
+                history_sync_config(context.params),
+            )));
+        }
+        if engines.contains(&BOOKMARKS_ENGINE) {
+            stores.push(Box::new(BookmarksStore::new(&conn, interruptee)));
+        }
+        if engines.contains(&READING_LIST_ENGINE) {
+            stores.push(Box::new(ReadingListStore::new(&conn, interruptee)));
+        }
+        let store_refs: Vec<&dyn Store> = stores.iter().map(|s| &**s).collect();
+        sync(&store_refs)
+    }
+
+    fn wipe(&self, engine: &str) -> Result<()> {
+        let places = self
+            .0
+            .upgrade()
+            .ok_or_else(|| ErrorKind::ConnectionClosed(engine.into()))?;
+        match engine {
+            HISTORY_ENGINE => places.wipe_history()?,
+            BOOKMARKS_ENGINE => places.wipe_bookmarks()?,
+            READING_LIST_ENGINE => places.wipe_reading_list()?,
+            _ => return Err(ErrorKind::UnknownEngine(engine.into()).into()),
+        }
+        Ok(())
+    }
+
+    fn reset(&self, engine: &str) -> Result<()> {
+        let places = self
+            .0
+            .upgrade()
+            .ok_or_else(|| ErrorKind::ConnectionClosed(engine.into()))?;
+        match engine {
+            HISTORY_ENGINE => places.reset_history()?,
+            BOOKMARKS_ENGINE => places.reset_bookmarks()?,
+            READING_LIST_ENGINE => places.reset_reading_list()?,
+            _ => return Err(ErrorKind::UnknownEngine(engine.into()).into()),
+        }
+        Ok(())
+    }
+}
+
+/// Builds the history sync configuration from `SyncParams`, using the default
+/// for any limit that isn't set.
+fn history_sync_config(p: &SyncParams) -> HistorySyncConfig {
+    let mut config = HistorySyncConfig::default();
+    if let Some(max) = p.history_max_incoming_places {
+        config.max_incoming_places = max as usize;
+    }
+    if let Some(max) = p.history_max_outgoing_places {
+        config.max_outgoing_places = max as usize;
+    }
+    if let Some(max) = p.history_max_visits {
+        config.max_visits = max as usize;
+    }
+    if let Some(days) = p.history_max_age_days {
+        config.max_age = Some(Duration::from_secs(u64::from(days) * 24 * 60 * 60));
+    }
+    config
+}
+
+/// Provides the passwords engine.
+pub(crate) struct LoginsProvider(pub Weak<Mutex<PasswordEngine>>);
+
+impl LoginsProvider {
+    fn upgrade(&self, engine: &str) -> Result<Arc<Mutex<PasswordEngine>>> {
+        Ok(self
+            .0
+            .upgrade()
+            .ok_or_else(|| ErrorKind::ConnectionClosed(engine.into()))?)
+    }
+}
+
+impl EngineProvider for LoginsProvider {
+    fn engine_names(&self) -> Vec<String> {
+        vec![LOGINS_ENGINE.into()]
+    }
+
+    fn is_open(&self) -> bool {
+        self.0.upgrade().is_some()
+    }
+
+    fn with_stores(
+        &self,
+        _engines: &[&str],
+        context: &SyncContext<'_>,
+        sync: &mut dyn FnMut(&[&dyn Store]) -> Result<()>,
+    ) -> Result<()> {
+        let logins = self.upgrade(LOGINS_ENGINE)?;
+        let engine = logins.lock().expect("poisoned logins mutex");
+        context.add_sql_interrupt_handle(engine.new_interrupt_handle());
+        let store = logins::LoginStore::new(&engine.db);
+        sync(&[&store])
+    }
+
+    fn wipe(&self, engine: &str) -> Result<()> {
+        let logins = self.upgrade(engine)?;
+        let engine = logins.lock().expect("poisoned logins mutex");
+        engine.wipe()?;
+        Ok(())
+    }
+
+    fn reset(&self, engine: &str) -> Result<()> {
+        let logins = self.upgrade(engine)?;
+        let engine = logins.lock().expect("poisoned logins mutex");
+        engine.reset()?;
+        Ok(())
+    }
+}
+
+/// Provides the tabs engine.
+pub(crate) struct TabsProvider(pub Weak<Mutex<TabsEngine>>);
+
+impl TabsProvider {
+    fn upgrade(&self, engine: &str) -> Result<Arc<Mutex<TabsEngine>>> {
+        Ok(self
+            .0
+            .upgrade()
+            .ok_or_else(|| ErrorKind::ConnectionClosed(engine.into()))?)
+    }
+}
+
+impl EngineProvider for TabsProvider {
+    fn engine_names(&self) -> Vec<String> {
+        vec![TABS_ENGINE.into()]
+    }
+
+    fn is_open(&self) -> bool {
+        self.0.upgrade().is_some()
+    }
+
+    fn with_stores(
+        &self,
+        _engines: &[&str],
+        _context: &SyncContext<'_>,
+        sync: &mut dyn FnMut(&[&dyn Store]) -> Result<()>,
+    ) -> Result<()> {
+        let tabs = self.upgrade(TABS_ENGINE)?;
+        let engine = tabs.lock().expect("poisoned tabs mutex");
+        let store = tabs::TabsStore::new(&engine.storage);
+        sync(&[&store])
+    }
+
+    fn wipe(&self, engine: &str) -> Result<()> {
+        let tabs = self.upgrade(engine)?;
+        tabs.lock().expect("poisoned tabs mutex").storage.wipe(true);
+        Ok(())
+    }
+
+    fn reset(&self, engine: &str) -> Result<()> {
+        let tabs = self.upgrade(engine)?;
+        tabs.lock()
+            .expect("poisoned tabs mutex")
+            .storage
+            .wipe(false);
+        Ok(())
+    }
+}
@@ -0,0 +1,98 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use sql_support::{SqlInterruptHandle, SqlInterruptScope};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+
+/// Interrupts a sync that's in progress, like when the app goes into the
+/// background. The sync stops before its next network request, and any SQL
+/// statements the engines are running are interrupted, so the sync finishes
+/// with an `Interrupted` status. Engines only save their changes in
+/// transactions, so they're left in the same state as a sync that failed.
+///
+/// Interrupting when no sync is in progress does nothing, including when a
+/// sync is waiting to start, since each sync only notices interrupts that
+/// come after it begins. Clones of a handle interrupt the same syncs.
+#[derive(Clone)]
+pub struct SyncInterruptHandle {
+    counter: Arc<AtomicUsize>,
+    // The interrupt handles for the connections used by the current sync.
+    sql_handles: Arc<Mutex<Vec<SqlInterruptHandle>>>,
+}
+
+impl SyncInterruptHandle {
+    pub(crate) fn new() -> Self {
+        Self {
+            counter: Arc::new(AtomicUsize::new(0)),
+            sql_handles: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    pub fn interrupt(&self) {
+        self.counter.fetch_add(1, Ordering::SeqCst);
+        for handle in self.sql_handles.lock().unwrap().iter() {
+            handle.interrupt();
+        }
+    }
+
+    /// Starts a sync. The returned scope should be used as the sync's
+    /// interruptee. Handles added with `add_sql_handle` are forgotten when
+    /// the guard is dropped.
+    pub(crate) fn begin_sync(&self) -> (SqlInterruptScope, SyncInterruptGuard<'_>) {
+        let scope = SqlInterruptScope::new(Arc::clone(&self.counter));
+        self.sql_handles.lock().unwrap().clear();
+        (scope, SyncInterruptGuard(self))
+    }
+
+    /// Adds the interrupt handle for a connection used by the current sync.
+    pub(crate) fn add_sql_handle(&self, handle: SqlInterruptHandle) {
+        self.sql_handles.lock().unwrap().push(handle);
+    }
+}
+
+pub(crate) struct SyncInterruptGuard<'a>(&'a SyncInterruptHandle);
+
+impl<'a> Drop for SyncInterruptGuard<'a> {
+    fn drop(&mut self) {
+        if let Ok(mut handles) = self.0.sql_handles.lock() {
+            handles.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interrupt_support::Interruptee;
+    use places::{ConnectionType, PlacesApi};
+
+    #[test]
+    fn test_interrupt_sql_handles() -> places::Result<()> {
+        let api = PlacesApi::new_memory("test_interrupt_sql_handles")?;
+        let conn = api.open_connection(ConnectionType::ReadWrite)?;
+        let handle = SyncInterruptHandle::new();
+
+        // Interrupting before the sync begins shouldn't affect it.
+        handle.interrupt();
+        let (interruptee, guard) = handle.begin_sync();
+        assert!(!interruptee.was_interrupted());
+
+        handle.add_sql_handle(conn.new_interrupt_handle());
+        let conn_scope = conn.begin_interrupt_scope();
+        handle.interrupt();
+        assert!(interruptee.was_interrupted());
+        assert!(conn_scope.was_interrupted());
+
+        // Once the sync is over, its connections shouldn't be interrupted.
+        drop(guard);
+        assert!(handle.sql_handles.lock().unwrap().is_empty());
+        let conn_scope = conn.begin_interrupt_scope();
+        handle.interrupt();
+        assert!(!conn_scope.was_interrupted());
+        Ok(())
+    }
+}
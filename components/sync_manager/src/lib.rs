@@ -5,11 +5,15 @@
 #![allow(unknown_lints)]
 #![warn(rust_2018_idioms)]
 
+mod engines;
 pub mod error;
 mod ffi;
+mod interrupt;
 mod manager;
 
+pub use engines::{EngineProvider, SyncContext};
 pub use error::{Error, ErrorKind, Result};
+pub use interrupt::SyncInterruptHandle;
 
 pub mod msg_types {
     include!("mozilla.appservices.syncmanager.protobuf.rs");
@@ -23,7 +27,17 @@ use std::sync::Mutex;
 use tabs::TabsEngine;
 
 lazy_static::lazy_static! {
-    static ref MANAGER: Mutex<SyncManager> = Mutex::new(SyncManager::new());
+    // The interrupt handle is kept outside the manager's mutex, since that's
+    // held for the whole sync.
+    static ref INTERRUPT_HANDLE: SyncInterruptHandle = SyncInterruptHandle::new();
+    static ref MANAGER: Mutex<SyncManager> = Mutex::new(SyncManager::new(INTERRUPT_HANDLE.clone()));
+}
+
+/// Registers a provider for engines that aren't built in. Once registered,
+/// its engines can be synced, wiped, and reset like the built-in ones.
+pub fn register_engine_provider(provider: Box<dyn EngineProvider>) {
+    let mut manager = MANAGER.lock().unwrap();
+    manager.register_engine_provider(provider);
 }
 
 pub fn set_places(places: Arc<PlacesApi>) {
@@ -66,6 +80,11 @@ pub fn reset_all() -> Result<()> {
     manager.reset_all()
 }
 
+/// Returns a handle that interrupts the sync that's in progress.
+pub fn interrupt_handle() -> SyncInterruptHandle {
+    INTERRUPT_HANDLE.clone()
+}
+
 pub fn sync(params: msg_types::SyncParams) -> Result<msg_types::SyncResult> {
     let mut manager = MANAGER.lock().unwrap();
     manager.sync(params)
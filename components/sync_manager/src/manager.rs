@@ -2,27 +2,25 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use crate::engines::{EngineProvider, LoginsProvider, PlacesProvider, SyncContext, TabsProvider};
 use crate::error::*;
+use crate::interrupt::SyncInterruptHandle;
 use crate::msg_types::{DeviceType, ServiceStatus, SyncParams, SyncReason, SyncResult};
-use crate::{reset, reset_all, wipe, wipe_all};
+use interrupt_support::Interruptee;
 use logins::PasswordEngine;
-use places::{bookmark_sync::store::BookmarksStore, history_sync::store::HistoryStore, PlacesApi};
+use places::PlacesApi;
+use std::cell::Cell;
 use std::collections::{HashMap, HashSet};
 use std::result;
-use std::sync::{atomic::AtomicUsize, Arc, Mutex, Weak};
+use std::sync::{Arc, Mutex};
 use std::time::SystemTime;
 use sync15::{
     self,
     clients::{self, Command, CommandProcessor, CommandStatus, Settings},
-    MemoryCachedState,
+    MemoryCachedState, Store,
 };
 use tabs::TabsEngine;
 
-const LOGINS_ENGINE: &str = "passwords";
-const HISTORY_ENGINE: &str = "history";
-const BOOKMARKS_ENGINE: &str = "bookmarks";
-const TABS_ENGINE: &str = "tabs";
-
 // Casts aren't allowed in `match` arms, so we can't directly match
 // `SyncParams.device_type`, which is an `i32`, against `DeviceType`
 // variants. Instead, we reflect all variants into constants, cast them
@@ -36,173 +34,124 @@ const DEVICE_TYPE_TV: i32 = DeviceType::Tv as i32;
 
 pub struct SyncManager {
     mem_cached_state: Option<MemoryCachedState>,
-    places: Weak<PlacesApi>,
-    logins: Weak<Mutex<PasswordEngine>>,
-    tabs: Weak<Mutex<TabsEngine>>,
+    providers: Vec<Box<dyn EngineProvider>>,
+    interrupt_handle: SyncInterruptHandle,
 }
 
 impl SyncManager {
-    pub fn new() -> Self {
+    pub fn new(interrupt_handle: SyncInterruptHandle) -> Self {
         Self {
             mem_cached_state: None,
-            places: Weak::new(),
-            logins: Weak::new(),
-            tabs: Weak::new(),
+            providers: vec![],
+            interrupt_handle,
         }
     }
 
+    /// Registers a provider for one or more engines. This replaces any
+    /// provider that was registered for the same engines, like when a
+    /// component is reopened.
+    pub fn register_engine_provider(&mut self, provider: Box<dyn EngineProvider>) {
+        let names = provider.engine_names();
+        self.providers
+            .retain(|p| !p.engine_names().iter().any(|name| names.contains(name)));
+        self.providers.push(provider);
+    }
+
     pub fn set_places(&mut self, places: Arc<PlacesApi>) {
-        self.places = Arc::downgrade(&places);
+        self.register_engine_provider(Box::new(PlacesProvider(Arc::downgrade(&places))));
     }
 
     pub fn set_logins(&mut self, logins: Arc<Mutex<PasswordEngine>>) {
-        self.logins = Arc::downgrade(&logins);
+        self.register_engine_provider(Box::new(LoginsProvider(Arc::downgrade(&logins))));
     }
 
     pub fn set_tabs(&mut self, tabs: Arc<Mutex<TabsEngine>>) {
-        self.tabs = Arc::downgrade(&tabs);
+        self.register_engine_provider(Box::new(TabsProvider(Arc::downgrade(&tabs))));
     }
 
-    pub fn wipe(&mut self, engine: &str) -> Result<()> {
-        match engine {
-            "logins" => {
-                if let Some(logins) = self
-                    .logins
-                    .upgrade()
-                    .as_ref()
-                    .map(|l| l.lock().expect("poisoned logins mutex"))
-                {
-                    logins.wipe()?;
-                    Ok(())
-                } else {
-                    Err(ErrorKind::ConnectionClosed(engine.into()).into())
-                }
+    /// Returns the names of all registered engines, and of the engines whose
+    /// components are still open.
+    fn engine_names(&self) -> (Vec<String>, Vec<String>) {
+        let mut known_engines = vec![];
+        let mut open_engines = vec![];
+        for provider in &self.providers {
+            let engines = provider.engine_names();
+            if provider.is_open() {
+                open_engines.extend(engines.iter().cloned());
             }
-            "bookmarks" => {
-                if let Some(places) = self.places.upgrade() {
-                    places.wipe_bookmarks()?;
-                    Ok(())
-                } else {
-                    Err(ErrorKind::ConnectionClosed(engine.into()).into())
-                }
-            }
-            "history" => {
-                if let Some(places) = self.places.upgrade() {
-                    places.wipe_history()?;
-                    Ok(())
-                } else {
-                    Err(ErrorKind::ConnectionClosed(engine.into()).into())
-                }
-            }
-            _ => Err(ErrorKind::UnknownEngine(engine.into()).into()),
+            known_engines.extend(engines);
         }
+        (known_engines, open_engines)
     }
 
-    pub fn wipe_all(&mut self) -> Result<()> {
-        if let Some(logins) = self
-            .logins
-            .upgrade()
-            .as_ref()
-            .map(|l| l.lock().expect("poisoned logins mutex"))
-        {
-            logins.wipe()?;
-        }
-        if let Some(places) = self.places.upgrade() {
-            places.wipe_bookmarks()?;
-            places.wipe_history()?;
+    /// Returns the provider for `engine`, if it's registered and open.
+    fn open_provider(&self, engine: &str) -> Result<&dyn EngineProvider> {
+        let provider = self
+            .providers
+            .iter()
+            .find(|p| p.engine_names().iter().any(|name| name == engine))
+            .ok_or_else(|| ErrorKind::UnknownEngine(engine.into()))?;
+        if !provider.is_open() {
+            return Err(ErrorKind::ConnectionClosed(engine.into()).into());
         }
-        Ok(())
+        Ok(&**provider)
+    }
+
+    pub fn wipe(&mut self, engine: &str) -> Result<()> {
+        self.open_provider(engine)?.wipe(engine)
+    }
+
+    /// Wipes every engine, even if wiping some of them fails, and returns the
+    /// first error. Engines whose components are closed are skipped.
+    pub fn wipe_all(&mut self) -> Result<()> {
+        self.for_each_engine("wipe", |provider, engine| provider.wipe(engine))
     }
 
     pub fn reset(&mut self, engine: &str) -> Result<()> {
-        match engine {
-            "logins" => {
-                if let Some(logins) = self
-                    .logins
-                    .upgrade()
-                    .as_ref()
-                    .map(|l| l.lock().expect("poisoned logins mutex"))
-                {
-                    logins.reset()?;
-                    Ok(())
-                } else {
-                    Err(ErrorKind::ConnectionClosed(engine.into()).into())
-                }
-            }
-            "bookmarks" | "history" => {
-                if let Some(places) = self.places.upgrade() {
-                    if engine == "bookmarks" {
-                        places.reset_bookmarks()?;
-                    } else {
-                        places.reset_history()?;
-                    }
-                    Ok(())
-                } else {
-                    Err(ErrorKind::ConnectionClosed(engine.into()).into())
-                }
-            }
-            _ => Err(ErrorKind::UnknownEngine(engine.into()).into()),
-        }
+        self.open_provider(engine)?.reset(engine)
     }
 
+    /// Resets every engine, like `wipe_all`.
     pub fn reset_all(&mut self) -> Result<()> {
-        if let Some(logins) = self
-            .logins
-            .upgrade()
-            .as_ref()
-            .map(|l| l.lock().expect("poisoned logins mutex"))
-        {
-            logins.reset()?;
-        }
-        if let Some(places) = self.places.upgrade() {
-            places.reset_bookmarks()?;
-            places.reset_history()?;
-        }
-        Ok(())
+        self.for_each_engine("reset", |provider, engine| provider.reset(engine))
     }
 
     pub fn disconnect(&mut self) {
-        if let Some(logins) = self
-            .logins
-            .upgrade()
-            .as_ref()
-            .map(|l| l.lock().expect("poisoned logins mutex"))
-        {
-            if let Err(e) = logins.reset() {
-                log::error!("Failed to reset logins: {}", e);
-            }
-        } else {
-            log::warn!("Unable to reset logins, be sure to call set_logins before disconnect if this is surprising");
+        if self.providers.is_empty() {
+            log::warn!("Unable to reset any engines, be sure to register them before disconnect if this is surprising");
         }
+        // `for_each_engine` already logs the errors, and there's nothing else
+        // we can do about them here.
+        let _ = self.reset_all();
+    }
 
-        if let Some(places) = self.places.upgrade() {
-            if let Err(e) = places.reset_bookmarks() {
-                log::error!("Failed to reset bookmarks: {}", e);
+    /// Calls `f` for every engine whose component is open, logging and
+    /// carrying on past any errors. Returns the first error.
+    fn for_each_engine(
+        &self,
+        action: &str,
+        f: impl Fn(&dyn EngineProvider, &str) -> Result<()>,
+    ) -> Result<()> {
+        let mut first_error = None;
+        for provider in &self.providers {
+            let engines = provider.engine_names();
+            if !provider.is_open() {
+                log::warn!("Unable to {} {:?}, because they're closed", action, engines);
+                continue;
             }
-            if let Err(e) = places.reset_history() {
-                log::error!("Failed to reset history: {}", e);
+            for engine in engines {
+                if let Err(e) = f(&**provider, &engine) {
+                    log::error!("Failed to {} {}: {}", action, engine, e);
+                    first_error.get_or_insert(e);
+                }
             }
-        } else {
-            log::warn!("Unable to reset places, be sure to call set_places before disconnect if this is surprising");
         }
+        first_error.map_or(Ok(()), Err)
     }
 
     pub fn sync(&mut self, params: SyncParams) -> Result<SyncResult> {
-        let mut have_engines = vec![];
-        let places = self.places.upgrade();
-        let tabs = self.tabs.upgrade();
-        let logins = self.logins.upgrade();
-        if places.is_some() {
-            have_engines.push(HISTORY_ENGINE);
-            have_engines.push(BOOKMARKS_ENGINE);
-        }
-        if logins.is_some() {
-            have_engines.push(LOGINS_ENGINE);
-        }
-        if tabs.is_some() {
-            have_engines.push(TABS_ENGINE);
-        }
-        check_engine_list(&params.engines_to_sync, &have_engines)?;
+        let (known_engines, open_engines) = self.engine_names();
+        check_engine_list(&params.engines_to_sync, &known_engines, &open_engines)?;
 
         let next_sync_after = self
             .mem_cached_state
@@ -231,72 +180,13 @@ impl SyncManager {
     }
 
     fn do_sync(&mut self, mut params: SyncParams) -> Result<SyncResult> {
-        let mut places = self.places.upgrade();
-        let logins = self.logins.upgrade();
-        let tabs = self.tabs.upgrade();
-
         let key_bundle = sync15::KeyBundle::from_ksync_base64(&params.acct_sync_key)?;
         let tokenserver_url = url::Url::parse(&params.acct_tokenserver_url)?;
 
-        let bookmarks_sync = should_sync(&params, BOOKMARKS_ENGINE) && places.is_some();
-        let history_sync = should_sync(&params, HISTORY_ENGINE) && places.is_some();
-        let logins_sync = should_sync(&params, LOGINS_ENGINE) && logins.is_some();
-        let tabs_sync = should_sync(&params, TABS_ENGINE) && tabs.is_some();
-
-        let places_conn = if bookmarks_sync || history_sync {
-            places
-                .as_mut()
-                .expect("trying to sync an engine that has not been configured")
-                .open_sync_connection()
-                .ok()
-        } else {
-            None
-        };
-        let l = if logins_sync {
-            logins.as_ref().map(|l| l.lock().expect("poisoned mutex"))
-        } else {
-            None
-        };
-        let t = if tabs_sync {
-            tabs.as_ref().map(|t| t.lock().expect("poisoned mutex"))
-        } else {
-            None
-        };
-
-        // TODO(issue 1684) this isn't ideal, we should have real support for interruption.
-        let p = Arc::new(AtomicUsize::new(0));
-        let interruptee = sql_support::SqlInterruptScope::new(p);
+        let (interruptee, _interrupt_guard) = self.interrupt_handle.begin_sync();
 
         let mut mem_cached_state = self.mem_cached_state.take().unwrap_or_default();
         let mut disk_cached_state = params.persisted_state.take();
-        // `sync_multiple` takes a &[&dyn Store], but we need something to hold
-        // ownership of our stores.
-        let mut stores: Vec<Box<dyn sync15::Store>> = vec![];
-
-        if let Some(pc) = places_conn.as_ref() {
-            assert!(
-                history_sync || bookmarks_sync,
-                "Should have already checked"
-            );
-            if history_sync {
-                stores.push(Box::new(HistoryStore::new(pc, &interruptee)))
-            }
-            if bookmarks_sync {
-                stores.push(Box::new(BookmarksStore::new(pc, &interruptee)))
-            }
-        }
-
-        if let Some(le) = l.as_ref() {
-            assert!(logins_sync, "Should have already checked");
-            stores.push(Box::new(logins::LoginStore::new(&le.db)));
-        }
-
-        if let Some(tbs) = t.as_ref() {
-            assert!(tabs_sync, "Should have already checked");
-            stores.push(Box::new(tabs::TabsStore::new(&tbs.storage)));
-        }
-
-        let store_refs: Vec<&dyn sync15::Store> = stores.iter().map(|s| &**s).collect();
 
         let client_init = sync15::Sync15StorageClientInit {
             key_id: params.acct_key_id.clone(),
@@ -310,8 +200,8 @@ impl SyncManager {
         };
 
         let settings = Settings {
-            fxa_device_id: params.fxa_device_id,
-            device_name: params.device_name,
+            fxa_device_id: params.fxa_device_id.clone(),
+            device_name: params.device_name.clone(),
             device_type: match params.device_type {
                 DEVICE_TYPE_DESKTOP => clients::DeviceType::Desktop,
                 DEVICE_TYPE_MOBILE => clients::DeviceType::Mobile,
@@ -327,10 +217,16 @@ impl SyncManager {
                 }
             },
         };
-        let c = SyncClient::new(settings);
-        let result = sync15::sync_multiple_with_command_processor(
+        let c = SyncClient::new(settings, self);
+        let is_user_action = params.reason == (SyncReason::User as i32);
+
+        // Commands from other devices need to be applied before the engines
+        // sync, so that we don't upload data that we were asked to wipe. The
+        // stores hold on to their components while they sync, so the first
+        // pass only syncs the clients collection, before we build any stores.
+        let mut result = sync15::sync_multiple_with_command_processor(
             Some(&c),
-            &store_refs,
+            &[],
             &mut disk_cached_state,
             &mut mem_cached_state,
             &client_init,
@@ -338,13 +234,51 @@ impl SyncManager {
             &interruptee,
             Some(sync15::SyncRequestInfo {
                 engines_to_state_change: engines_to_change,
-                is_user_action: params.reason == (SyncReason::User as i32),
+                is_user_action,
             }),
         );
+        if result.result.is_ok() && result.service_status == sync15::ServiceStatus::Ok {
+            // Commands that arrive now are kept in our client record, and
+            // applied on the next sync.
+            c.stop_applying_commands();
+            let providers: Vec<(&dyn EngineProvider, Vec<String>)> = self
+                .providers
+                .iter()
+                .filter(|p| p.is_open())
+                .map(|p| {
+                    let engines = p
+                        .engine_names()
+                        .into_iter()
+                        .filter(|e| should_sync(&params, e))
+                        .collect::<Vec<_>>();
+                    (&**p, engines)
+                })
+                .filter(|(_, engines)| !engines.is_empty())
+                .collect();
+            let context = SyncContext::new(&params, &interruptee, &self.interrupt_handle);
+            let mut engines_result = None;
+            with_stores(&providers, &context, &[], &mut |stores| {
+                engines_result = Some(sync15::sync_multiple_with_command_processor(
+                    Some(&c),
+                    stores,
+                    &mut disk_cached_state,
+                    &mut mem_cached_state,
+                    &client_init,
+                    &key_bundle,
+                    &interruptee,
+                    Some(sync15::SyncRequestInfo {
+                        engines_to_state_change: None,
+                        is_user_action,
+                    }),
+                ));
+                Ok(())
+            })?;
+            result = engines_result.expect("Should have synced");
+        }
         self.mem_cached_state = Some(mem_cached_state);
 
         log::info!("Sync finished with status {:?}", result.service_status);
-        let status = ServiceStatus::from(result.service_status) as i32;
+        let status = sync_status(result.service_status, &interruptee) as i32;
         let results: HashMap<String, String> = result
             .engine_results
             .into_iter()
@@ -419,6 +353,17 @@ fn backoff_in_effect(next_sync_after: Option<SystemTime>, p: &SyncParams) -> boo
     false
 }
 
+/// Returns the status to report for a sync that finished with `status`.
+/// Engines report interrupted SQL statements as their own errors, so we check
+/// the interruptee too.
+fn sync_status(status: sync15::ServiceStatus, interruptee: &dyn Interruptee) -> ServiceStatus {
+    if interruptee.was_interrupted() {
+        ServiceStatus::Interrupted
+    } else {
+        ServiceStatus::from(status)
+    }
+}
+
 impl From<sync15::ServiceStatus> for ServiceStatus {
     fn from(s15s: sync15::ServiceStatus) -> Self {
         use sync15::ServiceStatus::*;
@@ -428,7 +373,7 @@ impl From<sync15::ServiceStatus> for ServiceStatus {
             ServiceError => ServiceStatus::ServiceError,
             AuthenticationError => ServiceStatus::AuthError,
             BackedOff => ServiceStatus::BackedOff,
-            Interrupted => ServiceStatus::OtherError, // Eh...
+            Interrupted => ServiceStatus::Interrupted,
             OtherError => ServiceStatus::OtherError,
         }
     }
@@ -445,15 +390,49 @@ fn should_sync(p: &SyncParams, engine: &str) -> bool {
     p.sync_all_engines || p.engines_to_sync.iter().any(|e| e == engine)
 }
 
-fn check_engine_list(list: &[String], have_engines: &[&str]) -> Result<()> {
+/// Builds the stores for each provider in turn, and calls `sync` with all of
+/// them. Providers hand out their stores to a callback, so we nest the calls.
+/// A provider that fails to build its stores is skipped.
+fn with_stores(
+    providers: &[(&dyn EngineProvider, Vec<String>)],
+    context: &SyncContext<'_>,
+    outer: &[&dyn Store],
+    sync: &mut dyn FnMut(&[&dyn Store]) -> Result<()>,
+) -> Result<()> {
+    let ((provider, engines), rest) = match providers.split_first() {
+        Some(first) => first,
+        None => return sync(outer),
+    };
+    let engines: Vec<&str> = engines.iter().map(String::as_str).collect();
+    let mut called = false;
+    let result = provider.with_stores(&engines, context, &mut |stores| {
+        called = true;
+        let mut all = outer.to_vec();
+        all.extend_from_slice(stores);
+        with_stores(rest, context, &all, sync)
+    });
+    match result {
+        Err(e) if !called => {
+            log::error!("Failed to set up {:?} for syncing: {}", engines, e);
+            with_stores(rest, context, outer, sync)
+        }
+        result => result,
+    }
+}
+
+fn check_engine_list(
+    list: &[String],
+    known_engines: &[String],
+    have_engines: &[String],
+) -> Result<()> {
     log::trace!(
         "Checking engines requested ({:?}) vs local engines ({:?})",
         list,
         have_engines
     );
     for e in list {
-        if [BOOKMARKS_ENGINE, HISTORY_ENGINE, LOGINS_ENGINE, TABS_ENGINE].contains(&e.as_ref()) {
-            if !have_engines.iter().any(|engine| e == engine) {
+        if known_engines.contains(e) {
+            if !have_engines.contains(e) {
                 return Err(ErrorKind::UnsupportedFeature(e.to_string()).into());
             }
         } else {
@@ -463,39 +442,485 @@ fn check_engine_list(list: &[String], have_engines: &[&str]) -> Result<()> {
     Ok(())
 }
 
-struct SyncClient(Settings);
+/// Applies wipe and reset commands from other devices as they're received
+/// from the clients collection.
+struct SyncClient<'a> {
+    settings: Settings,
+    manager: &'a SyncManager,
+    /// The engines that can be wiped and reset. Commands for other engines
+    /// are kept in our client record, and retried on the next sync.
+    engines: Vec<String>,
+    /// Whether we can apply commands now. The stores hold on to their
+    /// components while they sync, so we only apply commands before they're
+    /// built.
+    applying: Cell<bool>,
+}
 
-impl SyncClient {
-    pub fn new(settings: Settings) -> SyncClient {
-        SyncClient(settings)
+impl<'a> SyncClient<'a> {
+    pub fn new(settings: Settings, manager: &'a SyncManager) -> SyncClient<'a> {
+        let (_, engines) = manager.engine_names();
+        SyncClient {
+            settings,
+            manager,
+            engines,
+            applying: Cell::new(true),
+        }
+    }
+
+    /// Keeps commands that we receive after this in our client record, to
+    /// apply on the next sync.
+    fn stop_applying_commands(&self) {
+        self.applying.set(false);
     }
 }
 
-impl CommandProcessor for SyncClient {
+impl<'a> CommandProcessor for SyncClient<'a> {
     fn settings(&self) -> &Settings {
-        &self.0
+        &self.settings
     }
 
+    /// Returns `Applied` once the command has run. If it fails, we return the
+    /// error, which stops the sync before we upload our client record, so the
+    /// command stays on the server to retry next time.
     fn apply_incoming_command(
         &self,
         command: Command,
     ) -> result::Result<CommandStatus, failure::Error> {
-        let result = match command {
-            Command::Wipe(engine) => wipe(&engine),
-            Command::WipeAll => wipe_all(),
-            Command::Reset(engine) => reset(&engine),
-            Command::ResetAll => reset_all(),
+        let supported = match &command {
+            Command::Wipe(engine) | Command::Reset(engine) => self.engines.contains(engine),
+            Command::WipeAll | Command::ResetAll => true,
         };
-        match result {
-            Ok(()) => Ok(CommandStatus::Applied),
-            Err(err) => match err.kind() {
-                ErrorKind::UnknownEngine(_) => Ok(CommandStatus::Unsupported),
-                _ => Err(err.into()),
-            },
+        if !supported || !self.applying.get() {
+            return Ok(CommandStatus::Unsupported);
         }
+        log::info!("Applying {:?}", command);
+        match &command {
+            Command::Wipe(engine) => self.manager.open_provider(engine)?.wipe(engine)?,
+            Command::WipeAll => self
+                .manager
+                .for_each_engine("wipe", |provider, engine| provider.wipe(engine))?,
+            Command::Reset(engine) => self.manager.open_provider(engine)?.reset(engine)?,
+            Command::ResetAll => self
+                .manager
+                .for_each_engine("reset", |provider, engine| provider.reset(engine))?,
+        }
+        Ok(CommandStatus::Applied)
     }
 
     fn fetch_outgoing_commands(&self) -> result::Result<HashSet<Command>, failure::Error> {
         Ok(HashSet::new())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interrupt_support::NeverInterrupts;
+    use serde_json::json;
+    use std::borrow::Cow;
+    use sync15::Payload;
+    use sync15::{
+        CollectionRequest, IncomingChangeset, OutgoingChangeset, ServerTimestamp,
+        StoreSyncAssociation,
+    };
+    use sync15_mock_server::MockSyncServer;
+    use sync_guid::Guid;
+
+    /// A store that uploads one record, and records when it's done, like
+    /// `"upload history"`, in the log.
+    struct TestStore {
+        name: String,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Store for TestStore {
+        fn collection_name(&self) -> Cow<'static, str> {
+            self.name.clone().into()
+        }
+
+        fn apply_incoming(
+            &self,
+            inbound: Vec<IncomingChangeset>,
+            _telem: &mut sync15::telemetry::Engine,
+        ) -> result::Result<OutgoingChangeset, failure::Error> {
+            let mut outgoing = OutgoingChangeset::new(self.collection_name(), inbound[0].timestamp);
+            outgoing
+                .changes
+                .push(Payload::from_json(json!({ "id": "record000001" }))?);
+            Ok(outgoing)
+        }
+
+        fn sync_finished(
+            &self,
+            _new_timestamp: ServerTimestamp,
+            _records_synced: Vec<Guid>,
+        ) -> result::Result<(), failure::Error> {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("upload {}", self.name));
+            Ok(())
+        }
+
+        fn get_collection_requests(
+            &self,
+            _server_timestamp: ServerTimestamp,
+        ) -> result::Result<Vec<CollectionRequest>, failure::Error> {
+            Ok(vec![])
+        }
+
+        fn get_sync_assoc(&self) -> result::Result<StoreSyncAssociation, failure::Error> {
+            Ok(StoreSyncAssociation::Disconnected)
+        }
+
+        fn reset(&self, _assoc: &StoreSyncAssociation) -> result::Result<(), failure::Error> {
+            Ok(())
+        }
+
+        fn wipe(&self) -> result::Result<(), failure::Error> {
+            unreachable!("these tests shouldn't call this");
+        }
+    }
+
+    /// A provider that records what the manager asks it to do, like
+    /// `"wipe history"` or `"sync history, bookmarks"`, in a log shared with
+    /// the test.
+    struct TestProvider {
+        names: Vec<&'static str>,
+        open: bool,
+        fails: bool,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl TestProvider {
+        fn new(names: &[&'static str], log: &Arc<Mutex<Vec<String>>>) -> Self {
+            Self {
+                names: names.to_vec(),
+                open: true,
+                fails: false,
+                log: Arc::clone(log),
+            }
+        }
+
+        fn record(&self, action: &str, engine: &str) -> Result<()> {
+            if self.fails {
+                return Err(ErrorKind::ConnectionClosed(engine.into()).into());
+            }
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", action, engine));
+            Ok(())
+        }
+    }
+
+    impl EngineProvider for TestProvider {
+        fn engine_names(&self) -> Vec<String> {
+            self.names.iter().map(|name| name.to_string()).collect()
+        }
+
+        fn is_open(&self) -> bool {
+            self.open
+        }
+
+        fn with_stores(
+            &self,
+            engines: &[&str],
+            _context: &SyncContext<'_>,
+            sync: &mut dyn FnMut(&[&dyn Store]) -> Result<()>,
+        ) -> Result<()> {
+            if self.fails {
+                return Err(ErrorKind::ConnectionClosed(engines.join(", ")).into());
+            }
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("sync {}", engines.join(", ")));
+            let stores: Vec<TestStore> = engines
+                .iter()
+                .map(|engine| TestStore {
+                    name: engine.to_string(),
+                    log: Arc::clone(&self.log),
+                })
+                .collect();
+            let store_refs: Vec<&dyn Store> = stores.iter().map(|s| s as &dyn Store).collect();
+            sync(&store_refs)
+        }
+
+        fn wipe(&self, engine: &str) -> Result<()> {
+            self.record("wipe", engine)
+        }
+
+        fn reset(&self, engine: &str) -> Result<()> {
+            self.record("reset", engine)
+        }
+    }
+
+    fn take_log(log: &Arc<Mutex<Vec<String>>>) -> Vec<String> {
+        std::mem::take(&mut *log.lock().unwrap())
+    }
+
+    #[test]
+    fn test_with_stores() {
+        let log = Arc::new(Mutex::new(vec![]));
+        let first = TestProvider::new(&["a", "b"], &log);
+        let broken = TestProvider {
+            fails: true,
+            ..TestProvider::new(&["c"], &log)
+        };
+        let last = TestProvider::new(&["d"], &log);
+        let providers: Vec<(&dyn EngineProvider, Vec<String>)> = vec![
+            (&first, vec!["a".into(), "b".into()]),
+            (&broken, vec!["c".into()]),
+            (&last, vec!["d".into()]),
+        ];
+
+        let params = SyncParams::default();
+        let interrupt_handle = SyncInterruptHandle::new();
+        let (interruptee, _guard) = interrupt_handle.begin_sync();
+        let context = SyncContext::new(&params, &interruptee, &interrupt_handle);
+        let mut synced = vec![];
+        with_stores(&providers, &context, &[], &mut |stores| {
+            synced = stores
+                .iter()
+                .map(|store| store.collection_name().into_owned())
+                .collect();
+            Ok(())
+        })
+        .expect("Should sync the stores that were set up");
+        // The broken provider's engines should be skipped.
+        assert_eq!(synced, vec!["a", "b", "d"]);
+    }
+
+    #[test]
+    fn test_sync_status() {
+        let interrupt_handle = SyncInterruptHandle::new();
+        let (interruptee, _guard) = interrupt_handle.begin_sync();
+        assert_eq!(
+            sync_status(sync15::ServiceStatus::OtherError, &interruptee),
+            ServiceStatus::OtherError
+        );
+        // An engine whose statements were interrupted fails with its own
+        // error, which should be reported as an interruption.
+        interrupt_handle.interrupt();
+        assert_eq!(
+            sync_status(sync15::ServiceStatus::OtherError, &interruptee),
+            ServiceStatus::Interrupted
+        );
+    }
+
+    #[test]
+    fn test_wipe_and_reset() {
+        let log = Arc::new(Mutex::new(vec![]));
+        let mut manager = SyncManager::new(SyncInterruptHandle::new());
+        manager.register_engine_provider(Box::new(TestProvider::new(&["a", "b"], &log)));
+        manager.register_engine_provider(Box::new(TestProvider {
+            open: false,
+            ..TestProvider::new(&["c"], &log)
+        }));
+
+        manager.wipe("a").expect("Should wipe a");
+        manager.reset("b").expect("Should reset b");
+        assert_eq!(take_log(&log), vec!["wipe a", "reset b"]);
+
+        match manager.wipe("c").unwrap_err().kind() {
+            ErrorKind::ConnectionClosed(engine) => assert_eq!(engine, "c"),
+            kind => panic!("Wrong error for closed engine: {:?}", kind),
+        }
+        match manager.reset("z").unwrap_err().kind() {
+            ErrorKind::UnknownEngine(engine) => assert_eq!(engine, "z"),
+            kind => panic!("Wrong error for unknown engine: {:?}", kind),
+        }
+
+        manager.wipe_all().expect("Should wipe all open engines");
+        assert_eq!(take_log(&log), vec!["wipe a", "wipe b"]);
+        manager.reset_all().expect("Should reset all open engines");
+        assert_eq!(take_log(&log), vec!["reset a", "reset b"]);
+    }
+
+    #[test]
+    fn test_wipe_all_continues_after_error() {
+        let log = Arc::new(Mutex::new(vec![]));
+        let mut manager = SyncManager::new(SyncInterruptHandle::new());
+        manager.register_engine_provider(Box::new(TestProvider {
+            fails: true,
+            ..TestProvider::new(&["a"], &log)
+        }));
+        manager.register_engine_provider(Box::new(TestProvider::new(&["b"], &log)));
+
+        assert!(manager.wipe_all().is_err());
+        assert_eq!(take_log(&log), vec!["wipe b"]);
+        assert!(manager.reset_all().is_err());
+        assert_eq!(take_log(&log), vec!["reset b"]);
+    }
+
+    #[test]
+    fn test_check_engine_list() {
+        let log = Arc::new(Mutex::new(vec![]));
+        let mut manager = SyncManager::new(SyncInterruptHandle::new());
+        manager.register_engine_provider(Box::new(TestProvider::new(&["a"], &log)));
+        manager.register_engine_provider(Box::new(TestProvider {
+            open: false,
+            ..TestProvider::new(&["b"], &log)
+        }));
+        let (known_engines, open_engines) = manager.engine_names();
+
+        check_engine_list(&["a".into()], &known_engines, &open_engines)
+            .expect("Should accept registered engines");
+        match check_engine_list(&["b".into()], &known_engines, &open_engines)
+            .unwrap_err()
+            .kind()
+        {
+            ErrorKind::UnsupportedFeature(engine) => assert_eq!(engine, "b"),
+            kind => panic!("Wrong error for closed engine: {:?}", kind),
+        }
+        match check_engine_list(&["z".into()], &known_engines, &open_engines)
+            .unwrap_err()
+            .kind()
+        {
+            ErrorKind::UnknownEngine(engine) => assert_eq!(engine, "z"),
+            kind => panic!("Wrong error for unknown engine: {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn test_register_replaces_provider() {
+        let old_log = Arc::new(Mutex::new(vec![]));
+        let new_log = Arc::new(Mutex::new(vec![]));
+        let mut manager = SyncManager::new(SyncInterruptHandle::new());
+        manager.register_engine_provider(Box::new(TestProvider::new(&["a", "b"], &old_log)));
+        manager.register_engine_provider(Box::new(TestProvider::new(&["a", "b"], &new_log)));
+
+        manager.wipe_all().expect("Should wipe all engines");
+        assert!(take_log(&old_log).is_empty());
+        assert_eq!(take_log(&new_log), vec!["wipe a", "wipe b"]);
+    }
+
+    #[test]
+    fn test_commands() {
+        let log = Arc::new(Mutex::new(vec![]));
+        let mut manager = SyncManager::new(SyncInterruptHandle::new());
+        manager.register_engine_provider(Box::new(TestProvider::new(&["a", "b"], &log)));
+        manager.register_engine_provider(Box::new(TestProvider {
+            fails: true,
+            ..TestProvider::new(&["c"], &log)
+        }));
+
+        let client = SyncClient::new(
+            Settings {
+                fxa_device_id: "device".into(),
+                device_name: "Device".into(),
+                device_type: clients::DeviceType::Mobile,
+            },
+            &manager,
+        );
+        let status = |command| client.apply_incoming_command(command).unwrap();
+        assert_eq!(status(Command::Wipe("a".into())), CommandStatus::Applied);
+        assert_eq!(status(Command::Reset("b".into())), CommandStatus::Applied);
+        assert_eq!(
+            status(Command::Wipe("z".into())),
+            CommandStatus::Unsupported
+        );
+        assert_eq!(take_log(&log), vec!["wipe a", "reset b"]);
+
+        // A command that fails should stop the sync, so that it's retried.
+        assert!(client
+            .apply_incoming_command(Command::Reset("c".into()))
+            .is_err());
+
+        // Commands shouldn't be applied once the stores are built.
+        client.stop_applying_commands();
+        assert_eq!(
+            status(Command::Wipe("a".into())),
+            CommandStatus::Unsupported
+        );
+        assert_eq!(status(Command::ResetAll), CommandStatus::Unsupported);
+        assert!(take_log(&log).is_empty());
+    }
+
+    /// Sends commands to the other devices on the account.
+    struct Sender {
+        settings: Settings,
+        commands: HashSet<Command>,
+    }
+
+    impl CommandProcessor for Sender {
+        fn settings(&self) -> &Settings {
+            &self.settings
+        }
+
+        fn apply_incoming_command(
+            &self,
+            _command: Command,
+        ) -> result::Result<CommandStatus, failure::Error> {
+            Ok(CommandStatus::Unsupported)
+        }
+
+        fn fetch_outgoing_commands(&self) -> result::Result<HashSet<Command>, failure::Error> {
+            Ok(self.commands.clone())
+        }
+    }
+
+    #[test]
+    fn test_wipe_command_before_upload() {
+        let server = MockSyncServer::new();
+        // Any 64-byte key will do.
+        let sync_key = "A".repeat(86);
+        let log = Arc::new(Mutex::new(vec![]));
+        let mut manager = SyncManager::new(SyncInterruptHandle::new());
+        manager.register_engine_provider(Box::new(TestProvider::new(&["forms"], &log)));
+        let params = || SyncParams {
+            sync_all_engines: true,
+            acct_key_id: "key-id".into(),
+            acct_access_token: "access-token".into(),
+            acct_tokenserver_url: server.tokenserver_url().to_string(),
+            acct_sync_key: sync_key.clone(),
+            fxa_device_id: "deviceBBBBBB".into(),
+            device_name: "Phone".into(),
+            device_type: DEVICE_TYPE_MOBILE,
+            ..SyncParams::default()
+        };
+
+        let result = manager.sync(params()).expect("Should sync");
+        assert_eq!(result.status, ServiceStatus::Ok as i32);
+        assert_eq!(take_log(&log), vec!["sync forms", "upload forms"]);
+
+        // Another device asks us to wipe our forms.
+        let sender = Sender {
+            settings: Settings {
+                fxa_device_id: "deviceAAAAAA".into(),
+                device_name: "Laptop".into(),
+                device_type: clients::DeviceType::Desktop,
+            },
+            commands: vec![Command::Wipe("forms".into())].into_iter().collect(),
+        };
+        let result = sync15::sync_multiple_with_command_processor(
+            Some(&sender),
+            &[],
+            &mut None,
+            &mut MemoryCachedState::default(),
+            &sync15::Sync15StorageClientInit {
+                key_id: "key-id".into(),
+                access_token: "access-token".into(),
+                tokenserver_url: server.tokenserver_url(),
+            },
+            &sync15::KeyBundle::from_ksync_base64(&sync_key).unwrap(),
+            &NeverInterrupts,
+            None,
+        );
+        assert!(result.result.is_ok());
+
+        // The wipe should happen before we build the stores, so that we don't
+        // upload the forms that we were asked to wipe.
+        let result = manager.sync(params()).expect("Should sync");
+        assert_eq!(result.status, ServiceStatus::Ok as i32);
+        assert_eq!(
+            take_log(&log),
+            vec!["wipe forms", "sync forms", "upload forms"]
+        );
+
+        // The command was applied, so we shouldn't wipe again.
+        manager.sync(params()).expect("Should sync");
+        assert_eq!(take_log(&log), vec!["sync forms", "upload forms"]);
+    }
+}
@@ -26,6 +26,16 @@ pub struct SyncParams {
     pub device_name: std::string::String,
     #[prost(enumeration="DeviceType", required, tag="12")]
     pub device_type: i32,
+    /// Limits for syncing history. Unset limits use the defaults.
+    #[prost(uint32, optional, tag="13")]
+    pub history_max_incoming_places: ::std::option::Option<u32>,
+    #[prost(uint32, optional, tag="14")]
+    pub history_max_outgoing_places: ::std::option::Option<u32>,
+    #[prost(uint32, optional, tag="15")]
+    pub history_max_visits: ::std::option::Option<u32>,
+    /// Only sync history visits from this many days before now.
+    #[prost(uint32, optional, tag="16")]
+    pub history_max_age_days: ::std::option::Option<u32>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct SyncResult {
@@ -73,4 +83,5 @@ pub enum ServiceStatus {
     AuthError = 4,
     BackedOff = 5,
     OtherError = 6,
+    Interrupted = 7,
 }
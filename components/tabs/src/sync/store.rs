@@ -189,6 +189,10 @@ impl<'a> Store for TabsStore<'a> {
         Ok(())
     }
 
+    fn get_last_sync(&self) -> result::Result<Option<ServerTimestamp>, failure::Error> {
+        Ok(self.last_sync.get())
+    }
+
     fn get_collection_requests(
         &self,
         server_timestamp: ServerTimestamp,